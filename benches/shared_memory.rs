@@ -0,0 +1,64 @@
+//! Instructions per second with and without [`Machine::share_memory`], to check that machines
+//! that don't share their memory aren't slowed down by the feature. Run with
+//! `cargo bench --bench shared_memory`.
+
+use std::time::{Duration, Instant};
+
+use rsoderh_jonsh_leben_emulator::machine::{Machine, MachineBuilder};
+
+const INSTRUCTIONS: usize = 20_000_000;
+const ROUNDS: usize = 5;
+
+/// Stores an increasing counter into a buffer forever.
+fn machine() -> Machine {
+    MachineBuilder::new()
+        .program(
+            &[
+                0x21, 0x00, 0x10, // 0000: LXI H, 1000H
+                0x3C, //             0003: INR A
+                0x77, //             0004: MOV M, A
+                0x23, //             0005: INX H
+                0xC3, 0x03, 0x00, // 0006: JMP 0003H
+            ],
+            0x0000,
+        )
+        .build()
+        .unwrap()
+}
+
+/// Fastest of `ROUNDS` runs of `INSTRUCTIONS` instructions.
+fn measure(setup: impl Fn(&mut Machine)) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let mut machine = machine();
+            setup(&mut machine);
+            let start = Instant::now();
+            for _ in 0..INSTRUCTIONS {
+                machine.step();
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<24} {:>8.2?}  {:>7.1} M instructions/s",
+        name,
+        elapsed,
+        INSTRUCTIONS as f64 / elapsed.as_secs_f64() / 1e6
+    );
+}
+
+fn main() {
+    report("not shared", measure(|_| {}));
+    for interval in [1_000_000, 100_000, 10_000] {
+        report(
+            &format!("shared every {}", interval),
+            measure(|machine| {
+                machine.share_memory(interval);
+            }),
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! Speed of the three stages a program goes through: assembling source, decoding instructions
+//! and executing them, measured with Criterion. Run with `cargo bench --bench stages`.
+//!
+//! - `decode` decodes 64 KiB of random documented instructions with `coding::decode`.
+//! - `execute` runs a `DCR`/`JNZ` loop of 10 million iterations.
+//! - `assemble` assembles a 2000-line program.
+//!
+//! Throughput is reported in instructions or lines per second.
+
+use std::hint::black_box;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use rsoderh_jonsh_leben_emulator::{
+    assembler::parse_assembly,
+    coding::{decode, encode_into, reader::Reader},
+    machine::{HaltReason, MachineBuilder, MachineState},
+};
+
+/// 64 KiB of documented instructions with random opcodes and operands. Bytes that don't decode
+/// as the start of an instruction are skipped.
+fn random_instructions() -> Vec<u8> {
+    let mut state: u32 = 0x2545_F491;
+    let mut random = move || {
+        // xorshift32
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state.to_le_bytes()
+    };
+    let mut program = Vec::with_capacity(0x10000);
+    while program.len() < 0x10000 - 3 {
+        let bytes = random();
+        if let Some(instruction) = decode(&mut Reader::new(&bytes[..3])) {
+            let mut encoded = [0; 3];
+            let length = encode_into(&mut encoded, instruction).unwrap();
+            program.extend_from_slice(&encoded[..length]);
+        }
+    }
+    program
+}
+
+fn decode_all(program: &[u8]) -> usize {
+    let mut stream = Reader::new(program);
+    let mut decoded = 0;
+    while decode(&mut stream).is_some() {
+        decoded += 1;
+    }
+    decoded
+}
+
+/// Runs `DCR B` and `JNZ` 160 × 250 × 250 = 10 million times, returning the number of executed
+/// instructions.
+fn count_down() -> usize {
+    let mut machine = MachineBuilder::new()
+        .program(
+            &[
+                0x1E, 0xA0, //       0000: MVI E, 160
+                0x16, 0xFA, //       0002: MVI D, 250
+                0x06, 0xFA, //       0004: MVI B, 250
+                0x05, //             0006: DCR B
+                0xC2, 0x06, 0x00, // 0007: JNZ 0006H
+                0x15, //             000A: DCR D
+                0xC2, 0x04, 0x00, // 000B: JNZ 0004H
+                0x1D, //             000E: DCR E
+                0xC2, 0x02, 0x00, // 000F: JNZ 0002H
+                0x76, //             0012: HLT
+            ],
+            0x0000,
+        )
+        .build()
+        .unwrap();
+    let mut executed = 0;
+    while machine.state() == MachineState::Running {
+        executed += machine.run_cycles(10_000).0 as usize;
+    }
+    assert_eq!(
+        machine.state(),
+        MachineState::Halted(HaltReason::HaltInstruction)
+    );
+    executed
+}
+
+/// 2000 lines of source: 500 blocks of four instructions, the first one labelled, referring to
+/// other labels.
+fn source() -> String {
+    let mut source = String::from("        ORG 0100H\n");
+    for block in 0..500 {
+        source += &format!("L{}:     MVI A, {}\n", block, block % 256);
+        source += &format!("        LXI H, L{}\n", (block + 1) % 500);
+        source += "        ADD M\n";
+        source += &format!("        JNZ L{}\n", (block + 7) % 500);
+    }
+    source += "        END\n";
+    source
+}
+
+fn stages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stages");
+    // The execute stage takes a while per iteration.
+    group.sample_size(10);
+
+    let program = random_instructions();
+    assert!(decode_all(&program) > 0x10000 / 3);
+    group.throughput(Throughput::Elements(decode_all(&program) as u64));
+    group.bench_function("decode", |b| b.iter(|| decode_all(black_box(&program))));
+
+    group.throughput(Throughput::Elements(count_down() as u64));
+    group.bench_function("execute", |b| b.iter(count_down));
+
+    let source = source();
+    group.throughput(Throughput::Elements(source.lines().count() as u64));
+    group.bench_function("assemble", |b| {
+        b.iter(|| parse_assembly(black_box(source.as_bytes())).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, stages);
+criterion_main!(benches);
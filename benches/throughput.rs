@@ -0,0 +1,97 @@
+//! Instructions per second of a counting loop that mixes register moves, memory accesses through
+//! `M`, 8-bit arithmetic, register pair increments and conditional jumps. Run with
+//! `cargo bench --bench throughput`.
+//!
+//! The loop is run both one `Machine::run_cycle` at a time and in batches with
+//! `Machine::run_cycles`, about 16 million instructions per round.
+
+use std::time::{Duration, Instant};
+
+use rsoderh_jonsh_leben_emulator::machine::{HaltReason, Machine, MachineBuilder, MachineState};
+
+/// Instructions per `Machine::run_cycles` call.
+const BATCH: u64 = 10_000;
+
+/// Runs a machine until it halts and returns the number of executed instructions.
+type Run = fn(&mut Machine) -> usize;
+
+const RUNS: usize = 40;
+const ROUNDS: usize = 5;
+
+/// Adds the inner counter to a byte of memory 65536 times, then halts.
+fn machine() -> Machine {
+    MachineBuilder::new()
+        .program(
+            &[
+                0x21, 0x00, 0x10, // 0000: LXI H, 1000H
+                0x11, 0x00, 0x00, // 0003: LXI D, 0000H
+                0x06, 0x00, //       0006: MVI B, 0
+                0x0E, 0x00, //       0008: MVI C, 0
+                0x7E, //             000A: MOV A, M
+                0x81, //             000B: ADD C
+                0x77, //             000C: MOV M, A
+                0x13, //             000D: INX D
+                0x0D, //             000E: DCR C
+                0xC2, 0x0A, 0x00, // 000F: JNZ 000AH
+                0x05, //             0012: DCR B
+                0xC2, 0x08, 0x00, // 0013: JNZ 0008H
+                0x76, //             0016: HLT
+            ],
+            0x0000,
+        )
+        .build()
+        .unwrap()
+}
+
+/// Fastest of `ROUNDS` rounds of `RUNS` runs of the program, with the number of instructions
+/// executed per round.
+fn measure(run: Run) -> (Duration, usize) {
+    (0..ROUNDS)
+        .map(|_| {
+            let mut machines: Vec<Machine> = (0..RUNS).map(|_| machine()).collect();
+            let start = Instant::now();
+            let mut executed = 0;
+            for machine in &mut machines {
+                executed += run(machine);
+            }
+            let elapsed = start.elapsed();
+            for machine in &machines {
+                assert_eq!(
+                    machine.state(),
+                    MachineState::Halted(HaltReason::HaltInstruction)
+                );
+            }
+            (elapsed, executed)
+        })
+        .min()
+        .unwrap()
+}
+
+fn step_by_step(machine: &mut Machine) -> usize {
+    let mut executed = 0;
+    while machine.run_cycle().is_some() {
+        executed += 1;
+    }
+    executed
+}
+
+fn batched(machine: &mut Machine) -> usize {
+    let mut executed = 0;
+    while machine.state() == MachineState::Running {
+        executed += machine.run_cycles(BATCH).0 as usize;
+    }
+    executed
+}
+
+fn main() {
+    let runs: [(&str, Run); 2] = [("run_cycle", step_by_step), ("run_cycles", batched)];
+    for (name, run) in runs {
+        let (elapsed, executed) = measure(run);
+        println!(
+            "counting loop, {:<10} {:>8.2?}  {:>7.1} M instructions/s",
+            name,
+            elapsed,
+            executed as f64 / elapsed.as_secs_f64() / 1e6
+        );
+    }
+}
@@ -0,0 +1,49 @@
+/*
+ * Run a small program through the C interface and print its output.
+ *
+ * Build the library with `cargo rustc --release --lib --crate-type cdylib`, then:
+ *
+ *     cc examples/c/embed.c -Iinclude -Ltarget/release -lrsoderh_jonsh_leben_emulator -o embed
+ *     LD_LIBRARY_PATH=target/release ./embed
+ */
+#include <stdio.h>
+
+#include "leben.h"
+
+int main(void) {
+    /* Echo two bytes of input, then halt. */
+    const uint8_t program[] = {
+        0xDB, 0x00, /* IN 0 */
+        0xD3, 0x00, /* OUT 0 */
+        0xDB, 0x00, /* IN 0 */
+        0xD3, 0x00, /* OUT 0 */
+        0x76,       /* HLT */
+    };
+
+    leben_machine_t *machine = leben_create();
+    if (machine == NULL) {
+        fprintf(stderr, "couldn't create machine: %s\n", leben_last_error_message());
+        return 1;
+    }
+
+    if (leben_load_program(machine, program, sizeof(program), 0x0100) != LEBEN_OK) {
+        fprintf(stderr, "couldn't load program: %s\n", leben_last_error_message());
+        leben_destroy(machine);
+        return 1;
+    }
+    leben_push_input(machine, (const uint8_t *)"ok", 2);
+
+    int64_t executed = leben_run(machine, 1000);
+
+    uint8_t output[64];
+    int64_t len = leben_drain_output(machine, output, sizeof(output));
+    printf("executed %lld instructions, state %d, output '%.*s'\n",
+           (long long)executed, leben_state(machine), (int)len, (const char *)output);
+
+    uint8_t a;
+    leben_get_register(machine, LEBEN_REGISTER_A, &a);
+    printf("A = 0x%02X, PC = 0x%04X\n", a, leben_get_pc(machine));
+
+    leben_destroy(machine);
+    return 0;
+}
@@ -0,0 +1,48 @@
+# Notebook-style tour of the Python bindings. Build them with `maturin develop` (see the README),
+# then run the file with `python examples/python/explore.py` or cell by cell in an editor that
+# understands `# %%` markers.
+
+# %% Assemble a program that sums the bytes of a table.
+import leben
+
+SOURCE = """        ORG 100H
+        LXI H, TABLE
+        MVI B, 5
+        MVI A, 0
+LOOP:   ADD M
+        INX H
+        DCR B
+        JNZ LOOP
+        STA SUM
+        HLT
+TABLE:  DB 1
+        DB 2
+        DB 3
+        DB 4
+        DB 5
+SUM:    DS 1
+        END
+"""
+
+program, origin, symbols = leben.assemble(SOURCE)
+print(f"{len(program)} bytes at {origin:#06x}, labels {symbols}")
+
+# %% Disassemble it again.
+for address, code, label, statement in leben.disassemble(program, origin):
+    label = f"{label}:" if label else ""
+    print(f"{address:04X}  {code.hex(' ').upper():<10} {label:<7} {statement}")
+
+# %% Step through the first instructions and watch the registers.
+machine = leben.Machine()
+machine.load_assembly(SOURCE)
+for _ in range(4):
+    machine.step()
+    print(
+        f"pc={machine.register('pc'):04X} a={machine.register('a'):02X} "
+        f"b={machine.register('b'):02X} hl={machine.register('hl'):04X}"
+    )
+
+# %% Run to the end and read the result from memory. Reads return a copy of the memory.
+machine.run(10_000)
+print(machine.halt_reason, machine.flags())
+print("sum:", machine.read_memory(symbols["SUM"], 1)[0])
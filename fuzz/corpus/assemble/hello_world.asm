@@ -0,0 +1,26 @@
+;
+; Print 'Hello, World!'
+;
+
+START:
+    MVI A, 0
+    LXI H, STR  ; Start of string
+
+LOOP:
+    MOV A, M    ; Read byte
+
+    CPI 0
+    JZ STOP     ; If null byte, stop
+
+    OUT 0       ; Print char
+
+    INX H       ; Pointer++
+    JMP LOOP
+
+STR:
+    DB 'Hello, World!'
+    DB 0
+
+STOP:
+    HLT
+    END
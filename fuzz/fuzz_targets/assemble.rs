@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rsoderh_jonsh_leben_emulator::fuzzing;
+
+fuzz_target!(|source: &[u8]| {
+    fuzzing::assemble(source);
+});
@@ -0,0 +1,3 @@
+LONGLABEL: HLT
+LONGL: HLT
+        END
@@ -0,0 +1,2 @@
+        DB 'unterminated
+        END
@@ -0,0 +1,163 @@
+#ifndef LEBEN_H
+#define LEBEN_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define LEBEN_OK 0
+
+/**
+ * A required pointer argument was null.
+ */
+#define LEBEN_ERR_NULL -1
+
+/**
+ * An argument was out of range, e.g. an unknown register or a memory range past 0xFFFF.
+ */
+#define LEBEN_ERR_INVALID_ARGUMENT -2
+
+/**
+ * The program couldn't be loaded.
+ */
+#define LEBEN_ERR_LOAD -3
+
+/**
+ * The emulator panicked. The machine should be destroyed.
+ */
+#define LEBEN_ERR_PANIC -4
+
+/**
+ * Machine states returned by `leben_step` and `leben_state`.
+ */
+#define LEBEN_STATE_RUNNING 0
+
+#define LEBEN_STATE_HALTED 1
+
+#define LEBEN_STATE_INVALID_INSTRUCTION 2
+
+#define LEBEN_STATE_STACK_OVERFLOW 3
+
+#define LEBEN_STATE_STACK_UNDERFLOW 4
+
+#define LEBEN_STATE_MEMORY_OVERFLOW 5
+
+#define LEBEN_STATE_WAITING_FOR_INPUT 6
+
+#define LEBEN_STATE_SELF_JUMP 7
+
+/**
+ * Registers readable with `leben_get_register`.
+ */
+#define LEBEN_REGISTER_A 0
+
+#define LEBEN_REGISTER_B 1
+
+#define LEBEN_REGISTER_C 2
+
+#define LEBEN_REGISTER_D 3
+
+#define LEBEN_REGISTER_E 4
+
+#define LEBEN_REGISTER_H 5
+
+#define LEBEN_REGISTER_L 6
+
+/**
+ * Opaque handle to a machine.
+ */
+typedef struct leben_machine_t leben_machine_t;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create a machine with empty memory. Returns null on failure.
+ */
+leben_machine_t *leben_create(void);
+
+/**
+ * Destroy a machine. Passing null does nothing.
+ */
+void leben_destroy(leben_machine_t *machine);
+
+/**
+ * Reset the machine and load `len` bytes at `origin`, starting execution there.
+ */
+int32_t leben_load_program(leben_machine_t *machine,
+                           const uint8_t *bytes,
+                           size_t len,
+                           uint16_t origin);
+
+/**
+ * Execute one instruction and return the resulting `LEBEN_STATE_*`.
+ */
+int32_t leben_step(leben_machine_t *machine);
+
+/**
+ * Execute up to `max_instructions` instructions, stopping early when the machine halts. Returns
+ * the number of executed instructions, or a negative error code.
+ */
+int64_t leben_run(leben_machine_t *machine, uint64_t max_instructions);
+
+/**
+ * The current `LEBEN_STATE_*` of the machine.
+ */
+int32_t leben_state(const leben_machine_t *machine);
+
+/**
+ * Store the value of the 8-bit register `register_id` (a `LEBEN_REGISTER_*`) in `out`.
+ */
+int32_t leben_get_register(const leben_machine_t *machine, uint32_t register_id, uint8_t *out);
+
+/**
+ * Store the flags byte, in the layout pushed by `PUSH PSW`, in `out`.
+ */
+int32_t leben_get_flags(const leben_machine_t *machine, uint8_t *out);
+
+/**
+ * The program counter. Returns 0 if `machine` is null.
+ */
+uint16_t leben_get_pc(const leben_machine_t *machine);
+
+/**
+ * The stack pointer. Returns 0 if `machine` is null.
+ */
+uint16_t leben_get_sp(const leben_machine_t *machine);
+
+/**
+ * Copy `len` bytes of memory starting at `address` into `buf`.
+ */
+int32_t leben_peek(const leben_machine_t *machine, uint16_t address, uint8_t *buf, size_t len);
+
+/**
+ * Copy `len` bytes from `buf` into memory starting at `address`.
+ */
+int32_t leben_poke(leben_machine_t *machine, uint16_t address, const uint8_t *buf, size_t len);
+
+/**
+ * Queue `len` bytes to be read by `IN 0`. Reading from an empty queue halts the machine.
+ */
+int32_t leben_push_input(leben_machine_t *machine, const uint8_t *bytes, size_t len);
+
+/**
+ * Move up to `capacity` bytes of program output into `buf`, oldest first. Returns the number of
+ * bytes written; output that didn't fit is kept for the next call.
+ */
+int64_t leben_drain_output(leben_machine_t *machine, uint8_t *buf, size_t capacity);
+
+/**
+ * Message describing the last error on this thread, or null if there was none. The string is
+ * owned by the library and valid until the next failing call on the same thread.
+ */
+const char *leben_last_error_message(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LEBEN_H */
@@ -13,6 +13,27 @@ pub type AssemblySource<'a> = &'a [u8];
 pub fn parse_assembly(
     source: AssemblySource,
 ) -> Result<(Vec<InstructionOrData>, u16), String> {
+    parse(source).map(|(instructions, origin, _)| (instructions, origin))
+}
+
+/// Like [`parse_assembly`], but also returns every label with its address, sorted by address.
+pub fn parse_assembly_with_labels(
+    source: AssemblySource,
+) -> Result<(Vec<InstructionOrData>, u16, Vec<(String, Address)>), String> {
+    let (instructions, origin, labels) = parse(source)?;
+    let mut labels: Vec<(String, Address)> = labels
+        .iter()
+        .map(|(name, address)| (String::from_utf8_lossy(name).into_owned(), address))
+        .collect();
+    labels.sort_by(|(a_name, a_address), (b_name, b_address)| {
+        a_address.cmp(b_address).then_with(|| a_name.cmp(b_name))
+    });
+    Ok((instructions, origin, labels))
+}
+
+fn parse(
+    source: AssemblySource,
+) -> Result<(Vec<InstructionOrData>, u16, LabelLookup), String> {
     let mut stream = parsable::ScopedStream::new(source);
     let outcome = parsable::WithEnd::<SourceFile>::parse(&mut stream);
     let source_file = match outcome.expect("parsing should give a result") {
@@ -83,7 +104,9 @@ pub fn parse_assembly(
                         .ok_or(format!("{}: Memory size overflowed", statement.index))?;
                 },
                 Statement::Instruction(instruction) => {
-                    current_address = current_address.checked_add(instruction.instruction_length())
+                    let length = instruction.instruction_length().ok_or(
+                        format!("{}: Invalid number", statement.index))?;
+                    current_address = current_address.checked_add(length as u16)
                         .ok_or(format!("{}: Memory size overflowed", statement.index))?;
                 },
             }
@@ -123,7 +146,7 @@ pub fn parse_assembly(
             }
         }
     }
-    Ok((instructions, origin_address))
+    Ok((instructions, origin_address, labels))
 }
 
 #[cfg(test)]
@@ -157,4 +180,51 @@ mod tests {
         ]);
         assert_eq!(start, 16);
     }
+
+    #[test]
+    fn labels() {
+        let source = b"
+                ORG 100H
+        START:  JMP LOOP
+        DATA:   DB 1
+        LOOP:   JMP START
+                END
+        ";
+
+        let (_, _, labels) = parse_assembly_with_labels(source).expect("Failed to parse program");
+        assert_eq!(labels, vec![
+            (String::from("START"), 0x100),
+            (String::from("DATA"), 0x103),
+            (String::from("LOOP"), 0x104),
+        ]);
+    }
+
+    #[test]
+    fn forward_references_and_invalid_numbers() {
+        let source = b"
+                ORG 100H
+                LXI H, TABLE
+                MVI A, 2
+                CALL DONE
+        TABLE:  DB 1
+        DONE:   HLT
+                END
+        ";
+        let (_, _, labels) = parse_assembly_with_labels(source).expect("Failed to parse program");
+        assert_eq!(labels, vec![
+            (String::from("TABLE"), 0x108),
+            (String::from("DONE"), 0x109),
+        ]);
+
+        let error = parse_assembly(b"        MVI A, 100H\n        END\n").unwrap_err();
+        assert!(error.ends_with("Invalid number"), "{}", error);
+    }
+
+    #[test]
+    fn string_longer_than_memory() {
+        let mut source = b"        DB '".to_vec();
+        source.extend(std::iter::repeat_n(b'X', 0x10010));
+        source.extend_from_slice(b"'\n        END\n");
+        assert!(parse_assembly(&source).is_err());
+    }
 }
@@ -6,12 +6,24 @@ use crate::instruction::Address;
 
 pub struct LabelLookup {
     map: HashMap<Vec<u8>, Address>,
+    /// Address of the labels that aren't in `map`.
+    fallback: Option<Address>,
 }
 
 impl LabelLookup {
     pub fn new() -> LabelLookup {
         LabelLookup {
             map: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// A lookup that resolves every label to 0, for when only the shape of an instruction matters
+    /// and not where its labels point.
+    pub fn placeholder() -> LabelLookup {
+        LabelLookup {
+            map: HashMap::new(),
+            fallback: Some(0),
         }
     }
 
@@ -31,7 +43,12 @@ impl LabelLookup {
 
     pub fn get(&self, label: Label) -> Option<Address> {
         let ident = LabelLookup::to_label_ident(&label);
-        self.map.get(&ident).copied()
+        self.map.get(&ident).copied().or(self.fallback)
+    }
+
+    /// All labels, by the part of their name that identifies them.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Address)> {
+        self.map.iter().map(|(ident, address)| (ident.as_slice(), *address))
     }
 }
 
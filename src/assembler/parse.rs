@@ -84,7 +84,7 @@ impl<'a> Parsable<'a> for LabelSegment {
     }
 
     fn error() -> parsable::ParseError {
-        todo!()
+        String::from("LabelSegment")
     }
 }
 
@@ -47,7 +47,7 @@ impl DataStatement {
             DataStatement::DefineByte(_, _, literal) => {
                 match literal {
                     LiteralStringOrNumber::String(literal_string) => {
-                        Some(literal_string.contents.span.len() as u16)
+                        literal_string.contents.span.len().try_into().ok()
                     },
                     LiteralStringOrNumber::Number(_) => {
                         Some(1)
@@ -94,8 +94,8 @@ impl ParsedInstruction {
             PI::Mov(_, _, r1, _, _, _, r2) => Some(I::Mov(r1, r2)),
             PI::Mvi(_, _, r1, _, _, _, data) => Some(I::Mvi(r1, data.try_into().ok()?)),
             PI::Lxi(_, _, rp, _, _, _, data) => Some(I::Lxi(rp, data.get(label_lookup)?.into())),
-            PI::Lda(_, _, label) => Some(I::Lda(label_lookup.get(label)?)),
-            PI::Sta(_, _, label) => Some(I::Sta(label_lookup.get(label)?)),
+            PI::Lda(_, _, address) => Some(I::Lda(address.get(label_lookup)?)),
+            PI::Sta(_, _, address) => Some(I::Sta(address.get(label_lookup)?)),
             PI::Lhld(_, _, data) => Some(I::Lhld(data.get(label_lookup)?)),
             PI::Shld(_, _, data) => Some(I::Shld(data.get(label_lookup)?)),
             PI::Ldax(_, _, rp) => Some(I::Ldax(rp)),
@@ -171,92 +171,19 @@ impl ParsedInstruction {
             PI::In(_, _, data) => Some(I::In(data.try_into().ok()?)),
             PI::Ei(_) => Some(I::Ei),
             PI::Di(_) => Some(I::Di),
+            PI::Rim(_) => Some(I::Rim),
+            PI::Sim(_) => Some(I::Sim),
             PI::Hlt(_) => Some(I::Hlt),
             PI::Nop(_) => Some(I::Nop),
         }
     }
 
-    pub fn instruction_length(&self) -> u16 {
-        match self.inner {
-            ParsedInstructionInner::Mov(..) => 1,
-            ParsedInstructionInner::Mvi(..) => 2,
-            ParsedInstructionInner::Lxi(..) => 3,
-            ParsedInstructionInner::Lda(..) => 3,
-            ParsedInstructionInner::Sta(..) => 3,
-            ParsedInstructionInner::Lhld(..) => 3,
-            ParsedInstructionInner::Shld(..) => 3,
-            ParsedInstructionInner::Ldax(..) => 1,
-            ParsedInstructionInner::Stax(..) => 1,
-            ParsedInstructionInner::Xchg(..) => 1,
-            ParsedInstructionInner::Add(..) => 1,
-            ParsedInstructionInner::Adi(..) => 2,
-            ParsedInstructionInner::Adc(..) => 1,
-            ParsedInstructionInner::Aci(..) => 2,
-            ParsedInstructionInner::Sub(..) => 1,
-            ParsedInstructionInner::Sui(..) => 2,
-            ParsedInstructionInner::Sbb(..) => 1,
-            ParsedInstructionInner::Sbi(..) => 2,
-            ParsedInstructionInner::Inr(..) => 1,
-            ParsedInstructionInner::Dcr(..) => 1,
-            ParsedInstructionInner::Inx(..) => 1,
-            ParsedInstructionInner::Dcx(..) => 1,
-            ParsedInstructionInner::Dad(..) => 1,
-            ParsedInstructionInner::Daa(..) => 1,
-            ParsedInstructionInner::Ana(..) => 1,
-            ParsedInstructionInner::Ani(..) => 2,
-            ParsedInstructionInner::Xra(..) => 1,
-            ParsedInstructionInner::Xri(..) => 2,
-            ParsedInstructionInner::Ora(..) => 1,
-            ParsedInstructionInner::Ori(..) => 2,
-            ParsedInstructionInner::Cmp(..) => 1,
-            ParsedInstructionInner::Cpi(..) => 2,
-            ParsedInstructionInner::Rlc(..) => 1,
-            ParsedInstructionInner::Rrc(..) => 1,
-            ParsedInstructionInner::Ral(..) => 1,
-            ParsedInstructionInner::Rar(..) => 1,
-            ParsedInstructionInner::Cma(..) => 1,
-            ParsedInstructionInner::Cmc(..) => 1,
-            ParsedInstructionInner::Stc(..) => 1,
-            ParsedInstructionInner::Jmp(..) => 3,
-            ParsedInstructionInner::Jc(..) => 3,
-            ParsedInstructionInner::Jnc(..) => 3,
-            ParsedInstructionInner::Jz(..) => 3,
-            ParsedInstructionInner::Jnz(..) => 3,
-            ParsedInstructionInner::Jp(..) => 3,
-            ParsedInstructionInner::Jm(..) => 3,
-            ParsedInstructionInner::Jpe(..) => 3,
-            ParsedInstructionInner::Jpo(..) => 3,
-            ParsedInstructionInner::Call(..) => 3,
-            ParsedInstructionInner::Cc(..) => 3,
-            ParsedInstructionInner::Cnc(..) => 3,
-            ParsedInstructionInner::Cz(..) => 3,
-            ParsedInstructionInner::Cnz(..) => 3,
-            ParsedInstructionInner::Cp(..) => 3,
-            ParsedInstructionInner::Cm(..) => 3,
-            ParsedInstructionInner::Cpe(..) => 3,
-            ParsedInstructionInner::Cpo(..) => 3,
-            ParsedInstructionInner::Ret(..) => 1,
-            ParsedInstructionInner::Rc(..) => 1,
-            ParsedInstructionInner::Rnc(..) => 1,
-            ParsedInstructionInner::Rz(..) => 1,
-            ParsedInstructionInner::Rnz(..) => 1,
-            ParsedInstructionInner::Rp(..) => 1,
-            ParsedInstructionInner::Rm(..) => 1,
-            ParsedInstructionInner::Rpe(..) => 1,
-            ParsedInstructionInner::Rpo(..) => 1,
-            ParsedInstructionInner::Rst(..) => 1,
-            ParsedInstructionInner::Pchl(..) => 1,
-            ParsedInstructionInner::Push(..) => 1,
-            ParsedInstructionInner::Pop(..) => 1,
-            ParsedInstructionInner::Xthl(..) => 1,
-            ParsedInstructionInner::Sphl(..) => 1,
-            ParsedInstructionInner::In(..) => 2,
-            ParsedInstructionInner::Out(..) => 2,
-            ParsedInstructionInner::Ei(..) => 1,
-            ParsedInstructionInner::Di(..) => 1,
-            ParsedInstructionInner::Hlt(..) => 1,
-            ParsedInstructionInner::Nop(..) => 1,
-        }
+    /// Length of the instruction in bytes, known before the addresses of the labels are. `None`
+    /// if an operand is out of range.
+    pub fn instruction_length(&self) -> Option<u8> {
+        self.clone()
+            .into_inner(&LabelLookup::placeholder())
+            .map(|instruction| instruction.length())
     }
 }
 
@@ -265,8 +192,8 @@ enum ParsedInstructionInner {
     Mov(Mov, Ws, Register, Ws, Comma, Ws, Register),
     Mvi(Mvi, Ws, Register, Ws, Comma, Ws, LiteralNumber),
     Lxi(Lxi, Ws, RegisterPair, Ws, Comma, Ws, LabelOrLiteralNumber),
-    Lda(Lda, Ws, Label),
-    Sta(Sta, Ws, Label),
+    Lda(Lda, Ws, LabelOrLiteralNumber),
+    Sta(Sta, Ws, LabelOrLiteralNumber),
     Lhld(Lhld, Ws, LabelOrLiteralNumber),
     Shld(Shld, Ws, LabelOrLiteralNumber),
     Ldax(Ldax, Ws, RegisterPairIndirect),
@@ -342,6 +269,8 @@ enum ParsedInstructionInner {
     Out(Out, Ws, LiteralNumber),
     Ei(Ei),
     Di(Di),
+    Rim(Rim),
+    Sim(Sim),
     Hlt(Hlt),
     Nop(Nop),
 }
@@ -89,6 +89,8 @@ parsable::literals! {
     pub struct Out = b"OUT";
     pub struct Ei = b"EI";
     pub struct Di = b"DI";
+    pub struct Rim = b"RIM";
+    pub struct Sim = b"SIM";
     pub struct Hlt = b"HLT";
     pub struct Nop = b"NOP";
 }
@@ -1,72 +1,1247 @@
-use std::{fs, io, path};
+use std::{
+    ffi::OsString,
+    fmt::Display,
+    fs,
+    io::{self, IsTerminal, Read, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::anyhow;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
-use crate::{assembler, coding, machine::Machine, ui};
+use crate::{
+    assembler, coding,
+    devices::{self, Framebuffer, SerialConsole, TextDisplay},
+    disasm::{self, Listing, ListingColumns, Symbols},
+    gdb,
+    instruction::{Address, Data16, RegisterPair},
+    loader::{self, MemoryImage},
+    machine::{
+        self, AluOperation, HaltReason, Machine, MachineBuilder, MachineState, MemoryDump,
+        RunOutcome, SaveInfo,
+    },
+    runner::{self, ProgramJob, Summary},
+    testing,
+    trace::{JsonTraceWriter, RecordedTrace, Replay, TraceWriter},
+};
+#[cfg(feature = "tui")]
+use crate::ui;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    /// Load a program with the assembled machine code from the specified file. Specify '-' to read
-    /// from stdin.
+#[command(name = "leben", version, about = "Intel 8080 assembler and emulator", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Load a program and run it, either in the terminal UI or headlessly.
+    Run(RunArgs),
+    /// Assemble a source file into a flat binary image.
+    Asm(AsmArgs),
+    /// Load a program and let GDB debug it over the remote serial protocol.
+    Gdb(GdbArgs),
+    /// Step forward and backward through a JSON trace of an earlier run in the terminal UI.
+    Replay(ReplayArgs),
+    /// Write a disassembly listing of a program.
+    Disasm(DisasmArgs),
+    /// Run every assembly program in a directory and compare its output to the expected output.
+    Test(TestArgs),
+    /// Write the truth table of an arithmetic or logic instruction as CSV.
+    AluDump(AluDumpArgs),
+    /// List the differences between two machine states, each a save state or a JSON state dump.
+    Diff(DiffArgs),
+}
+
+#[derive(Args, Debug)]
+struct LoadArgs {
+    /// Program to load. Specify '-' to read from stdin. If omitted, an empty machine is started.
+    file: Option<PathBuf>,
+    /// Address to load the program at, e.g. '0x100', '100H' or '256'. Defaults to 0 for flat
+    /// binaries and 0x100 for CP/M .com files. Assembly sources are placed by their ORG statement.
+    #[arg(long, value_parser = parse_address)]
+    origin: Option<Address>,
+    /// Format of the program file. Detected from the file extension when omitted.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// File whose contents are fed to the program through `IN 0`.
+    #[arg(long, value_name = "FILE")]
+    input_file: Option<PathBuf>,
+    /// Another file to load, as 'FILE@ADDRESS', or 'FILE' to use the default address of its format.
+    /// Can be given several times. The files must not overlap each other or the program.
+    #[arg(long = "load", value_name = "FILE[@ADDRESS]", value_parser = parse_segment)]
+    segments: Vec<Segment>,
+    /// Address execution starts at. Defaults to the entry point of the program, or of the first
+    /// '--load' file without a program.
+    #[arg(long, value_parser = parse_address)]
+    entry: Option<Address>,
+    /// Seed of the pseudo-random numbers read with `IN 1`. Runs with the same seed read the same
+    /// numbers.
+    #[arg(long, value_name = "SEED", default_value_t = devices::DEFAULT_SEED)]
+    random_seed: u32,
+    /// Let '--load' files overlap each other and the program, later files overwriting earlier ones.
+    #[arg(long)]
+    allow_overlap: bool,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    load: LoadArgs,
+    /// Run without the terminal UI, writing program output to stdout as it's produced or to the
+    /// output file. This is the default when stdout isn't a terminal.
     #[arg(long)]
-    binary: Option<path::PathBuf>,
+    headless: bool,
+    /// Start the terminal UI even if stdout isn't a terminal.
+    #[arg(long, conflicts_with = "headless")]
+    ui: bool,
+    /// Stop a headless run after executing this many instructions.
+    #[arg(long, value_name = "N")]
+    max_instructions: Option<u64>,
+    /// File to write the program output to after a headless run, instead of stdout.
+    #[arg(long, value_name = "FILE")]
+    output_file: Option<PathBuf>,
+    /// Write a line with the CPU state for every executed instruction to this file.
+    #[arg(long, value_name = "FILE")]
+    trace_file: Option<PathBuf>,
+    /// Format of the trace file.
+    #[arg(long, value_enum, default_value_t = TraceFormat::Text, requires = "trace_file")]
+    trace_format: TraceFormat,
+    /// Write a table of the opcodes executed by a headless run, and of the documented ones it
+    /// never executed, to this file when it stops.
+    #[arg(long, value_name = "FILE")]
+    coverage_report: Option<PathBuf>,
+    /// Write the machine state as JSON to this file when a headless run halts.
+    #[arg(long, value_name = "FILE")]
+    dump_state_on_halt: Option<PathBuf>,
+    /// Write a save state to this file when a headless run stops, or when the UI saves or the
+    /// machine halts in it. The UI saves next to the program file by default.
+    #[arg(long, value_name = "FILE")]
+    save_state: Option<PathBuf>,
+    /// Continue from a save state instead of starting the program. The program file can still be
+    /// given, it's only used to warn if the state was saved from a different program.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["origin", "segments", "entry", "input_file", "random_seed"])]
+    resume: Option<PathBuf>,
+    /// Attach an 80x25 text display at ports 0x30 to 0x33, shown above the output in the terminal
+    /// UI.
+    #[arg(long)]
+    text_display: bool,
+    /// Attach a serial console reading whole lines of input, with data at port 0x10 and status at
+    /// port 0x11.
+    #[arg(long)]
+    serial_console: bool,
+    /// Map a 32x32 character framebuffer at 0xF000 to 0xF3FF, shown above the output in the
+    /// terminal UI.
+    #[arg(long)]
+    framebuffer: bool,
+    /// Emulate the CP/M BDOS calls at 0x0005 that print text, and halt when the program jumps or
+    /// returns to 0x0000, to run CP/M programs such as the 8080 diagnostics.
+    #[arg(long)]
+    cpm: bool,
+    /// Log emulator events up to this level to stderr during a headless run. Without it, the
+    /// `RUST_LOG` environment variable selects what is logged, e.g. `RUST_LOG=trace`.
+    #[cfg(feature = "trace-log")]
+    #[arg(long, value_enum)]
+    log_level: Option<LogLevel>,
+    /// Color theme of the terminal UI.
+    #[cfg(feature = "tui")]
+    #[arg(long, value_enum, default_value_t = ThemeName::Mocha)]
+    theme: ThemeName,
+    /// Number of instructions the terminal UI executes at a time while running. Larger batches
+    /// run programs faster, but update the view less often.
+    #[cfg(feature = "tui")]
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    batch: u64,
+    /// Run in the terminal UI at the speed of an 8080 clocked at this many hertz, e.g. 2000000,
+    /// so that delay loops take as long as on real hardware.
+    #[cfg(feature = "tui")]
+    #[arg(long, value_name = "HZ", conflicts_with = "batch", value_parser = clap::value_parser!(u32).range(1..))]
+    clock_hz: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+struct GdbArgs {
+    #[command(flatten)]
+    load: LoadArgs,
+    /// TCP port on localhost to wait for GDB on.
+    #[arg(long, default_value_t = 3333)]
+    port: u16,
+}
+
+#[derive(Args, Debug)]
+struct ReplayArgs {
+    /// Trace written by 'run' with '--trace-format json'.
+    trace: PathBuf,
+    /// Program the trace was recorded with, loaded like for 'run' to show its memory. Without it
+    /// the memory starts out empty and only the recorded writes are shown.
+    file: Option<PathBuf>,
+    /// Address to load the program at, like for 'run'.
+    #[arg(long, value_parser = parse_address)]
+    origin: Option<Address>,
+    /// Format of the program file. Detected from the file extension when omitted.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// Another file to load, like for 'run'.
+    #[arg(long = "load", value_name = "FILE[@ADDRESS]", value_parser = parse_segment)]
+    segments: Vec<Segment>,
+    /// Let the loaded files overlap, like for 'run'.
     #[arg(long)]
-    assembly: Option<path::PathBuf>,
+    allow_overlap: bool,
+    /// Color theme of the terminal UI.
+    #[cfg(feature = "tui")]
+    #[arg(long, value_enum, default_value_t = ThemeName::Mocha)]
+    theme: ThemeName,
+}
+
+#[derive(Args, Debug)]
+struct AsmArgs {
+    /// Assembly source file. Specify '-' to read from stdin.
+    file: PathBuf,
+    /// File to write the assembled image to. Specify '-' to write to stdout.
+    #[arg(short, long, value_name = "FILE")]
+    output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct DisasmArgs {
+    /// Program to disassemble. Specify '-' to read from stdin.
+    file: PathBuf,
+    /// Address the program is placed at, like for 'run'.
+    #[arg(long, value_parser = parse_address)]
+    origin: Option<Address>,
+    /// Format of the program file. Detected from the file extension when omitted.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+    /// Address execution can start at, used to tell code from data. Can be given several times.
+    /// Defaults to the entry point of the program.
+    #[arg(long = "entry", value_name = "ADDRESS", value_parser = parse_address)]
+    entries: Vec<Address>,
+    /// File to write the listing to. Specify '-' to write to stdout. Defaults to the program file
+    /// with the extension '.lst'.
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Number of bytes shown per line.
+    #[arg(long, value_name = "N", default_value_t = ListingColumns::default().bytes)]
+    bytes_column: usize,
+    /// Width of the label column.
+    #[arg(long, value_name = "N", default_value_t = ListingColumns::default().label)]
+    label_column: usize,
+    /// Decode the bytes one instruction after the other instead of following the control flow
+    /// from the entry points. Only jump, call and restart targets are labeled.
+    #[arg(long, conflicts_with_all = ["entries", "bytes_column", "label_column"])]
+    linear: bool,
+}
+
+#[derive(Args, Debug)]
+struct TestArgs {
+    /// Directory with the programs. Every 'name.asm' is run with 'name.input' as input, if it
+    /// exists, and must halt at a HLT with the contents of 'name.expected' as output, if it exists.
+    dir: PathBuf,
+    /// Number of programs to run at the same time. Defaults to the number of CPUs.
+    #[arg(long, value_name = "N")]
+    jobs: Option<usize>,
+    /// Fail programs that don't halt within this many instructions.
+    #[arg(long, value_name = "N", default_value_t = runner::DEFAULT_BUDGET)]
+    max_instructions: usize,
+}
+
+#[derive(Args, Debug)]
+struct AluDumpArgs {
+    /// Instruction to tabulate: ADD, ADC, SUB, SBB, ANA, XRA, ORA, CMP, INR, DCR, DAA, RLC, RRC,
+    /// RAL or RAR.
+    operation: AluOperation,
+    /// File to write the table to. Defaults to stdout.
+    #[arg(short, long, value_name = "FILE", default_value = "-")]
+    output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// First state, written with '--save-state' or '--dump-state-on-halt'.
+    left: PathBuf,
+    /// Second state, in either format like the first.
+    right: PathBuf,
+}
+
+/// Program file formats understood by the loader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Raw machine code loaded at the origin.
+    Bin,
+    /// Intel HEX records.
+    Hex,
+    /// Assembly source, assembled before loading.
+    Asm,
+    /// CP/M executable, a raw binary loaded and started at 0x100.
+    Com,
+}
+
+impl Format {
+    /// Guess the format of a file from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "bin" | "rom" => Some(Self::Bin),
+            "hex" | "ihx" => Some(Self::Hex),
+            "asm" | "s" | "8080" => Some(Self::Asm),
+            "com" => Some(Self::Com),
+            _ => None,
+        }
+    }
+}
+
+/// Formats of the `--trace-file` of a run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum TraceFormat {
+    /// A line of registers and the instruction per step, see `trace::format_line`.
+    Text,
+    /// A JSON object per step with the registers, memory writes and output, for 'leben replay'.
+    Json,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum ThemeName {
+    Mocha,
+    Latte,
+    Plain,
+}
+
+#[cfg(feature = "tui")]
+impl From<ThemeName> for ui::Theme {
+    fn from(value: ThemeName) -> Self {
+        match value {
+            ThemeName::Mocha => ui::Theme::mocha(),
+            ThemeName::Latte => ui::Theme::latte(),
+            ThemeName::Plain => ui::Theme::plain(),
+        }
+    }
+}
+
+#[cfg(feature = "trace-log")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[cfg(feature = "trace-log")]
+impl From<LogLevel> for tracing::Level {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Error => tracing::Level::ERROR,
+            LogLevel::Warn => tracing::Level::WARN,
+            LogLevel::Info => tracing::Level::INFO,
+            LogLevel::Debug => tracing::Level::DEBUG,
+            LogLevel::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+/// Outcome of a CLI invocation, mapped to the process exit code.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Exit {
+    /// Everything went fine, exit code 0.
+    Success,
+    /// Some I/O or loading step failed, exit code 1.
+    Error,
+    /// The command line was invalid, exit code 2.
+    Usage,
+    /// The emulated program faulted or didn't finish within its budget, or the compared states
+    /// differ, exit code 3.
+    Fault,
+}
+
+impl Exit {
+    pub fn code(self) -> u8 {
+        match self {
+            Exit::Success => 0,
+            Exit::Error => 1,
+            Exit::Usage => 2,
+            Exit::Fault => 3,
+        }
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(value: Exit) -> Self {
+        ExitCode::from(value.code())
+    }
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Other(anyhow::Error),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(message) => f.write_str(message),
+            CliError::Other(err) => write!(f, "{:#}", err),
+        }
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for CliError {
+    fn from(value: E) -> Self {
+        CliError::Other(value.into())
+    }
+}
+
+/// Parse an address given as hexadecimal (`0x100` or `100H`) or decimal (`256`).
+fn parse_address(text: &str) -> Result<Address, String> {
+    let parsed = if let Some(digits) = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+    {
+        u16::from_str_radix(digits, 16)
+    } else if let Some(digits) = text
+        .strip_suffix('H')
+        .or_else(|| text.strip_suffix('h'))
+    {
+        u16::from_str_radix(digits, 16)
+    } else {
+        text.parse::<u16>()
+    };
+    parsed.map_err(|_| {
+        format!(
+            "'{}' is not an address in the range 0x0000-0xFFFF (use e.g. '0x100', '100H' or '256')",
+            text
+        )
+    })
+}
+
+/// A file given with `--load`.
+#[derive(Clone, Debug)]
+struct Segment {
+    path: PathBuf,
+    origin: Option<Address>,
+}
+
+/// Parse `FILE@ADDRESS` or `FILE`.
+fn parse_segment(text: &str) -> Result<Segment, String> {
+    match text.rsplit_once('@') {
+        Some((path, origin)) => Ok(Segment {
+            path: PathBuf::from(path),
+            origin: Some(parse_address(origin)?),
+        }),
+        None => Ok(Segment {
+            path: PathBuf::from(text),
+            origin: None,
+        }),
+    }
+}
+
+/// Host streams a run is connected to. `IN 0` reads `input` once the queued input runs out, and
+/// a headless run writes the program output to `output` as soon as it's produced.
+pub struct HostIo {
+    pub input: Box<dyn Read + Send>,
+    pub output: Box<dyn Write + Send>,
+    /// Whether `output` is a terminal the UI can be drawn on. Unless `--headless` or `--ui` is
+    /// given, runs are headless if it isn't.
+    pub terminal: bool,
+}
+
+impl HostIo {
+    /// The process's stdin and stdout.
+    pub fn stdio() -> Self {
+        Self {
+            input: Box::new(io::stdin()),
+            output: Box::new(io::stdout()),
+            terminal: io::stdout().is_terminal(),
+        }
+    }
+}
+
+/// Parse the arguments (including the program name) and execute the selected subcommand.
+pub fn dispatch<I, T>(args: I) -> Exit
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    dispatch_with(args, HostIo::stdio())
+}
+
+/// Like [`dispatch`], with runs connected to `host` instead of stdin and stdout.
+pub fn dispatch_with<I, T>(args: I, host: HostIo) -> Exit
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let cli = match Cli::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(err) => {
+            let _ = err.print();
+            return if err.use_stderr() {
+                Exit::Usage
+            } else {
+                // --help and --version
+                Exit::Success
+            };
+        }
+    };
+
+    let result = match cli.command {
+        Command::Run(args) => run(args, host),
+        Command::Asm(args) => asm(args),
+        Command::Gdb(args) => gdb(args),
+        Command::Replay(args) => replay(args),
+        Command::Disasm(args) => disasm(args),
+        Command::Test(args) => test(args),
+        Command::AluDump(args) => alu_dump(args),
+        Command::Diff(args) => diff(args, host),
+    };
+
+    match result {
+        Ok(exit) => exit,
+        Err(CliError::Usage(message)) => {
+            eprintln!("error: {}", message);
+            Exit::Usage
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            Exit::Error
+        }
+    }
+}
+
+pub fn start() -> ExitCode {
+    dispatch(std::env::args_os()).into()
+}
+
+fn read_input(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    if path.to_str() == Some("-") {
+        io::stdin().read_to_end(&mut buf)?;
+    } else {
+        fs::File::open(path)
+            .map_err(|err| anyhow!("Couldn't open '{}': {}", path.display(), err))?
+            .read_to_end(&mut buf)?;
+    }
+    Ok(buf)
+}
+
+fn write_output(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    if path.to_str() == Some("-") {
+        io::stdout().write_all(bytes)?;
+    } else {
+        fs::write(path, bytes)
+            .map_err(|err| anyhow!("Couldn't write '{}': {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+/// A program image given on the command line, with its labels if it was assembled.
+struct Program {
+    image: MemoryImage,
+    /// Only shown by the terminal UI.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    symbols: Symbols,
+}
+
+fn assemble(source: &[u8]) -> anyhow::Result<Program> {
+    let (instructions, base_addr, labels) =
+        assembler::parse_assembly_with_labels(source).map_err(|err| anyhow!("{}", err))?;
+
+    let mut program = Vec::new();
+    coding::encode_program(&mut program, &instructions)?;
+
+    Ok(Program {
+        image: MemoryImage {
+            origin: base_addr,
+            entry: base_addr,
+            bytes: program,
+        },
+        symbols: labels.into_iter().collect(),
+    })
+}
+
+/// Stack pointer of programs run with `--cpm`, before the warm boot address is pushed.
+const CPM_STACK: Address = 0xFF00;
+
+/// Instructions a headless run executes between checks whether writing the output failed.
+const OUTPUT_CHECK_INTERVAL: u64 = 10_000;
+
+/// Program output of a headless run, passed on as the program writes it.
+struct StreamedOutput {
+    writer: Box<dyn Write + Send>,
+    /// Everything written since the run started, added to [`Machine::output_buffer`] when it
+    /// ends.
+    collected: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Write for StreamedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.collected
+            .lock()
+            .unwrap()
+            .extend_from_slice(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Write the output `machine` already has to `writer`, followed by all further output as the
+/// program writes it. Returns where the further output is collected.
+fn stream_output(
+    machine: &mut Machine,
+    mut writer: Box<dyn Write + Send>,
+) -> anyhow::Result<Arc<Mutex<Vec<u8>>>> {
+    writer
+        .write_all(machine.output_buffer())
+        .and_then(|()| writer.flush())
+        .map_err(|err| anyhow!("Couldn't write the program output: {}", err))?;
+    let collected = Arc::new(Mutex::new(Vec::new()));
+    machine.set_output(StreamedOutput {
+        writer,
+        collected: Arc::clone(&collected),
+    });
+    Ok(collected)
 }
 
-pub fn start() -> anyhow::Result<()> {
-    let args = Args::parse();
-    
-    let mut machine = Machine::new();
+/// Let `IN 0` read from `input` once the queued input runs out. Bytes are read one at a time when
+/// the program asks for them, so interactive programs work through a pipe.
+fn input_source(mut input: Box<dyn Read + Send>) -> impl FnMut() -> Option<u8> + Send + 'static {
+    move || {
+        let mut byte = [0];
+        input.read_exact(&mut byte).ok()?;
+        Some(byte[0])
+    }
+}
+
+fn load_program(
+    path: &Path,
+    format: Option<Format>,
+    origin: Option<Address>,
+) -> Result<Program, CliError> {
+    let format = match format {
+        Some(format) => format,
+        None if path.to_str() == Some("-") => Format::Bin,
+        None => Format::from_path(path).ok_or_else(|| {
+            CliError::Usage(format!(
+                "Can't detect the format of '{}' from its extension, specify it with --format",
+                path.display()
+            ))
+        })?,
+    };
+
+    let flat = |origin: Address| -> Result<Program, CliError> {
+        Ok(Program {
+            image: loader::load_flat_file(path, origin)?,
+            symbols: Symbols::new(),
+        })
+    };
+    match format {
+        Format::Bin => flat(origin.unwrap_or(0x0000)),
+        Format::Com => flat(origin.unwrap_or(0x0100)),
+        Format::Asm => {
+            if origin.is_some() {
+                return Err(CliError::Usage(String::from(
+                    "--origin can't be used with assembly sources, use ORG in the source instead",
+                )));
+            }
+            Ok(assemble(&read_input(path)?)?)
+        }
+        Format::Hex => {
+            if origin.is_some() {
+                return Err(CliError::Usage(String::from(
+                    "--origin can't be used with Intel HEX files, their records have addresses",
+                )));
+            }
+            Ok(Program {
+                image: loader::load_ihex_file(path)?,
+                symbols: Symbols::new(),
+            })
+        }
+    }
+}
+
+fn configure(
+    builder: MachineBuilder,
+    args: &LoadArgs,
+) -> Result<(MachineBuilder, Vec<Program>), CliError> {
+    let builder = builder
+        .random_seed(args.random_seed)
+        .allow_overlap(args.allow_overlap);
+    let mut builder = match &args.input_file {
+        Some(path) => builder.input(&read_input(path)?),
+        None => builder,
+    };
 
-    if let Some(path) = args.binary {
-        let mut file: Box<dyn io::Read> = if path.to_str() == Some("-") {
-            Box::new(io::stdin())
+    let mut programs = Vec::new();
+    if let Some(path) = &args.file {
+        programs.push(load_program(path, args.format, args.origin)?);
+    }
+    for segment in &args.segments {
+        programs.push(load_program(&segment.path, None, segment.origin)?);
+    }
+
+    for (index, program) in programs.iter().enumerate() {
+        builder = if index == 0 {
+            builder.image(&program.image)
         } else {
-            Box::new(
-                fs::OpenOptions::new()
-                    .read(true)
-                    .open(path)?,
-            )
+            builder.segment(&program.image.bytes, program.image.origin)
         };
+    }
+    if let Some(entry) = args.entry {
+        builder = builder.pc(entry);
+    }
+    Ok((builder, programs))
+}
+
+/// One line per file given with `args`, with the addresses it was loaded at, e.g.
+/// `loaded 0x0100..0x03FF from prog.com`.
+fn load_summary(args: &LoadArgs, programs: &[Program]) -> Vec<String> {
+    let paths = args
+        .file
+        .iter()
+        .chain(args.segments.iter().map(|segment| &segment.path));
+    paths
+        .zip(programs)
+        .map(|(path, program)| {
+            let source = match path.to_str() {
+                Some("-") => String::from("stdin"),
+                _ => path.display().to_string(),
+            };
+            let range = program.image.range();
+            if range.is_empty() {
+                format!("loaded nothing from {}", source)
+            } else {
+                format!(
+                    "loaded 0x{:04X}..0x{:04X} from {}",
+                    range.start,
+                    range.end - 1,
+                    source
+                )
+            }
+        })
+        .collect()
+}
+
+/// Where a file made from the program at `path`, e.g. its listing, goes when no output file is
+/// given: next to the program with `extension`, or `leben.<extension>` without a program file.
+fn companion_path(path: Option<&Path>, extension: &str) -> PathBuf {
+    match path {
+        Some(path) if path.to_str() != Some("-") => path.with_extension(extension),
+        _ => PathBuf::from("leben").with_extension(extension),
+    }
+}
+
+/// Load a save state, warning if it was saved from another program than the one with
+/// `program_hash`.
+fn resume(path: &Path, program_hash: Option<u64>) -> anyhow::Result<(Machine, SaveInfo)> {
+    let file = fs::File::open(path)
+        .map_err(|err| anyhow!("Couldn't open '{}': {}", path.display(), err))?;
+    let (machine, info) = Machine::load_state(io::BufReader::new(file))
+        .map_err(|err| anyhow!("Couldn't resume from '{}': {}", path.display(), err))?;
+    if let (Some(saved), Some(current)) = (info.program_hash, program_hash)
+        && saved != current
+    {
+        eprintln!(
+            "warning: '{}' was saved from a different program, resuming anyway",
+            path.display()
+        );
+    }
+    Ok((machine, info))
+}
+
+/// The regions loaded into `machine`, which the UI draws in their own color, and what its export
+/// keys write.
+#[cfg(feature = "tui")]
+fn ui_regions(
+    machine: &Machine,
+    args: &LoadArgs,
+    programs: &[Program],
+) -> (Vec<std::ops::Range<usize>>, ui::ListingExport) {
+    let regions = machine.loaded_ranges().to_vec();
+    let mut symbols = Symbols::new();
+    for program in programs {
+        symbols.merge(&program.symbols);
+    }
+    let export = ui::ListingExport {
+        path: companion_path(args.file.as_deref(), "lst"),
+        image: regions
+            .iter()
+            .map(|region| region.start)
+            .min()
+            .zip(regions.iter().map(|region| region.end).max())
+            .map(|(start, end)| start..end),
+        entry: args
+            .entry
+            .or(programs.first().map(|program| program.image.entry))
+            .unwrap_or(0),
+        symbols,
+        columns: ListingColumns::default(),
+    };
+    (regions, export)
+}
+
+#[cfg(feature = "tui")]
+fn run_ui(
+    machine: Machine,
+    args: &RunArgs,
+    programs: &[Program],
+    save_info: SaveInfo,
+) -> Result<Exit, CliError> {
+    let (regions, export) = ui_regions(&machine, &args.load, programs);
+    let save = ui::StateSave {
+        on_halt: args.save_state.is_some(),
+        path: args
+            .save_state
+            .clone()
+            .unwrap_or_else(|| companion_path(args.load.file.as_deref(), "sav")),
+        info: save_info,
+    };
+    let speed = match args.clock_hz {
+        Some(clock_hz) => ui::Speed::Clock(clock_hz),
+        None => ui::Speed::Batch(args.batch),
+    };
+    ui::start(machine, args.theme.into(), regions, export, save, speed)?;
+    Ok(Exit::Success)
+}
 
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf)?;
-        
-        if machine.memory_mut().write_slice(0, &buf).is_none() {
-            return Err(anyhow!("Program doesn't fit in memory. Must be smaller than 256 Kib (65536 bytes)."));
-            
+#[cfg(not(feature = "tui"))]
+fn run_ui(
+    _machine: Machine,
+    _args: &RunArgs,
+    _programs: &[Program],
+    _save_info: SaveInfo,
+) -> Result<Exit, CliError> {
+    Err(CliError::Usage(String::from(
+        "This build has no terminal UI, run the program without --ui",
+    )))
+}
+
+#[cfg(feature = "tui")]
+fn replay_ui(
+    replay: Replay,
+    args: &ReplayArgs,
+    load: &LoadArgs,
+    programs: &[Program],
+) -> Result<Exit, CliError> {
+    let (regions, export) = ui_regions(replay.machine(), load, programs);
+    ui::replay(replay, args.theme.into(), regions, export)?;
+    Ok(Exit::Success)
+}
+
+#[cfg(not(feature = "tui"))]
+fn replay_ui(
+    _replay: Replay,
+    _args: &ReplayArgs,
+    _load: &LoadArgs,
+    _programs: &[Program],
+) -> Result<Exit, CliError> {
+    Err(CliError::Usage(String::from(
+        "This build has no terminal UI, which replaying a trace needs",
+    )))
+}
+
+/// Whether to run without the terminal UI: when asked to, and otherwise when there's no terminal
+/// to draw it on or the build has no UI.
+fn is_headless(args: &RunArgs, terminal: bool) -> bool {
+    if args.headless {
+        true
+    } else if args.ui {
+        false
+    } else {
+        !terminal || cfg!(not(feature = "tui"))
+    }
+}
+
+fn run(args: RunArgs, host: HostIo) -> Result<Exit, CliError> {
+    let (builder, programs) = configure(MachineBuilder::new(), &args.load)?;
+    let program_hash = programs
+        .iter()
+        .map(|program| program.image.hash())
+        .reduce(|hash, next| hash.rotate_left(5) ^ next);
+    let (mut machine, save_info) = match &args.resume {
+        Some(path) => resume(path, program_hash)?,
+        None => {
+            let machine = builder.build()?;
+            for line in load_summary(&args.load, &programs) {
+                eprintln!("{}", line);
+            }
+            (machine, SaveInfo { program_hash })
         }
+    };
+    if args.text_display {
+        TextDisplay::default().attach(&mut machine);
     }
-    
-    if let Some(path) = args.assembly {
-        let mut file: Box<dyn io::Read> = if path.to_str() == Some("-") {
-            Box::new(io::stdin())
-        } else {
-            Box::new(
-                fs::OpenOptions::new()
-                    .read(true)
-                    .open(path)?,
-            )
+    if args.serial_console {
+        SerialConsole::default().attach(&mut machine);
+    }
+    if args.framebuffer {
+        Framebuffer::default().attach(&mut machine);
+    }
+    if args.cpm {
+        machine.enable_cpm_shim();
+        if args.resume.is_none() {
+            // Like CP/M, start with a stack below the system and the warm boot address on it, so
+            // returning from the program ends it.
+            machine.set_register_16(RegisterPair::Sp, Data16::from(CPM_STACK));
+            machine
+                .stack_push(Data16::from(0x0000))
+                .expect("the stack is far from address 0");
+        }
+    }
+
+    if let Some(path) = &args.trace_file {
+        let file = io::BufWriter::new(
+            fs::File::create(path)
+                .map_err(|err| anyhow!("Couldn't create '{}': {}", path.display(), err))?,
+        );
+        match args.trace_format {
+            TraceFormat::Text => machine.add_observer(Box::new(TraceWriter::new(file))),
+            TraceFormat::Json => machine.add_observer(Box::new(JsonTraceWriter::new(file))),
+        }
+    }
+
+    if !is_headless(&args, host.terminal) {
+        // Reading stdin would block the UI, which asks for the input instead.
+        machine.set_wait_for_input(true);
+        return run_ui(machine, &args, &programs, save_info);
+    }
+    machine.set_input_source(input_source(host.input));
+
+    // Without an output file the output is passed on unbuffered as the program writes it. It's
+    // still collected in the machine, so save states include it.
+    let streamed = if args
+        .output_file
+        .as_ref()
+        .is_none_or(|path| path.to_str() == Some("-"))
+    {
+        Some(stream_output(&mut machine, host.output)?)
+    } else {
+        None
+    };
+
+    if args.coverage_report.is_some() {
+        machine.enable_coverage();
+    }
+
+    #[cfg(feature = "trace-log")]
+    {
+        use tracing_subscriber::{EnvFilter, filter::LevelFilter};
+
+        let filter = match args.log_level {
+            Some(level) => {
+                let level = LevelFilter::from(tracing::Level::from(level));
+                Some(EnvFilter::default().add_directive(level.into()))
+            }
+            None => std::env::var_os("RUST_LOG").map(|_| EnvFilter::from_default_env()),
         };
+        if let Some(filter) = filter {
+            // Fails if a subscriber is already installed, e.g. when called from a test.
+            let _ = tracing_subscriber::fmt()
+                .with_env_filter(filter)
+                .with_writer(io::stderr)
+                .try_init();
+        }
+    }
+    #[cfg(feature = "trace-log")]
+    let _span = tracing::info_span!("headless_run", max_instructions = args.max_instructions)
+        .entered();
+
+    let mut executed = 0;
+    let exit = loop {
+        let budget = args.max_instructions.map_or(u64::MAX, |max| max - executed);
+        let (outcome, count) = machine.run_until_halt(budget.min(OUTPUT_CHECK_INTERVAL));
+        executed += count;
+        if let Some(err) = machine.take_output_error() {
+            return Err(anyhow!("Couldn't write the program output: {}", err).into());
+        }
+        match outcome {
+            RunOutcome::Halted(HaltReason::HaltInstruction) => break Exit::Success,
+            RunOutcome::Halted(reason) => {
+                eprintln!(
+                    "Program faulted at 0x{:04X}: {}",
+                    machine.pc().value(),
+                    reason
+                );
+                break Exit::Fault;
+            }
+            // Only when resumed from a save state of the UI waiting for input.
+            RunOutcome::WaitingForInput => {
+                eprintln!("Program is waiting for input, but the input has ended");
+                break Exit::Fault;
+            }
+            RunOutcome::BudgetExhausted if args.max_instructions == Some(executed) => {
+                eprintln!(
+                    "Program didn't halt within {} instructions",
+                    executed
+                );
+                break Exit::Fault;
+            }
+            // Headless runs don't set breakpoints or watchpoints, or step through subroutines.
+            RunOutcome::BudgetExhausted
+            | RunOutcome::Breakpoint(_)
+            | RunOutcome::Watchpoint(_)
+            | RunOutcome::Returned(_)
+            | RunOutcome::Stepped(_) => {}
+        }
+    };
 
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf)?;
-        
-        let (instructions, base_addr) = assembler::parse_assembly(&buf).map_err(|err| anyhow!("{}", err))?;
-        
-        let mut program = Vec::new();
-        coding::encode_program(&mut program, &instructions)?;
-        
-        if machine.memory_mut().write_slice(base_addr, &program).is_none() {
-            return Err(anyhow!("Program doesn't fit in memory. It is {} bytes large, but must be smaller than 256 Kib (65536 bytes).", program.len()));
-            
+    match streamed {
+        Some(collected) => machine
+            .output
+            .append(&mut collected.lock().unwrap()),
+        None => {
+            if let Some(path) = &args.output_file {
+                write_output(path, machine.output_buffer())?;
+            }
         }
     }
-    
-    ui::start(machine)?;
 
-    Ok(())
+    if let Some(path) = &args.coverage_report
+        && let Some(coverage) = machine.coverage()
+    {
+        let mut report = String::new();
+        coverage.write(&mut report)?;
+        write_output(path, report.as_bytes())?;
+    }
+
+    if let Some(path) = &args.dump_state_on_halt
+        && matches!(machine.state(), MachineState::Halted(_))
+    {
+        let mut dump = Vec::new();
+        machine.dump_json(&mut dump, MemoryDump::NonZero)?;
+        write_output(path, &dump)?;
+    }
+
+    if let Some(path) = &args.save_state {
+        let mut save = Vec::new();
+        machine.save_state(&mut save, &save_info)?;
+        write_output(path, &save)?;
+    }
+
+    Ok(exit)
+}
+
+fn gdb(args: GdbArgs) -> Result<Exit, CliError> {
+    let (builder, programs) = configure(MachineBuilder::new(), &args.load)?;
+    let machine = builder.build()?;
+    for line in load_summary(&args.load, &programs) {
+        eprintln!("{}", line);
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", args.port))
+        .map_err(|err| anyhow!("Couldn't listen on port {}: {}", args.port, err))?;
+    eprintln!(
+        "Waiting for GDB on 127.0.0.1:{} (use 'target remote :{}')",
+        args.port, args.port
+    );
+    gdb::serve(machine, &listener)?;
+
+    Ok(Exit::Success)
+}
+
+fn replay(args: ReplayArgs) -> Result<Exit, CliError> {
+    let load = LoadArgs {
+        file: args.file.clone(),
+        origin: args.origin,
+        format: args.format,
+        input_file: None,
+        segments: args.segments.clone(),
+        entry: None,
+        random_seed: devices::DEFAULT_SEED,
+        allow_overlap: args.allow_overlap,
+    };
+    let (builder, programs) = configure(MachineBuilder::new(), &load)?;
+
+    let file = fs::File::open(&args.trace)
+        .map_err(|err| anyhow!("Couldn't open '{}': {}", args.trace.display(), err))?;
+    let trace = RecordedTrace::read(io::BufReader::new(file))
+        .map_err(|err| anyhow!("Couldn't read '{}': {}", args.trace.display(), err))?;
+
+    replay_ui(Replay::new(trace, builder.build()?), &args, &load, &programs)
+}
+
+fn asm(args: AsmArgs) -> Result<Exit, CliError> {
+    let source = read_input(&args.file)?;
+    let Program { image, .. } = assemble(&source)?;
+    write_output(&args.output, &image.bytes)?;
+    eprintln!(
+        "Assembled {} bytes starting at 0x{:04X}",
+        image.bytes.len(),
+        image.origin
+    );
+    Ok(Exit::Success)
+}
+
+fn disasm(args: DisasmArgs) -> Result<Exit, CliError> {
+    let Program { image, symbols } = load_program(&args.file, args.format, args.origin)?;
+    let output = args.output.unwrap_or_else(|| companion_path(Some(&args.file), "lst"));
+    if args.linear {
+        let mut memory = vec![0; image.origin as usize];
+        memory.extend_from_slice(&image.bytes);
+        let mut text = Vec::new();
+        for line in disasm::disassemble(&memory, image.origin, image.bytes.len()) {
+            writeln!(text, "{}", line)?;
+        }
+        write_output(&output, &text)?;
+        return Ok(Exit::Success);
+    }
+
+    let entries = if args.entries.is_empty() {
+        vec![image.entry]
+    } else {
+        args.entries
+    };
+    let listing = Listing::new(&image.bytes, image.origin, &entries, &symbols);
+
+    let columns = ListingColumns {
+        bytes: args.bytes_column,
+        label: args.label_column,
+    };
+    let mut text = Vec::new();
+    listing.write(&mut text, &columns)?;
+    write_output(&output, &text)?;
+    Ok(Exit::Success)
+}
+
+fn alu_dump(args: AluDumpArgs) -> Result<Exit, CliError> {
+    let mut table = String::new();
+    machine::write_alu_csv(&machine::alu_table(args.operation), &mut table)?;
+    write_output(&args.output, table.as_bytes())?;
+    Ok(Exit::Success)
+}
+
+/// Load the machine from a save state or a JSON state dump, told apart by the header of save
+/// states.
+fn load_machine_state(path: &Path) -> anyhow::Result<Machine> {
+    let bytes =
+        fs::read(path).map_err(|err| anyhow!("Couldn't read '{}': {}", path.display(), err))?;
+    let machine = if bytes.starts_with(machine::SAVE_MAGIC.as_bytes()) {
+        Machine::load_state(bytes.as_slice()).map(|(machine, _)| machine)
+    } else {
+        Machine::restore_json(bytes.as_slice())
+    };
+    machine.map_err(|err| anyhow!("Couldn't load '{}': {}", path.display(), err))
+}
+
+fn diff(args: DiffArgs, mut host: HostIo) -> Result<Exit, CliError> {
+    let left = load_machine_state(&args.left)?;
+    let right = load_machine_state(&args.right)?;
+    let diff = testing::diff_machines(&left, &right);
+    let identical = diff.is_empty();
+    write!(host.output, "{}", diff.colored(host.terminal))?;
+
+    if identical {
+        Ok(Exit::Success)
+    } else {
+        Ok(Exit::Fault)
+    }
+}
+
+/// Read the file at `path`, treating a missing file as absent.
+fn read_optional(path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(anyhow!("Couldn't read '{}': {}", path.display(), err)),
+    }
+}
+
+fn test(args: TestArgs) -> Result<Exit, CliError> {
+    let entries = fs::read_dir(&args.dir)
+        .map_err(|err| anyhow!("Couldn't read '{}': {}", args.dir.display(), err))?;
+    let mut sources = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "asm") {
+            sources.push(path);
+        }
+    }
+    sources.sort();
+    if sources.is_empty() {
+        return Err(CliError::Other(anyhow!(
+            "No '.asm' files found in '{}'",
+            args.dir.display()
+        )));
+    }
+
+    let mut jobs = Vec::new();
+    for source in &sources {
+        let mut machine = MachineBuilder::new().assembly(&read_input(source)?);
+        if let Some(input) = read_optional(&source.with_extension("input"))? {
+            machine = machine.input(&input);
+        }
+        let name = source.file_stem().unwrap_or_default().to_string_lossy();
+        let mut job = ProgramJob::new(name, machine).budget(args.max_instructions);
+        if let Some(expected) = read_optional(&source.with_extension("expected"))? {
+            job = job.expected(&expected);
+        }
+        jobs.push(job);
+    }
+
+    let parallelism = args.jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, |parallelism| parallelism.get())
+    });
+    let reports = runner::run_batch(jobs, parallelism);
+    print!("{}", Summary(&reports));
+
+    if reports.iter().all(|report| report.passed()) {
+        Ok(Exit::Success)
+    } else {
+        Ok(Exit::Fault)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(origin: Address, length: usize) -> Program {
+        Program {
+            image: MemoryImage {
+                origin,
+                entry: origin,
+                bytes: vec![0; length],
+            },
+            symbols: Symbols::new(),
+        }
+    }
+
+    #[test]
+    fn load_summary_lines() {
+        let args = LoadArgs {
+            file: Some(PathBuf::from("prog.com")),
+            origin: None,
+            format: None,
+            input_file: None,
+            segments: vec![
+                parse_segment("data.bin@0x2000").unwrap(),
+                parse_segment("-").unwrap(),
+            ],
+            entry: None,
+            random_seed: devices::DEFAULT_SEED,
+            allow_overlap: false,
+        };
+        let programs = [
+            program(0x0100, 0x300),
+            program(0x2000, 2),
+            program(0x0000, 0),
+        ];
+        assert_eq!(
+            load_summary(&args, &programs),
+            [
+                "loaded 0x0100..0x03FF from prog.com",
+                "loaded 0x2000..0x2001 from data.bin",
+                "loaded nothing from stdin",
+            ]
+        );
+    }
 }
@@ -1,25 +1,29 @@
-use std::io::{self, Write};
-
 use crate::{
-    coding::reader::Reader,
-    instruction::{Instruction, InstructionOrData},
+    coding::{reader::Reader, sink::Sink},
+    instruction::{Instruction, InstructionOrData, Variant},
 };
 
+// The `parse_*` functions are only used to check the table against.
+#[cfg_attr(not(test), allow(dead_code))]
 mod decode;
 mod encode;
+pub mod ihex;
 pub mod reader;
+pub mod sink;
+pub(crate) mod table;
 
-pub fn encode_program(buffer: &mut impl Write, items: &[InstructionOrData]) -> io::Result<()> {
+/// Encode `items` into `buffer` one after the other, as the assembler lays them out.
+pub fn encode_program(buffer: &mut impl Sink, items: &[InstructionOrData]) -> sink::Result<()> {
     for item in items {
         match item {
             InstructionOrData::Instruction(instruction) => {
                 encode(buffer, *instruction)?;
             }
             InstructionOrData::Byte(data) => {
-                buffer.write(&[*data])?;
+                buffer.write_all(&[*data])?;
             }
             InstructionOrData::Slice(slice) => {
-                buffer.write_all(&slice)?;
+                buffer.write_all(slice)?;
             },
         }
     }
@@ -27,7 +31,17 @@ pub fn encode_program(buffer: &mut impl Write, items: &[InstructionOrData]) -> i
     Ok(())
 }
 
-pub fn encode(buffer: &mut impl Write, instruction: Instruction) -> std::io::Result<()> {
+/// Encode `instruction` at the start of `buffer`, returning the number of bytes written, or `None`
+/// if it doesn't fit. No instruction is longer than 3 bytes.
+pub fn encode_into(buffer: &mut [u8], instruction: Instruction) -> Option<usize> {
+    let length = buffer.len();
+    let mut rest = buffer;
+    encode(&mut rest, instruction).ok()?;
+    Some(length - rest.len())
+}
+
+/// Encode `instruction` into `buffer`.
+pub fn encode(buffer: &mut impl Sink, instruction: Instruction) -> sink::Result<()> {
     match instruction {
         Instruction::Mov(register, register1) => encode::encode_mov(buffer, register, register1),
         Instruction::Mvi(register, data) => encode::encode_mvi(buffer, register, data),
@@ -94,67 +108,351 @@ pub fn encode(buffer: &mut impl Write, instruction: Instruction) -> std::io::Res
         Instruction::Out(port) => encode::encode_out(buffer, port),
         Instruction::Ei => encode::encode_ei(buffer),
         Instruction::Di => encode::encode_di(buffer),
+        Instruction::Rim => encode::encode_rim(buffer),
+        Instruction::Sim => encode::encode_sim(buffer),
         Instruction::Hlt => encode::encode_hlt(buffer),
         Instruction::Nop => encode::encode_nop(buffer),
     }
 }
 
+/// Decode the instruction at the start of `stream`, or return `None` without consuming anything if
+/// it isn't a valid instruction.
 pub fn decode<'a>(stream: &mut Reader<'a>) -> Option<Instruction> {
-    None.or_else(|| decode::parse_mov(stream))
-        .or_else(|| decode::parse_mvi(stream))
-        .or_else(|| decode::parse_lxi(stream))
-        .or_else(|| decode::parse_lda(stream))
-        .or_else(|| decode::parse_sta(stream))
-        .or_else(|| decode::parse_lhld(stream))
-        .or_else(|| decode::parse_shld(stream))
-        .or_else(|| decode::parse_ldax(stream))
-        .or_else(|| decode::parse_stax(stream))
-        .or_else(|| decode::parse_xchg(stream))
-        .or_else(|| decode::parse_add(stream))
-        .or_else(|| decode::parse_adi(stream))
-        .or_else(|| decode::parse_adc(stream))
-        .or_else(|| decode::parse_aci(stream))
-        .or_else(|| decode::parse_sub(stream))
-        .or_else(|| decode::parse_sui(stream))
-        .or_else(|| decode::parse_sbb(stream))
-        .or_else(|| decode::parse_sbi(stream))
-        .or_else(|| decode::parse_inr(stream))
-        .or_else(|| decode::parse_dcr(stream))
-        .or_else(|| decode::parse_inx(stream))
-        .or_else(|| decode::parse_dcx(stream))
-        .or_else(|| decode::parse_dad(stream))
-        .or_else(|| decode::parse_daa(stream))
-        .or_else(|| decode::parse_ana(stream))
-        .or_else(|| decode::parse_ani(stream))
-        .or_else(|| decode::parse_xra(stream))
-        .or_else(|| decode::parse_xri(stream))
-        .or_else(|| decode::parse_ora(stream))
-        .or_else(|| decode::parse_ori(stream))
-        .or_else(|| decode::parse_cmp(stream))
-        .or_else(|| decode::parse_cpi(stream))
-        .or_else(|| decode::parse_rlc(stream))
-        .or_else(|| decode::parse_rrc(stream))
-        .or_else(|| decode::parse_ral(stream))
-        .or_else(|| decode::parse_rar(stream))
-        .or_else(|| decode::parse_cma(stream))
-        .or_else(|| decode::parse_cmc(stream))
-        .or_else(|| decode::parse_stc(stream))
-        .or_else(|| decode::parse_jmp(stream))
-        .or_else(|| decode::parse_jcc(stream))
-        .or_else(|| decode::parse_call(stream))
-        .or_else(|| decode::parse_ccc(stream))
-        .or_else(|| decode::parse_ret(stream))
-        .or_else(|| decode::parse_rcc(stream))
-        .or_else(|| decode::parse_rst(stream))
-        .or_else(|| decode::parse_pchl(stream))
-        .or_else(|| decode::parse_push(stream))
-        .or_else(|| decode::parse_pop(stream))
-        .or_else(|| decode::parse_xthl(stream))
-        .or_else(|| decode::parse_sphl(stream))
-        .or_else(|| decode::parse_in(stream))
-        .or_else(|| decode::parse_out(stream))
-        .or_else(|| decode::parse_ei(stream))
-        .or_else(|| decode::parse_di(stream))
-        .or_else(|| decode::parse_hlt(stream))
-        .or_else(|| decode::parse_nop(stream))
+    table::decode(stream, Variant::Intel8080, false)
+}
+
+/// Like [`decode`], but for the instruction set of `variant`. For [`Variant::Intel8085`], 0x20 and
+/// 0x30 decode to `RIM` and `SIM` instead of being undocumented.
+///
+/// With `aliases`, decode the remaining undocumented opcodes to the instructions they behave like
+/// on real hardware: `NOP` for 0x08, 0x10, ..., 0x38, `JMP` for 0xCB, `RET` for 0xD9 and `CALL`
+/// for 0xDD, 0xED and 0xFD.
+pub fn decode_variant<'a>(
+    stream: &mut Reader<'a>,
+    variant: Variant,
+    aliases: bool,
+) -> Option<Instruction> {
+    table::decode(stream, variant, aliases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{
+        Condition, Data16, OPCODES, Register, RegisterPair, RegisterPairIndirect,
+        RegisterPairOrStatus, RestartNumber,
+    };
+
+    const IMMEDIATE: [fn(u8) -> Instruction; 8] = [
+        Instruction::Adi,
+        Instruction::Aci,
+        Instruction::Sui,
+        Instruction::Sbi,
+        Instruction::Ani,
+        Instruction::Xri,
+        Instruction::Ori,
+        Instruction::Cpi,
+    ];
+
+    /// Decode by trying every `parse_*` function in turn, like before the table.
+    fn decode_chain<'a>(stream: &mut Reader<'a>) -> Option<Instruction> {
+        None.or_else(|| decode::parse_mov(stream))
+            .or_else(|| decode::parse_mvi(stream))
+            .or_else(|| decode::parse_lxi(stream))
+            .or_else(|| decode::parse_lda(stream))
+            .or_else(|| decode::parse_sta(stream))
+            .or_else(|| decode::parse_lhld(stream))
+            .or_else(|| decode::parse_shld(stream))
+            .or_else(|| decode::parse_ldax(stream))
+            .or_else(|| decode::parse_stax(stream))
+            .or_else(|| decode::parse_xchg(stream))
+            .or_else(|| decode::parse_add(stream))
+            .or_else(|| decode::parse_adi(stream))
+            .or_else(|| decode::parse_adc(stream))
+            .or_else(|| decode::parse_aci(stream))
+            .or_else(|| decode::parse_sub(stream))
+            .or_else(|| decode::parse_sui(stream))
+            .or_else(|| decode::parse_sbb(stream))
+            .or_else(|| decode::parse_sbi(stream))
+            .or_else(|| decode::parse_inr(stream))
+            .or_else(|| decode::parse_dcr(stream))
+            .or_else(|| decode::parse_inx(stream))
+            .or_else(|| decode::parse_dcx(stream))
+            .or_else(|| decode::parse_dad(stream))
+            .or_else(|| decode::parse_daa(stream))
+            .or_else(|| decode::parse_ana(stream))
+            .or_else(|| decode::parse_ani(stream))
+            .or_else(|| decode::parse_xra(stream))
+            .or_else(|| decode::parse_xri(stream))
+            .or_else(|| decode::parse_ora(stream))
+            .or_else(|| decode::parse_ori(stream))
+            .or_else(|| decode::parse_cmp(stream))
+            .or_else(|| decode::parse_cpi(stream))
+            .or_else(|| decode::parse_rlc(stream))
+            .or_else(|| decode::parse_rrc(stream))
+            .or_else(|| decode::parse_ral(stream))
+            .or_else(|| decode::parse_rar(stream))
+            .or_else(|| decode::parse_cma(stream))
+            .or_else(|| decode::parse_cmc(stream))
+            .or_else(|| decode::parse_stc(stream))
+            .or_else(|| decode::parse_jmp(stream))
+            .or_else(|| decode::parse_jcc(stream))
+            .or_else(|| decode::parse_call(stream))
+            .or_else(|| decode::parse_ccc(stream))
+            .or_else(|| decode::parse_ret(stream))
+            .or_else(|| decode::parse_rcc(stream))
+            .or_else(|| decode::parse_rst(stream))
+            .or_else(|| decode::parse_pchl(stream))
+            .or_else(|| decode::parse_push(stream))
+            .or_else(|| decode::parse_pop(stream))
+            .or_else(|| decode::parse_xthl(stream))
+            .or_else(|| decode::parse_sphl(stream))
+            .or_else(|| decode::parse_in(stream))
+            .or_else(|| decode::parse_out(stream))
+            .or_else(|| decode::parse_ei(stream))
+            .or_else(|| decode::parse_di(stream))
+            .or_else(|| decode::parse_hlt(stream))
+            .or_else(|| decode::parse_nop(stream))
+    }
+
+    fn round_trip(instruction: Instruction) -> Option<Instruction> {
+        let mut buffer = [0; 3];
+        let length = encode_into(&mut buffer, instruction).unwrap();
+        let mut reader = Reader::new(&buffer[..length]);
+        let decoded = decode(&mut reader);
+        assert!(reader.at_end(), "{:?} wasn't decoded completely", instruction);
+        decoded
+    }
+
+    #[test]
+    fn immediate_group_round_trip() {
+        for make in IMMEDIATE {
+            for data in [0x00, 0x42, 0xFF] {
+                assert_eq!(round_trip(make(data)), Some(make(data)));
+            }
+        }
+    }
+
+    #[test]
+    fn encode_immediate_group() {
+        let opcodes = [0xC6, 0xCE, 0xD6, 0xDE, 0xE6, 0xEE, 0xF6, 0xFE];
+        for (make, opcode) in IMMEDIATE.into_iter().zip(opcodes) {
+            let mut buffer = Vec::new();
+            encode(&mut buffer, make(0x5A)).unwrap();
+            assert_eq!(buffer, [opcode, 0x5A], "{:?}", make(0x5A));
+        }
+
+        let program = [
+            InstructionOrData::Instruction(Instruction::Cpi(0x0A)),
+            InstructionOrData::Instruction(Instruction::Adi(0x01)),
+        ];
+        let mut buffer = Vec::new();
+        encode_program(&mut buffer, &program).unwrap();
+        assert_eq!(buffer, [0xFE, 0x0A, 0xC6, 0x01]);
+    }
+
+    /// One of every instruction variant, with its encoding.
+    fn every_variant() -> Vec<(Instruction, &'static [u8])> {
+        vec![
+            (Instruction::Mov(Register::B, Register::M), &[0x46]),
+            (Instruction::Mvi(Register::A, 0x12), &[0x3E, 0x12]),
+            (Instruction::Lxi(RegisterPair::Sp, Data16::new(0x34, 0x12)), &[0x31, 0x34, 0x12]),
+            (Instruction::Lda(0x1234), &[0x3A, 0x34, 0x12]),
+            (Instruction::Sta(0x1234), &[0x32, 0x34, 0x12]),
+            (Instruction::Lhld(0x1234), &[0x2A, 0x34, 0x12]),
+            (Instruction::Shld(0x1234), &[0x22, 0x34, 0x12]),
+            (Instruction::Ldax(RegisterPairIndirect::De), &[0x1A]),
+            (Instruction::Stax(RegisterPairIndirect::Bc), &[0x02]),
+            (Instruction::Xchg, &[0xEB]),
+            (Instruction::Add(Register::C), &[0x81]),
+            (Instruction::Adi(0x12), &[0xC6, 0x12]),
+            (Instruction::Adc(Register::D), &[0x8A]),
+            (Instruction::Aci(0x12), &[0xCE, 0x12]),
+            (Instruction::Sub(Register::E), &[0x93]),
+            (Instruction::Sui(0x12), &[0xD6, 0x12]),
+            (Instruction::Sbb(Register::H), &[0x9C]),
+            (Instruction::Sbi(0x12), &[0xDE, 0x12]),
+            (Instruction::Inr(Register::L), &[0x2C]),
+            (Instruction::Dcr(Register::M), &[0x35]),
+            (Instruction::Inx(RegisterPair::Hl), &[0x23]),
+            (Instruction::Dcx(RegisterPair::Bc), &[0x0B]),
+            (Instruction::Dad(RegisterPair::De), &[0x19]),
+            (Instruction::Daa, &[0x27]),
+            (Instruction::Ana(Register::A), &[0xA7]),
+            (Instruction::Ani(0x12), &[0xE6, 0x12]),
+            (Instruction::Xra(Register::B), &[0xA8]),
+            (Instruction::Xri(0x12), &[0xEE, 0x12]),
+            (Instruction::Ora(Register::C), &[0xB1]),
+            (Instruction::Ori(0x12), &[0xF6, 0x12]),
+            (Instruction::Cmp(Register::D), &[0xBA]),
+            (Instruction::Cpi(0x12), &[0xFE, 0x12]),
+            (Instruction::Rlc, &[0x07]),
+            (Instruction::Rrc, &[0x0F]),
+            (Instruction::Ral, &[0x17]),
+            (Instruction::Rar, &[0x1F]),
+            (Instruction::Cma, &[0x2F]),
+            (Instruction::Cmc, &[0x3F]),
+            (Instruction::Stc, &[0x37]),
+            (Instruction::Jmp(0x1234), &[0xC3, 0x34, 0x12]),
+            (Instruction::Jcc(Condition::Minus, 0x1234), &[0xFA, 0x34, 0x12]),
+            (Instruction::Call(0x1234), &[0xCD, 0x34, 0x12]),
+            (Instruction::Ccc(Condition::NoZero, 0x1234), &[0xC4, 0x34, 0x12]),
+            (Instruction::Ret, &[0xC9]),
+            (Instruction::Rcc(Condition::Carry), &[0xD8]),
+            (Instruction::Rst(RestartNumber::R7), &[0xFF]),
+            (Instruction::Pchl, &[0xE9]),
+            (Instruction::Push(RegisterPairOrStatus::StatusWord), &[0xF5]),
+            (Instruction::Pop(RegisterPairOrStatus::De), &[0xD1]),
+            (Instruction::Xthl, &[0xE3]),
+            (Instruction::Sphl, &[0xF9]),
+            (Instruction::In(0x12), &[0xDB, 0x12]),
+            (Instruction::Out(0x12), &[0xD3, 0x12]),
+            (Instruction::Ei, &[0xFB]),
+            (Instruction::Di, &[0xF3]),
+            (Instruction::Hlt, &[0x76]),
+            (Instruction::Nop, &[0x00]),
+        ]
+    }
+
+    #[test]
+    fn encode_every_variant() {
+        for (instruction, expected) in every_variant() {
+            let mut buffer = Vec::new();
+            encode(&mut buffer, instruction).unwrap();
+            assert_eq!(buffer, expected, "{:?}", instruction);
+            assert_eq!(buffer.len(), instruction.length() as usize, "{:?}", instruction);
+        }
+    }
+
+    #[test]
+    fn decode_every_variant() {
+        for (instruction, bytes) in every_variant() {
+            assert_eq!(decode(&mut Reader::new(bytes)), Some(instruction));
+        }
+    }
+
+    #[test]
+    fn decode_arithmetic_group() {
+        const ARITHMETIC: [&str; 14] = [
+            "ADD", "ADI", "ADC", "ACI", "SUB", "SUI", "SBB", "SBI", "INR", "DCR", "INX", "DCX",
+            "DAD", "DAA",
+        ];
+        let mut decoded = 0;
+        for opcode in 0..=0xFF {
+            let info = &OPCODES[opcode as usize];
+            let name = info.mnemonic.split(' ').next().unwrap();
+            if !info.documented || !ARITHMETIC.contains(&name) {
+                continue;
+            }
+
+            let bytes = [opcode, 0x34, 0x12];
+            let mut reader = Reader::new(&bytes);
+            let instruction = decode(&mut reader)
+                .unwrap_or_else(|| panic!("{:02X} ({}) isn't decoded", opcode, info.mnemonic));
+            let expected = info.mnemonic.replace("d16", "1234H").replace("d8", "34H");
+            assert_eq!(instruction.to_string(), expected, "{:02X}", opcode);
+            assert_eq!(reader.read_amount_bytes(), info.length as usize, "{:02X}", opcode);
+            decoded += 1;
+        }
+        // 8 registers for each of ADD ADC SUB SBB INR DCR, 4 pairs for INX DCX DAD, ADI ACI SUI
+        // SBI and DAA.
+        assert_eq!(decoded, 8 * 6 + 4 * 3 + 5);
+    }
+
+    #[test]
+    fn encoded_length_of_every_opcode() {
+        for opcode in 0..=0xFF {
+            let Some(instruction) = decode(&mut Reader::new(&[opcode, 0x34, 0x12])) else {
+                continue;
+            };
+            let mut buffer = Vec::new();
+            encode(&mut buffer, instruction).unwrap();
+            assert_eq!(buffer.len(), instruction.length() as usize, "{:02X}", opcode);
+            assert_eq!(instruction.length(), OPCODES[opcode as usize].length, "{:02X}", opcode);
+        }
+    }
+
+    #[test]
+    fn cma_is_not_lhld() {
+        let mut buffer = Vec::new();
+        encode(&mut buffer, Instruction::Cma).unwrap();
+        assert_eq!(buffer, [0x2F]);
+        assert_eq!(decode(&mut Reader::new(&[0x2F])), Some(Instruction::Cma));
+        assert_eq!(
+            decode(&mut Reader::new(&[0x2A, 0x34, 0x12])),
+            Some(Instruction::Lhld(0x1234))
+        );
+    }
+
+    #[test]
+    fn table_agrees_with_parsers() {
+        for opcode in 0..=0xFF {
+            for bytes in [&[opcode, 0x34, 0x12][..], &[opcode, 0xCD], &[opcode]] {
+                let mut table = Reader::new(bytes);
+                let mut chain = Reader::new(bytes);
+                assert_eq!(
+                    (decode(&mut table), table.read_amount_bytes()),
+                    (decode_chain(&mut chain), chain.read_amount_bytes()),
+                    "{:02X?}",
+                    bytes
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decode_undocumented_aliases() {
+        for opcode in 0..=0xFF {
+            let bytes = [opcode, 0x34, 0x12];
+            let info = &OPCODES[opcode as usize];
+            let mut reader = Reader::new(&bytes);
+            let instruction = decode_variant(&mut reader, Variant::Intel8080, true)
+                .unwrap_or_else(|| panic!("{:02X} isn't decoded", opcode));
+            let length = reader.read_amount_bytes();
+            assert_eq!(length, info.length as usize, "{:02X}", opcode);
+            if info.documented {
+                assert_eq!(decode(&mut Reader::new(&bytes)), Some(instruction));
+            } else {
+                assert_eq!(decode(&mut Reader::new(&bytes)), None, "{:02X}", opcode);
+                let expected = info.mnemonic.replace("a16", "1234H");
+                assert_eq!(instruction.to_string(), expected, "{:02X}", opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_immediate_group() {
+        let bytes = [0xC6, 0x01, 0xFE, 0x0A, 0xD6];
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(decode(&mut reader), Some(Instruction::Adi(0x01)));
+        assert_eq!(decode(&mut reader), Some(Instruction::Cpi(0x0A)));
+        // The immediate byte is missing.
+        assert_eq!(decode(&mut reader), None);
+    }
+
+    #[test]
+    fn decode_8085() {
+        for (instruction, opcode) in [(Instruction::Rim, 0x20), (Instruction::Sim, 0x30)] {
+            let mut bytes = Vec::new();
+            encode(&mut bytes, instruction).unwrap();
+            assert_eq!(bytes, [opcode]);
+            for aliases in [false, true] {
+                let mut reader = Reader::new(&bytes);
+                assert_eq!(
+                    decode_variant(&mut reader, Variant::Intel8085, aliases),
+                    Some(instruction)
+                );
+                assert_eq!(reader.read_amount_bytes(), 1);
+            }
+            assert_eq!(
+                decode_variant(&mut Reader::new(&bytes), Variant::Intel8080, false),
+                None
+            );
+        }
+        // The other undocumented opcodes are the same on both.
+        let decode_8085 =
+            |aliases| decode_variant(&mut Reader::new(&[0x28]), Variant::Intel8085, aliases);
+        assert_eq!(decode_8085(false), None);
+        assert_eq!(decode_8085(true), Some(Instruction::Nop));
+    }
 }
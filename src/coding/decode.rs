@@ -1,4 +1,4 @@
-use std::ops::Range;
+use core::ops::Range;
 
 use crate::{
     coding::reader::Reader,
@@ -600,7 +600,7 @@ pub fn parse_cma<'a>(stream: &mut Reader<'a>) -> Option<Instruction> {
     static LEN: usize = 1;
     let bytes = stream.peek_n(LEN)?;
     let opcode = bytes[0];
-    if !is_eq_masked(opcode, 0b0010_1010, 0b1111_1111) {
+    if !is_eq_masked(opcode, 0b0010_1111, 0b1111_1111) {
         return None;
     };
 
@@ -815,7 +815,7 @@ pub fn parse_xthl<'a>(stream: &mut Reader<'a>) -> Option<Instruction> {
 
     stream.skip_n(LEN);
 
-    return Some(Instruction::Xchg);
+    return Some(Instruction::Xthl);
 }
 
 pub fn parse_sphl<'a>(stream: &mut Reader<'a>) -> Option<Instruction> {
@@ -828,7 +828,7 @@ pub fn parse_sphl<'a>(stream: &mut Reader<'a>) -> Option<Instruction> {
 
     stream.skip_n(LEN);
 
-    return Some(Instruction::Xchg);
+    return Some(Instruction::Sphl);
 }
 
 pub fn parse_in<'a>(stream: &mut Reader<'a>) -> Option<Instruction> {
@@ -1,4 +1,4 @@
-use std::io;
+use super::sink::{self, Sink};
 
 use crate::instruction::{Address, Condition, Data8, Data16, Port, Register, RegisterPair, RegisterPairIndirect, RegisterPairOrStatus, RestartNumber};
 
@@ -6,320 +6,328 @@ fn bits_write_offset(value: u8, insert: u8, index: u8) -> u8 {
     value | (insert << index)
 }
 
-fn write_opcode(stream: &mut impl io::Write, opcode: u8) -> io::Result<()> {
-    stream.write(&[opcode]).map(|_| ())
+fn write_opcode(stream: &mut impl Sink, opcode: u8) -> sink::Result<()> {
+    stream.write_all(&[opcode])
 }
 
-fn write_opcode_rp(stream: &mut impl io::Write, opcode: u8, rp: RegisterPair) -> io::Result<()> {
+fn write_opcode_rp(stream: &mut impl Sink, opcode: u8, rp: RegisterPair) -> sink::Result<()> {
     let opcode = bits_write_offset(opcode, rp.repr(), 4);
     write_opcode(stream, opcode)
 }
 
-fn write_opcode_rp_indirect(stream: &mut impl io::Write, opcode: u8, rp: RegisterPairIndirect) -> io::Result<()> {
+fn write_opcode_rp_indirect(stream: &mut impl Sink, opcode: u8, rp: RegisterPairIndirect) -> sink::Result<()> {
     let opcode = bits_write_offset(opcode, rp.repr(), 4);
     write_opcode(stream, opcode)
 }
 
-fn write_opcode_rp_or_status(stream: &mut impl io::Write, opcode: u8, rp: RegisterPairOrStatus) -> io::Result<()> {
+fn write_opcode_rp_or_status(stream: &mut impl Sink, opcode: u8, rp: RegisterPairOrStatus) -> sink::Result<()> {
     let opcode = bits_write_offset(opcode, rp.repr(), 4);
     write_opcode(stream, opcode)
 }
 
-fn write_opcode_ddd(stream: &mut impl io::Write, opcode: u8, ddd: Register) -> io::Result<()> {
+fn write_opcode_ddd(stream: &mut impl Sink, opcode: u8, ddd: Register) -> sink::Result<()> {
     let opcode = bits_write_offset(opcode, ddd.repr(), 3);
     write_opcode(stream, opcode)
 }
 
-fn write_opcode_sss(stream: &mut impl io::Write, opcode: u8, sss: Register) -> io::Result<()> {
+fn write_opcode_sss(stream: &mut impl Sink, opcode: u8, sss: Register) -> sink::Result<()> {
     let opcode = bits_write_offset(opcode, sss.repr(), 0);
     write_opcode(stream, opcode)
 }
 
-fn write_opcode_ddd_sss(stream: &mut impl io::Write, opcode: u8, ddd: Register, sss: Register) -> io::Result<()> {
+fn write_opcode_ddd_sss(stream: &mut impl Sink, opcode: u8, ddd: Register, sss: Register) -> sink::Result<()> {
     let opcode = bits_write_offset(opcode, ddd.repr(), 3);
     let opcode = bits_write_offset(opcode, sss.repr(), 0);
     write_opcode(stream, opcode)
 }
 
-fn write_opcode_cc(stream: &mut impl io::Write, opcode: u8, cc: Condition) -> io::Result<()> {
+fn write_opcode_cc(stream: &mut impl Sink, opcode: u8, cc: Condition) -> sink::Result<()> {
     let opcode = bits_write_offset(opcode, cc as u8, 3);
     write_opcode(stream, opcode)
 }
 
-fn write_opcode_restart_number(stream: &mut impl io::Write, opcode: u8, n: RestartNumber) -> io::Result<()> {
+fn write_opcode_restart_number(stream: &mut impl Sink, opcode: u8, n: RestartNumber) -> sink::Result<()> {
     let opcode = bits_write_offset(opcode, n as u8, 3);
     write_opcode(stream, opcode)
 }
 
-fn write_data_8(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
-    stream.write(&[data]).map(|_| ())
+fn write_data_8(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
+    stream.write_all(&[data])
 }
 
-fn write_data_16(stream: &mut impl io::Write, data: Data16) -> io::Result<()> {
-    stream.write(&[data.low, data.high]).map(|_| ())
+fn write_data_16(stream: &mut impl Sink, data: Data16) -> sink::Result<()> {
+    stream.write_all(&[data.low, data.high])
 }
 
-fn write_addr(stream: &mut impl io::Write, addr: Address) -> io::Result<()> {
+fn write_addr(stream: &mut impl Sink, addr: Address) -> sink::Result<()> {
     let data: Data16 = addr.into();
-    stream.write(&[data.low, data.high]).map(|_| ())
+    stream.write_all(&[data.low, data.high])
 }
 
-fn write_port(stream: &mut impl io::Write, port: Port) -> io::Result<()> {
-    stream.write(&[port]).map(|_| ())
+fn write_port(stream: &mut impl Sink, port: Port) -> sink::Result<()> {
+    stream.write_all(&[port])
 }
 
 
 
-pub fn encode_mov<'a>(stream: &mut impl io::Write, ddd: Register, sss: Register) -> io::Result<()> {
+pub fn encode_mov<'a>(stream: &mut impl Sink, ddd: Register, sss: Register) -> sink::Result<()> {
     write_opcode_ddd_sss(stream, 0b0100_0000, ddd, sss)
 }
 
-pub fn encode_mvi<'a>(stream: &mut impl io::Write, ddd: Register, data: Data8) -> io::Result<()> {
+pub fn encode_mvi<'a>(stream: &mut impl Sink, ddd: Register, data: Data8) -> sink::Result<()> {
     write_opcode_ddd(stream, 0b0000_0110, ddd)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_lxi<'a>(stream: &mut impl io::Write, rp: RegisterPair, data: Data16) -> io::Result<()> {
+pub fn encode_lxi<'a>(stream: &mut impl Sink, rp: RegisterPair, data: Data16) -> sink::Result<()> {
     write_opcode_rp(stream, 0b0000_0001, rp)?;
     write_data_16(stream, data)
 }
 
-pub fn encode_lda<'a>(stream: &mut impl io::Write, addr: Address) -> io::Result<()> {
+pub fn encode_lda<'a>(stream: &mut impl Sink, addr: Address) -> sink::Result<()> {
     write_opcode(stream, 0b0011_1010)?;
     write_addr(stream, addr)
 }
 
-pub fn encode_sta<'a>(stream: &mut impl io::Write, addr: Address) -> io::Result<()> {
+pub fn encode_sta<'a>(stream: &mut impl Sink, addr: Address) -> sink::Result<()> {
     write_opcode(stream, 0b0011_0010)?;
     write_addr(stream, addr)
 }
 
-pub fn encode_lhld<'a>(stream: &mut impl io::Write, addr: Address) -> io::Result<()> {
+pub fn encode_lhld<'a>(stream: &mut impl Sink, addr: Address) -> sink::Result<()> {
     write_opcode(stream, 0b0010_1010)?;
     write_addr(stream, addr)
 }
 
-pub fn encode_shld<'a>(stream: &mut impl io::Write, addr: Address) -> io::Result<()> {
+pub fn encode_shld<'a>(stream: &mut impl Sink, addr: Address) -> sink::Result<()> {
     write_opcode(stream, 0b0010_0010)?;
     write_addr(stream, addr)
 }
 
-pub fn encode_ldax<'a>(stream: &mut impl io::Write, rp: RegisterPairIndirect) -> io::Result<()> {
+pub fn encode_ldax<'a>(stream: &mut impl Sink, rp: RegisterPairIndirect) -> sink::Result<()> {
     write_opcode_rp_indirect(stream, 0b0000_1010, rp)
 }
 
-pub fn encode_stax<'a>(stream: &mut impl io::Write, rp: RegisterPairIndirect) -> io::Result<()> {
+pub fn encode_stax<'a>(stream: &mut impl Sink, rp: RegisterPairIndirect) -> sink::Result<()> {
     write_opcode_rp_indirect(stream, 0b0000_0010, rp)
 }
 
-pub fn encode_xchg<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_xchg<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b1110_1011)
 }
 
 
 
-pub fn encode_add<'a>(stream: &mut impl io::Write, sss: Register) -> io::Result<()> {
+pub fn encode_add<'a>(stream: &mut impl Sink, sss: Register) -> sink::Result<()> {
     write_opcode_sss(stream, 0b1000_0000, sss)
 }
 
-pub fn encode_adi<'a>(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
+pub fn encode_adi<'a>(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
     write_opcode(stream, 0b1100_0110)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_adc<'a>(stream: &mut impl io::Write, sss: Register) -> io::Result<()> {
+pub fn encode_adc<'a>(stream: &mut impl Sink, sss: Register) -> sink::Result<()> {
     write_opcode_sss(stream, 0b1000_1000, sss)
 }
 
-pub fn encode_aci<'a>(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
+pub fn encode_aci<'a>(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
     write_opcode(stream, 0b1100_1110)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_sub<'a>(stream: &mut impl io::Write, sss: Register) -> io::Result<()> {
+pub fn encode_sub<'a>(stream: &mut impl Sink, sss: Register) -> sink::Result<()> {
     write_opcode_sss(stream, 0b1001_0000, sss)
 }
 
-pub fn encode_sui<'a>(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
+pub fn encode_sui<'a>(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
     write_opcode(stream, 0b1101_0110)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_sbb<'a>(stream: &mut impl io::Write, sss: Register) -> io::Result<()> {
+pub fn encode_sbb<'a>(stream: &mut impl Sink, sss: Register) -> sink::Result<()> {
     write_opcode_sss(stream, 0b1001_1000, sss)
 }
 
-pub fn encode_sbi<'a>(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
+pub fn encode_sbi<'a>(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
     write_opcode(stream, 0b1101_1110)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_inr<'a>(stream: &mut impl io::Write, ddd: Register) -> io::Result<()> {
+pub fn encode_inr<'a>(stream: &mut impl Sink, ddd: Register) -> sink::Result<()> {
     write_opcode_ddd(stream, 0b0000_0100, ddd)
 }
 
-pub fn encode_dcr<'a>(stream: &mut impl io::Write, ddd: Register) -> io::Result<()> {
+pub fn encode_dcr<'a>(stream: &mut impl Sink, ddd: Register) -> sink::Result<()> {
     write_opcode_ddd(stream, 0b0000_0101, ddd)
 }
 
-pub fn encode_inx<'a>(stream: &mut impl io::Write, rp: RegisterPair) -> io::Result<()> {
+pub fn encode_inx<'a>(stream: &mut impl Sink, rp: RegisterPair) -> sink::Result<()> {
     write_opcode_rp(stream, 0b0000_0011, rp)
 }
 
-pub fn encode_dcx<'a>(stream: &mut impl io::Write, rp: RegisterPair) -> io::Result<()> {
+pub fn encode_dcx<'a>(stream: &mut impl Sink, rp: RegisterPair) -> sink::Result<()> {
     write_opcode_rp(stream, 0b0000_1011, rp)
 }
 
-pub fn encode_dad<'a>(stream: &mut impl io::Write, rp: RegisterPair) -> io::Result<()> {
+pub fn encode_dad<'a>(stream: &mut impl Sink, rp: RegisterPair) -> sink::Result<()> {
     write_opcode_rp(stream, 0b0000_1001, rp)
 }
 
-pub fn encode_daa<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_daa<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0010_0111)
 }
 
 
 
-pub fn encode_ana<'a>(stream: &mut impl io::Write, sss: Register) -> io::Result<()> {
+pub fn encode_ana<'a>(stream: &mut impl Sink, sss: Register) -> sink::Result<()> {
     write_opcode_sss(stream, 0b1010_0000, sss)
 }
 
-pub fn encode_ani<'a>(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
+pub fn encode_ani<'a>(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
     write_opcode(stream, 0b1110_0110)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_xra<'a>(stream: &mut impl io::Write, sss: Register) -> io::Result<()> {
+pub fn encode_xra<'a>(stream: &mut impl Sink, sss: Register) -> sink::Result<()> {
     write_opcode_sss(stream, 0b1010_1000, sss)
 }
 
-pub fn encode_xri<'a>(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
+pub fn encode_xri<'a>(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
     write_opcode(stream, 0b1110_1110)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_ora<'a>(stream: &mut impl io::Write, sss: Register) -> io::Result<()> {
+pub fn encode_ora<'a>(stream: &mut impl Sink, sss: Register) -> sink::Result<()> {
     write_opcode_sss(stream, 0b1011_0000, sss)
 }
 
-pub fn encode_ori<'a>(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
+pub fn encode_ori<'a>(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
     write_opcode(stream, 0b1111_0110)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_cmp<'a>(stream: &mut impl io::Write, sss: Register) -> io::Result<()> {
+pub fn encode_cmp<'a>(stream: &mut impl Sink, sss: Register) -> sink::Result<()> {
     write_opcode_sss(stream, 0b1011_1000, sss)
 }
 
-pub fn encode_cpi<'a>(stream: &mut impl io::Write, data: Data8) -> io::Result<()> {
+pub fn encode_cpi<'a>(stream: &mut impl Sink, data: Data8) -> sink::Result<()> {
     write_opcode(stream, 0b1111_1110)?;
     write_data_8(stream, data)
 }
 
-pub fn encode_rlc<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_rlc<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0000_0111)
 }
 
-pub fn encode_rrc<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_rrc<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0000_1111)
 }
 
-pub fn encode_ral<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_ral<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0001_0111)
 }
 
-pub fn encode_rar<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_rar<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0001_1111)
 }
 
-pub fn encode_cma<'a>(stream: &mut impl io::Write) -> io::Result<()> {
-    write_opcode(stream, 0b0010_1010)
+pub fn encode_cma<'a>(stream: &mut impl Sink) -> sink::Result<()> {
+    write_opcode(stream, 0b0010_1111)
 }
 
-pub fn encode_cmc<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_cmc<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0011_1111)
 }
 
-pub fn encode_stc<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_stc<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0011_0111)
 }
 
-pub fn encode_jmp<'a>(stream: &mut impl io::Write, addr: Address) -> io::Result<()> {
+pub fn encode_jmp<'a>(stream: &mut impl Sink, addr: Address) -> sink::Result<()> {
     write_opcode(stream, 0b1100_0011)?;
     write_addr(stream, addr)
 }
 
-pub fn encode_jcc<'a>(stream: &mut impl io::Write, cc: Condition, addr: Address) -> io::Result<()> {
+pub fn encode_jcc<'a>(stream: &mut impl Sink, cc: Condition, addr: Address) -> sink::Result<()> {
     write_opcode_cc(stream, 0b1100_0010, cc)?;
     write_addr(stream, addr)
 }
 
-pub fn encode_call<'a>(stream: &mut impl io::Write, addr: Address) -> io::Result<()> {
+pub fn encode_call<'a>(stream: &mut impl Sink, addr: Address) -> sink::Result<()> {
     write_opcode(stream, 0b1100_1101)?;
     write_addr(stream, addr)
 }
 
-pub fn encode_ccc<'a>(stream: &mut impl io::Write, cc: Condition, addr: Address) -> io::Result<()> {
+pub fn encode_ccc<'a>(stream: &mut impl Sink, cc: Condition, addr: Address) -> sink::Result<()> {
     write_opcode_cc(stream, 0b1100_0100, cc)?;
     write_addr(stream, addr)
 }
 
-pub fn encode_ret<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_ret<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b1100_1001)
 }
 
-pub fn encode_rcc<'a>(stream: &mut impl io::Write, cc: Condition) -> io::Result<()> {
+pub fn encode_rcc<'a>(stream: &mut impl Sink, cc: Condition) -> sink::Result<()> {
     write_opcode_cc(stream, 0b1100_0000, cc)
 }
 
-pub fn encode_rst<'a>(stream: &mut impl io::Write, n: RestartNumber) -> io::Result<()> {
+pub fn encode_rst<'a>(stream: &mut impl Sink, n: RestartNumber) -> sink::Result<()> {
     write_opcode_restart_number(stream, 0b1100_0111, n)
 }
 
-pub fn encode_pchl<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_pchl<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b1110_1001)
 }
 
 
 
-pub fn encode_push<'a>(stream: &mut impl io::Write, rp: RegisterPairOrStatus) -> io::Result<()> {
+pub fn encode_push<'a>(stream: &mut impl Sink, rp: RegisterPairOrStatus) -> sink::Result<()> {
     write_opcode_rp_or_status(stream, 0b1100_0101, rp)
 }
 
-pub fn encode_pop<'a>(stream: &mut impl io::Write, rp: RegisterPairOrStatus) -> io::Result<()> {
+pub fn encode_pop<'a>(stream: &mut impl Sink, rp: RegisterPairOrStatus) -> sink::Result<()> {
     write_opcode_rp_or_status(stream, 0b1100_0001, rp)
 }
 
-pub fn encode_xthl<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_xthl<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b1110_0011)
 }
 
-pub fn encode_sphl<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_sphl<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b1111_1001)
 }
 
-pub fn encode_in<'a>(stream: &mut impl io::Write, port: Port) -> io::Result<()> {
+pub fn encode_in<'a>(stream: &mut impl Sink, port: Port) -> sink::Result<()> {
     write_opcode(stream, 0b1101_1011)?;
     write_port(stream, port)
 }
 
-pub fn encode_out<'a>(stream: &mut impl io::Write, port: Port) -> io::Result<()> {
+pub fn encode_out<'a>(stream: &mut impl Sink, port: Port) -> sink::Result<()> {
     write_opcode(stream, 0b1101_0011)?;
     write_port(stream, port)
 }
 
-pub fn encode_ei<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_ei<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b1111_1011)
 }
 
-pub fn encode_di<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_di<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b1111_0011)
 }
 
-pub fn encode_hlt<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_rim(stream: &mut impl Sink) -> sink::Result<()> {
+    write_opcode(stream, 0b0010_0000)
+}
+
+pub fn encode_sim(stream: &mut impl Sink) -> sink::Result<()> {
+    write_opcode(stream, 0b0011_0000)
+}
+
+pub fn encode_hlt<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0111_0110)
 }
 
-pub fn encode_nop<'a>(stream: &mut impl io::Write) -> io::Result<()> {
+pub fn encode_nop<'a>(stream: &mut impl Sink) -> sink::Result<()> {
     write_opcode(stream, 0b0000_0000)
 }
@@ -0,0 +1,261 @@
+//! Parsing of Intel HEX, the text format most 8080 toolchains write programs in.
+//!
+//! Every line is a record `:LLAAAATT<data>CC` of hexadecimal byte pairs: the number of data bytes
+//! `LL`, the address `AAAA` of the first one, the record type `TT`, the data, and a checksum `CC`
+//! making all bytes of the record add up to 0. Only data records (type 00) and the end-of-file
+//! record (type 01) are used for 16-bit addresses.
+
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::instruction::Address;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+
+/// Error returned by [`parse`], on line `line`, starting at 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IhexError {
+    pub line: usize,
+    pub kind: IhexErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IhexErrorKind {
+    /// The line doesn't start with `:`.
+    MissingStartCode,
+    /// The record isn't an even number of hexadecimal digits.
+    InvalidHex,
+    /// The record is shorter than its byte count says.
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    /// The record is longer than its byte count says.
+    TrailingBytes {
+        expected: usize,
+        actual: usize,
+    },
+    /// The checksum byte is `actual` instead of `expected`.
+    Checksum {
+        expected: u8,
+        actual: u8,
+    },
+    UnsupportedRecordType(u8),
+    /// The data of a record at `address` would run past 0xFFFF.
+    PastEnd {
+        address: Address,
+        length: usize,
+    },
+    /// The file ends without an end-of-file record.
+    MissingEndOfFile,
+}
+
+impl Display for IhexError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Line {}: ", self.line)?;
+        match &self.kind {
+            IhexErrorKind::MissingStartCode => write!(f, "Record doesn't start with ':'"),
+            IhexErrorKind::InvalidHex => write!(f, "Record isn't made of hexadecimal byte pairs"),
+            IhexErrorKind::Truncated { expected, actual } => write!(
+                f,
+                "Record should be {} bytes long, but is only {} bytes",
+                expected, actual
+            ),
+            IhexErrorKind::TrailingBytes { expected, actual } => write!(
+                f,
+                "Record should be {} bytes long, but is {} bytes",
+                expected, actual
+            ),
+            IhexErrorKind::Checksum { expected, actual } => write!(
+                f,
+                "Checksum is 0x{:02X}, but should be 0x{:02X}",
+                actual, expected
+            ),
+            IhexErrorKind::UnsupportedRecordType(record_type) => write!(
+                f,
+                "Record type 0x{:02X} isn't supported, only data (00) and end of file (01) are",
+                record_type
+            ),
+            IhexErrorKind::PastEnd { address, length } => {
+                write!(f, "{} bytes at 0x{:04X} run past 0xFFFF", length, address)
+            }
+            IhexErrorKind::MissingEndOfFile => write!(f, "End-of-file record is missing"),
+        }
+    }
+}
+
+impl core::error::Error for IhexError {}
+
+/// Parse the data records of `text`, as the address and bytes of each in the order of the file.
+/// Empty lines are skipped, and so is everything after the end-of-file record.
+pub fn parse(text: &str) -> Result<Vec<(Address, Vec<u8>)>, IhexError> {
+    let mut records = Vec::new();
+    let mut lines = 0;
+    for (index, line) in text.lines().enumerate() {
+        lines = index + 1;
+        let error = |kind| IhexError { line: lines, kind };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bytes = decode_hex(
+            line.strip_prefix(':')
+                .ok_or(error(IhexErrorKind::MissingStartCode))?,
+        )
+        .ok_or(error(IhexErrorKind::InvalidHex))?;
+
+        // Byte count, address, record type and checksum.
+        let expected = bytes.first().map_or(5, |&count| count as usize + 5);
+        if bytes.len() < expected {
+            return Err(error(IhexErrorKind::Truncated {
+                expected,
+                actual: bytes.len(),
+            }));
+        }
+        if bytes.len() > expected {
+            return Err(error(IhexErrorKind::TrailingBytes {
+                expected,
+                actual: bytes.len(),
+            }));
+        }
+        let (&checksum, record) = bytes.split_last().expect("records are at least 5 bytes");
+        let sum = record
+            .iter()
+            .fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+            .wrapping_neg();
+        if sum != checksum {
+            return Err(error(IhexErrorKind::Checksum {
+                expected: sum,
+                actual: checksum,
+            }));
+        }
+
+        let address = u16::from_be_bytes([record[1], record[2]]);
+        let data = &record[4..];
+        match record[3] {
+            DATA => {
+                if address as usize + data.len() > 0x10000 {
+                    return Err(error(IhexErrorKind::PastEnd {
+                        address,
+                        length: data.len(),
+                    }));
+                }
+                records.push((address, data.to_vec()));
+            }
+            END_OF_FILE => return Ok(records),
+            record_type => return Err(error(IhexErrorKind::UnsupportedRecordType(record_type))),
+        }
+    }
+    Err(IhexError {
+        line: lines,
+        kind: IhexErrorKind::MissingEndOfFile,
+    })
+}
+
+/// The bytes of a string of hexadecimal digit pairs.
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high << 4 | low) as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO: &str = include_str!("../../tests/data/hello.hex");
+
+    fn error(text: &str) -> IhexError {
+        parse(text).unwrap_err()
+    }
+
+    #[test]
+    fn records() {
+        let records = parse(HELLO).unwrap();
+        assert_eq!(
+            records,
+            [
+                (0x0100, vec![0x21, 0x00, 0x02, 0x7E, 0xB7, 0xCA, 0x0E, 0x01]),
+                (0x0108, vec![0xD3, 0x00, 0x23, 0xC3, 0x03, 0x01, 0x76]),
+                (0x0200, b"Hi\0".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let err = error(include_str!("../../tests/data/bad_checksum.hex"));
+        assert_eq!(
+            err,
+            IhexError {
+                line: 2,
+                kind: IhexErrorKind::Checksum {
+                    expected: 0xBD,
+                    actual: 0xBE
+                },
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "Line 2: Checksum is 0xBE, but should be 0xBD"
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(error("\n0100\n").kind, IhexErrorKind::MissingStartCode);
+        assert_eq!(error("\n0100\n").line, 2);
+        assert_eq!(error(":0G").kind, IhexErrorKind::InvalidHex);
+        assert_eq!(error(":000").kind, IhexErrorKind::InvalidHex);
+        assert_eq!(
+            error(":02010000AA"),
+            IhexError {
+                line: 1,
+                kind: IhexErrorKind::Truncated {
+                    expected: 7,
+                    actual: 5
+                },
+            }
+        );
+        assert_eq!(
+            error(":00000001FFFF").kind,
+            IhexErrorKind::TrailingBytes {
+                expected: 5,
+                actual: 6
+            }
+        );
+        assert_eq!(
+            error(":020000040000FA").kind,
+            IhexErrorKind::UnsupportedRecordType(0x04)
+        );
+        assert_eq!(
+            error(":02FFFF001122CD\n:00000001FF").to_string(),
+            "Line 1: 2 bytes at 0xFFFF run past 0xFFFF"
+        );
+        assert_eq!(error(":01000000+689").kind, IhexErrorKind::InvalidHex);
+    }
+
+    #[test]
+    fn end_of_file() {
+        assert_eq!(
+            error(":010000007689\n\n"),
+            IhexError {
+                line: 2,
+                kind: IhexErrorKind::MissingEndOfFile,
+            }
+        );
+        assert_eq!(
+            parse(":010000007689\r\n:00000001FF\r\nnot a record\n").unwrap(),
+            [(0x0000, vec![0x76])]
+        );
+    }
+}
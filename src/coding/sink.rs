@@ -0,0 +1,43 @@
+//! Where encoded instructions are written. With `std` this is any [`std::io::Write`], without it
+//! a `Vec<u8>` or a byte slice, which is filled from the start like `std`'s `impl Write for &mut
+//! [u8]`.
+
+#[cfg(feature = "std")]
+pub use std::io::{Result, Write as Sink};
+
+#[cfg(not(feature = "std"))]
+pub use no_std::{Result, Sink};
+
+#[cfg(not(feature = "std"))]
+mod no_std {
+    use alloc::vec::Vec;
+
+    /// The destination slice ran out of space.
+    #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+    pub struct BufferFull;
+
+    pub type Result<T> = core::result::Result<T, BufferFull>;
+
+    pub trait Sink {
+        fn write_all(&mut self, bytes: &[u8]) -> Result<()>;
+    }
+
+    impl Sink for Vec<u8> {
+        fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+            self.extend_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    impl Sink for &mut [u8] {
+        fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+            if self.len() < bytes.len() {
+                return Err(BufferFull);
+            }
+            let (head, tail) = core::mem::take(self).split_at_mut(bytes.len());
+            head.copy_from_slice(bytes);
+            *self = tail;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Decoding by looking the opcode up in a table of all 256, instead of trying every `parse_*`
+//! function in turn.
+
+use crate::{
+    coding::reader::Reader,
+    instruction::{
+        Condition, Data16, Instruction, Register, RegisterPair, RegisterPairIndirect,
+        RegisterPairOrStatus, RestartNumber, Variant,
+    },
+};
+
+/// What the first byte of an instruction determines.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Entry {
+    /// The instruction, with 0 for its immediate data or address.
+    pub(crate) instruction: Instruction,
+    /// Length of the instruction in bytes, including the opcode.
+    pub(crate) length: u8,
+    /// Clock periods the instruction takes, the shorter one for conditional calls and returns.
+    pub(crate) cycles: u8,
+}
+
+/// Indexed by opcode, `None` for the undocumented opcodes.
+static TABLE: [Option<Entry>; 256] = build();
+
+const fn build() -> [Option<Entry>; 256] {
+    let mut table = [None; 256];
+    let mut opcode = 0;
+    while opcode < 256 {
+        if let Some(instruction) = instruction(opcode as u8) {
+            table[opcode] = Some(Entry {
+                instruction,
+                length: instruction.length(),
+                cycles: instruction.cycles().0,
+            });
+        }
+        opcode += 1;
+    }
+    table
+}
+
+/// The entry of `opcode` in [`TABLE`], `None` for the undocumented opcodes.
+pub(crate) const fn documented(opcode: u8) -> Option<Entry> {
+    TABLE[opcode as usize]
+}
+
+const fn register(bits: u8) -> Register {
+    match bits & 0b111 {
+        0b000 => Register::B,
+        0b001 => Register::C,
+        0b010 => Register::D,
+        0b011 => Register::E,
+        0b100 => Register::H,
+        0b101 => Register::L,
+        0b110 => Register::M,
+        _ => Register::A,
+    }
+}
+
+const fn register_pair(bits: u8) -> RegisterPair {
+    match bits & 0b11 {
+        0b00 => RegisterPair::Bc,
+        0b01 => RegisterPair::De,
+        0b10 => RegisterPair::Hl,
+        _ => RegisterPair::Sp,
+    }
+}
+
+const fn register_pair_or_status(bits: u8) -> RegisterPairOrStatus {
+    match bits & 0b11 {
+        0b00 => RegisterPairOrStatus::Bc,
+        0b01 => RegisterPairOrStatus::De,
+        0b10 => RegisterPairOrStatus::Hl,
+        _ => RegisterPairOrStatus::StatusWord,
+    }
+}
+
+const fn condition(bits: u8) -> Condition {
+    match bits & 0b111 {
+        0b000 => Condition::NoZero,
+        0b001 => Condition::Zero,
+        0b010 => Condition::NoCarry,
+        0b011 => Condition::Carry,
+        0b100 => Condition::ParityOdd,
+        0b101 => Condition::ParityEven,
+        0b110 => Condition::Positive,
+        _ => Condition::Minus,
+    }
+}
+
+const fn restart_number(bits: u8) -> RestartNumber {
+    match bits & 0b111 {
+        0b000 => RestartNumber::R0,
+        0b001 => RestartNumber::R1,
+        0b010 => RestartNumber::R2,
+        0b011 => RestartNumber::R3,
+        0b100 => RestartNumber::R4,
+        0b101 => RestartNumber::R5,
+        0b110 => RestartNumber::R6,
+        _ => RestartNumber::R7,
+    }
+}
+
+/// The instruction `opcode` starts, with 0 for its immediate data or address.
+const fn instruction(opcode: u8) -> Option<Instruction> {
+    let ddd = register(opcode >> 3);
+    let sss = register(opcode);
+    let rp = register_pair(opcode >> 4);
+    let cc = condition(opcode >> 3);
+
+    let instruction = match opcode {
+        0x00 => Instruction::Nop,
+        0x07 => Instruction::Rlc,
+        0x0F => Instruction::Rrc,
+        0x17 => Instruction::Ral,
+        0x1F => Instruction::Rar,
+        0x22 => Instruction::Shld(0),
+        0x27 => Instruction::Daa,
+        0x2A => Instruction::Lhld(0),
+        0x2F => Instruction::Cma,
+        0x32 => Instruction::Sta(0),
+        0x37 => Instruction::Stc,
+        0x3A => Instruction::Lda(0),
+        0x3F => Instruction::Cmc,
+        0x76 => Instruction::Hlt,
+        0xC3 => Instruction::Jmp(0),
+        0xC6 => Instruction::Adi(0),
+        0xC9 => Instruction::Ret,
+        0xCD => Instruction::Call(0),
+        0xCE => Instruction::Aci(0),
+        0xD3 => Instruction::Out(0),
+        0xD6 => Instruction::Sui(0),
+        0xDB => Instruction::In(0),
+        0xDE => Instruction::Sbi(0),
+        0xE3 => Instruction::Xthl,
+        0xE6 => Instruction::Ani(0),
+        0xE9 => Instruction::Pchl,
+        0xEB => Instruction::Xchg,
+        0xEE => Instruction::Xri(0),
+        0xF3 => Instruction::Di,
+        0xF6 => Instruction::Ori(0),
+        0xF9 => Instruction::Sphl,
+        0xFB => Instruction::Ei,
+        0xFE => Instruction::Cpi(0),
+        0x02 | 0x12 => Instruction::Stax(match rp {
+            RegisterPair::Bc => RegisterPairIndirect::Bc,
+            _ => RegisterPairIndirect::De,
+        }),
+        0x0A | 0x1A => Instruction::Ldax(match rp {
+            RegisterPair::Bc => RegisterPairIndirect::Bc,
+            _ => RegisterPairIndirect::De,
+        }),
+        0x40..=0x7F => Instruction::Mov(ddd, sss),
+        0x80..=0x87 => Instruction::Add(sss),
+        0x88..=0x8F => Instruction::Adc(sss),
+        0x90..=0x97 => Instruction::Sub(sss),
+        0x98..=0x9F => Instruction::Sbb(sss),
+        0xA0..=0xA7 => Instruction::Ana(sss),
+        0xA8..=0xAF => Instruction::Xra(sss),
+        0xB0..=0xB7 => Instruction::Ora(sss),
+        0xB8..=0xBF => Instruction::Cmp(sss),
+        _ if opcode & 0b1100_0111 == 0b0000_0100 => Instruction::Inr(ddd),
+        _ if opcode & 0b1100_0111 == 0b0000_0101 => Instruction::Dcr(ddd),
+        _ if opcode & 0b1100_0111 == 0b0000_0110 => Instruction::Mvi(ddd, 0),
+        _ if opcode & 0b1100_1111 == 0b0000_0001 => Instruction::Lxi(rp, Data16::ZERO),
+        _ if opcode & 0b1100_1111 == 0b0000_0011 => Instruction::Inx(rp),
+        _ if opcode & 0b1100_1111 == 0b0000_1001 => Instruction::Dad(rp),
+        _ if opcode & 0b1100_1111 == 0b0000_1011 => Instruction::Dcx(rp),
+        _ if opcode & 0b1100_0111 == 0b1100_0000 => Instruction::Rcc(cc),
+        _ if opcode & 0b1100_0111 == 0b1100_0010 => Instruction::Jcc(cc, 0),
+        _ if opcode & 0b1100_0111 == 0b1100_0100 => Instruction::Ccc(cc, 0),
+        _ if opcode & 0b1100_0111 == 0b1100_0111 => Instruction::Rst(restart_number(opcode >> 3)),
+        _ if opcode & 0b1100_1111 == 0b1100_0001 => {
+            Instruction::Pop(register_pair_or_status(opcode >> 4))
+        }
+        _ if opcode & 0b1100_1111 == 0b1100_0101 => {
+            Instruction::Push(register_pair_or_status(opcode >> 4))
+        }
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+/// `instruction` with the immediate data or address in `low` and `high`, the bytes after the
+/// opcode. Instructions without them are returned as they are.
+#[inline]
+pub(crate) fn with_operands(instruction: Instruction, low: u8, high: u8) -> Instruction {
+    let address = u16::from_le_bytes([low, high]);
+    match instruction {
+        Instruction::Mvi(register, _) => Instruction::Mvi(register, low),
+        Instruction::Adi(_) => Instruction::Adi(low),
+        Instruction::Aci(_) => Instruction::Aci(low),
+        Instruction::Sui(_) => Instruction::Sui(low),
+        Instruction::Sbi(_) => Instruction::Sbi(low),
+        Instruction::Ani(_) => Instruction::Ani(low),
+        Instruction::Xri(_) => Instruction::Xri(low),
+        Instruction::Ori(_) => Instruction::Ori(low),
+        Instruction::Cpi(_) => Instruction::Cpi(low),
+        Instruction::In(_) => Instruction::In(low),
+        Instruction::Out(_) => Instruction::Out(low),
+        Instruction::Lxi(pair, _) => Instruction::Lxi(pair, Data16::new(low, high)),
+        Instruction::Lda(_) => Instruction::Lda(address),
+        Instruction::Sta(_) => Instruction::Sta(address),
+        Instruction::Lhld(_) => Instruction::Lhld(address),
+        Instruction::Shld(_) => Instruction::Shld(address),
+        Instruction::Jmp(_) => Instruction::Jmp(address),
+        Instruction::Jcc(condition, _) => Instruction::Jcc(condition, address),
+        Instruction::Call(_) => Instruction::Call(address),
+        Instruction::Ccc(condition, _) => Instruction::Ccc(condition, address),
+        instruction => instruction,
+    }
+}
+
+/// The documented opcode an undocumented one behaves like on real hardware, `None` for the
+/// documented opcodes.
+const fn alias(opcode: u8) -> Option<u8> {
+    match opcode {
+        0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => Some(0x00),
+        0xCB => Some(0xC3),
+        0xD9 => Some(0xC9),
+        0xDD | 0xED | 0xFD => Some(0xCD),
+        _ => None,
+    }
+}
+
+/// The instruction an opcode the 8080 doesn't document decodes to on the 8085.
+const fn intel_8085(opcode: u8) -> Option<Entry> {
+    let instruction = match opcode {
+        0x20 => Instruction::Rim,
+        0x30 => Instruction::Sim,
+        _ => return None,
+    };
+    Some(Entry {
+        instruction,
+        length: 1,
+        cycles: 4,
+    })
+}
+
+/// Decode the instruction at the start of `stream` with a single lookup in [`TABLE`]. With
+/// `aliases`, the undocumented opcodes decode to the instructions they alias.
+pub fn decode<'a>(stream: &mut Reader<'a>, variant: Variant, aliases: bool) -> Option<Instruction> {
+    let opcode = stream.peek()?;
+    let extension = match variant {
+        Variant::Intel8080 => None,
+        Variant::Intel8085 => intel_8085(opcode),
+    };
+    let entry = match TABLE[opcode as usize].or(extension) {
+        Some(entry) => entry,
+        None if aliases => TABLE[alias(opcode)? as usize]?,
+        None => return None,
+    };
+    let bytes = stream.read_n(entry.length as usize)?;
+    let operand = |index| bytes.get(index).copied().unwrap_or(0);
+    Some(with_operands(entry.instruction, operand(1), operand(2)))
+}
@@ -0,0 +1,24 @@
+//! Bundled I/O devices, attached to a machine with [`Machine::attach_device`].
+//!
+//! [`Machine::attach_device`]: crate::machine::Machine::attach_device
+
+mod console;
+mod framebuffer;
+mod random;
+mod scripted;
+mod serial_console;
+mod sio;
+mod text_display;
+mod timer;
+
+pub use console::{
+    CONSOLE_DATA, CONSOLE_HEX, CONSOLE_NEWLINE, CONSOLE_NUMBER, CONSOLE_OUTPUTS, CONSOLE_PAIR,
+    CONSOLE_PAIR_HEX, CONSOLE_SIGNED, Console, OutputFormat, output_format,
+};
+pub use framebuffer::{FRAMEBUFFER_COLUMNS, FRAMEBUFFER_ROWS, Framebuffer};
+pub use random::{DEFAULT_SEED, Random};
+pub use scripted::ScriptedInput;
+pub use serial_console::{SERIAL_INPUT_AVAILABLE, SerialConsole};
+pub use sio::Sio;
+pub use text_display::{TEXT_CLEAR, TEXT_COLUMNS, TEXT_ROWS, TextDisplay};
+pub use timer::Timer;
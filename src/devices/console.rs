@@ -0,0 +1,155 @@
+use alloc::{format, vec, vec::Vec};
+
+use crate::{
+    instruction::{Data8, Port, RegisterPair},
+    machine::{IoDevice, Machine},
+};
+
+/// Port of [`Console`] reading input and writing characters.
+pub const CONSOLE_DATA: Port = 0;
+/// Port of [`Console`] writing the accumulator in decimal.
+pub const CONSOLE_NUMBER: Port = 1;
+/// Port of [`Console`] writing HL in decimal.
+pub const CONSOLE_PAIR: Port = 2;
+/// Port of [`Console`] writing the accumulator in hexadecimal.
+pub const CONSOLE_HEX: Port = 3;
+/// Port of [`Console`] writing the accumulator as a signed decimal number.
+pub const CONSOLE_SIGNED: Port = 4;
+/// Port of [`Console`] writing HL in hexadecimal.
+pub const CONSOLE_PAIR_HEX: Port = 5;
+/// Port of [`Console`] writing a newline.
+pub const CONSOLE_NEWLINE: Port = 6;
+
+/// What [`Console`] writes for `OUT` on one of its ports.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The accumulator as a byte.
+    Byte,
+    /// The accumulator as an unsigned decimal number, e.g. `255`.
+    Decimal,
+    /// The accumulator as two hexadecimal digits, e.g. `3F`.
+    Hex,
+    /// The accumulator as a signed decimal number, e.g. `-1` for 0xFF.
+    SignedDecimal,
+    /// HL as an unsigned decimal number.
+    PairDecimal,
+    /// HL as four hexadecimal digits, e.g. `01FF`.
+    PairHex,
+    /// A newline, whatever the accumulator holds.
+    Newline,
+}
+
+impl OutputFormat {
+    /// The bytes written with the accumulator holding `a` and HL holding `hl`.
+    pub fn render(self, a: Data8, hl: u16) -> Vec<u8> {
+        match self {
+            OutputFormat::Byte => vec![a],
+            OutputFormat::Decimal => format!("{}", a).into_bytes(),
+            OutputFormat::Hex => format!("{:02X}", a).into_bytes(),
+            OutputFormat::SignedDecimal => format!("{}", a as i8).into_bytes(),
+            OutputFormat::PairDecimal => format!("{}", hl).into_bytes(),
+            OutputFormat::PairHex => format!("{:04X}", hl).into_bytes(),
+            OutputFormat::Newline => vec![b'\n'],
+        }
+    }
+}
+
+/// The output ports of [`Console`] and what it writes on each.
+pub const CONSOLE_OUTPUTS: [(Port, OutputFormat); 7] = [
+    (CONSOLE_DATA, OutputFormat::Byte),
+    (CONSOLE_NUMBER, OutputFormat::Decimal),
+    (CONSOLE_PAIR, OutputFormat::PairDecimal),
+    (CONSOLE_HEX, OutputFormat::Hex),
+    (CONSOLE_SIGNED, OutputFormat::SignedDecimal),
+    (CONSOLE_PAIR_HEX, OutputFormat::PairHex),
+    (CONSOLE_NEWLINE, OutputFormat::Newline),
+];
+
+/// What [`Console`] writes on `port`, `None` if it ignores the port.
+pub fn output_format(port: Port) -> Option<OutputFormat> {
+    CONSOLE_OUTPUTS
+        .iter()
+        .find(|&&(output, _)| output == port)
+        .map(|&(_, format)| format)
+}
+
+/// The console programs talk to by default, on the machine's input and output:
+///
+/// - `IN 0` reads the next byte of input. Running out of input halts the machine, or makes it
+///   [wait for more](crate::machine::MachineState::WaitingForInput).
+/// - `OUT 0` writes the accumulator as a byte.
+/// - `OUT 1` writes the accumulator as a decimal number.
+/// - `OUT 2` writes HL as a decimal number.
+/// - `OUT 3` writes the accumulator as two hexadecimal digits.
+/// - `OUT 4` writes the accumulator as a signed decimal number.
+/// - `OUT 5` writes HL as four hexadecimal digits.
+/// - `OUT 6` writes a newline. Writes to an output writer are flushed, so the line shows up right
+///   away.
+///
+/// The output ports are listed in [`CONSOLE_OUTPUTS`]. The machine handles these ports with a
+/// console unless devices are attached to them. `IN 1` and `IN 2` aren't console ports: `IN 1`
+/// reads the machine's [`Random`](super::Random) generator, and the console returns `None` for
+/// ports it doesn't read.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Console;
+
+impl IoDevice for Console {
+    fn read(&mut self, port: Port, machine: &mut Machine) -> Option<Data8> {
+        match port {
+            CONSOLE_DATA => machine.read_input(),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, port: Port, value: Data8, machine: &mut Machine) {
+        let Some(format) = output_format(port) else {
+            return;
+        };
+        let hl = machine.register_16(RegisterPair::Hl).value();
+        machine.write_output(&format.render(value, hl));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    /// The output of `OUT port` with the accumulator holding `a` and HL holding `hl`.
+    fn out(port: Port, a: Data8, hl: u16) -> Vec<u8> {
+        let [l, h] = hl.to_le_bytes();
+        // MVI A, a; LXI H, hl; OUT port; HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0x3E, a, 0x21, l, h, 0xD3, port, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        machine.steps().for_each(drop);
+        machine.take_output()
+    }
+
+    #[test]
+    fn output_ports() {
+        let cases: [(Port, Data8, u16, &[u8]); 17] = [
+            (CONSOLE_DATA, b'A', 0, b"A"),
+            (CONSOLE_DATA, 0xFF, 0, b"\xFF"),
+            (CONSOLE_NUMBER, 0x00, 0, b"0"),
+            (CONSOLE_NUMBER, 0x80, 0, b"128"),
+            (CONSOLE_NUMBER, 0xFF, 0, b"255"),
+            (CONSOLE_PAIR, 0, 0xFFFF, b"65535"),
+            (CONSOLE_HEX, 0x3F, 0, b"3F"),
+            (CONSOLE_HEX, 0x0A, 0, b"0A"),
+            (CONSOLE_HEX, 0x80, 0, b"80"),
+            (CONSOLE_HEX, 0xFF, 0, b"FF"),
+            (CONSOLE_SIGNED, 0x7F, 0, b"127"),
+            (CONSOLE_SIGNED, 0x80, 0, b"-128"),
+            (CONSOLE_SIGNED, 0xFF, 0, b"-1"),
+            (CONSOLE_PAIR_HEX, 0, 0x0080, b"0080"),
+            (CONSOLE_PAIR_HEX, 0, 0xFFFF, b"FFFF"),
+            (CONSOLE_NEWLINE, 0x41, 0, b"\n"),
+            (7, 0x41, 0x1234, b""),
+        ];
+        for (port, a, hl, expected) in cases {
+            assert_eq!(out(port, a, hl), expected, "OUT {} with A={:02X}", port, a);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use alloc::boxed::Box;
+use core::ops::Range;
+
+use crate::{
+    instruction::{Address, Data8},
+    machine::{Machine, MemoryHandler},
+};
+
+/// Characters per row of a [`Framebuffer`].
+pub const FRAMEBUFFER_COLUMNS: usize = 32;
+/// Rows of a [`Framebuffer`].
+pub const FRAMEBUFFER_ROWS: usize = 32;
+
+/// A 32×32 character screen mapped into memory, one byte per character, row by row. The character
+/// at row `r` and column `c` is at `base + 32 * r + c`, and reading an address returns the
+/// character written there.
+///
+/// Unlike a [`TextDisplay`](super::TextDisplay) there are no control characters: every byte is
+/// stored as it is, and the UI draws bytes outside printable ASCII as spaces. The screen starts
+/// out zeroed, like memory. The UI shows it above the program output with the cell written last
+/// highlighted; find it with [`Machine::memory_handler`] to read the cells.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Framebuffer {
+    base: Address,
+    cells: [[u8; FRAMEBUFFER_COLUMNS]; FRAMEBUFFER_ROWS],
+    cursor: (usize, usize),
+}
+
+impl Default for Framebuffer {
+    /// Mapped at 0xF000 to 0xF3FF.
+    fn default() -> Self {
+        Self::new(0xF000)
+    }
+}
+
+impl Framebuffer {
+    /// A blank screen mapped from `base`.
+    ///
+    /// # Panics
+    ///
+    /// If the screen would run past 0xFFFF.
+    pub fn new(base: Address) -> Self {
+        assert!(
+            base as usize + FRAMEBUFFER_ROWS * FRAMEBUFFER_COLUMNS <= 0x10000,
+            "a framebuffer at 0x{:04X} runs past 0xFFFF",
+            base
+        );
+        Self {
+            base,
+            cells: [[0; FRAMEBUFFER_COLUMNS]; FRAMEBUFFER_ROWS],
+            cursor: (0, 0),
+        }
+    }
+
+    /// Addresses the screen is mapped at.
+    pub fn range(&self) -> Range<usize> {
+        let start = self.base as usize;
+        start..start + FRAMEBUFFER_ROWS * FRAMEBUFFER_COLUMNS
+    }
+
+    /// Map the screen into the memory of `machine`.
+    pub fn attach(self, machine: &mut Machine) {
+        machine.map_region(self.range(), Box::new(self));
+    }
+
+    /// The characters on the screen, top row first.
+    pub fn cells(&self) -> &[[u8; FRAMEBUFFER_COLUMNS]; FRAMEBUFFER_ROWS] {
+        &self.cells
+    }
+
+    /// Row and column of the cell written last, the top left before any writes.
+    pub fn cursor(&self) -> (usize, usize) {
+        self.cursor
+    }
+
+    fn cell(&self, address: Address) -> (usize, usize) {
+        let offset = address.wrapping_sub(self.base) as usize;
+        (offset / FRAMEBUFFER_COLUMNS, offset % FRAMEBUFFER_COLUMNS)
+    }
+}
+
+impl MemoryHandler for Framebuffer {
+    fn read(&mut self, address: Address) -> Data8 {
+        let (row, column) = self.cell(address);
+        self.cells[row][column]
+    }
+
+    fn write(&mut self, address: Address, value: Data8) {
+        let (row, column) = self.cell(address);
+        self.cells[row][column] = value;
+        self.cursor = (row, column);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    #[test]
+    fn cells_by_address() {
+        let mut framebuffer = Framebuffer::new(0x8000);
+        framebuffer.write(0x8000, b'a');
+        framebuffer.write(0x8021, b'b');
+        framebuffer.write(0x83FF, b'c');
+
+        assert_eq!(framebuffer.cells()[0][0], b'a');
+        assert_eq!(framebuffer.cells()[1][1], b'b');
+        assert_eq!(framebuffer.cells()[31][31], b'c');
+        assert_eq!(framebuffer.read(0x8021), b'b');
+        assert_eq!(framebuffer.cursor(), (31, 31));
+        assert_eq!(framebuffer.range(), 0x8000..0x8400);
+    }
+
+    #[test]
+    #[should_panic(expected = "a framebuffer at 0xFC01 runs past 0xFFFF")]
+    fn past_end_of_memory() {
+        Framebuffer::new(0xFC01);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hello() {
+        let mut machine = MachineBuilder::new()
+            .assembly(include_bytes!("../../tests/data/framebuffer_hello.asm"))
+            .build()
+            .unwrap();
+        Framebuffer::default().attach(&mut machine);
+        machine.steps().for_each(drop);
+
+        let framebuffer = machine.memory_handler::<Framebuffer>().unwrap();
+        assert_eq!(&framebuffer.cells()[0][..6], b"HELLO\0");
+        assert_eq!(&framebuffer.cells()[1][10..15], b"WORLD");
+        assert_eq!(framebuffer.cursor(), (1, 14));
+        assert!(
+            framebuffer.cells()[2..]
+                .iter()
+                .flatten()
+                .all(|&byte| byte == 0)
+        );
+        // Memory behind the screen is left alone.
+        assert!((0xF000..0xF400).all(|address| machine.memory().read_8(address) == 0));
+    }
+}
@@ -0,0 +1,140 @@
+use alloc::boxed::Box;
+
+use crate::{
+    instruction::{Data8, Port},
+    machine::{IoDevice, Machine},
+};
+
+/// Seed of `IN 1` unless [`Machine::set_random_seed`] is called, also used in place of a zero seed,
+/// which xorshift can't leave.
+pub const DEFAULT_SEED: u32 = 0x2545_F491;
+
+/// Seeded pseudo-random number generator, so programs that use randomness run the same way every
+/// time. `IN 1` reads from one of these without attaching a device.
+///
+/// Reading the port returns the next byte of a 32-bit xorshift generator. Writing a byte to the
+/// port reseeds the generator from the written byte combined with its current state, so a program
+/// can mix in its own entropy, e.g. the time until a key was pressed.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Random {
+    port: Port,
+    state: u32,
+}
+
+impl Random {
+    pub fn new(port: Port, seed: u32) -> Self {
+        let mut random = Self { port, state: 0 };
+        random.seed(seed);
+        random
+    }
+
+    fn seed(&mut self, seed: u32) {
+        self.state = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    /// Advance the generator, returning the next byte.
+    pub fn next_byte(&mut self) -> Data8 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        (x >> 24) as Data8
+    }
+
+    /// Current state of the generator. A generator created with this as the seed continues with
+    /// the same bytes.
+    pub fn state(&self) -> u32 {
+        self.state
+    }
+
+    /// Reseed the generator from `value` and the current state, like writing `value` to the port.
+    pub fn reseed(&mut self, value: Data8) {
+        self.seed(self.state.rotate_left(8) ^ value as u32);
+    }
+
+    /// Attach the generator to `machine` at its port.
+    pub fn attach(self, machine: &mut Machine) {
+        machine.attach_device(&[self.port], Box::new(self));
+    }
+}
+
+impl IoDevice for Random {
+    fn read(&mut self, _port: Port, _machine: &mut Machine) -> Option<Data8> {
+        Some(self.next_byte())
+    }
+
+    fn write(&mut self, _port: Port, value: Data8, _machine: &mut Machine) {
+        self.reseed(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instruction::Register, machine::MachineBuilder};
+
+    fn bytes(random: &mut Random, count: usize) -> alloc::vec::Vec<u8> {
+        (0..count).map(|_| random.next_byte()).collect()
+    }
+
+    #[test]
+    fn known_sequence() {
+        let mut random = Random::new(0x20, DEFAULT_SEED);
+        assert_eq!(
+            bytes(&mut random, 8),
+            [0xE1, 0x8B, 0x64, 0x00, 0xF2, 0xFE, 0x8A, 0x12]
+        );
+    }
+
+    #[test]
+    fn reseeding() {
+        let mut random = Random::new(0x20, DEFAULT_SEED);
+        random.reseed(0x42);
+        assert_eq!(bytes(&mut random, 4), [0x2C, 0xF1, 0x80, 0xA4]);
+
+        let mut first = Random::new(0x20, 1234);
+        let mut second = Random::new(0x20, 1234);
+        first.reseed(0x42);
+        second.reseed(0x42);
+        assert_eq!(bytes(&mut first, 16), bytes(&mut second, 16));
+
+        let mut other = Random::new(0x20, 1234);
+        other.reseed(0x43);
+        assert_ne!(
+            bytes(&mut Random::new(0x20, 1234), 16),
+            bytes(&mut other, 16)
+        );
+    }
+
+    #[test]
+    fn zero_seed() {
+        assert_eq!(Random::new(0x20, 0), Random::new(0x20, DEFAULT_SEED));
+    }
+
+    #[test]
+    fn program_reads_and_reseeds() {
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[
+                    0xDB, 0x20, // IN 20H
+                    0x47, // MOV B, A
+                    0xD3, 0x20, // OUT 20H
+                    0xDB, 0x20, // IN 20H
+                    0x76, // HLT
+                ],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+        Random::new(0x20, DEFAULT_SEED).attach(&mut machine);
+        machine.steps().count();
+
+        let mut expected = Random::new(0x20, DEFAULT_SEED);
+        let first = expected.next_byte();
+        expected.reseed(first);
+        assert_eq!(machine.register_8(Register::B), first);
+        assert_eq!(machine.register_8(Register::A), expected.next_byte());
+        assert!(machine.output_buffer().is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+use alloc::{collections::VecDeque, vec::Vec};
+
+use crate::{
+    devices::Console,
+    instruction::{Data8, Port},
+    machine::{IoDevice, Machine},
+};
+
+/// Input read from a fixed script instead of the host, so that tests can run interactive programs
+/// the same way every time, e.g. in place of the console with
+/// `machine.attach_device(&[0], Box::new(ScriptedInput::new(b"42\n")))`.
+///
+/// Reads return the bytes of the script in order, and then the end-of-input byte, 0 unless set
+/// with [`ScriptedInput::end_of_input`]. A byte can be delayed until the program has executed a
+/// number of instructions, counting the `IN` reading it, since the byte before it was read or
+/// since the start for the first byte. Until then reads return 0 without taking it, like a serial
+/// port with nothing received.
+///
+/// Writes are handled like the [`Console`] does, so replacing the console input keeps the output.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScriptedInput {
+    /// The bytes not yet read, each with the number of instructions to wait before it.
+    script: VecDeque<(u64, Data8)>,
+    end_of_input: Data8,
+    consumed: usize,
+    /// [`Machine::instructions`] when the last byte was read.
+    last_read: u64,
+}
+
+impl ScriptedInput {
+    /// Input of the bytes of `script` without delays.
+    pub fn new(script: &[u8]) -> Self {
+        Self::with_delays(&script.iter().map(|&byte| (0, byte)).collect::<Vec<_>>())
+    }
+
+    /// Input of the bytes of `script`, each after waiting the given number of instructions.
+    pub fn with_delays(script: &[(u64, Data8)]) -> Self {
+        Self {
+            script: script.iter().copied().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Value read once the script has been read.
+    pub fn end_of_input(mut self, value: Data8) -> Self {
+        self.end_of_input = value;
+        self
+    }
+
+    /// Number of bytes of the script read so far.
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Number of bytes of the script not read yet.
+    pub fn remaining(&self) -> usize {
+        self.script.len()
+    }
+}
+
+impl IoDevice for ScriptedInput {
+    fn read(&mut self, _port: Port, machine: &mut Machine) -> Option<Data8> {
+        let Some(&(delay, byte)) = self.script.front() else {
+            return Some(self.end_of_input);
+        };
+        // The executing `IN` is already counted.
+        let now = machine.instructions();
+        if now.saturating_sub(self.last_read) < delay {
+            return Some(0);
+        }
+        self.script.pop_front();
+        self.consumed += 1;
+        self.last_read = now;
+        Some(byte)
+    }
+
+    fn write(&mut self, port: Port, value: Data8, machine: &mut Machine) {
+        Console.write(port, value, machine);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+    use crate::machine::{HaltReason, MachineBuilder, RunOutcome};
+
+    #[test]
+    fn echo() {
+        // 0000: IN 0
+        // 0002: OUT 0
+        // 0004: CPI 0AH
+        // 0006: JNZ 0000H
+        // 0009: HLT
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[0xDB, 0x00, 0xD3, 0x00, 0xFE, 0x0A, 0xC2, 0x00, 0x00, 0x76],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+        machine.attach_device(&[0], Box::new(ScriptedInput::new(b"42\nrest")));
+
+        let (outcome, executed) = machine.run_until_halt(1000);
+        assert_eq!(outcome, RunOutcome::Halted(HaltReason::HaltInstruction));
+        assert_eq!(executed, 3 * 4 + 1);
+        assert_eq!(machine.take_output(), b"42\n");
+        let input = machine.device::<ScriptedInput>().unwrap();
+        assert_eq!(input.consumed(), 3);
+        assert_eq!(input.remaining(), 4);
+    }
+
+    #[test]
+    fn end_of_input() {
+        let mut machine = Machine::new();
+        let mut input = ScriptedInput::new(b"A").end_of_input(0x1A);
+        assert_eq!(input.read(0, &mut machine), Some(b'A'));
+        assert_eq!(input.read(0, &mut machine), Some(0x1A));
+        assert_eq!(input.read(0, &mut machine), Some(0x1A));
+        assert_eq!(input.consumed(), 1);
+    }
+
+    #[test]
+    fn delays() {
+        // 0000: IN 0
+        // 0002: ORA A
+        // 0003: JZ 0000H
+        // 0006: OUT 0
+        // 0008: CPI 'B'
+        // 000A: JNZ 0000H
+        // 000D: HLT
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[
+                    0xDB, 0x00, 0xB7, 0xCA, 0x00, 0x00, 0xD3, 0x00, 0xFE, 0x42, 0xC2, 0x00, 0x00,
+                    0x76,
+                ],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+        let input = ScriptedInput::with_delays(&[(0, b'A'), (20, b'B')]);
+        machine.attach_device(&[0], Box::new(input));
+
+        let (outcome, executed) = machine.run_until_halt(1000);
+        assert_eq!(outcome, RunOutcome::Halted(HaltReason::HaltInstruction));
+        assert_eq!(machine.take_output(), b"AB");
+        // Reading A and echoing it, 5 polls reading 0, then reading and echoing B.
+        assert_eq!(executed, 6 + 5 * 3 + 7);
+        assert_eq!(machine.device::<ScriptedInput>().unwrap().consumed(), 2);
+    }
+}
@@ -0,0 +1,175 @@
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+
+use crate::{
+    instruction::{Data8, Port},
+    machine::{IoDevice, Machine},
+};
+
+/// Status bit of [`SerialConsole`] set while a line of input is being read.
+pub const SERIAL_INPUT_AVAILABLE: u8 = 0b01;
+
+/// Terminal on a UART-style port pair, reading the machine's input a line at a time.
+///
+/// Writing the data port sends a character to the machine's output and reading it returns the
+/// next character of input, or 0 if there is none. Reading the status port returns
+/// [`SERIAL_INPUT_AVAILABLE`] while there are characters to read. Writes to the status port are
+/// ignored.
+///
+/// Like a terminal in line mode, input only becomes available once a whole line has been typed,
+/// ending with a newline or carriage return, and backspace or delete erase the character before
+/// them. Programs poll the status port until a line arrives, so running out of input doesn't halt
+/// the machine. With an input source such as stdin, reading the status port waits until the
+/// source returns a line.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SerialConsole {
+    data_port: Port,
+    status_port: Port,
+    /// The rest of the line being read.
+    line: VecDeque<u8>,
+    /// Input after the last complete line.
+    partial: Vec<u8>,
+}
+
+impl Default for SerialConsole {
+    /// Data at 0x10 and status at 0x11.
+    fn default() -> Self {
+        Self::new(0x10, 0x11)
+    }
+}
+
+impl SerialConsole {
+    pub fn new(data_port: Port, status_port: Port) -> Self {
+        Self {
+            data_port,
+            status_port,
+            line: VecDeque::new(),
+            partial: Vec::new(),
+        }
+    }
+
+    /// Attach the console to `machine` at its ports.
+    pub fn attach(self, machine: &mut Machine) {
+        machine.attach_device(&[self.data_port, self.status_port], Box::new(self));
+    }
+
+    /// Take input from `machine` until a line is complete, returning whether there are characters
+    /// to read.
+    fn fill_line(&mut self, machine: &mut Machine) -> bool {
+        while self.line.is_empty() && machine.input_available() {
+            let Some(byte) = machine.read_input() else {
+                break;
+            };
+            match byte {
+                0x08 | 0x7F => {
+                    self.partial.pop();
+                }
+                b'\n' | b'\r' => {
+                    self.partial.push(byte);
+                    self.line.extend(self.partial.drain(..));
+                }
+                _ => self.partial.push(byte),
+            }
+        }
+        !self.line.is_empty()
+    }
+}
+
+impl IoDevice for SerialConsole {
+    fn read(&mut self, port: Port, machine: &mut Machine) -> Option<Data8> {
+        if port == self.status_port {
+            let available = self.fill_line(machine);
+            Some(if available { SERIAL_INPUT_AVAILABLE } else { 0 })
+        } else {
+            self.fill_line(machine);
+            Some(self.line.pop_front().unwrap_or(0))
+        }
+    }
+
+    fn write(&mut self, port: Port, value: Data8, machine: &mut Machine) {
+        if port == self.data_port {
+            machine.write_output(&[value]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{HaltReason, MachineBuilder, MachineState};
+
+    /// 0000: IN 11H
+    /// 0002: ANI 01H
+    /// 0004: JZ 0000H
+    /// 0007: IN 10H
+    /// 0009: OUT 10H
+    /// 000B: CPI 0AH
+    /// 000D: JNZ 0000H
+    /// 0010: HLT
+    const ECHO_LINE: [u8; 17] = [
+        0xDB, 0x11, 0xE6, 0x01, 0xCA, 0x00, 0x00, 0xDB, 0x10, 0xD3, 0x10, 0xFE, 0x0A, 0xC2, 0x00,
+        0x00, 0x76,
+    ];
+
+    fn echo_machine() -> Machine {
+        let mut machine = MachineBuilder::new()
+            .program(&ECHO_LINE, 0x0000)
+            .build()
+            .unwrap();
+        SerialConsole::default().attach(&mut machine);
+        machine
+    }
+
+    #[test]
+    fn status_and_data() {
+        let mut machine = Machine::new();
+        let mut console = SerialConsole::default();
+        assert_eq!(console.read(0x11, &mut machine), Some(0));
+        assert_eq!(console.read(0x10, &mut machine), Some(0));
+
+        machine.push_input(b"A\rB");
+        assert_eq!(
+            console.read(0x11, &mut machine),
+            Some(SERIAL_INPUT_AVAILABLE)
+        );
+        assert_eq!(console.read(0x10, &mut machine), Some(b'A'));
+        assert_eq!(console.read(0x10, &mut machine), Some(b'\r'));
+        // B isn't followed by a newline yet.
+        assert_eq!(console.read(0x11, &mut machine), Some(0));
+        assert_eq!(console.read(0x10, &mut machine), Some(0));
+
+        console.write(0x10, b'x', &mut machine);
+        console.write(0x11, b'y', &mut machine);
+        assert_eq!(machine.take_output(), b"x");
+    }
+
+    #[test]
+    fn echoes_line() {
+        let mut machine = echo_machine();
+        machine.push_input(b"hello\n");
+        machine.steps().for_each(drop);
+
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.take_output(), b"hello\n");
+    }
+
+    #[test]
+    fn waits_for_whole_line() {
+        let mut machine = echo_machine();
+        machine.push_input(b"ab");
+        machine.steps().take(100).for_each(drop);
+        assert_eq!(machine.state(), MachineState::Running);
+        assert!(machine.output_buffer().is_empty());
+
+        // Backspace erases the b.
+        machine.push_input(b"\x08c\n");
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.take_output(), b"ac\n");
+    }
+}
@@ -0,0 +1,147 @@
+use alloc::boxed::Box;
+
+use crate::{
+    instruction::{Data8, Port},
+    machine::{IoDevice, Machine},
+};
+
+/// Status bit set while a byte of input is waiting to be read from the data port.
+pub const STATUS_INPUT_AVAILABLE: u8 = 0b01;
+/// Status bit set while the data port accepts output, which is always.
+pub const STATUS_OUTPUT_READY: u8 = 0b10;
+
+/// Serial card with the register layout of the Altair 2SIO, as expected by Altair BASIC.
+///
+/// Reading the status port returns [`STATUS_INPUT_AVAILABLE`] and [`STATUS_OUTPUT_READY`] bits.
+/// Reading the data port takes the next byte of the machine's input, or returns 0 if there is
+/// none, and writing it sends the byte to the machine's output. Writes to the status port (the
+/// card's control register) are ignored.
+///
+/// Programs poll the status port in a loop until input arrives, so unlike `IN 0` running out of
+/// input doesn't halt the machine. With an input source such as stdin, reading the status port
+/// waits until the source returns a byte.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Sio {
+    status_port: Port,
+    data_port: Port,
+}
+
+impl Default for Sio {
+    /// The first port pair of a 2SIO, status at 0x10 and data at 0x11.
+    fn default() -> Self {
+        Self::new(0x10, 0x11)
+    }
+}
+
+impl Sio {
+    pub fn new(status_port: Port, data_port: Port) -> Self {
+        Self {
+            status_port,
+            data_port,
+        }
+    }
+
+    /// Attach the card to `machine` at its ports.
+    pub fn attach(self, machine: &mut Machine) {
+        machine.attach_device(&[self.status_port, self.data_port], Box::new(self));
+    }
+}
+
+impl IoDevice for Sio {
+    fn read(&mut self, port: Port, machine: &mut Machine) -> Option<Data8> {
+        if port == self.status_port {
+            let mut status = STATUS_OUTPUT_READY;
+            if machine.input_available() {
+                status |= STATUS_INPUT_AVAILABLE;
+            }
+            Some(status)
+        } else {
+            Some(machine.read_input().unwrap_or(0))
+        }
+    }
+
+    fn write(&mut self, port: Port, value: Data8, machine: &mut Machine) {
+        if port == self.data_port {
+            machine.write_output(&[value]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::Register,
+        machine::{HaltReason, MachineBuilder, MachineState},
+    };
+
+    #[cfg(feature = "std")]
+    const ECHO: &str = "        ORG 0
+        LXI SP, 0F000H
+WAIT:   IN 10H
+        ANI 01H
+        JZ WAIT
+        IN 11H
+        OUT 11H
+        CPI 0DH
+        JNZ WAIT
+        HLT
+        END
+";
+
+    #[test]
+    fn status_bits() {
+        let mut machine = Machine::new();
+        let mut sio = Sio::default();
+        assert_eq!(sio.read(0x10, &mut machine), Some(STATUS_OUTPUT_READY));
+
+        machine.push_input(b"A");
+        assert_eq!(
+            sio.read(0x10, &mut machine),
+            Some(STATUS_OUTPUT_READY | STATUS_INPUT_AVAILABLE)
+        );
+        assert_eq!(sio.read(0x11, &mut machine), Some(b'A'));
+        assert_eq!(sio.read(0x10, &mut machine), Some(STATUS_OUTPUT_READY));
+        assert_eq!(sio.read(0x11, &mut machine), Some(0));
+    }
+
+    #[cfg(feature = "std")]
+    /// Step `machine` up to `count` times, returning the status byte read by each `IN 10H`.
+    fn run_polling(machine: &mut Machine, count: usize) -> Vec<u8> {
+        let mut statuses = Vec::new();
+        for _ in 0..count {
+            let Some(step) = machine.step() else {
+                break;
+            };
+            if step.pc_before.value() == 0x0003 {
+                statuses.push(machine.register_8(Register::A));
+            }
+        }
+        statuses
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn polling_echo() {
+        let mut machine = MachineBuilder::new()
+            .assembly(ECHO.as_bytes())
+            .build()
+            .unwrap();
+        Sio::default().attach(&mut machine);
+
+        // Without input the program keeps polling.
+        let statuses = run_polling(&mut machine, 30);
+        assert_eq!(statuses, [STATUS_OUTPUT_READY; 10]);
+        assert_eq!(machine.state(), MachineState::Running);
+        assert!(machine.output_buffer().is_empty());
+
+        machine.push_input(b"HI\r");
+        let statuses = run_polling(&mut machine, 100);
+        assert_eq!(statuses, [STATUS_OUTPUT_READY | STATUS_INPUT_AVAILABLE; 3]);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.output_buffer(), b"HI\r");
+    }
+}
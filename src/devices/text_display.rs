@@ -0,0 +1,245 @@
+use alloc::boxed::Box;
+
+use crate::{
+    instruction::{Data8, Port},
+    machine::{IoDevice, Machine},
+};
+
+/// Characters per row of a [`TextDisplay`].
+pub const TEXT_COLUMNS: usize = 80;
+/// Rows of a [`TextDisplay`].
+pub const TEXT_ROWS: usize = 25;
+
+/// Bit of the control port that clears the screen and moves the cursor to the top left.
+pub const TEXT_CLEAR: u8 = 0b01;
+
+/// An 80×25 character display driven through four consecutive ports:
+///
+/// - `base`, data: writing puts the character at the cursor and moves the cursor right, to the
+///   next row after the last column. `\n` moves the cursor to the start of the next row, `\r` to
+///   the start of the current one and backspace (0x08) one column left. Writing past the last row
+///   scrolls the screen up. Reading returns the character under the cursor.
+/// - `base + 1`, row: writing moves the cursor to that row, clamped to the last one. Reading
+///   returns the cursor row.
+/// - `base + 2`, column: like the row port, for the cursor column.
+/// - `base + 3`, control: writing a value with [`TEXT_CLEAR`] set clears the screen. Reading
+///   returns 0.
+///
+/// The screen starts out blank, filled with spaces. The UI shows it in place of the program
+/// output when a display is attached; find it with [`Machine::device`] to read the cells.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TextDisplay {
+    base: Port,
+    cells: [[u8; TEXT_COLUMNS]; TEXT_ROWS],
+    row: usize,
+    column: usize,
+}
+
+impl Default for TextDisplay {
+    /// Ports 0x30 to 0x33.
+    fn default() -> Self {
+        Self::new(0x30)
+    }
+}
+
+impl TextDisplay {
+    /// A blank display at the ports starting at `base`.
+    pub fn new(base: Port) -> Self {
+        Self {
+            base,
+            cells: [[b' '; TEXT_COLUMNS]; TEXT_ROWS],
+            row: 0,
+            column: 0,
+        }
+    }
+
+    /// Attach the display to `machine` at its ports.
+    pub fn attach(self, machine: &mut Machine) {
+        let base = self.base;
+        machine.attach_device(
+            &[
+                base,
+                base.wrapping_add(1),
+                base.wrapping_add(2),
+                base.wrapping_add(3),
+            ],
+            Box::new(self),
+        );
+    }
+
+    /// The characters on the screen, top row first.
+    pub fn cells(&self) -> &[[u8; TEXT_COLUMNS]; TEXT_ROWS] {
+        &self.cells
+    }
+
+    /// Row and column of the cursor.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.column)
+    }
+
+    pub fn clear(&mut self) {
+        self.cells = [[b' '; TEXT_COLUMNS]; TEXT_ROWS];
+        self.row = 0;
+        self.column = 0;
+    }
+
+    /// Move the cursor to the start of the next row, scrolling if it's on the last one.
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row + 1 < TEXT_ROWS {
+            self.row += 1;
+        } else {
+            self.cells.copy_within(1.., 0);
+            self.cells[TEXT_ROWS - 1] = [b' '; TEXT_COLUMNS];
+        }
+    }
+
+    fn put(&mut self, character: u8) {
+        match character {
+            b'\n' => self.new_line(),
+            b'\r' => self.column = 0,
+            0x08 => self.column = self.column.saturating_sub(1),
+            _ => {
+                self.cells[self.row][self.column] = character;
+                self.column += 1;
+                if self.column == TEXT_COLUMNS {
+                    self.new_line();
+                }
+            }
+        }
+    }
+}
+
+impl IoDevice for TextDisplay {
+    fn read(&mut self, port: Port, _machine: &mut Machine) -> Option<Data8> {
+        Some(match port.wrapping_sub(self.base) {
+            0 => self.cells[self.row][self.column],
+            1 => self.row as Data8,
+            2 => self.column as Data8,
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, port: Port, value: Data8, _machine: &mut Machine) {
+        match port.wrapping_sub(self.base) {
+            0 => self.put(value),
+            1 => self.row = (value as usize).min(TEXT_ROWS - 1),
+            2 => self.column = (value as usize).min(TEXT_COLUMNS - 1),
+            _ => {
+                if value & TEXT_CLEAR != 0 {
+                    self.clear();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::machine::{HaltReason, MachineBuilder, MachineState};
+
+    #[cfg(feature = "std")]
+    const HELLO: &str = "        ORG 0
+        MVI A, 01H
+        OUT 33H
+        MVI A, 5
+        OUT 31H
+        MVI A, 10
+        OUT 32H
+        LXI H, TEXT
+LOOP:   MOV A, M
+        CPI 0
+        JZ DONE
+        OUT 30H
+        INX H
+        JMP LOOP
+DONE:   HLT
+TEXT:   DB 'hello'
+        DB 0
+        END
+";
+
+    fn write(display: &mut TextDisplay, text: &[u8]) {
+        let mut machine = Machine::new();
+        for &character in text {
+            display.write(0x30, character, &mut machine);
+        }
+    }
+
+    fn row(display: &TextDisplay, row: usize) -> &str {
+        core::str::from_utf8(&display.cells()[row])
+            .unwrap()
+            .trim_end()
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn hello_at_row_5_column_10() {
+        let mut machine = MachineBuilder::new()
+            .assembly(HELLO.as_bytes())
+            .build()
+            .unwrap();
+        TextDisplay::default().attach(&mut machine);
+        while machine.step().is_some() {}
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+
+        let display = machine.device::<TextDisplay>().unwrap();
+        for (index, cells) in display.cells().iter().enumerate() {
+            if index == 5 {
+                assert_eq!(&cells[..15], b"          hello");
+                assert!(cells[15..].iter().all(|&cell| cell == b' '));
+            } else {
+                assert_eq!(cells, &[b' '; TEXT_COLUMNS], "row {}", index);
+            }
+        }
+        assert_eq!(display.cursor(), (5, 15));
+        assert!(machine.output_buffer().is_empty());
+    }
+
+    #[test]
+    fn control_characters_and_wrapping() {
+        let mut display = TextDisplay::default();
+        write(&mut display, b"one\ntwo\rT\x08W");
+        assert_eq!(row(&display, 0), "one");
+        assert_eq!(row(&display, 1), "Wwo");
+
+        write(&mut display, &[b'x'; TEXT_COLUMNS]);
+        assert_eq!(display.cursor(), (2, 1));
+        assert_eq!(row(&display, 2), "x");
+    }
+
+    #[test]
+    fn scrolls_past_the_last_row() {
+        let mut display = TextDisplay::default();
+        for line in 0..TEXT_ROWS {
+            write(&mut display, &[b'a' + line as u8, b'\n']);
+        }
+        assert_eq!(row(&display, 0), "b");
+        assert_eq!(row(&display, TEXT_ROWS - 2), "y");
+        assert_eq!(row(&display, TEXT_ROWS - 1), "");
+        assert_eq!(display.cursor(), (TEXT_ROWS - 1, 0));
+    }
+
+    #[test]
+    fn cursor_and_control_ports() {
+        let mut machine = Machine::new();
+        let mut display = TextDisplay::default();
+        display.write(0x31, 200, &mut machine);
+        display.write(0x32, 3, &mut machine);
+        display.write(0x30, b'!', &mut machine);
+        display.write(0x32, 3, &mut machine);
+        assert_eq!(display.read(0x30, &mut machine), Some(b'!'));
+        assert_eq!(display.read(0x31, &mut machine), Some(TEXT_ROWS as u8 - 1));
+        assert_eq!(display.read(0x32, &mut machine), Some(3));
+
+        display.write(0x33, 0b10, &mut machine);
+        assert_eq!(display.cells()[TEXT_ROWS - 1][3], b'!');
+        display.write(0x33, TEXT_CLEAR, &mut machine);
+        assert_eq!(display, TextDisplay::default());
+    }
+}
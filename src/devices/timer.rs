@@ -0,0 +1,156 @@
+use alloc::boxed::Box;
+
+use crate::{
+    instruction::{Data8, Instruction, Port, RestartNumber},
+    machine::{IoDevice, Machine},
+};
+
+/// Timer interrupting the program with an `RST` every `period` clock periods, e.g. to drive a
+/// clock or a scheduler.
+///
+/// The timer ticks each time [`Machine::cycles`] passes a multiple of the period, so a program
+/// is interrupted at the same instructions on every run. Time only passes while instructions
+/// execute, so a program waiting for ticks should loop instead of executing `HLT`.
+///
+/// The interrupt is requested after the instruction during which the timer ticked. While
+/// interrupts are disabled the tick is kept pending and requested once `EI` enables them, but at
+/// most one tick is pending, so ticks that elapse in the meantime are lost.
+///
+/// The timer isn't attached to any ports. Attached with [`Timer::attach`], it can be looked up
+/// with [`Machine::device`] to see how often it ticked.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Timer {
+    period: u64,
+    restart: RestartNumber,
+    /// Number of periods that had passed at the last check.
+    periods: u64,
+    pending: bool,
+    ticks: u64,
+    interrupts: u64,
+}
+
+impl Timer {
+    /// # Panics
+    ///
+    /// If `period` is 0.
+    pub fn new(period: u64, restart: RestartNumber) -> Self {
+        assert!(period > 0, "the period of a timer can't be 0");
+        Self {
+            period,
+            restart,
+            periods: 0,
+            pending: false,
+            ticks: 0,
+            interrupts: 0,
+        }
+    }
+
+    /// Number of times the period elapsed.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Number of interrupts the machine accepted. Lower than [`Timer::ticks`] if ticks were lost
+    /// or one is still pending.
+    pub fn interrupts(&self) -> u64 {
+        self.interrupts
+    }
+
+    /// Attach the timer to `machine`.
+    pub fn attach(self, machine: &mut Machine) {
+        machine.attach_device(&[], Box::new(self));
+    }
+}
+
+impl IoDevice for Timer {
+    fn read(&mut self, _port: Port, _machine: &mut Machine) -> Option<Data8> {
+        Some(0)
+    }
+
+    fn write(&mut self, _port: Port, _value: Data8, _machine: &mut Machine) {}
+
+    fn tick(&mut self, machine: &mut Machine) {
+        // The cycle count goes back to 0 when the machine is reset.
+        let periods = machine.cycles() / self.period;
+        if periods > self.periods {
+            self.ticks += periods - self.periods;
+            self.pending = true;
+        }
+        self.periods = periods;
+        if self.pending && machine.request_interrupt(Instruction::Rst(self.restart)) {
+            self.pending = false;
+            self.interrupts += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::Data16,
+        machine::{MachineBuilder, MachineState},
+    };
+
+    /// 0000: LXI SP, 1000H
+    /// 0003: EI
+    /// 0004: JMP 0004H
+    ///
+    /// 0038: PUSH PSW
+    /// 0039: LDA 2000H
+    /// 003C: INR A
+    /// 003D: STA 2000H
+    /// 0040: POP PSW
+    /// 0041: EI
+    /// 0042: RET
+    fn counting_machine() -> Machine {
+        MachineBuilder::new()
+            .program(&[0x31, 0x00, 0x10, 0xFB, 0xC3, 0x04, 0x00], 0x0000)
+            .segment(
+                &[
+                    0xF5, 0x3A, 0x00, 0x20, 0x3C, 0x32, 0x00, 0x20, 0xF1, 0xFB, 0xC9,
+                ],
+                0x0038,
+            )
+            .build()
+            .unwrap()
+    }
+
+    fn run_for(machine: &mut Machine, cycles: u64) {
+        while machine.cycles() < cycles {
+            machine.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn counts_ticks() {
+        let mut machine = counting_machine();
+        Timer::new(1000, RestartNumber::R7).attach(&mut machine);
+        run_for(&mut machine, 100_000);
+
+        assert_eq!(machine.state(), MachineState::Running);
+        let timer = machine.device::<Timer>().unwrap();
+        assert_eq!(timer.ticks(), 100);
+        // The last tick is accepted, but its RST hasn't been executed yet.
+        assert_eq!(timer.interrupts(), 100);
+        assert_eq!(machine.memory().read_8(0x2000), 99);
+    }
+
+    #[test]
+    fn keeps_one_tick_pending() {
+        let mut machine = counting_machine();
+        // Skip EI.
+        machine.set_pc(Data16::from(0x0004));
+        Timer::new(100, RestartNumber::R7).attach(&mut machine);
+        run_for(&mut machine, 1000);
+        let timer = machine.device::<Timer>().unwrap();
+        assert_eq!((timer.ticks(), timer.interrupts()), (10, 0));
+
+        // The pending tick is taken once interrupts are enabled, the lost ones aren't.
+        machine.set_pc(Data16::from(0x0003));
+        run_for(&mut machine, 1090);
+        let timer = machine.device::<Timer>().unwrap();
+        assert_eq!((timer.ticks(), timer.interrupts()), (10, 1));
+        assert_eq!(machine.memory().read_8(0x2000), 1);
+    }
+}
@@ -0,0 +1,684 @@
+//! Disassembly listings of memory images.
+//!
+//! A listing has one line per instruction or run of data bytes, with the address, the raw bytes,
+//! the label defined at the address and the statement:
+//!
+//! ```text
+//!                           ORG 0100H
+//! 0100  21 0A 01     START: LXI H,MSG
+//! 0103  CD 08 01            CALL L0108
+//! 0106  76                  HLT
+//! 0107  00                  DB 00H
+//! 0108  7E           L0108: MOV A,M
+//! 0109  C9                  RET
+//! 010A  48 69 00     MSG:   DB 48H,69H,00H
+//!                           END
+//! ```
+//!
+//! Code is told apart from data by following the control flow from the entry points: every byte
+//! of an instruction that can be reached from an entry is code, everything else is data and is
+//! listed as `DB`. Labels come from the symbol table of the assembled program when there is one.
+//! Jump and call targets without a symbol are labeled `Lxxxx`, and data used by instructions is
+//! labeled `Dxxxx`, where `xxxx` is the address.
+//!
+//! [`disassemble`] instead decodes a range one instruction after the other, for looking at memory
+//! without knowing where the code is.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{self, Write},
+};
+
+use crate::{
+    coding::{self, reader::Reader},
+    instruction::{self, Address, Instruction, RegisterPair},
+};
+
+/// Size of the address space, one past the highest address.
+const ADDRESS_SPACE: usize = 0x10000;
+
+/// Names of addresses, shown as labels in listings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    names: BTreeMap<Address, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name `address`. An address keeps the first name it's given.
+    pub fn insert(&mut self, address: Address, name: impl Into<String>) {
+        self.names.entry(address).or_insert_with(|| name.into());
+    }
+
+    /// Add the names of `other`, keeping the names of addresses that already have one.
+    pub fn merge(&mut self, other: &Symbols) {
+        for (address, name) in &other.names {
+            self.insert(*address, name.clone());
+        }
+    }
+
+    pub fn get(&self, address: Address) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, Address)> for Symbols {
+    fn from_iter<T: IntoIterator<Item = (S, Address)>>(iter: T) -> Self {
+        let mut symbols = Symbols::new();
+        for (name, address) in iter {
+            symbols.insert(address, name);
+        }
+        symbols
+    }
+}
+
+/// Column layout of a listing.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ListingColumns {
+    /// Number of bytes the bytes column has room for, which is also the most bytes a `DB` line
+    /// holds. The column is always wide enough for the longest instruction.
+    pub bytes: usize,
+    /// Width of the label column, including the colon after the label.
+    pub label: usize,
+}
+
+impl Default for ListingColumns {
+    fn default() -> Self {
+        Self { bytes: 4, label: 6 }
+    }
+}
+
+/// A number in the notation used by [`Instruction`]'s `Display` implementation.
+struct Hex(u16, usize);
+
+impl Display for Hex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        instruction::write_hex(f, self.0, self.1)
+    }
+}
+
+/// Text of an instruction, with its address operand replaced by a label if there is one.
+fn statement(instruction: Instruction, labels: &BTreeMap<Address, String>) -> String {
+    let text = instruction.to_string();
+    let address = match instruction {
+        Instruction::Lxi(_, value) => value.value(),
+        Instruction::Lda(address)
+        | Instruction::Sta(address)
+        | Instruction::Lhld(address)
+        | Instruction::Shld(address)
+        | Instruction::Jmp(address)
+        | Instruction::Jcc(_, address)
+        | Instruction::Call(address)
+        | Instruction::Ccc(_, address) => address,
+        _ => return text,
+    };
+    match labels.get(&address) {
+        Some(label) => {
+            // The address is always the last operand.
+            let operand = text.rfind([' ', ',']).expect("instruction has an operand") + 1;
+            format!("{}{}", &text[..operand], label)
+        }
+        None => text,
+    }
+}
+
+/// A line of a [`Listing`]: an instruction, or a run of data bytes listed as `DB`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ListingLine {
+    pub address: Address,
+    pub bytes: Vec<u8>,
+    pub label: Option<String>,
+    /// The instruction or `DB` statement, with address operands replaced by labels.
+    pub statement: String,
+}
+
+/// Disassembly of a memory region, written with [`Listing::write`].
+#[derive(Clone, Debug)]
+pub struct Listing {
+    origin: Address,
+    bytes: Vec<u8>,
+    /// Decoded instructions by offset from `origin`.
+    instructions: BTreeMap<usize, Instruction>,
+    /// Whether each byte is part of an instruction.
+    code: Vec<bool>,
+    labels: BTreeMap<Address, String>,
+}
+
+impl Listing {
+    /// Disassemble `bytes` placed at `origin`, following the control flow from `entries`, or from
+    /// `origin` if there are none. Entries outside the region are ignored, and bytes that would
+    /// end up past 0xFFFF are left out.
+    pub fn new(bytes: &[u8], origin: Address, entries: &[Address], symbols: &Symbols) -> Self {
+        let bytes = &bytes[..bytes.len().min(ADDRESS_SPACE - origin as usize)];
+        let mut listing = Listing {
+            origin,
+            bytes: bytes.to_vec(),
+            instructions: BTreeMap::new(),
+            code: vec![false; bytes.len()],
+            labels: BTreeMap::new(),
+        };
+
+        let mut pending = if entries.is_empty() {
+            vec![origin]
+        } else {
+            entries.to_vec()
+        };
+        let mut targets = Vec::new();
+        let mut data = Vec::new();
+        while let Some(address) = pending.pop() {
+            let Some(mut offset) = listing.offset(address) else {
+                continue;
+            };
+            while offset < listing.bytes.len() && !listing.code[offset] {
+                let mut reader = Reader::new(&listing.bytes[offset..]);
+                let Some(instruction) = coding::decode(&mut reader) else {
+                    break;
+                };
+                let end = offset + instruction.length() as usize;
+                if listing.code[offset..end].contains(&true) {
+                    break;
+                }
+                listing.code[offset..end].fill(true);
+                listing.instructions.insert(offset, instruction);
+                offset = end;
+
+                match instruction {
+                    Instruction::Jmp(target) => {
+                        targets.push(target);
+                        pending.push(target);
+                        break;
+                    }
+                    Instruction::Jcc(_, target)
+                    | Instruction::Call(target)
+                    | Instruction::Ccc(_, target) => {
+                        targets.push(target);
+                        pending.push(target);
+                    }
+                    Instruction::Rst(number) => {
+                        let target = u16::from(number) * 8;
+                        targets.push(target);
+                        pending.push(target);
+                    }
+                    Instruction::Ret | Instruction::Hlt | Instruction::Pchl => break,
+                    Instruction::Lda(address)
+                    | Instruction::Sta(address)
+                    | Instruction::Lhld(address)
+                    | Instruction::Shld(address) => data.push(address),
+                    Instruction::Lxi(pair, value) if pair != RegisterPair::Sp => {
+                        data.push(value.value())
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for (address, name) in &symbols.names {
+            if listing.is_line_start(*address) {
+                listing.labels.insert(*address, name.clone());
+            }
+        }
+        for target in targets {
+            if listing.is_line_start(target) && listing.is_code(target) {
+                listing
+                    .labels
+                    .entry(target)
+                    .or_insert_with(|| format!("L{:04X}", target));
+            }
+        }
+        for address in data {
+            if listing.is_line_start(address) && !listing.is_code(address) {
+                listing
+                    .labels
+                    .entry(address)
+                    .or_insert_with(|| format!("D{:04X}", address));
+            }
+        }
+
+        listing
+    }
+
+    fn offset(&self, address: Address) -> Option<usize> {
+        let offset = (address as usize).checked_sub(self.origin as usize)?;
+        (offset < self.bytes.len()).then_some(offset)
+    }
+
+    fn is_code(&self, address: Address) -> bool {
+        self.offset(address).is_some_and(|offset| self.code[offset])
+    }
+
+    /// Whether a line can start at `address`, which is where labels can be placed. Data lines are
+    /// split wherever needed, but instructions can't be.
+    fn is_line_start(&self, address: Address) -> bool {
+        self.offset(address)
+            .is_some_and(|offset| !self.code[offset] || self.instructions.contains_key(&offset))
+    }
+
+    /// The lines of the listing, with at most `data_per_line` bytes in each `DB` statement.
+    pub fn lines(&self, data_per_line: usize) -> Vec<ListingLine> {
+        let data_per_line = data_per_line.max(1);
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        while offset < self.bytes.len() {
+            let address = self.origin + offset as u16;
+            let (end, statement) = match self.instructions.get(&offset) {
+                Some(instruction) => (
+                    offset + instruction.length() as usize,
+                    statement(*instruction, &self.labels),
+                ),
+                None => {
+                    let mut end = offset + 1;
+                    while end < self.bytes.len()
+                        && end - offset < data_per_line
+                        && !self.code[end]
+                        && !self.labels.contains_key(&(self.origin + end as u16))
+                    {
+                        end += 1;
+                    }
+                    let values: Vec<String> = self.bytes[offset..end]
+                        .iter()
+                        .map(|byte| Hex(*byte as u16, 2).to_string())
+                        .collect();
+                    (end, format!("DB {}", values.join(",")))
+                }
+            };
+
+            lines.push(ListingLine {
+                address,
+                bytes: self.bytes[offset..end].to_vec(),
+                label: self.labels.get(&address).cloned(),
+                statement,
+            });
+            offset = end;
+        }
+        lines
+    }
+
+    /// Write the listing, one line per instruction or run of data bytes.
+    pub fn write(&self, mut writer: impl Write, columns: &ListingColumns) -> io::Result<()> {
+        let bytes_width = columns.bytes.max(3) * 3 - 1;
+        let indent = " ".repeat(4 + 2 + bytes_width + 2 + columns.label);
+
+        writeln!(writer, "{} ORG {}", indent, Hex(self.origin, 4))?;
+        for line in self.lines(columns.bytes) {
+            let bytes: Vec<String> = line
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            let label = match &line.label {
+                Some(label) => format!("{}:", label),
+                None => String::new(),
+            };
+            writeln!(
+                writer,
+                "{:04X}  {:bytes_width$}  {:label_width$} {}",
+                line.address,
+                bytes.join(" "),
+                label,
+                line.statement,
+                bytes_width = bytes_width,
+                label_width = columns.label,
+            )?;
+        }
+        writeln!(writer, "{} END", indent)
+    }
+}
+
+/// A line of [`disassemble`]: an instruction, or a byte that doesn't start one.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct DisassembledLine {
+    pub address: Address,
+    pub bytes: Vec<u8>,
+    /// `None` when the byte isn't an opcode or the instruction doesn't fit in the range.
+    pub instruction: Option<Instruction>,
+    /// `Lxxxx` if a jump, call or restart in the range goes to the line.
+    pub label: Option<String>,
+    /// The instruction, with its address operand replaced by a label if there is one, or a `DB`
+    /// statement for a byte that isn't one.
+    pub statement: String,
+}
+
+impl DisassembledLine {
+    /// A line without labels.
+    pub fn new(address: Address, bytes: Vec<u8>, instruction: Option<Instruction>) -> Self {
+        let statement = match instruction {
+            Some(instruction) => instruction.to_string(),
+            None => format!("DB {}", Hex(bytes[0] as u16, 2)),
+        };
+        Self {
+            address,
+            bytes,
+            instruction,
+            label: None,
+            statement,
+        }
+    }
+}
+
+/// Width of the label column of [`DisassembledLine`]s, enough for `Lxxxx: `.
+const TARGET_LABEL_WIDTH: usize = 7;
+
+impl Display for DisassembledLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let label = match &self.label {
+            Some(label) => format!("{}:", label),
+            None => String::new(),
+        };
+        write!(
+            f,
+            "{:04X}  {:8}  {:label_width$}{}",
+            self.address,
+            bytes.join(" "),
+            label,
+            self.statement,
+            label_width = TARGET_LABEL_WIDTH,
+        )
+    }
+}
+
+/// Decode `len` bytes of `memory` from `origin` one instruction after the other, without
+/// following the control flow like [`Listing`] does. A byte that doesn't start an instruction,
+/// or starts one that would run past the end of the range, becomes a one-byte `DB` line. The range
+/// ends at the end of `memory` and at 0xFFFF.
+///
+/// Lines that a `JMP`, `Jcc`, `CALL`, `Ccc` or `RST` in the range goes to are labeled `Lxxxx`,
+/// where `xxxx` is the address, and address operands refer to them by label. Targets outside the
+/// range or inside an instruction stay numeric.
+pub fn disassemble(memory: &[u8], origin: Address, len: usize) -> Vec<DisassembledLine> {
+    let start = (origin as usize).min(memory.len());
+    let end = start
+        .saturating_add(len)
+        .min(memory.len())
+        .min(ADDRESS_SPACE);
+    let mut lines = Vec::new();
+    let mut offset = start;
+    while offset < end {
+        let instruction = coding::decode(&mut Reader::new(&memory[offset..end]));
+        let length = instruction.map_or(1, |instruction| instruction.length() as usize);
+        lines.push(DisassembledLine::new(
+            offset as Address,
+            memory[offset..offset + length].to_vec(),
+            instruction,
+        ));
+        offset += length;
+    }
+
+    let starts: BTreeMap<Address, usize> = lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| (line.address, index))
+        .collect();
+    let mut labels = BTreeMap::new();
+    for line in &lines {
+        let target = match line.instruction {
+            Some(
+                Instruction::Jmp(target)
+                | Instruction::Jcc(_, target)
+                | Instruction::Call(target)
+                | Instruction::Ccc(_, target),
+            ) => target,
+            Some(Instruction::Rst(number)) => u16::from(number) * 8,
+            _ => continue,
+        };
+        if starts.contains_key(&target) {
+            labels.insert(target, format!("L{:04X}", target));
+        }
+    }
+    for (address, label) in &labels {
+        lines[starts[address]].label = Some(label.clone());
+    }
+    for line in &mut lines {
+        if let Some(instruction) = line.instruction {
+            line.statement = statement(instruction, &labels);
+        }
+    }
+    lines
+}
+
+/// Write [`disassemble`]d lines as assembly source that assembles back to the same bytes.
+pub fn write_source(lines: &[DisassembledLine], mut writer: impl Write) -> io::Result<()> {
+    let indent = " ".repeat(TARGET_LABEL_WIDTH);
+    if let Some(first) = lines.first() {
+        writeln!(writer, "{}ORG {}", indent, Hex(first.address, 4))?;
+    }
+    for line in lines {
+        let label = match &line.label {
+            Some(label) => format!("{}:", label),
+            None => String::new(),
+        };
+        writeln!(
+            writer,
+            "{:label_width$}{}",
+            label,
+            line.statement,
+            label_width = TARGET_LABEL_WIDTH,
+        )?;
+    }
+    writeln!(writer, "{}END", indent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(listing: &Listing, columns: &ListingColumns) -> String {
+        let mut text = Vec::new();
+        listing.write(&mut text, columns).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn module_example() {
+        let bytes = [
+            0x21, 0x0A, 0x01, // LXI H,MSG
+            0xCD, 0x08, 0x01, // CALL 0108H
+            0x76, // HLT
+            0x00, // unreachable
+            0x7E, // MOV A,M
+            0xC9, // RET
+            0x48, 0x69, 0x00, // MSG
+        ];
+        let symbols: Symbols = [("START", 0x0100), ("MSG", 0x010A)].into_iter().collect();
+        let listing = Listing::new(&bytes, 0x0100, &[], &symbols);
+        assert_eq!(
+            render(&listing, &ListingColumns::default()),
+            concat!(
+                "                          ORG 0100H\n",
+                "0100  21 0A 01     START: LXI H,MSG\n",
+                "0103  CD 08 01            CALL L0108\n",
+                "0106  76                  HLT\n",
+                "0107  00                  DB 00H\n",
+                "0108  7E           L0108: MOV A,M\n",
+                "0109  C9                  RET\n",
+                "010A  48 69 00     MSG:   DB 48H,69H,00H\n",
+                "                          END\n",
+            )
+        );
+    }
+
+    #[test]
+    fn truncated_instruction_is_data() {
+        let listing = Listing::new(&[0x00, 0xC3, 0x00], 0x0000, &[], &Symbols::new());
+        assert_eq!(
+            render(&listing, &ListingColumns { bytes: 1, label: 0 }),
+            concat!(
+                "                 ORG 0000H\n",
+                "0000  00         NOP\n",
+                "0001  C3         DB 0C3H\n",
+                "0002  00         DB 00H\n",
+                "                 END\n",
+            )
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let symbols: Symbols = [("MSG", 0x0004)].into_iter().collect();
+        let listing = Listing::new(&[0x3A, 0x04, 0x00, 0x76, 0x48, 0x69], 0x0000, &[], &symbols);
+        assert_eq!(
+            listing.lines(1),
+            [
+                ListingLine {
+                    address: 0x0000,
+                    bytes: vec![0x3A, 0x04, 0x00],
+                    label: None,
+                    statement: String::from("LDA MSG"),
+                },
+                ListingLine {
+                    address: 0x0003,
+                    bytes: vec![0x76],
+                    label: None,
+                    statement: String::from("HLT"),
+                },
+                ListingLine {
+                    address: 0x0004,
+                    bytes: vec![0x48],
+                    label: Some(String::from("MSG")),
+                    statement: String::from("DB 48H"),
+                },
+                ListingLine {
+                    address: 0x0005,
+                    bytes: vec![0x69],
+                    label: None,
+                    statement: String::from("DB 69H"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn linear_listing() {
+        let mut memory = vec![0; ADDRESS_SPACE];
+        memory[0x0100..0x010A].copy_from_slice(&[
+            0x21, 0x08, 0x01, // LXI H,0108H
+            0xCD, 0x09, 0x01, // CALL 0109H
+            0x76, // HLT
+            0xED, // not an opcode
+            0xC3, 0x00, // JMP past the end of the range
+        ]);
+        let text: Vec<String> = disassemble(&memory, 0x0100, 10)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            text,
+            [
+                "0100  21 08 01         LXI H,0108H",
+                "0103  CD 09 01         CALL L0109",
+                "0106  76               HLT",
+                "0107  ED               DB 0EDH",
+                "0108  C3               DB 0C3H",
+                "0109  00        L0109: NOP",
+            ]
+        );
+    }
+
+    #[test]
+    fn linear_listing_stops_at_the_end_of_memory() {
+        let memory = [0x00, 0x3E, 0x01, 0x3E];
+        let lines = disassemble(&memory, 0x0001, 100);
+        assert_eq!(
+            lines,
+            [
+                DisassembledLine::new(
+                    0x0001,
+                    vec![0x3E, 0x01],
+                    Some(Instruction::Mvi(instruction::Register::A, 0x01)),
+                ),
+                DisassembledLine::new(0x0003, vec![0x3E], None),
+            ]
+        );
+        assert!(disassemble(&memory, 0x0004, 1).is_empty());
+        assert!(disassemble(&memory, 0x0000, 0).is_empty());
+    }
+
+    #[test]
+    fn linear_listing_labels_targets_in_the_range() {
+        #[rustfmt::skip]
+        let memory = [
+            0xC3, 0x07, 0x00, // JMP 0007H, forward
+            0xCF,             // RST 1
+            0xC3, 0x01, 0x00, // JMP 0001H, into the first instruction
+            0x05,             // DCR B
+            0xC2, 0x07, 0x00, // JNZ 0007H, backward
+            0xDC, 0x00, 0x20, // CC 2000H, outside the range
+            0xC9,             // RET
+        ];
+        let lines = disassemble(&memory, 0x0000, memory.len());
+        let text: Vec<String> = lines.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            text,
+            [
+                "0000  C3 07 00         JMP L0007",
+                "0003  CF               RST 1",
+                "0004  C3 01 00         JMP 0001H",
+                "0007  05        L0007: DCR B",
+                "0008  C2 07 00  L0008: JNZ L0007",
+                "000B  DC 00 20         CC 2000H",
+                "000E  C9               RET",
+            ]
+        );
+        assert_eq!(
+            disassemble(&memory, 0x0008, 3)[0].to_string(),
+            "0008  C2 07 00         JNZ 0007H"
+        );
+    }
+
+    #[test]
+    fn linear_source_assembles_to_the_same_bytes() {
+        #[rustfmt::skip]
+        let program = [
+            0x06, 0x03,       // MVI B, 3
+            0xCD, 0x0E, 0x01, // CALL 010EH, forward
+            0x05,             // DCR B
+            0xC2, 0x02, 0x01, // JNZ 0102H, backward
+            0x3A, 0x12, 0x01, // LDA 0112H
+            0x76,             // HLT
+            0xED,             // not an opcode
+            0x3C,             // INR A
+            0xD3, 0x00,       // OUT 0
+            0xC9,             // RET
+            0x2A,             // LHLD cut off by the end of the range
+        ];
+        let mut memory = vec![0; 0x0100];
+        memory.extend_from_slice(&program);
+        let lines = disassemble(&memory, 0x0100, program.len());
+
+        let mut source = Vec::new();
+        write_source(&lines, &mut source).unwrap();
+        let source = String::from_utf8(source).unwrap();
+        assert!(source.contains("L0102: CALL L010E\n"), "{}", source);
+        assert!(source.contains("       JNZ L0102\n"), "{}", source);
+
+        let machine = crate::machine::MachineBuilder::new()
+            .assembly(source.as_bytes())
+            .build()
+            .unwrap();
+        assert_eq!(machine.pc().value(), 0x0100);
+        assert_eq!(
+            &machine.memory().as_raw()[0x0100..0x0100 + program.len()],
+            program
+        );
+    }
+
+    #[test]
+    fn label_inside_instruction_is_dropped() {
+        // JMP 0001H jumps into its own operand.
+        let symbols: Symbols = [("MID", 0x0001)].into_iter().collect();
+        let listing = Listing::new(&[0xC3, 0x01, 0x00], 0x0000, &[], &symbols);
+        assert!(render(&listing, &ListingColumns::default()).contains("JMP 0001H\n"));
+    }
+}
@@ -0,0 +1,443 @@
+//! C interface for embedding the emulator in non-Rust hosts. The header is `include/leben.h`,
+//! generated with `cbindgen --config cbindgen.toml --output include/leben.h`.
+//!
+//! Functions returning `int32_t` return `LEBEN_OK` (0) or a positive value on success and one of
+//! the negative `LEBEN_ERR_*` codes on failure, in which case [`leben_last_error_message`]
+//! describes the error. Panics are caught at the boundary and reported as `LEBEN_ERR_PANIC`.
+
+use std::{
+    cell::RefCell,
+    ffi::{CString, c_char},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{
+    instruction::{Register, RegisterPair},
+    machine::{HaltReason, Machine, MachineBuilder, MachineState},
+};
+
+pub const LEBEN_OK: i32 = 0;
+/// A required pointer argument was null.
+pub const LEBEN_ERR_NULL: i32 = -1;
+/// An argument was out of range, e.g. an unknown register or a memory range past 0xFFFF.
+pub const LEBEN_ERR_INVALID_ARGUMENT: i32 = -2;
+/// The program couldn't be loaded.
+pub const LEBEN_ERR_LOAD: i32 = -3;
+/// The emulator panicked. The machine should be destroyed.
+pub const LEBEN_ERR_PANIC: i32 = -4;
+
+/// Machine states returned by `leben_step` and `leben_state`.
+pub const LEBEN_STATE_RUNNING: i32 = 0;
+pub const LEBEN_STATE_HALTED: i32 = 1;
+pub const LEBEN_STATE_INVALID_INSTRUCTION: i32 = 2;
+pub const LEBEN_STATE_STACK_OVERFLOW: i32 = 3;
+pub const LEBEN_STATE_STACK_UNDERFLOW: i32 = 4;
+pub const LEBEN_STATE_MEMORY_OVERFLOW: i32 = 5;
+pub const LEBEN_STATE_WAITING_FOR_INPUT: i32 = 6;
+pub const LEBEN_STATE_SELF_JUMP: i32 = 7;
+
+/// Registers readable with `leben_get_register`.
+pub const LEBEN_REGISTER_A: u32 = 0;
+pub const LEBEN_REGISTER_B: u32 = 1;
+pub const LEBEN_REGISTER_C: u32 = 2;
+pub const LEBEN_REGISTER_D: u32 = 3;
+pub const LEBEN_REGISTER_E: u32 = 4;
+pub const LEBEN_REGISTER_H: u32 = 5;
+pub const LEBEN_REGISTER_L: u32 = 6;
+
+/// Opaque handle to a machine.
+pub struct LebenMachine {
+    machine: Machine,
+}
+
+struct Error {
+    code: i32,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn null(argument: &str) -> Self {
+        Self::new(LEBEN_ERR_NULL, format!("'{}' is null", argument))
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Interior nul bytes can't be represented in a C string.
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run `body`, turning errors and panics into `on_error(code)` and recording their message.
+fn guard<T>(on_error: impl FnOnce(i32) -> T, body: impl FnOnce() -> Result<T, Error>) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            set_last_error(err.message);
+            on_error(err.code)
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown panic"));
+            set_last_error(format!("panic: {}", message));
+            on_error(LEBEN_ERR_PANIC)
+        }
+    }
+}
+
+/// # Safety
+/// `machine` must be null or a pointer returned by [`leben_create`] that hasn't been destroyed.
+unsafe fn machine_mut<'a>(machine: *mut LebenMachine) -> Result<&'a mut Machine, Error> {
+    unsafe { machine.as_mut() }
+        .map(|machine| &mut machine.machine)
+        .ok_or_else(|| Error::null("machine"))
+}
+
+/// # Safety
+/// `machine` must be null or a pointer returned by [`leben_create`] that hasn't been destroyed.
+unsafe fn machine_ref<'a>(machine: *const LebenMachine) -> Result<&'a Machine, Error> {
+    unsafe { machine.as_ref() }
+        .map(|machine| &machine.machine)
+        .ok_or_else(|| Error::null("machine"))
+}
+
+/// # Safety
+/// `bytes` must be null or valid for reads of `len` bytes. Null is accepted when `len` is 0.
+unsafe fn input_slice<'a>(bytes: *const u8, len: usize, name: &str) -> Result<&'a [u8], Error> {
+    if len == 0 {
+        Ok(&[])
+    } else if bytes.is_null() {
+        Err(Error::null(name))
+    } else {
+        Ok(unsafe { slice::from_raw_parts(bytes, len) })
+    }
+}
+
+fn past_end(address: u16, len: usize) -> Error {
+    Error::new(
+        LEBEN_ERR_INVALID_ARGUMENT,
+        format!(
+            "{} bytes at 0x{:04X} run past the end of memory",
+            len, address
+        ),
+    )
+}
+
+fn state_code(state: MachineState) -> i32 {
+    match state {
+        MachineState::Running => LEBEN_STATE_RUNNING,
+        MachineState::Halted(HaltReason::HaltInstruction) => LEBEN_STATE_HALTED,
+        MachineState::Halted(HaltReason::InvalidInstruction) => LEBEN_STATE_INVALID_INSTRUCTION,
+        MachineState::Halted(HaltReason::StackOverflow) => LEBEN_STATE_STACK_OVERFLOW,
+        MachineState::Halted(HaltReason::StackUnderflow) => LEBEN_STATE_STACK_UNDERFLOW,
+        MachineState::Halted(HaltReason::MemoryOverflow) => LEBEN_STATE_MEMORY_OVERFLOW,
+        MachineState::WaitingForInput => LEBEN_STATE_WAITING_FOR_INPUT,
+        MachineState::Halted(HaltReason::SelfJump) => LEBEN_STATE_SELF_JUMP,
+    }
+}
+
+/// Create a machine with empty memory. Returns null on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn leben_create() -> *mut LebenMachine {
+    guard(
+        |_| ptr::null_mut(),
+        || {
+            Ok(Box::into_raw(Box::new(LebenMachine {
+                machine: Machine::new(),
+            })))
+        },
+    )
+}
+
+/// Destroy a machine. Passing null does nothing.
+///
+/// # Safety
+/// `machine` must be null or a pointer returned by [`leben_create`] that hasn't been destroyed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_destroy(machine: *mut LebenMachine) {
+    if !machine.is_null() {
+        drop(unsafe { Box::from_raw(machine) });
+    }
+}
+
+/// Reset the machine and load `len` bytes at `origin`, starting execution there.
+///
+/// # Safety
+/// `machine` must be a live machine and `bytes` valid for reads of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_load_program(
+    machine: *mut LebenMachine,
+    bytes: *const u8,
+    len: usize,
+    origin: u16,
+) -> i32 {
+    guard(
+        |code| code,
+        || {
+            let machine = unsafe { machine_mut(machine) }?;
+            let bytes = unsafe { input_slice(bytes, len, "bytes") }?;
+            *machine = MachineBuilder::new()
+                .program(bytes, origin)
+                .build()
+                .map_err(|err| Error::new(LEBEN_ERR_LOAD, err.to_string()))?;
+            Ok(LEBEN_OK)
+        },
+    )
+}
+
+/// Execute one instruction and return the resulting `LEBEN_STATE_*`.
+///
+/// # Safety
+/// `machine` must be a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_step(machine: *mut LebenMachine) -> i32 {
+    guard(
+        |code| code,
+        || {
+            let machine = unsafe { machine_mut(machine) }?;
+            machine.step();
+            Ok(state_code(machine.state()))
+        },
+    )
+}
+
+/// Execute up to `max_instructions` instructions, stopping early when the machine halts. Returns
+/// the number of executed instructions, or a negative error code.
+///
+/// # Safety
+/// `machine` must be a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_run(machine: *mut LebenMachine, max_instructions: u64) -> i64 {
+    guard(
+        |code| code as i64,
+        || {
+            let machine = unsafe { machine_mut(machine) }?;
+            let executed = machine.steps().take(max_instructions as usize).count();
+            Ok(executed as i64)
+        },
+    )
+}
+
+/// The current `LEBEN_STATE_*` of the machine.
+///
+/// # Safety
+/// `machine` must be a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_state(machine: *const LebenMachine) -> i32 {
+    guard(
+        |code| code,
+        || Ok(state_code(unsafe { machine_ref(machine) }?.state())),
+    )
+}
+
+/// Store the value of the 8-bit register `register_id` (a `LEBEN_REGISTER_*`) in `out`.
+///
+/// # Safety
+/// `machine` must be a live machine and `out` valid for a write of one byte.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_get_register(
+    machine: *const LebenMachine,
+    register_id: u32,
+    out: *mut u8,
+) -> i32 {
+    guard(
+        |code| code,
+        || {
+            let machine = unsafe { machine_ref(machine) }?;
+            let register = match register_id {
+                LEBEN_REGISTER_A => Register::A,
+                LEBEN_REGISTER_B => Register::B,
+                LEBEN_REGISTER_C => Register::C,
+                LEBEN_REGISTER_D => Register::D,
+                LEBEN_REGISTER_E => Register::E,
+                LEBEN_REGISTER_H => Register::H,
+                LEBEN_REGISTER_L => Register::L,
+                _ => {
+                    return Err(Error::new(
+                        LEBEN_ERR_INVALID_ARGUMENT,
+                        format!("unknown register {}", register_id),
+                    ));
+                }
+            };
+            let out = unsafe { out.as_mut() }.ok_or_else(|| Error::null("out"))?;
+            *out = machine.register_8(register);
+            Ok(LEBEN_OK)
+        },
+    )
+}
+
+/// Store the flags byte, in the layout pushed by `PUSH PSW`, in `out`.
+///
+/// # Safety
+/// `machine` must be a live machine and `out` valid for a write of one byte.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_get_flags(machine: *const LebenMachine, out: *mut u8) -> i32 {
+    guard(
+        |code| code,
+        || {
+            let machine = unsafe { machine_ref(machine) }?;
+            let out = unsafe { out.as_mut() }.ok_or_else(|| Error::null("out"))?;
+            *out = machine.get_status_word().low;
+            Ok(LEBEN_OK)
+        },
+    )
+}
+
+/// The program counter. Returns 0 if `machine` is null.
+///
+/// # Safety
+/// `machine` must be null or a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_get_pc(machine: *const LebenMachine) -> u16 {
+    guard(|_| 0, || Ok(unsafe { machine_ref(machine) }?.pc().value()))
+}
+
+/// The stack pointer. Returns 0 if `machine` is null.
+///
+/// # Safety
+/// `machine` must be null or a live machine.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_get_sp(machine: *const LebenMachine) -> u16 {
+    guard(
+        |_| 0,
+        || {
+            let machine = unsafe { machine_ref(machine) }?;
+            Ok(machine.register_16(RegisterPair::Sp).value())
+        },
+    )
+}
+
+/// Copy `len` bytes of memory starting at `address` into `buf`.
+///
+/// # Safety
+/// `machine` must be a live machine and `buf` valid for writes of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_peek(
+    machine: *const LebenMachine,
+    address: u16,
+    buf: *mut u8,
+    len: usize,
+) -> i32 {
+    guard(
+        |code| code,
+        || {
+            let machine = unsafe { machine_ref(machine) }?;
+            let start = address as usize;
+            let memory = machine
+                .memory()
+                .as_raw()
+                .get(start..start.saturating_add(len))
+                .ok_or_else(|| past_end(address, len))?;
+            if len == 0 {
+                return Ok(LEBEN_OK);
+            }
+            if buf.is_null() {
+                return Err(Error::null("buf"));
+            }
+            let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+            buf.copy_from_slice(memory);
+            Ok(LEBEN_OK)
+        },
+    )
+}
+
+/// Copy `len` bytes from `buf` into memory starting at `address`.
+///
+/// # Safety
+/// `machine` must be a live machine and `buf` valid for reads of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_poke(
+    machine: *mut LebenMachine,
+    address: u16,
+    buf: *const u8,
+    len: usize,
+) -> i32 {
+    guard(
+        |code| code,
+        || {
+            let machine = unsafe { machine_mut(machine) }?;
+            let bytes = unsafe { input_slice(buf, len, "buf") }?;
+            machine
+                .memory_mut()
+                .write_slice(address, bytes)
+                .ok_or_else(|| past_end(address, len))?;
+            Ok(LEBEN_OK)
+        },
+    )
+}
+
+/// Queue `len` bytes to be read by `IN 0`. Reading from an empty queue halts the machine.
+///
+/// # Safety
+/// `machine` must be a live machine and `bytes` valid for reads of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_push_input(
+    machine: *mut LebenMachine,
+    bytes: *const u8,
+    len: usize,
+) -> i32 {
+    guard(
+        |code| code,
+        || {
+            let machine = unsafe { machine_mut(machine) }?;
+            let bytes = unsafe { input_slice(bytes, len, "bytes") }?;
+            machine.push_input(bytes);
+            Ok(LEBEN_OK)
+        },
+    )
+}
+
+/// Move up to `capacity` bytes of program output into `buf`, oldest first. Returns the number of
+/// bytes written; output that didn't fit is kept for the next call.
+///
+/// # Safety
+/// `machine` must be a live machine and `buf` valid for writes of `capacity` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn leben_drain_output(
+    machine: *mut LebenMachine,
+    buf: *mut u8,
+    capacity: usize,
+) -> i64 {
+    guard(
+        |code| code as i64,
+        || {
+            let machine = unsafe { machine_mut(machine) }?;
+            let len = capacity.min(machine.output.len());
+            if len == 0 {
+                return Ok(0);
+            }
+            if buf.is_null() {
+                return Err(Error::null("buf"));
+            }
+            let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+            for (dst, src) in buf.iter_mut().zip(machine.output.drain(..len)) {
+                *dst = src;
+            }
+            Ok(len as i64)
+        },
+    )
+}
+
+/// Message describing the last error on this thread, or null if there was none. The string is
+/// owned by the library and valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn leben_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
@@ -0,0 +1,63 @@
+//! Entry points of the fuzz targets in `fuzz/`, shared with `tests/fuzz_regressions.rs` so inputs
+//! found by the fuzzer are replayed as ordinary tests. Each function panics if an invariant
+//! doesn't hold. Not part of the stable API.
+
+use crate::{
+    assembler,
+    coding::{self, reader::Reader},
+};
+
+/// Assemble `source`. The assembler must not panic, and an assembled program must fit in memory
+/// after its origin.
+pub fn assemble(source: &[u8]) {
+    let Ok((items, origin)) = assembler::parse_assembly(source) else {
+        return;
+    };
+    let mut bytes = Vec::new();
+    coding::encode_program(&mut bytes, &items).expect("writing to Vec can't error");
+    assert!(
+        origin as usize + bytes.len() <= 0x10000,
+        "{} bytes assembled at 0x{:04X} don't fit in memory",
+        bytes.len(),
+        origin
+    );
+}
+
+/// Decode `bytes` like a disassembler, skipping a byte whenever no instruction can be decoded.
+/// The decoder must not panic, must consume exactly the length of the decoded instruction, and
+/// must consume nothing when it fails.
+pub fn decode(bytes: &[u8]) {
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        let mut reader = Reader::new(rest);
+        let Some(instruction) = coding::decode(&mut reader) else {
+            assert_eq!(
+                reader.read_amount_bytes(),
+                0,
+                "failed decode at offset {} consumed bytes",
+                offset
+            );
+            offset += 1;
+            continue;
+        };
+
+        let consumed = reader.read_amount_bytes();
+        assert_eq!(
+            consumed,
+            instruction.length() as usize,
+            "decoding {:?} at offset {} consumed the wrong number of bytes",
+            instruction,
+            offset
+        );
+        let mut encoded = [0; 3];
+        let length = coding::encode_into(&mut encoded, instruction)
+            .expect("instructions are at most 3 bytes");
+        assert_eq!(
+            length, consumed,
+            "{:?} decoded from {} bytes encodes to {}",
+            instruction, consumed, length
+        );
+        offset += consumed;
+    }
+}
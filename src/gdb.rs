@@ -0,0 +1,436 @@
+//! Minimal GDB remote serial protocol (RSP) server.
+//!
+//! GDB has no built-in 8080 target, so the stub describes its registers with a target description
+//! (see [`TARGET_XML`]) which GDB fetches through `qXfer:features:read`. Registers are numbered
+//! and sent in `g`/`G` packets in this order:
+//!
+//! | Number | Name | Size    |
+//! |--------|------|---------|
+//! | 0      | a    | 8 bits  |
+//! | 1      | f    | 8 bits  |
+//! | 2      | b    | 8 bits  |
+//! | 3      | c    | 8 bits  |
+//! | 4      | d    | 8 bits  |
+//! | 5      | e    | 8 bits  |
+//! | 6      | h    | 8 bits  |
+//! | 7      | l    | 8 bits  |
+//! | 8      | sp   | 16 bits |
+//! | 9      | pc   | 16 bits |
+//!
+//! `f` is the flags byte as pushed by `PUSH PSW`. 16-bit registers are sent little-endian, like
+//! everything else in RSP.
+//!
+//! Supported packets are `?`, `g`, `G`, `p`, `P`, `m`, `M`, `c`, `s`, `Z0`/`z0` (`Z1`/`z1` are
+//! treated the same), `D`, `k`, `qSupported`, `qAttached` and `qXfer:features:read`. Anything else
+//! gets the empty "unsupported" reply. Breakpoints are the machine's own, see
+//! [`Machine::add_breakpoint`], so they stay set in the returned machine.
+
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    instruction::{Address, Data16, Register, RegisterPair},
+    machine::{HaltReason, Machine, MachineState, RunOutcome},
+};
+
+/// Target description sent to GDB, see the module documentation for the register layout.
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.leben.i8080.core">
+    <reg name="a" bitsize="8" regnum="0" type="uint8"/>
+    <reg name="f" bitsize="8" regnum="1" type="uint8"/>
+    <reg name="b" bitsize="8" regnum="2" type="uint8"/>
+    <reg name="c" bitsize="8" regnum="3" type="uint8"/>
+    <reg name="d" bitsize="8" regnum="4" type="uint8"/>
+    <reg name="e" bitsize="8" regnum="5" type="uint8"/>
+    <reg name="h" bitsize="8" regnum="6" type="uint8"/>
+    <reg name="l" bitsize="8" regnum="7" type="uint8"/>
+    <reg name="sp" bitsize="16" regnum="8" type="data_ptr"/>
+    <reg name="pc" bitsize="16" regnum="9" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// Number of instructions executed by `c` between checks for an interrupt from GDB.
+const INTERRUPT_CHECK_INTERVAL: u64 = 1024;
+
+const INTERRUPT: u8 = 0x03;
+
+/// 8-bit registers in the order of the register numbers.
+const REGISTERS_8: [Register; 7] = [
+    Register::B,
+    Register::C,
+    Register::D,
+    Register::E,
+    Register::H,
+    Register::L,
+    Register::A,
+];
+
+/// Wait for a single GDB connection on `listener` and serve it until GDB detaches, kills the
+/// program or disconnects. Returns the machine in its final state.
+pub fn serve(machine: Machine, listener: &TcpListener) -> anyhow::Result<Machine> {
+    let (stream, _) = listener.accept()?;
+    let mut session = Session::new(machine, stream)?;
+    session.run()?;
+    Ok(session.machine)
+}
+
+enum Action {
+    Reply(String),
+    /// Reply and end the session.
+    ReplyAndClose(String),
+    /// End the session without replying.
+    Close,
+}
+
+struct Session {
+    machine: Machine,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Session {
+    fn new(machine: Machine, stream: TcpStream) -> io::Result<Self> {
+        let writer = stream.try_clone()?;
+        Ok(Self {
+            machine,
+            reader: BufReader::new(stream),
+            writer,
+        })
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet) {
+                Action::Reply(reply) => self.write_packet(&reply)?,
+                Action::ReplyAndClose(reply) => {
+                    self.write_packet(&reply)?;
+                    break;
+                }
+                Action::Close => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let buf = self.reader.fill_buf()?;
+        let Some(&byte) = buf.first() else {
+            return Ok(None);
+        };
+        self.reader.consume(1);
+        Ok(Some(byte))
+    }
+
+    /// Read the next packet, acknowledging it. An interrupt outside of a packet is returned as a
+    /// packet consisting of just the interrupt byte. Returns `None` when GDB disconnects.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(INTERRUPT) => return Ok(Some(String::from("\x03"))),
+                Some(b'$') => {}
+                // Acknowledgements of our packets, and any noise between packets.
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in checksum.iter_mut() {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(byte) => *digit = byte,
+                }
+            }
+
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            if expected == Some(checksum_of(&data)) {
+                self.writer.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            } else {
+                self.writer.write_all(b"-")?;
+            }
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        write!(
+            self.writer,
+            "${}#{:02x}",
+            data,
+            checksum_of(data.as_bytes())
+        )?;
+        self.writer.flush()
+    }
+
+    fn handle(&mut self, packet: &str) -> Action {
+        let reply = match packet.as_bytes().first() {
+            Some(&INTERRUPT) | Some(b'?') => self.stop_reply(),
+            Some(b'g') => self.read_registers(),
+            Some(b'G') => ok_or_error(self.write_registers(&packet[1..])),
+            Some(b'p') => self
+                .read_register(&packet[1..])
+                .unwrap_or_else(|| String::from("E01")),
+            Some(b'P') => ok_or_error(self.write_register(&packet[1..])),
+            Some(b'm') => self
+                .read_memory(&packet[1..])
+                .unwrap_or_else(|| String::from("E01")),
+            Some(b'M') => ok_or_error(self.write_memory(&packet[1..])),
+            Some(b'c') => match self.resume(&packet[1..], false) {
+                Ok(reply) => reply,
+                Err(_) => return Action::Close,
+            },
+            Some(b's') => match self.resume(&packet[1..], true) {
+                Ok(reply) => reply,
+                Err(_) => return Action::Close,
+            },
+            Some(b'Z') => ok_or_error(self.breakpoint(&packet[1..], true)),
+            Some(b'z') => ok_or_error(self.breakpoint(&packet[1..], false)),
+            Some(b'D') => return Action::ReplyAndClose(String::from("OK")),
+            Some(b'k') => return Action::Close,
+            Some(b'H') => String::from("OK"),
+            Some(b'q') => self.query(&packet[1..]),
+            _ => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    fn stop_reply(&self) -> String {
+        String::from(match self.machine.state() {
+            MachineState::Running | MachineState::WaitingForInput => "S05",
+            MachineState::Halted(HaltReason::HaltInstruction | HaltReason::SelfJump) => "W00",
+            MachineState::Halted(HaltReason::InvalidInstruction) => "S04",
+            MachineState::Halted(_) => "S0b",
+        })
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            String::from("PacketSize=1000;qXfer:features:read+")
+        } else if query == "Attached" {
+            String::from("1")
+        } else if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_pair(range, ',') else {
+                return String::from("E01");
+            };
+            let bytes = TARGET_XML.as_bytes();
+            let start = (offset as usize).min(bytes.len());
+            let end = start.saturating_add(length as usize).min(bytes.len());
+            let prefix = if end == bytes.len() { 'l' } else { 'm' };
+            format!("{}{}", prefix, String::from_utf8_lossy(&bytes[start..end]))
+        } else {
+            String::new()
+        }
+    }
+
+    fn register_bytes(&self, number: usize) -> Option<Vec<u8>> {
+        let bytes = match number {
+            0 => vec![self.machine.register_8(Register::A)],
+            1 => vec![self.machine.get_status_word().low],
+            2..=7 => vec![self.machine.register_8(REGISTERS_8[number - 2])],
+            8 => {
+                let sp = self.machine.register_16(RegisterPair::Sp);
+                vec![sp.low, sp.high]
+            }
+            9 => {
+                let pc = self.machine.pc();
+                vec![pc.low, pc.high]
+            }
+            _ => return None,
+        };
+        Some(bytes)
+    }
+
+    fn set_register_bytes(&mut self, number: usize, bytes: &[u8]) -> Option<()> {
+        match (number, bytes) {
+            (0, &[value]) => self.machine.set_register_8(Register::A, value),
+            (1, &[value]) => {
+                let a = self.machine.register_8(Register::A);
+                self.machine.set_status_word(Data16::new(value, a));
+            }
+            (2..=7, &[value]) => self.machine.set_register_8(REGISTERS_8[number - 2], value),
+            (8, &[low, high]) => self
+                .machine
+                .set_register_16(RegisterPair::Sp, Data16::new(low, high)),
+            (9, &[low, high]) => self.machine.set_pc(Data16::new(low, high)),
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn read_registers(&self) -> String {
+        (0..10)
+            .filter_map(|number| self.register_bytes(number))
+            .map(|bytes| encode_hex(&bytes))
+            .collect()
+    }
+
+    fn write_registers(&mut self, data: &str) -> Option<()> {
+        let bytes = decode_hex(data)?;
+        if bytes.len() != 12 {
+            return None;
+        }
+        for number in 0..8 {
+            self.set_register_bytes(number, &bytes[number..number + 1])?;
+        }
+        self.set_register_bytes(8, &bytes[8..10])?;
+        self.set_register_bytes(9, &bytes[10..12])
+    }
+
+    fn read_register(&self, args: &str) -> Option<String> {
+        let number = usize::from_str_radix(args, 16).ok()?;
+        self.register_bytes(number).map(|bytes| encode_hex(&bytes))
+    }
+
+    fn write_register(&mut self, args: &str) -> Option<()> {
+        let (number, value) = args.split_once('=')?;
+        let number = usize::from_str_radix(number, 16).ok()?;
+        self.set_register_bytes(number, &decode_hex(value)?)
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (address, length) = parse_pair(args, ',')?;
+        let memory = &self.machine.memory().as_raw()[address as usize..];
+        Some(encode_hex(&memory[..(length as usize).min(memory.len())]))
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<()> {
+        let (range, data) = args.split_once(':')?;
+        let (address, length) = parse_pair(range, ',')?;
+        let bytes = decode_hex(data)?;
+        if bytes.len() != length as usize {
+            return None;
+        }
+        self.machine.memory_mut().write_slice(address, &bytes)
+    }
+
+    fn breakpoint(&mut self, args: &str, insert: bool) -> Option<()> {
+        let mut parts = args.split(',');
+        let kind = parts.next()?;
+        if kind != "0" && kind != "1" {
+            return None;
+        }
+        let address = Address::from_str_radix(parts.next()?, 16).ok()?;
+        if insert {
+            self.machine.add_breakpoint(address);
+        } else {
+            self.machine.remove_breakpoint(address);
+        }
+        Some(())
+    }
+
+    /// Continue or single-step, optionally from a new address, and return the stop reply.
+    fn resume(&mut self, args: &str, single_step: bool) -> io::Result<String> {
+        if !args.is_empty() {
+            match Address::from_str_radix(args, 16) {
+                Ok(address) => self.machine.set_pc(address.into()),
+                Err(_) => return Ok(String::from("E01")),
+            }
+        }
+
+        if single_step {
+            let _ = self.machine.step();
+            return Ok(self.stop_reply());
+        }
+
+        loop {
+            match self.machine.run_until_halt(INTERRUPT_CHECK_INTERVAL) {
+                (RunOutcome::Breakpoint(_), _) => return Ok(String::from("S05")),
+                (RunOutcome::BudgetExhausted, _) => {
+                    if self.poll_interrupt()? {
+                        return Ok(String::from("S02"));
+                    }
+                }
+                _ => return Ok(self.stop_reply()),
+            }
+        }
+    }
+
+    /// Check without blocking whether GDB has sent an interrupt.
+    fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let result = match self.reader.fill_buf() {
+            Ok(buf) => Ok(buf.contains(&INTERRUPT)),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        };
+        self.reader.get_ref().set_nonblocking(false)?;
+
+        if result.as_ref().is_ok_and(|interrupted| *interrupted) {
+            let position = self
+                .reader
+                .buffer()
+                .iter()
+                .position(|&byte| byte == INTERRUPT)
+                .unwrap_or(0);
+            self.reader.consume(position + 1);
+        }
+        result
+    }
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    match result {
+        Some(()) => String::from("OK"),
+        None => String::from("E01"),
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn parse_pair(text: &str, separator: char) -> Option<(Address, u32)> {
+    let (first, second) = text.split_once(separator)?;
+    Some((
+        Address::from_str_radix(first, 16).ok()?,
+        u32::from_str_radix(second, 16).ok()?,
+    ))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(checksum_of(b"g"), 0x67);
+        assert_eq!(checksum_of(b"qAttached"), 0x8f);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = [0x00, 0x2a, 0xff];
+        assert_eq!(encode_hex(&bytes), "002aff");
+        assert_eq!(decode_hex("002aff"), Some(bytes.to_vec()));
+        assert_eq!(decode_hex("2"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+}
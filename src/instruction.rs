@@ -1,25 +1,33 @@
-use std::{fmt::Display, ops::{Add, Sub}};
+use alloc::{boxed::Box, format};
+use core::{fmt::Display, ops::{Add, Sub}};
 
+#[cfg(feature = "std")]
 use parsable::Parsable;
 
+mod opcodes;
+
+pub use opcodes::OPCODES;
+
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Parsable)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "std", derive(Parsable))]
 pub enum Register {
-    #[literal = b"A"]
+    #[cfg_attr(feature = "std", literal = b"A")]
     A = 0b111,
-    #[literal = b"B"]
+    #[cfg_attr(feature = "std", literal = b"B")]
     B = 0b000,
-    #[literal = b"C"]
+    #[cfg_attr(feature = "std", literal = b"C")]
     C = 0b001,
-    #[literal = b"D"]
+    #[cfg_attr(feature = "std", literal = b"D")]
     D = 0b010,
-    #[literal = b"E"]
+    #[cfg_attr(feature = "std", literal = b"E")]
     E = 0b011,
-    #[literal = b"H"]
+    #[cfg_attr(feature = "std", literal = b"H")]
     H = 0b100,
-    #[literal = b"L"]
+    #[cfg_attr(feature = "std", literal = b"L")]
     L = 0b101,
-    #[literal = b"M"]
+    #[cfg_attr(feature = "std", literal = b"M")]
     M = 0b110,
 }
 
@@ -39,7 +47,7 @@ impl Register {
 }
 
 impl Display for Register {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_str(match self {
             Register::B => "B",
             Register::C => "C",
@@ -71,15 +79,17 @@ impl TryFrom<u8> for Register {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Parsable)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "std", derive(Parsable))]
 pub enum RegisterPair {
-    #[literal = b"B"]
+    #[cfg_attr(feature = "std", literal = b"B")]
     Bc = 0b00,
-    #[literal = b"D"]
+    #[cfg_attr(feature = "std", literal = b"D")]
     De = 0b01,
-    #[literal = b"H"]
+    #[cfg_attr(feature = "std", literal = b"H")]
     Hl = 0b10,
-    #[literal = b"SP"]
+    #[cfg_attr(feature = "std", literal = b"SP")]
     Sp = 0b11,
 }
 
@@ -95,7 +105,7 @@ impl RegisterPair {
 }
 
 impl Display for RegisterPair {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.write_str(match self {
             RegisterPair::Bc => "BC",
             RegisterPair::De => "DE",
@@ -119,11 +129,13 @@ impl TryFrom<u8> for RegisterPair {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Parsable)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "std", derive(Parsable))]
 pub enum RegisterPairIndirect {
-    #[literal = b"B"]
+    #[cfg_attr(feature = "std", literal = b"B")]
     Bc = 0b00,
-    #[literal = b"D"]
+    #[cfg_attr(feature = "std", literal = b"D")]
     De = 0b01,
 }
 
@@ -155,15 +167,17 @@ impl TryFrom<u8> for RegisterPairIndirect {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, Parsable)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "std", derive(Parsable))]
 pub enum RegisterPairOrStatus {
-    #[literal = b"B"]
+    #[cfg_attr(feature = "std", literal = b"B")]
     Bc = 0b00,
-    #[literal = b"D"]
+    #[cfg_attr(feature = "std", literal = b"D")]
     De = 0b01,
-    #[literal = b"H"]
+    #[cfg_attr(feature = "std", literal = b"H")]
     Hl = 0b10,
-    #[literal = b"PSW"]
+    #[cfg_attr(feature = "std", literal = b"PSW")]
     StatusWord = 0b11,
 }
 
@@ -203,6 +217,7 @@ impl TryFrom<u8> for RegisterPairOrStatus {
 pub type Data8 = u8;
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Data16 {
     pub low: Data8,
     pub high: Data8,
@@ -268,6 +283,7 @@ pub type Address = u16;
 
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Condition {
     Carry = 0b011,
     NoCarry = 0b10,
@@ -300,6 +316,7 @@ pub type Port = Data8;
 
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RestartNumber {
     R0 = 0b000,
     R1 = 0b001,
@@ -343,6 +360,7 @@ impl TryFrom<u8> for RestartNumber {
     }
 }
 
+/// An item of an assembled program, encoded with [`encode_program`](crate::coding::encode_program).
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum InstructionOrData {
     Instruction(Instruction),
@@ -350,7 +368,28 @@ pub enum InstructionOrData {
     Slice(Box<[u8]>),
 }
 
+impl InstructionOrData {
+    /// Number of bytes the item is encoded as.
+    pub fn length(&self) -> usize {
+        match self {
+            InstructionOrData::Instruction(instruction) => instruction.length() as usize,
+            InstructionOrData::Byte(_) => 1,
+            InstructionOrData::Slice(slice) => slice.len(),
+        }
+    }
+}
+
+/// The processor whose instruction set is decoded. The 8085 runs 8080 programs unchanged, and adds
+/// `RIM` and `SIM` at the opcodes 0x20 and 0x30, which the 8080 doesn't document.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum Variant {
+    #[default]
+    Intel8080,
+    Intel8085,
+}
+
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     // Data Transfer Group
     /// Move register / Move from memory / Move to memory
@@ -471,6 +510,10 @@ pub enum Instruction {
     Ei,
     /// Disable interrupts
     Di,
+    /// Read interrupt mask, 8085 only
+    Rim,
+    /// Set interrupt mask, 8085 only
+    Sim,
     /// Halt
     Hlt,
     /// No op
@@ -478,7 +521,8 @@ pub enum Instruction {
 }
 
 impl Instruction {
-    pub fn byte_length(&self) -> u16 {
+    /// Length of the instruction in bytes, including the opcode.
+    pub const fn length(&self) -> u8 {
         match self {
             Instruction::Mov(..) => 1,
             Instruction::Mvi(..) => 2,
@@ -535,8 +579,331 @@ impl Instruction {
             Instruction::Out(..) => 2,
             Instruction::Ei => 1,
             Instruction::Di => 1,
+            Instruction::Rim => 1,
+            Instruction::Sim => 1,
             Instruction::Hlt => 1,
             Instruction::Nop => 1,
         }
     }
+
+    /// Number of clock periods (T-states) the instruction takes, as listed in the Intel 8080
+    /// datasheet. Conditional calls and returns take longer when the condition holds; the second
+    /// value is their duration then.
+    pub const fn cycles(&self) -> (u8, Option<u8>) {
+        match self {
+            Instruction::Mov(Register::M, _) | Instruction::Mov(_, Register::M) => (7, None),
+            Instruction::Mov(..) => (5, None),
+            Instruction::Mvi(Register::M, _) => (10, None),
+            Instruction::Mvi(..) => (7, None),
+            Instruction::Lxi(..) => (10, None),
+            Instruction::Lda(..) => (13, None),
+            Instruction::Sta(..) => (13, None),
+            Instruction::Lhld(..) => (16, None),
+            Instruction::Shld(..) => (16, None),
+            Instruction::Ldax(..) => (7, None),
+            Instruction::Stax(..) => (7, None),
+            Instruction::Xchg => (4, None),
+            Instruction::Add(Register::M)
+            | Instruction::Adc(Register::M)
+            | Instruction::Sub(Register::M)
+            | Instruction::Sbb(Register::M)
+            | Instruction::Ana(Register::M)
+            | Instruction::Xra(Register::M)
+            | Instruction::Ora(Register::M)
+            | Instruction::Cmp(Register::M) => (7, None),
+            Instruction::Add(..)
+            | Instruction::Adc(..)
+            | Instruction::Sub(..)
+            | Instruction::Sbb(..)
+            | Instruction::Ana(..)
+            | Instruction::Xra(..)
+            | Instruction::Ora(..)
+            | Instruction::Cmp(..) => (4, None),
+            Instruction::Adi(..)
+            | Instruction::Aci(..)
+            | Instruction::Sui(..)
+            | Instruction::Sbi(..)
+            | Instruction::Ani(..)
+            | Instruction::Xri(..)
+            | Instruction::Ori(..)
+            | Instruction::Cpi(..) => (7, None),
+            Instruction::Inr(Register::M) | Instruction::Dcr(Register::M) => (10, None),
+            Instruction::Inr(..) | Instruction::Dcr(..) => (5, None),
+            Instruction::Inx(..) => (5, None),
+            Instruction::Dcx(..) => (5, None),
+            Instruction::Dad(..) => (10, None),
+            Instruction::Daa => (4, None),
+            Instruction::Rlc => (4, None),
+            Instruction::Rrc => (4, None),
+            Instruction::Ral => (4, None),
+            Instruction::Rar => (4, None),
+            Instruction::Cma => (4, None),
+            Instruction::Cmc => (4, None),
+            Instruction::Stc => (4, None),
+            Instruction::Jmp(..) => (10, None),
+            Instruction::Jcc(..) => (10, None),
+            Instruction::Call(..) => (17, None),
+            Instruction::Ccc(..) => (11, Some(17)),
+            Instruction::Ret => (10, None),
+            Instruction::Rcc(..) => (5, Some(11)),
+            Instruction::Rst(..) => (11, None),
+            Instruction::Pchl => (5, None),
+            Instruction::Push(..) => (11, None),
+            Instruction::Pop(..) => (10, None),
+            Instruction::Xthl => (18, None),
+            Instruction::Sphl => (5, None),
+            Instruction::In(..) => (10, None),
+            Instruction::Out(..) => (10, None),
+            Instruction::Ei => (4, None),
+            Instruction::Di => (4, None),
+            Instruction::Rim => (4, None),
+            Instruction::Sim => (4, None),
+            Instruction::Hlt => (7, None),
+            Instruction::Nop => (4, None),
+        }
+    }
+}
+
+/// Write a number in Intel hex notation, e.g. `2AH` or `0FF00H`. A leading zero is added when the
+/// first digit is a letter so the operand can't be mistaken for a label.
+pub(crate) fn write_hex(
+    f: &mut core::fmt::Formatter<'_>,
+    value: u16,
+    width: usize,
+) -> core::fmt::Result {
+    let digits = format!("{:0width$X}", value, width = width);
+    if digits.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        f.write_str("0")?;
+    }
+    write!(f, "{}H", digits)
+}
+
+impl Condition {
+    /// Suffix used in mnemonics of conditional instructions, e.g. the `NZ` of `JNZ`.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Condition::Carry => "C",
+            Condition::NoCarry => "NC",
+            Condition::Zero => "Z",
+            Condition::NoZero => "NZ",
+            Condition::Positive => "P",
+            Condition::Minus => "M",
+            Condition::ParityEven => "PE",
+            Condition::ParityOdd => "PO",
+        }
+    }
+}
+
+/// Name of a register pair as an instruction operand, which is the name of its high register.
+fn pair_operand(pair: RegisterPair) -> &'static str {
+    match pair {
+        RegisterPair::Bc => "B",
+        RegisterPair::De => "D",
+        RegisterPair::Hl => "H",
+        RegisterPair::Sp => "SP",
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Instruction::Mov(dst, src) => write!(f, "MOV {},{}", dst, src),
+            Instruction::Mvi(dst, data) => {
+                write!(f, "MVI {},", dst)?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Lxi(pair, data) => {
+                write!(f, "LXI {},", pair_operand(pair))?;
+                write_hex(f, data.value(), 4)
+            }
+            Instruction::Lda(address) => {
+                f.write_str("LDA ")?;
+                write_hex(f, address, 4)
+            }
+            Instruction::Sta(address) => {
+                f.write_str("STA ")?;
+                write_hex(f, address, 4)
+            }
+            Instruction::Lhld(address) => {
+                f.write_str("LHLD ")?;
+                write_hex(f, address, 4)
+            }
+            Instruction::Shld(address) => {
+                f.write_str("SHLD ")?;
+                write_hex(f, address, 4)
+            }
+            Instruction::Ldax(pair) => write!(f, "LDAX {}", pair_operand(pair.to_register_pair())),
+            Instruction::Stax(pair) => write!(f, "STAX {}", pair_operand(pair.to_register_pair())),
+            Instruction::Xchg => f.write_str("XCHG"),
+            Instruction::Add(register) => write!(f, "ADD {}", register),
+            Instruction::Adi(data) => {
+                f.write_str("ADI ")?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Adc(register) => write!(f, "ADC {}", register),
+            Instruction::Aci(data) => {
+                f.write_str("ACI ")?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Sub(register) => write!(f, "SUB {}", register),
+            Instruction::Sui(data) => {
+                f.write_str("SUI ")?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Sbb(register) => write!(f, "SBB {}", register),
+            Instruction::Sbi(data) => {
+                f.write_str("SBI ")?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Inr(register) => write!(f, "INR {}", register),
+            Instruction::Dcr(register) => write!(f, "DCR {}", register),
+            Instruction::Inx(pair) => write!(f, "INX {}", pair_operand(pair)),
+            Instruction::Dcx(pair) => write!(f, "DCX {}", pair_operand(pair)),
+            Instruction::Dad(pair) => write!(f, "DAD {}", pair_operand(pair)),
+            Instruction::Daa => f.write_str("DAA"),
+            Instruction::Ana(register) => write!(f, "ANA {}", register),
+            Instruction::Ani(data) => {
+                f.write_str("ANI ")?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Xra(register) => write!(f, "XRA {}", register),
+            Instruction::Xri(data) => {
+                f.write_str("XRI ")?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Ora(register) => write!(f, "ORA {}", register),
+            Instruction::Ori(data) => {
+                f.write_str("ORI ")?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Cmp(register) => write!(f, "CMP {}", register),
+            Instruction::Cpi(data) => {
+                f.write_str("CPI ")?;
+                write_hex(f, data as u16, 2)
+            }
+            Instruction::Rlc => f.write_str("RLC"),
+            Instruction::Rrc => f.write_str("RRC"),
+            Instruction::Ral => f.write_str("RAL"),
+            Instruction::Rar => f.write_str("RAR"),
+            Instruction::Cma => f.write_str("CMA"),
+            Instruction::Cmc => f.write_str("CMC"),
+            Instruction::Stc => f.write_str("STC"),
+            Instruction::Jmp(address) => {
+                f.write_str("JMP ")?;
+                write_hex(f, address, 4)
+            }
+            Instruction::Jcc(condition, address) => {
+                write!(f, "J{} ", condition.suffix())?;
+                write_hex(f, address, 4)
+            }
+            Instruction::Call(address) => {
+                f.write_str("CALL ")?;
+                write_hex(f, address, 4)
+            }
+            Instruction::Ccc(condition, address) => {
+                write!(f, "C{} ", condition.suffix())?;
+                write_hex(f, address, 4)
+            }
+            Instruction::Ret => f.write_str("RET"),
+            Instruction::Rcc(condition) => write!(f, "R{}", condition.suffix()),
+            Instruction::Rst(number) => write!(f, "RST {}", u16::from(number)),
+            Instruction::Pchl => f.write_str("PCHL"),
+            Instruction::Push(pair) => match pair.to_register_pair() {
+                Some(pair) => write!(f, "PUSH {}", pair_operand(pair)),
+                None => f.write_str("PUSH PSW"),
+            },
+            Instruction::Pop(pair) => match pair.to_register_pair() {
+                Some(pair) => write!(f, "POP {}", pair_operand(pair)),
+                None => f.write_str("POP PSW"),
+            },
+            Instruction::Xthl => f.write_str("XTHL"),
+            Instruction::Sphl => f.write_str("SPHL"),
+            Instruction::In(port) => {
+                f.write_str("IN ")?;
+                write_hex(f, port as u16, 2)
+            }
+            Instruction::Out(port) => {
+                f.write_str("OUT ")?;
+                write_hex(f, port as u16, 2)
+            }
+            Instruction::Ei => f.write_str("EI"),
+            Instruction::Di => f.write_str("DI"),
+            Instruction::Rim => f.write_str("RIM"),
+            Instruction::Sim => f.write_str("SIM"),
+            Instruction::Hlt => f.write_str("HLT"),
+            Instruction::Nop => f.write_str("NOP"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles() {
+        let cases = [
+            (Instruction::Mov(Register::A, Register::B), (5, None)),
+            (Instruction::Mov(Register::M, Register::B), (7, None)),
+            (Instruction::Mov(Register::A, Register::M), (7, None)),
+            (Instruction::Mvi(Register::M, 0x2A), (10, None)),
+            (Instruction::Lhld(0x0100), (16, None)),
+            (Instruction::Add(Register::C), (4, None)),
+            (Instruction::Sbb(Register::M), (7, None)),
+            (Instruction::Cpi(0x01), (7, None)),
+            (Instruction::Inr(Register::M), (10, None)),
+            (Instruction::Dad(RegisterPair::Hl), (10, None)),
+            (Instruction::Jcc(Condition::Zero, 0x0100), (10, None)),
+            (Instruction::Call(0x0100), (17, None)),
+            (Instruction::Ccc(Condition::Carry, 0x0100), (11, Some(17))),
+            (Instruction::Rcc(Condition::Minus), (5, Some(11))),
+            (Instruction::Push(RegisterPairOrStatus::StatusWord), (11, None)),
+            (Instruction::Xthl, (18, None)),
+            (Instruction::Hlt, (7, None)),
+        ];
+        for (instruction, cycles) in cases {
+            assert_eq!(instruction.cycles(), cycles, "{}", instruction);
+        }
+    }
+
+    #[test]
+    fn display_every_group() {
+        let cases = [
+            // Data transfer
+            (Instruction::Mov(Register::A, Register::B), "MOV A,B"),
+            (Instruction::Mvi(Register::M, 0x2A), "MVI M,2AH"),
+            (Instruction::Lxi(RegisterPair::Sp, Data16::new(0x00, 0xFF)), "LXI SP,0FF00H"),
+            (Instruction::Lda(0x0100), "LDA 0100H"),
+            (Instruction::Stax(RegisterPairIndirect::De), "STAX D"),
+            (Instruction::Xchg, "XCHG"),
+            // Arithmetic
+            (Instruction::Adi(0xC0), "ADI 0C0H"),
+            (Instruction::Sbb(Register::M), "SBB M"),
+            (Instruction::Dad(RegisterPair::Hl), "DAD H"),
+            (Instruction::Daa, "DAA"),
+            // Logical
+            (Instruction::Ana(Register::C), "ANA C"),
+            (Instruction::Cpi(0x0A), "CPI 0AH"),
+            (Instruction::Ral, "RAL"),
+            (Instruction::Cma, "CMA"),
+            // Branch
+            (Instruction::Jmp(0x1234), "JMP 1234H"),
+            (Instruction::Jcc(Condition::NoZero, 0x0008), "JNZ 0008H"),
+            (Instruction::Ccc(Condition::Carry, 0xABCD), "CC 0ABCDH"),
+            (Instruction::Rcc(Condition::ParityEven), "RPE"),
+            (Instruction::Rst(RestartNumber::R7), "RST 7"),
+            (Instruction::Pchl, "PCHL"),
+            // Stack, I/O and machine control
+            (Instruction::Push(RegisterPairOrStatus::StatusWord), "PUSH PSW"),
+            (Instruction::Pop(RegisterPairOrStatus::Bc), "POP B"),
+            (Instruction::Out(0x01), "OUT 01H"),
+            (Instruction::In(0xFF), "IN 0FFH"),
+            (Instruction::Ei, "EI"),
+            (Instruction::Sim, "SIM"),
+            (Instruction::Hlt, "HLT"),
+        ];
+        for (instruction, text) in cases {
+            assert_eq!(instruction.to_string(), text, "{:?}", instruction);
+        }
+    }
 }
@@ -0,0 +1,310 @@
+//! Metadata of the 256 opcodes, indexed by the first byte of an instruction.
+
+/// What the first byte of an instruction stands for.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    /// The instruction with its operands, e.g. `MVI B,d8`. `d8` and `d16` stand for immediate
+    /// data and `a16` for an address.
+    pub mnemonic: &'static str,
+    /// Length of the instruction in bytes, including the opcode.
+    pub length: u8,
+    /// Whether Intel documents the opcode. The undocumented ones behave like `NOP`, `JMP`, `RET`
+    /// or `CALL` on real hardware.
+    pub documented: bool,
+}
+
+impl OpcodeInfo {
+    const fn new(mnemonic: &'static str, length: u8) -> Self {
+        Self {
+            mnemonic,
+            length,
+            documented: true,
+        }
+    }
+
+    const fn undocumented(mnemonic: &'static str, length: u8) -> Self {
+        Self {
+            mnemonic,
+            length,
+            documented: false,
+        }
+    }
+}
+
+pub static OPCODES: [OpcodeInfo; 256] = [
+    OpcodeInfo::new("NOP", 1),               // 00
+    OpcodeInfo::new("LXI B,d16", 3),         // 01
+    OpcodeInfo::new("STAX B", 1),            // 02
+    OpcodeInfo::new("INX B", 1),             // 03
+    OpcodeInfo::new("INR B", 1),             // 04
+    OpcodeInfo::new("DCR B", 1),             // 05
+    OpcodeInfo::new("MVI B,d8", 2),          // 06
+    OpcodeInfo::new("RLC", 1),               // 07
+    OpcodeInfo::undocumented("NOP", 1),      // 08
+    OpcodeInfo::new("DAD B", 1),             // 09
+    OpcodeInfo::new("LDAX B", 1),            // 0A
+    OpcodeInfo::new("DCX B", 1),             // 0B
+    OpcodeInfo::new("INR C", 1),             // 0C
+    OpcodeInfo::new("DCR C", 1),             // 0D
+    OpcodeInfo::new("MVI C,d8", 2),          // 0E
+    OpcodeInfo::new("RRC", 1),               // 0F
+    OpcodeInfo::undocumented("NOP", 1),      // 10
+    OpcodeInfo::new("LXI D,d16", 3),         // 11
+    OpcodeInfo::new("STAX D", 1),            // 12
+    OpcodeInfo::new("INX D", 1),             // 13
+    OpcodeInfo::new("INR D", 1),             // 14
+    OpcodeInfo::new("DCR D", 1),             // 15
+    OpcodeInfo::new("MVI D,d8", 2),          // 16
+    OpcodeInfo::new("RAL", 1),               // 17
+    OpcodeInfo::undocumented("NOP", 1),      // 18
+    OpcodeInfo::new("DAD D", 1),             // 19
+    OpcodeInfo::new("LDAX D", 1),            // 1A
+    OpcodeInfo::new("DCX D", 1),             // 1B
+    OpcodeInfo::new("INR E", 1),             // 1C
+    OpcodeInfo::new("DCR E", 1),             // 1D
+    OpcodeInfo::new("MVI E,d8", 2),          // 1E
+    OpcodeInfo::new("RAR", 1),               // 1F
+    OpcodeInfo::undocumented("NOP", 1),      // 20
+    OpcodeInfo::new("LXI H,d16", 3),         // 21
+    OpcodeInfo::new("SHLD a16", 3),          // 22
+    OpcodeInfo::new("INX H", 1),             // 23
+    OpcodeInfo::new("INR H", 1),             // 24
+    OpcodeInfo::new("DCR H", 1),             // 25
+    OpcodeInfo::new("MVI H,d8", 2),          // 26
+    OpcodeInfo::new("DAA", 1),               // 27
+    OpcodeInfo::undocumented("NOP", 1),      // 28
+    OpcodeInfo::new("DAD H", 1),             // 29
+    OpcodeInfo::new("LHLD a16", 3),          // 2A
+    OpcodeInfo::new("DCX H", 1),             // 2B
+    OpcodeInfo::new("INR L", 1),             // 2C
+    OpcodeInfo::new("DCR L", 1),             // 2D
+    OpcodeInfo::new("MVI L,d8", 2),          // 2E
+    OpcodeInfo::new("CMA", 1),               // 2F
+    OpcodeInfo::undocumented("NOP", 1),      // 30
+    OpcodeInfo::new("LXI SP,d16", 3),        // 31
+    OpcodeInfo::new("STA a16", 3),           // 32
+    OpcodeInfo::new("INX SP", 1),            // 33
+    OpcodeInfo::new("INR M", 1),             // 34
+    OpcodeInfo::new("DCR M", 1),             // 35
+    OpcodeInfo::new("MVI M,d8", 2),          // 36
+    OpcodeInfo::new("STC", 1),               // 37
+    OpcodeInfo::undocumented("NOP", 1),      // 38
+    OpcodeInfo::new("DAD SP", 1),            // 39
+    OpcodeInfo::new("LDA a16", 3),           // 3A
+    OpcodeInfo::new("DCX SP", 1),            // 3B
+    OpcodeInfo::new("INR A", 1),             // 3C
+    OpcodeInfo::new("DCR A", 1),             // 3D
+    OpcodeInfo::new("MVI A,d8", 2),          // 3E
+    OpcodeInfo::new("CMC", 1),               // 3F
+    OpcodeInfo::new("MOV B,B", 1),           // 40
+    OpcodeInfo::new("MOV B,C", 1),           // 41
+    OpcodeInfo::new("MOV B,D", 1),           // 42
+    OpcodeInfo::new("MOV B,E", 1),           // 43
+    OpcodeInfo::new("MOV B,H", 1),           // 44
+    OpcodeInfo::new("MOV B,L", 1),           // 45
+    OpcodeInfo::new("MOV B,M", 1),           // 46
+    OpcodeInfo::new("MOV B,A", 1),           // 47
+    OpcodeInfo::new("MOV C,B", 1),           // 48
+    OpcodeInfo::new("MOV C,C", 1),           // 49
+    OpcodeInfo::new("MOV C,D", 1),           // 4A
+    OpcodeInfo::new("MOV C,E", 1),           // 4B
+    OpcodeInfo::new("MOV C,H", 1),           // 4C
+    OpcodeInfo::new("MOV C,L", 1),           // 4D
+    OpcodeInfo::new("MOV C,M", 1),           // 4E
+    OpcodeInfo::new("MOV C,A", 1),           // 4F
+    OpcodeInfo::new("MOV D,B", 1),           // 50
+    OpcodeInfo::new("MOV D,C", 1),           // 51
+    OpcodeInfo::new("MOV D,D", 1),           // 52
+    OpcodeInfo::new("MOV D,E", 1),           // 53
+    OpcodeInfo::new("MOV D,H", 1),           // 54
+    OpcodeInfo::new("MOV D,L", 1),           // 55
+    OpcodeInfo::new("MOV D,M", 1),           // 56
+    OpcodeInfo::new("MOV D,A", 1),           // 57
+    OpcodeInfo::new("MOV E,B", 1),           // 58
+    OpcodeInfo::new("MOV E,C", 1),           // 59
+    OpcodeInfo::new("MOV E,D", 1),           // 5A
+    OpcodeInfo::new("MOV E,E", 1),           // 5B
+    OpcodeInfo::new("MOV E,H", 1),           // 5C
+    OpcodeInfo::new("MOV E,L", 1),           // 5D
+    OpcodeInfo::new("MOV E,M", 1),           // 5E
+    OpcodeInfo::new("MOV E,A", 1),           // 5F
+    OpcodeInfo::new("MOV H,B", 1),           // 60
+    OpcodeInfo::new("MOV H,C", 1),           // 61
+    OpcodeInfo::new("MOV H,D", 1),           // 62
+    OpcodeInfo::new("MOV H,E", 1),           // 63
+    OpcodeInfo::new("MOV H,H", 1),           // 64
+    OpcodeInfo::new("MOV H,L", 1),           // 65
+    OpcodeInfo::new("MOV H,M", 1),           // 66
+    OpcodeInfo::new("MOV H,A", 1),           // 67
+    OpcodeInfo::new("MOV L,B", 1),           // 68
+    OpcodeInfo::new("MOV L,C", 1),           // 69
+    OpcodeInfo::new("MOV L,D", 1),           // 6A
+    OpcodeInfo::new("MOV L,E", 1),           // 6B
+    OpcodeInfo::new("MOV L,H", 1),           // 6C
+    OpcodeInfo::new("MOV L,L", 1),           // 6D
+    OpcodeInfo::new("MOV L,M", 1),           // 6E
+    OpcodeInfo::new("MOV L,A", 1),           // 6F
+    OpcodeInfo::new("MOV M,B", 1),           // 70
+    OpcodeInfo::new("MOV M,C", 1),           // 71
+    OpcodeInfo::new("MOV M,D", 1),           // 72
+    OpcodeInfo::new("MOV M,E", 1),           // 73
+    OpcodeInfo::new("MOV M,H", 1),           // 74
+    OpcodeInfo::new("MOV M,L", 1),           // 75
+    OpcodeInfo::new("HLT", 1),               // 76
+    OpcodeInfo::new("MOV M,A", 1),           // 77
+    OpcodeInfo::new("MOV A,B", 1),           // 78
+    OpcodeInfo::new("MOV A,C", 1),           // 79
+    OpcodeInfo::new("MOV A,D", 1),           // 7A
+    OpcodeInfo::new("MOV A,E", 1),           // 7B
+    OpcodeInfo::new("MOV A,H", 1),           // 7C
+    OpcodeInfo::new("MOV A,L", 1),           // 7D
+    OpcodeInfo::new("MOV A,M", 1),           // 7E
+    OpcodeInfo::new("MOV A,A", 1),           // 7F
+    OpcodeInfo::new("ADD B", 1),             // 80
+    OpcodeInfo::new("ADD C", 1),             // 81
+    OpcodeInfo::new("ADD D", 1),             // 82
+    OpcodeInfo::new("ADD E", 1),             // 83
+    OpcodeInfo::new("ADD H", 1),             // 84
+    OpcodeInfo::new("ADD L", 1),             // 85
+    OpcodeInfo::new("ADD M", 1),             // 86
+    OpcodeInfo::new("ADD A", 1),             // 87
+    OpcodeInfo::new("ADC B", 1),             // 88
+    OpcodeInfo::new("ADC C", 1),             // 89
+    OpcodeInfo::new("ADC D", 1),             // 8A
+    OpcodeInfo::new("ADC E", 1),             // 8B
+    OpcodeInfo::new("ADC H", 1),             // 8C
+    OpcodeInfo::new("ADC L", 1),             // 8D
+    OpcodeInfo::new("ADC M", 1),             // 8E
+    OpcodeInfo::new("ADC A", 1),             // 8F
+    OpcodeInfo::new("SUB B", 1),             // 90
+    OpcodeInfo::new("SUB C", 1),             // 91
+    OpcodeInfo::new("SUB D", 1),             // 92
+    OpcodeInfo::new("SUB E", 1),             // 93
+    OpcodeInfo::new("SUB H", 1),             // 94
+    OpcodeInfo::new("SUB L", 1),             // 95
+    OpcodeInfo::new("SUB M", 1),             // 96
+    OpcodeInfo::new("SUB A", 1),             // 97
+    OpcodeInfo::new("SBB B", 1),             // 98
+    OpcodeInfo::new("SBB C", 1),             // 99
+    OpcodeInfo::new("SBB D", 1),             // 9A
+    OpcodeInfo::new("SBB E", 1),             // 9B
+    OpcodeInfo::new("SBB H", 1),             // 9C
+    OpcodeInfo::new("SBB L", 1),             // 9D
+    OpcodeInfo::new("SBB M", 1),             // 9E
+    OpcodeInfo::new("SBB A", 1),             // 9F
+    OpcodeInfo::new("ANA B", 1),             // A0
+    OpcodeInfo::new("ANA C", 1),             // A1
+    OpcodeInfo::new("ANA D", 1),             // A2
+    OpcodeInfo::new("ANA E", 1),             // A3
+    OpcodeInfo::new("ANA H", 1),             // A4
+    OpcodeInfo::new("ANA L", 1),             // A5
+    OpcodeInfo::new("ANA M", 1),             // A6
+    OpcodeInfo::new("ANA A", 1),             // A7
+    OpcodeInfo::new("XRA B", 1),             // A8
+    OpcodeInfo::new("XRA C", 1),             // A9
+    OpcodeInfo::new("XRA D", 1),             // AA
+    OpcodeInfo::new("XRA E", 1),             // AB
+    OpcodeInfo::new("XRA H", 1),             // AC
+    OpcodeInfo::new("XRA L", 1),             // AD
+    OpcodeInfo::new("XRA M", 1),             // AE
+    OpcodeInfo::new("XRA A", 1),             // AF
+    OpcodeInfo::new("ORA B", 1),             // B0
+    OpcodeInfo::new("ORA C", 1),             // B1
+    OpcodeInfo::new("ORA D", 1),             // B2
+    OpcodeInfo::new("ORA E", 1),             // B3
+    OpcodeInfo::new("ORA H", 1),             // B4
+    OpcodeInfo::new("ORA L", 1),             // B5
+    OpcodeInfo::new("ORA M", 1),             // B6
+    OpcodeInfo::new("ORA A", 1),             // B7
+    OpcodeInfo::new("CMP B", 1),             // B8
+    OpcodeInfo::new("CMP C", 1),             // B9
+    OpcodeInfo::new("CMP D", 1),             // BA
+    OpcodeInfo::new("CMP E", 1),             // BB
+    OpcodeInfo::new("CMP H", 1),             // BC
+    OpcodeInfo::new("CMP L", 1),             // BD
+    OpcodeInfo::new("CMP M", 1),             // BE
+    OpcodeInfo::new("CMP A", 1),             // BF
+    OpcodeInfo::new("RNZ", 1),               // C0
+    OpcodeInfo::new("POP B", 1),             // C1
+    OpcodeInfo::new("JNZ a16", 3),           // C2
+    OpcodeInfo::new("JMP a16", 3),           // C3
+    OpcodeInfo::new("CNZ a16", 3),           // C4
+    OpcodeInfo::new("PUSH B", 1),            // C5
+    OpcodeInfo::new("ADI d8", 2),            // C6
+    OpcodeInfo::new("RST 0", 1),             // C7
+    OpcodeInfo::new("RZ", 1),                // C8
+    OpcodeInfo::new("RET", 1),               // C9
+    OpcodeInfo::new("JZ a16", 3),            // CA
+    OpcodeInfo::undocumented("JMP a16", 3),  // CB
+    OpcodeInfo::new("CZ a16", 3),            // CC
+    OpcodeInfo::new("CALL a16", 3),          // CD
+    OpcodeInfo::new("ACI d8", 2),            // CE
+    OpcodeInfo::new("RST 1", 1),             // CF
+    OpcodeInfo::new("RNC", 1),               // D0
+    OpcodeInfo::new("POP D", 1),             // D1
+    OpcodeInfo::new("JNC a16", 3),           // D2
+    OpcodeInfo::new("OUT d8", 2),            // D3
+    OpcodeInfo::new("CNC a16", 3),           // D4
+    OpcodeInfo::new("PUSH D", 1),            // D5
+    OpcodeInfo::new("SUI d8", 2),            // D6
+    OpcodeInfo::new("RST 2", 1),             // D7
+    OpcodeInfo::new("RC", 1),                // D8
+    OpcodeInfo::undocumented("RET", 1),      // D9
+    OpcodeInfo::new("JC a16", 3),            // DA
+    OpcodeInfo::new("IN d8", 2),             // DB
+    OpcodeInfo::new("CC a16", 3),            // DC
+    OpcodeInfo::undocumented("CALL a16", 3), // DD
+    OpcodeInfo::new("SBI d8", 2),            // DE
+    OpcodeInfo::new("RST 3", 1),             // DF
+    OpcodeInfo::new("RPO", 1),               // E0
+    OpcodeInfo::new("POP H", 1),             // E1
+    OpcodeInfo::new("JPO a16", 3),           // E2
+    OpcodeInfo::new("XTHL", 1),              // E3
+    OpcodeInfo::new("CPO a16", 3),           // E4
+    OpcodeInfo::new("PUSH H", 1),            // E5
+    OpcodeInfo::new("ANI d8", 2),            // E6
+    OpcodeInfo::new("RST 4", 1),             // E7
+    OpcodeInfo::new("RPE", 1),               // E8
+    OpcodeInfo::new("PCHL", 1),              // E9
+    OpcodeInfo::new("JPE a16", 3),           // EA
+    OpcodeInfo::new("XCHG", 1),              // EB
+    OpcodeInfo::new("CPE a16", 3),           // EC
+    OpcodeInfo::undocumented("CALL a16", 3), // ED
+    OpcodeInfo::new("XRI d8", 2),            // EE
+    OpcodeInfo::new("RST 5", 1),             // EF
+    OpcodeInfo::new("RP", 1),                // F0
+    OpcodeInfo::new("POP PSW", 1),           // F1
+    OpcodeInfo::new("JP a16", 3),            // F2
+    OpcodeInfo::new("DI", 1),                // F3
+    OpcodeInfo::new("CP a16", 3),            // F4
+    OpcodeInfo::new("PUSH PSW", 1),          // F5
+    OpcodeInfo::new("ORI d8", 2),            // F6
+    OpcodeInfo::new("RST 6", 1),             // F7
+    OpcodeInfo::new("RM", 1),                // F8
+    OpcodeInfo::new("SPHL", 1),              // F9
+    OpcodeInfo::new("JM a16", 3),            // FA
+    OpcodeInfo::new("EI", 1),                // FB
+    OpcodeInfo::new("CM a16", 3),            // FC
+    OpcodeInfo::undocumented("CALL a16", 3), // FD
+    OpcodeInfo::new("CPI d8", 2),            // FE
+    OpcodeInfo::new("RST 7", 1),             // FF
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documented_opcodes() {
+        let undocumented: Vec<usize> = (0..256).filter(|&op| !OPCODES[op].documented).collect();
+        assert_eq!(
+            undocumented,
+            [
+                0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0xCB, 0xD9, 0xDD, 0xED, 0xFD
+            ]
+        );
+        assert_eq!(OPCODES[0x3E].mnemonic, "MVI A,d8");
+        assert_eq!(OPCODES[0x76].mnemonic, "HLT");
+        assert_eq!(OPCODES[0xCD].length, 3);
+    }
+}
@@ -1,6 +1,42 @@
-mod assembler;
-mod coding;
-mod instruction;
+//! Emulator of the Intel 8080, with the `leben` command line interface on top.
+//!
+//! The public contract of the library is [`machine`] with [`machine::Machine`] and
+//! [`machine::MachineBuilder`], the instruction set in [`instruction`], decoding and encoding of
+//! single instructions and programs in [`coding`], the [`assembler`] and the [`devices`] that can
+//! be attached to a machine. Modules behind the `cli` and `tui` features exist for the `leben`
+//! binary and may change with it.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod assembler;
+pub mod coding;
+pub mod devices;
+#[cfg(feature = "std")]
+pub mod disasm;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod ffi;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod fuzzing;
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
+pub mod gdb;
+pub mod instruction;
+#[cfg(feature = "std")]
+pub mod loader;
 pub mod machine;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod runner;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(all(feature = "tui", not(target_arch = "wasm32")))]
 pub mod ui;
+#[cfg(all(feature = "cli", not(target_arch = "wasm32")))]
 pub mod cli;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,254 @@
+//! Loading program images from files.
+
+use std::{
+    fmt::Display,
+    fs,
+    io::{self, Read},
+    ops::Range,
+    path::Path,
+};
+
+use crate::{
+    coding::ihex::{self, IhexError},
+    instruction::Address,
+    machine::fnv1a,
+};
+
+/// Size of the address space, one past the highest address.
+pub(crate) const ADDRESS_SPACE: usize = 0x10000;
+
+/// A program image ready to be placed in memory, see [`MachineBuilder::image`].
+///
+/// [`MachineBuilder::image`]: crate::machine::MachineBuilder::image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryImage {
+    /// Address of the first byte.
+    pub origin: Address,
+    /// Address execution starts at.
+    pub entry: Address,
+    pub bytes: Vec<u8>,
+}
+
+impl MemoryImage {
+    /// Addresses the image occupies.
+    pub fn range(&self) -> Range<usize> {
+        self.origin as usize..self.origin as usize + self.bytes.len()
+    }
+
+    /// 64-bit FNV-1a hash of the origin and the bytes, which stays the same across platforms and
+    /// versions, e.g. to tell whether a save state belongs to this image.
+    pub fn hash(&self) -> u64 {
+        fnv1a(self.origin.to_le_bytes().iter().chain(&self.bytes))
+    }
+}
+
+/// Error returned when an image can't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The image couldn't be read.
+    Io(io::Error),
+    /// The image doesn't fit in memory when placed at `origin`.
+    TooLarge { origin: Address, length: usize },
+    /// The image isn't valid Intel HEX.
+    Ihex(IhexError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{}", err),
+            LoadError::TooLarge { origin, length } => {
+                let available = ADDRESS_SPACE - *origin as usize;
+                write!(
+                    f,
+                    "Image is {} bytes large, {} more than the {} bytes that fit between 0x{:04X} and 0xFFFF",
+                    length,
+                    length - available,
+                    available,
+                    origin,
+                )
+            }
+            LoadError::Ihex(err) => write!(f, "Invalid Intel HEX: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::TooLarge { .. } => None,
+            LoadError::Ihex(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(value: io::Error) -> Self {
+        LoadError::Io(value)
+    }
+}
+
+/// Read a raw binary to be placed at `origin`, with execution starting at `origin`.
+pub fn load_flat(mut reader: impl Read, origin: Address) -> Result<MemoryImage, LoadError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if origin as usize + bytes.len() > ADDRESS_SPACE {
+        return Err(LoadError::TooLarge {
+            origin,
+            length: bytes.len(),
+        });
+    }
+    Ok(MemoryImage {
+        origin,
+        entry: origin,
+        bytes,
+    })
+}
+
+/// Like [`load_flat`], reading the file at `path`, or stdin if `path` is `-`.
+pub fn load_flat_file(path: &Path, origin: Address) -> Result<MemoryImage, LoadError> {
+    load_flat_path(path, origin, io::stdin().lock())
+}
+
+fn load_flat_path(
+    path: &Path,
+    origin: Address,
+    stdin: impl Read,
+) -> Result<MemoryImage, LoadError> {
+    if path.to_str() == Some("-") {
+        return load_flat(stdin, origin);
+    }
+    load_flat(open(path)?, origin)
+}
+
+/// Read Intel HEX records, with execution starting at the lowest address they load. The image
+/// spans all records, with zeros in the gaps between them.
+pub fn load_ihex(mut reader: impl Read) -> Result<MemoryImage, LoadError> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    let records = ihex::parse(&text).map_err(LoadError::Ihex)?;
+    let ranges = || {
+        records
+            .iter()
+            .filter(|(_, bytes)| !bytes.is_empty())
+            .map(|(address, bytes)| *address as usize..*address as usize + bytes.len())
+    };
+    let (Some(start), Some(end)) = (
+        ranges().map(|range| range.start).min(),
+        ranges().map(|range| range.end).max(),
+    ) else {
+        return Ok(MemoryImage {
+            origin: 0,
+            entry: 0,
+            bytes: Vec::new(),
+        });
+    };
+
+    let mut bytes = vec![0; end - start];
+    for (address, data) in &records {
+        let offset = *address as usize - start;
+        bytes[offset..offset + data.len()].copy_from_slice(data);
+    }
+    let origin = start as Address;
+    Ok(MemoryImage {
+        origin,
+        entry: origin,
+        bytes,
+    })
+}
+
+/// Like [`load_ihex`], reading the file at `path`, or stdin if `path` is `-`.
+pub fn load_ihex_file(path: &Path) -> Result<MemoryImage, LoadError> {
+    if path.to_str() == Some("-") {
+        return load_ihex(io::stdin().lock());
+    }
+    load_ihex(open(path)?)
+}
+
+fn open(path: &Path) -> io::Result<fs::File> {
+    fs::File::open(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Couldn't open '{}': {}", path.display(), err),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn exact_fit() {
+        let image = load_flat(&[0xAA; 0x100][..], 0xFF00).unwrap();
+        assert_eq!(image.origin, 0xFF00);
+        assert_eq!(image.entry, 0xFF00);
+        assert_eq!(image.bytes.len(), 0x100);
+    }
+
+    #[test]
+    fn hash() {
+        let image = |origin, bytes: &[u8]| MemoryImage {
+            origin,
+            entry: origin,
+            bytes: bytes.to_vec(),
+        };
+        assert_eq!(image(0x0000, &[]).hash(), 0x0832_8807_B4EB_6FED);
+        assert_ne!(image(0x0100, &[0x76]).hash(), image(0x0000, &[0x76]).hash());
+        assert_ne!(image(0x0100, &[0x76]).hash(), image(0x0100, &[0x00]).hash());
+    }
+
+    #[test]
+    fn too_large() {
+        let err = load_flat(&[0xAA; 0x101][..], 0xFF00).unwrap_err();
+        assert!(matches!(
+            err,
+            LoadError::TooLarge {
+                origin: 0xFF00,
+                length: 0x101
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Image is 257 bytes large, 1 more than the 256 bytes that fit between 0xFF00 and 0xFFFF"
+        );
+    }
+
+    #[test]
+    fn stdin() {
+        let image = load_flat_path(Path::new("-"), 0x0100, Cursor::new(vec![0x3E, 0x2A, 0x76]));
+        assert_eq!(
+            image.unwrap(),
+            MemoryImage {
+                origin: 0x0100,
+                entry: 0x0100,
+                bytes: vec![0x3E, 0x2A, 0x76],
+            }
+        );
+    }
+
+    #[test]
+    fn ihex() {
+        let image = load_ihex(&include_bytes!("../tests/data/hello.hex")[..]).unwrap();
+        assert_eq!(image.origin, 0x0100);
+        assert_eq!(image.entry, 0x0100);
+        assert_eq!(image.bytes.len(), 0x0203 - 0x0100);
+        assert_eq!(image.bytes[..3], [0x21, 0x00, 0x02]);
+        assert_eq!(image.bytes[0x0F..0x100], [0; 0xF1]);
+        assert_eq!(image.bytes[0x100..], *b"Hi\0");
+
+        let err = load_ihex(&include_bytes!("../tests/data/bad_checksum.hex")[..]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid Intel HEX: Line 2: Checksum is 0xBE, but should be 0xBD"
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        let err = load_flat_file(Path::new("does/not/exist.bin"), 0).unwrap_err();
+        assert!(err.to_string().starts_with("Couldn't open 'does/not/exist.bin'"));
+    }
+}
@@ -1,16 +1,80 @@
-use std::{fmt::Display, io::{self, Read}};
-
-use rand::Rng;
+use alloc::{
+    boxed::Box,
+    collections::{BTreeSet, VecDeque},
+    vec::Vec,
+};
+use core::{any::Any, fmt::Display, ops::Range};
 
 use crate::{
     coding::{self, reader::Reader},
+    devices::{self, Console, Random},
     instruction::{
-        Address, Condition, Data8, Data16, Instruction, Register, RegisterPair,
-        RegisterPairOrStatus,
+        Address, Condition, Data8, Data16, Instruction, Port, Register, RegisterPair,
+        RegisterPairOrStatus, Variant,
     },
 };
 
-static MEMORY_SIZE_BYTES: usize = 2 << 16;
+mod alu;
+mod builder;
+mod bus;
+mod clock;
+mod coverage;
+mod cpm;
+mod diff;
+mod dispatch;
+#[cfg(feature = "std")]
+mod dump;
+mod fingerprint;
+mod hooks;
+#[cfg(feature = "std")]
+pub(crate) mod json;
+mod mmio;
+mod observer;
+mod profile;
+mod rewind;
+#[cfg(feature = "std")]
+mod save;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod stream;
+mod truth_table;
+
+#[cfg(feature = "std")]
+pub use builder::AssembleLoadError;
+pub use builder::{BuildError, DeviceBus, MachineBuilder};
+pub use crate::coding::ihex::{IhexError, IhexErrorKind};
+use alu::Flags;
+use bus::IoBus;
+use coverage::CoverageObserver;
+use hooks::Hooks;
+use mmio::MemoryMap;
+use profile::ProfileObserver;
+pub use bus::IoDevice;
+pub use clock::Clock;
+#[cfg(feature = "std")]
+pub use clock::SystemClock;
+pub use coverage::{CoverageReport, OpcodeCoverage};
+pub use diff::MachineDiff;
+#[cfg(feature = "std")]
+pub use dump::{DumpError, DumpFormat};
+pub(crate) use fingerprint::fnv1a;
+#[cfg(feature = "std")]
+pub use json::{MemoryDump, StateJsonError};
+pub use mmio::MemoryHandler;
+pub use observer::ExecutionObserver;
+pub use profile::{BranchCount, ProfileReport};
+#[cfg(feature = "std")]
+pub use save::{SAVE_MAGIC, SAVE_VERSION, SaveInfo};
+#[cfg(feature = "std")]
+pub use shared::SharedMemory;
+#[cfg(feature = "serde")]
+pub use snapshot::{MachineSnapshot, SNAPSHOT_VERSION, SnapshotError};
+pub use stream::{Event, PROGRESS_INTERVAL};
+pub use truth_table::{AluOperation, AluRow, UnknownAluOperation, alu_table, write_alu_csv};
+
+static MEMORY_SIZE_BYTES: usize = 1 << 16;
 pub struct Memory([u8; MEMORY_SIZE_BYTES]);
 
 impl Memory {
@@ -18,26 +82,52 @@ impl Memory {
         Self([0; MEMORY_SIZE_BYTES])
     }
 
+    #[inline]
     pub fn read_8(&self, address: Address) -> Data8 {
         self.0[address as usize]
     }
+    /// The 16-bit value at `address`, or `None` if its high byte would be past 0xFFFF.
+    #[inline]
     pub fn read_16(&self, address: Address) -> Option<Data16> {
         let low = self.0[address as usize];
-        let high = *self.0.get(address as usize + 1)?;
+        let high = self.0[address.checked_add(1)? as usize];
         Some(Data16::new(low, high))
     }
+    /// The 16-bit value at `address`, with the high byte at 0x0000 if `address` is 0xFFFF.
+    #[inline]
+    pub fn read_16_wrapping(&self, address: Address) -> Data16 {
+        Data16::new(self.read_8(address), self.read_8(address.wrapping_add(1)))
+    }
+
+    /// The bytes of the longest instruction starting at `address`, wrapping around to 0x0000
+    /// past 0xFFFF like the address bus does.
+    #[inline]
+    pub fn fetch(&self, address: Address) -> [u8; 3] {
+        [0, 1, 2].map(|offset| self.read_8(address.wrapping_add(offset)))
+    }
 
+    #[inline]
     pub fn write_8(&mut self, address: Address, value: Data8) {
         self.0[address as usize] = value;
     }
     #[must_use]
+    #[inline]
     pub fn write_16(&mut self, address: Address, value: Data16) -> Option<()> {
+        let high = address.checked_add(1)?;
         self.0[address as usize] = value.low;
-        *self.0.get_mut(address as usize + 1)? = value.high;
+        self.0[high as usize] = value.high;
 
         Some(())
     }
+    /// Write a 16-bit value at `address`, with the high byte at 0x0000 if `address` is 0xFFFF.
+    #[inline]
+    pub fn write_16_wrapping(&mut self, address: Address, value: Data16) {
+        self.write_8(address, value.low);
+        self.write_8(address.wrapping_add(1), value.high);
+    }
 
+    /// Copy `value` into memory from `address`. Returns `None` without writing anything if it
+    /// doesn't fit below 0x10000.
     pub fn write_slice(&mut self, address: Address, value: &[u8]) -> Option<()> {
         let range = (address as usize)..((address as usize) + value.len());
         self.0
@@ -59,6 +149,7 @@ pub enum ConditionRegister {
     Parity,
 }
 
+#[derive(Clone)]
 pub struct ConditionRegisters {
     flags: [bool; 5],
 }
@@ -84,147 +175,167 @@ impl ConditionRegisters {
     pub fn set(&mut self, condition: ConditionRegister, value: bool) {
         self.flags[Self::condition_index(condition)] = value;
     }
+
+    /// The flags as the low byte of the PSW, as `PUSH PSW` stores them: `S Z 0 AC 0 P 1 CY` from
+    /// bit 7 to bit 0.
+    pub fn to_psw_byte(&self) -> Data8 {
+        PSW_FLAG_BITS
+            .iter()
+            .fold(PSW_FIXED_BITS, |byte, &(condition, bit)| {
+                byte | (self.get(condition) as u8) << bit
+            })
+    }
+
+    /// Flags from the low byte of the PSW, as `POP PSW` loads them. Bits 1, 3 and 5 are ignored.
+    pub fn from_psw_byte(byte: Data8) -> Self {
+        let mut conditions = Self::new();
+        for (condition, bit) in PSW_FLAG_BITS {
+            conditions.set(condition, byte >> bit & 1 == 1);
+        }
+        conditions
+    }
 }
 
+/// The bit of each flag in the PSW.
+const PSW_FLAG_BITS: [(ConditionRegister, u32); 5] = [
+    (ConditionRegister::Carry, 0),
+    (ConditionRegister::Parity, 2),
+    (ConditionRegister::AuxiliaryCarry, 4),
+    (ConditionRegister::Zero, 6),
+    (ConditionRegister::Sign, 7),
+];
+
+/// Bits of the PSW that don't hold a flag: bit 1 is always set, bits 3 and 5 always clear.
+const PSW_FIXED_BITS: Data8 = 0b0000_0010;
+
 // Struct containing program addressable registers.
+#[derive(Clone)]
 pub struct RegisterMap {
-    a: Data8,
-    b: Data8,
-    c: Data8,
-    d: Data8,
-    e: Data8,
-    h: Data8,
-    l: Data8,
-    sp: Data16,
+    /// B, C, D, E, H, L and A, indexed by their encoding. The slot of M is unused.
+    bytes: [Data8; 8],
+    sp: Address,
 }
 
 impl RegisterMap {
     pub fn new() -> Self {
         Self {
-            a: 0,
-            b: 0,
-            c: 0,
-            d: 0,
-            e: 0,
-            h: 0,
-            l: 0,
-            sp: Data16::ZERO,
+            bytes: [0; 8],
+            sp: 0,
         }
     }
 
+    #[inline]
     pub fn get_8(&self, register: Register, memory: &Memory) -> Data8 {
         match register {
-            Register::B => {
-                return self.b;
-            }
-            Register::C => {
-                return self.c;
-            }
-            Register::D => {
-                return self.d;
-            }
-            Register::E => {
-                return self.e;
-            }
-            Register::H => {
-                return self.h;
-            }
-            Register::L => {
-                return self.l;
-            }
-            Register::M => {
-                let address = self.get_16(RegisterPair::Hl);
-
-                return memory.read_8(address.into());
-            }
-            Register::A => {
-                return self.a;
-            }
+            Register::M => memory.read_8(self.hl()),
+            register => self.byte(register),
         }
     }
 
+    #[inline]
     pub fn set_8(&mut self, register: Register, value: Data8, memory: &mut Memory) {
         match register {
-            Register::B => {
-                self.b = value;
-            }
-            Register::C => {
-                self.c = value;
-            }
-            Register::D => {
-                self.d = value;
-            }
-            Register::E => {
-                self.e = value;
-            }
-            Register::H => {
-                self.h = value;
-            }
-            Register::L => {
-                self.l = value;
-            }
-            Register::M => {
-                let address = self.get_16(RegisterPair::Hl);
-
-                memory.write_8(address.into(), value);
-            }
-            Register::A => {
-                self.a = value;
-            }
+            Register::M => memory.write_8(self.hl(), value),
+            register => self.set_byte(register, value),
         }
     }
 
+    /// The value of a register other than `M`, which has no slot and reads as 0 here.
+    #[inline]
+    fn byte(&self, register: Register) -> Data8 {
+        self.bytes[register as usize]
+    }
+
+    /// Set a register other than `M`, which has no slot.
+    #[inline]
+    fn set_byte(&mut self, register: Register, value: Data8) {
+        self.bytes[register as usize] = value;
+    }
+
+    /// The accumulator, which needs no memory to read.
+    #[inline]
+    fn a(&self) -> Data8 {
+        self.bytes[Register::A as usize]
+    }
+
+    #[inline]
+    fn set_a(&mut self, value: Data8) {
+        self.bytes[Register::A as usize] = value;
+    }
+
+    #[inline]
+    fn hl(&self) -> Address {
+        self.pair(RegisterPair::Hl)
+    }
+
+    #[inline]
     pub fn get_16(&self, register: RegisterPair) -> Data16 {
+        self.pair(register).into()
+    }
+    #[inline]
+    pub fn set_16(&mut self, register: RegisterPair, value: Data16) {
+        self.set_pair(register, value.value());
+    }
+
+    /// Like [`RegisterMap::get_16`], without going through [`Data16`].
+    #[inline]
+    fn pair(&self, register: RegisterPair) -> u16 {
         match register {
-            RegisterPair::Bc => Data16::new(self.c, self.b),
-            RegisterPair::De => Data16::new(self.e, self.d),
-            RegisterPair::Hl => Data16::new(self.l, self.h),
             RegisterPair::Sp => self.sp,
+            pair => {
+                // The high register of a pair comes right before the low one.
+                let high = pair as usize * 2;
+                u16::from_le_bytes([self.bytes[high + 1], self.bytes[high]])
+            }
         }
     }
-    pub fn set_16(&mut self, register: RegisterPair, value: Data16) {
+
+    #[inline]
+    fn set_pair(&mut self, register: RegisterPair, value: u16) {
         match register {
-            RegisterPair::Bc => {
-                self.c = value.low;
-                self.b = value.high;
-            }
-            RegisterPair::De => {
-                self.e = value.low;
-                self.d = value.high;
-            }
-            RegisterPair::Hl => {
-                self.l = value.low;
-                self.h = value.high;
-            }
             RegisterPair::Sp => self.sp = value,
+            pair => {
+                let high = pair as usize * 2;
+                [self.bytes[high + 1], self.bytes[high]] = value.to_le_bytes();
+            }
         }
     }
 }
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HaltReason {
     HaltInstruction,
     InvalidInstruction,
     StackOverflow,
     StackUnderflow,
     MemoryOverflow,
+    /// A jump to its own address, which would spin forever. Only detected if enabled with
+    /// [`Machine::set_halt_on_self_jump`].
+    SelfJump,
 }
 
 impl Display for HaltReason {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             HaltReason::HaltInstruction => write!(f, "Encountered halt instruction"),
             HaltReason::InvalidInstruction => write!(f, "Encountered invalid instruction"),
             HaltReason::StackOverflow => write!(f, "Stack overflowed"),
             HaltReason::StackUnderflow => write!(f, "Stack underflowed"),
             HaltReason::MemoryOverflow => write!(f, "Encountered invalid memory address"),
+            HaltReason::SelfJump => write!(f, "Encountered jump to itself"),
         }
     }
 }
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MachineState {
     Running,
+    /// `IN 0` found no input. The program counter stays at the `IN`, which is executed again once
+    /// input is pushed with [`Machine::push_input`]. Only entered if enabled with
+    /// [`Machine::set_wait_for_input`].
+    WaitingForInput,
     Halted(HaltReason),
 }
 
@@ -236,8 +347,188 @@ pub enum ExecutionResult {
     StackOverflow,
     // Is generated when the stack is popped too many times.
     StackUnderflow,
-    // When an instruction attempts to write a 16-bit value to the very last byte of memory
+    // In strict mode, when an instruction reads or writes a 16-bit value at the very last byte of
+    // memory.
     MemoryOverflow,
+    // The bytes at the program counter don't encode a valid instruction.
+    InvalidInstruction,
+    // `IN 0` found no input and the machine waits for more.
+    WaitingForInput,
+    // A taken jump to its own address, if the machine halts on those.
+    SelfJump,
+}
+
+impl ExecutionResult {
+    /// The state the machine is in after an instruction produced this result.
+    pub fn machine_state(self) -> MachineState {
+        match self {
+            ExecutionResult::Running => MachineState::Running,
+            ExecutionResult::ControlTransfer => MachineState::Running,
+            ExecutionResult::Halt => MachineState::Halted(HaltReason::HaltInstruction),
+            ExecutionResult::StackOverflow => MachineState::Halted(HaltReason::StackOverflow),
+            ExecutionResult::StackUnderflow => MachineState::Halted(HaltReason::StackUnderflow),
+            ExecutionResult::MemoryOverflow => MachineState::Halted(HaltReason::MemoryOverflow),
+            ExecutionResult::InvalidInstruction => {
+                MachineState::Halted(HaltReason::InvalidInstruction)
+            }
+            ExecutionResult::WaitingForInput => MachineState::WaitingForInput,
+            ExecutionResult::SelfJump => MachineState::Halted(HaltReason::SelfJump),
+        }
+    }
+}
+
+/// What the machine does with the opcodes Intel doesn't document, see
+/// [`Machine::set_undocumented_opcodes`].
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum UndocumentedPolicy {
+    /// Halt with [`HaltReason::InvalidInstruction`].
+    #[default]
+    Halt,
+    /// Execute the documented instruction the opcode behaves like on real hardware, see
+    /// [`coding::decode_variant`].
+    Alias,
+}
+
+/// How 16-bit memory accesses and stack operations past 0xFFFF behave, see
+/// [`Machine::set_strict_memory`].
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub enum MemoryModel {
+    /// Wrap around to 0x0000, like the 8080.
+    #[default]
+    Wrapping,
+    /// Halt the machine.
+    Strict,
+}
+
+/// Whether the machine accepts interrupts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum InterruptEnable {
+    Disabled,
+    /// `EI` was just executed. Interrupts are accepted after the next instruction, so that a
+    /// handler ending in `EI` and `RET` returns before the next interrupt.
+    Delayed,
+    Enabled,
+}
+
+#[cfg(feature = "std")]
+impl InterruptEnable {
+    /// Whether interrupts are accepted, and whether `EI` was just executed, so that they will be
+    /// after the next instruction.
+    fn flags(self) -> (bool, bool) {
+        (self == Self::Enabled, self == Self::Delayed)
+    }
+
+    /// The inverse of [`InterruptEnable::flags`].
+    fn from_flags(enabled: bool, delayed: bool) -> Self {
+        match (enabled, delayed) {
+            (_, true) => Self::Delayed,
+            (true, false) => Self::Enabled,
+            (false, false) => Self::Disabled,
+        }
+    }
+}
+
+/// Information about a single executed instruction, as returned by [`Machine::step`].
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct StepInfo {
+    /// Value of the program counter before the instruction was executed.
+    pub pc_before: Data16,
+    /// The executed instruction, or `None` if the bytes at `pc_before` couldn't be decoded.
+    pub instruction: Option<Instruction>,
+    pub result: ExecutionResult,
+}
+
+/// Why [`Machine::run_until_halt`] stopped.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum RunOutcome {
+    Halted(HaltReason),
+    /// The program counter reached a [breakpoint](Machine::add_breakpoint). The instruction there
+    /// hasn't been executed yet.
+    Breakpoint(Address),
+    /// An instruction accessed a [watched](Machine::watch_write) address. It has been executed.
+    Watchpoint(WatchHit),
+    /// The instruction budget ran out while the machine was still running.
+    BudgetExhausted,
+    /// `IN 0` found no input and none became available, see [`MachineState::WaitingForInput`].
+    WaitingForInput,
+    /// The subroutine [`Machine::run_until_return`] was started in returned to the address.
+    Returned(Address),
+    /// [`Machine::step_over`] executed the instruction, and the subroutine it called if any
+    /// returned, leaving the program counter at the address.
+    Stepped(Address),
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Read,
+    Write,
+}
+
+/// An access to a watched address, reported by [`Machine::run_until_halt`].
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Address of the instruction that accessed the memory.
+    pub pc: Address,
+    pub instruction: Instruction,
+    /// The watched address.
+    pub address: Address,
+    pub access: MemoryAccess,
+    /// The value read from or written to `address`.
+    pub value: Data8,
+}
+
+/// Iterator executing one instruction per call to `next`, created by [`Machine::steps`].
+///
+/// The iterator holds a mutable borrow of the machine, so the machine can't be inspected while
+/// the iterator is alive. Drop it (e.g. by letting a `for` loop or an adaptor like `find` finish)
+/// to get access to the machine again. Execution can be resumed later by calling
+/// [`Machine::steps`] again.
+///
+/// Like [`Machine::run_until_halt`], the iterator ends before the instruction at a
+/// [breakpoint](Machine::add_breakpoint), and after yielding the step of an instruction that
+/// accessed a [watched](Machine::watch_write) address. [`Steps::stopped`] tells which.
+pub struct Steps<'a> {
+    machine: &'a mut Machine,
+    stopped: Option<RunOutcome>,
+}
+
+impl Steps<'_> {
+    /// The [`RunOutcome::Breakpoint`] or [`RunOutcome::Watchpoint`] that ended the iteration, or
+    /// `None` if it hasn't ended or ended because the machine halted or waits for input.
+    pub fn stopped(&self) -> Option<RunOutcome> {
+        self.stopped
+    }
+}
+
+impl<'a> Iterator for Steps<'a> {
+    type Item = StepInfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stopped.is_some() {
+            return None;
+        }
+        if let Some(address) = self.machine.stop_at_breakpoint() {
+            self.stopped = Some(RunOutcome::Breakpoint(address));
+            return None;
+        }
+        let step = self.machine.step()?;
+        self.stopped = self.machine.watch_hit(&step).map(RunOutcome::Watchpoint);
+        Some(step)
+    }
+}
+
+type InputSource = Box<dyn FnMut() -> Option<u8> + Send>;
+type OutputCallback = Box<dyn FnMut(&[u8]) + Send>;
+
+/// Where program output goes.
+#[derive(Default)]
+enum OutputSink {
+    /// Collected in [`Machine::output_buffer`].
+    #[default]
+    Buffer,
+    Callback(OutputCallback),
+    #[cfg(feature = "std")]
+    Writer(Box<dyn std::io::Write + Send>),
 }
 
 pub struct Machine {
@@ -245,12 +536,68 @@ pub struct Machine {
     memory: Box<Memory>,
     registers: RegisterMap,
     conditions: ConditionRegisters,
-    pc: Data16,
-    pub stdout: Vec<u8>,
-}
-
-fn is_even(value: u32) -> bool {
-    value % 2 == 0
+    pc: Address,
+    input: VecDeque<u8>,
+    input_source: Option<InputSource>,
+    observers: Vec<Box<dyn ExecutionObserver>>,
+    output_sink: OutputSink,
+    /// The first write to the output writer that failed, see [`Machine::take_output_error`].
+    #[cfg(feature = "std")]
+    output_error: Option<std::io::Error>,
+    io: IoBus,
+    memory_map: MemoryMap,
+    /// Output collected without an output writer or callback, see [`Machine::output_buffer`].
+    pub(crate) output: Vec<u8>,
+    /// Generator behind `IN 1`.
+    random: Random,
+    #[cfg(feature = "std")]
+    shared_memory: Option<shared::Publisher>,
+    /// Addresses of the segments loaded with [`Machine::load_segment`].
+    loaded: Vec<Range<usize>>,
+    /// Clock periods (T-states) taken by the executed instructions.
+    cycles: u64,
+    /// Number of executed instructions.
+    instructions: u64,
+    interrupt_enable: InterruptEnable,
+    /// Instruction of an accepted interrupt, executed as the next step.
+    interrupt: Option<Instruction>,
+    /// Value read from ports without a device.
+    unmapped_input: Data8,
+    /// Wait for input instead of halting when `IN 0` finds none.
+    wait_for_input: bool,
+    /// Where [`Machine::reset`] moves the program counter.
+    entry: Address,
+    breakpoints: BTreeSet<Address>,
+    /// The breakpoint [`Machine::run_until_halt`] last stopped at, if no instruction has been
+    /// executed since. Running again executes the instruction there instead of stopping again.
+    stopped_at: Option<Address>,
+    watched_reads: BTreeSet<Address>,
+    watched_writes: BTreeSet<Address>,
+    /// The first access to a watched address by the last executed instruction, as the address,
+    /// kind of access and value.
+    watched_access: Option<(Address, MemoryAccess, Data8)>,
+    /// Undo records for [`Machine::step_back`], if enabled.
+    rewind: Option<rewind::History>,
+    /// Whether [`Machine::enable_cpm_shim`] was called.
+    cpm_shim: bool,
+    /// Halt on 16-bit accesses and stack operations past 0xFFFF instead of wrapping.
+    strict_memory: bool,
+    undocumented_opcodes: UndocumentedPolicy,
+    variant: Variant,
+    /// The 8085 interrupt mask set by `SIM`, in the low 3 bits.
+    interrupt_mask: u8,
+    /// The 8085 serial output line set by `SIM`.
+    serial_output: bool,
+    /// Lowest address the stack may grow down to, see [`Machine::set_stack_limit`].
+    stack_limit: Option<Address>,
+    /// Highest value SP may be popped up to, see [`Machine::set_stack_ceiling`].
+    stack_ceiling: Option<Address>,
+    /// Halt on taken jumps to their own address instead of executing them.
+    halt_on_self_jump: bool,
+    hooks: Hooks,
+    /// Memory overwritten by the instruction [`Machine::step_with_diff`] executes, as the address
+    /// and old value.
+    written: Option<Vec<(Address, Data8)>>,
 }
 
 impl Machine {
@@ -260,8 +607,44 @@ impl Machine {
             memory: Box::new(Memory::new()),
             registers: RegisterMap::new(),
             conditions: ConditionRegisters::new(),
-            pc: Data16::ZERO,
-            stdout: Vec::new(),
+            pc: 0,
+            input: VecDeque::new(),
+            input_source: None,
+            observers: Vec::new(),
+            output_sink: OutputSink::Buffer,
+            #[cfg(feature = "std")]
+            output_error: None,
+            io: IoBus::default(),
+            memory_map: MemoryMap::default(),
+            output: Vec::new(),
+            random: Random::new(1, devices::DEFAULT_SEED),
+            #[cfg(feature = "std")]
+            shared_memory: None,
+            loaded: Vec::new(),
+            cycles: 0,
+            instructions: 0,
+            interrupt_enable: InterruptEnable::Disabled,
+            interrupt: None,
+            unmapped_input: 0,
+            wait_for_input: false,
+            entry: 0,
+            breakpoints: BTreeSet::new(),
+            stopped_at: None,
+            watched_reads: BTreeSet::new(),
+            watched_writes: BTreeSet::new(),
+            watched_access: None,
+            rewind: None,
+            cpm_shim: false,
+            strict_memory: false,
+            undocumented_opcodes: UndocumentedPolicy::Halt,
+            variant: Variant::Intel8080,
+            interrupt_mask: 0,
+            serial_output: false,
+            stack_limit: None,
+            stack_ceiling: None,
+            halt_on_self_jump: false,
+            hooks: Hooks::default(),
+            written: None,
         }
     }
 
@@ -277,6 +660,10 @@ impl Machine {
         &self.conditions
     }
 
+    pub fn conditions_mut(&mut self) -> &mut ConditionRegisters {
+        &mut self.conditions
+    }
+
     pub fn memory(&self) -> &Memory {
         &self.memory
     }
@@ -293,117 +680,990 @@ impl Machine {
         self.registers().get_16(register)
     }
 
+    /// Set a register without executing an instruction, e.g. from a debugger. Setting `M` writes
+    /// memory at HL, like `MVI M` does.
+    pub fn set_register_8(&mut self, register: Register, value: Data8) {
+        self.registers.set_8(register, value, &mut self.memory);
+    }
+
+    /// Set a register pair without executing an instruction, the high register to the high byte.
+    pub fn set_register_16(&mut self, register: RegisterPair, value: Data16) {
+        self.registers.set_16(register, value);
+    }
+
+    /// Set a flag without executing an instruction, like [`ConditionRegisters::set`] on
+    /// [`Machine::conditions_mut`].
+    pub fn set_flag(&mut self, flag: ConditionRegister, value: bool) {
+        self.conditions.set(flag, value);
+    }
+
     pub fn pc(&self) -> Data16 {
-        self.pc
+        self.pc.into()
+    }
+
+    /// Number of clock periods (T-states) the executed instructions took, with the longer duration
+    /// of conditional calls and returns whose condition held.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Number of executed instructions, including those supplied by interrupts. Like
+    /// [`Machine::cycles`] it counts from the start or the last [`Machine::reset`].
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Move the program counter, which also forgets the breakpoint the machine last stopped at.
+    pub fn set_pc(&mut self, pc: Data16) {
+        self.pc = pc.value();
+        self.stopped_at = None;
+    }
+
+    /// Make [`Machine::run_until_halt`] stop before executing the instruction at `address`.
+    /// Returns `false` if there already is a breakpoint there.
+    pub fn add_breakpoint(&mut self, address: Address) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Returns `false` if there was no breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: Address) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<Address> {
+        &self.breakpoints
+    }
+
+    /// Make [`Machine::run_until_halt`] stop after an instruction that reads `address`, including
+    /// through `M`, the stack and 16-bit loads. Instruction fetches aren't reads. Returns `false`
+    /// if reads of `address` already are watched.
+    pub fn watch_read(&mut self, address: Address) -> bool {
+        self.watched_reads.insert(address)
+    }
+
+    /// Make [`Machine::run_until_halt`] stop after an instruction that writes `address`, including
+    /// through `M`, the stack and 16-bit stores, even if the value doesn't change. Returns `false`
+    /// if writes to `address` already are watched.
+    pub fn watch_write(&mut self, address: Address) -> bool {
+        self.watched_writes.insert(address)
+    }
+
+    /// Stop watching reads and writes of `address`. Returns `false` if neither was watched.
+    pub fn unwatch(&mut self, address: Address) -> bool {
+        let read = self.watched_reads.remove(&address);
+        let write = self.watched_writes.remove(&address);
+        read || write
+    }
+
+    /// Where [`Machine::reset`] restarts the program, set by [`MachineBuilder::build`] to the
+    /// program counter of the built machine. 0 for machines created otherwise.
+    pub fn entry(&self) -> Address {
+        self.entry
+    }
+
+    pub fn set_entry(&mut self, entry: Address) {
+        self.entry = entry;
+    }
+
+    /// Restart the program at [`Machine::entry`] without reloading it. Registers, flags, SP, the
+    /// cycle count and [`Machine::output_buffer`] are cleared, interrupts are disabled and the
+    /// machine is running again. Memory, queued input and attached devices are left as they are,
+    /// so a program that modifies itself or its data may run differently the second time.
+    pub fn reset(&mut self) {
+        self.registers = RegisterMap::new();
+        self.conditions = ConditionRegisters::new();
+        self.pc = self.entry;
+        self.stopped_at = None;
+        self.state = MachineState::Running;
+        self.output.clear();
+        self.cycles = 0;
+        self.instructions = 0;
+        self.interrupt_enable = InterruptEnable::Disabled;
+        self.interrupt = None;
+        self.interrupt_mask = 0;
+        self.serial_output = false;
+        self.clear_undo_records();
+    }
+
+    /// [`Machine::reset`] the machine and zero all of memory, which also forgets the
+    /// [loaded segments](Machine::loaded_ranges).
+    pub fn reset_full(&mut self) {
+        self.reset();
+        self.memory.0.fill(0);
+        self.loaded.clear();
+    }
+
+    /// Queue bytes to be read by `IN 0` before falling back to the input source. A machine
+    /// [waiting for input](MachineState::WaitingForInput) starts running again.
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+        if self.state == MachineState::WaitingForInput && !self.input.is_empty() {
+            self.state = MachineState::Running;
+        }
+    }
+
+    /// Whether `IN 0` finding no input puts the machine in [`MachineState::WaitingForInput`]
+    /// instead of halting it, off by default. Lets a UI ask for input instead of blocking on an
+    /// input source.
+    pub fn set_wait_for_input(&mut self, wait: bool) {
+        self.wait_for_input = wait;
+    }
+
+    /// Whether 16-bit memory accesses and stack operations that run past 0xFFFF halt the machine,
+    /// off by default. The 8080 wraps them around to 0x0000, so that e.g. `LHLD 0FFFFH` reads H
+    /// from 0x0000 and `PUSH` with SP at 0x0000 writes 0xFFFE and 0xFFFF. In strict mode they halt
+    /// with [`HaltReason::MemoryOverflow`], [`HaltReason::StackOverflow`] or
+    /// [`HaltReason::StackUnderflow`] instead, to catch programs that do it by mistake.
+    pub fn set_strict_memory(&mut self, strict: bool) {
+        self.strict_memory = strict;
+    }
+
+    /// Halt with [`HaltReason::StackOverflow`] when a push would move SP below `lowest`, e.g.
+    /// because the stack is about to grow into the program's code or data. Only pushes that wrap
+    /// below 0x0000 are caught without a limit, and only in strict mode.
+    pub fn set_stack_limit(&mut self, lowest: Address) {
+        self.stack_limit = Some(lowest);
+    }
+
+    /// Halt with [`HaltReason::StackUnderflow`] when a pop would move SP above `highest`, usually
+    /// the address the stack pointer starts at.
+    pub fn set_stack_ceiling(&mut self, highest: Address) {
+        self.stack_ceiling = Some(highest);
+    }
+
+    /// Remove the limits set with [`Machine::set_stack_limit`] and [`Machine::set_stack_ceiling`].
+    pub fn clear_stack_limits(&mut self) {
+        self.stack_limit = None;
+        self.stack_ceiling = None;
+    }
+
+    pub fn stack_limit(&self) -> Option<Address> {
+        self.stack_limit
+    }
+
+    pub fn stack_ceiling(&self) -> Option<Address> {
+        self.stack_ceiling
+    }
+
+    /// The addresses between the stack limit and ceiling, for drawing the stack. Either end is
+    /// open if its limit isn't set, and there is no region if neither is.
+    pub fn stack_region(&self) -> Option<Range<usize>> {
+        if self.stack_limit.is_none() && self.stack_ceiling.is_none() {
+            return None;
+        }
+        let start = self.stack_limit.map_or(0, usize::from);
+        let end = self.stack_ceiling.map_or(0x10000, usize::from);
+        Some(start..end)
+    }
+
+    /// Whether a `JMP` or taken conditional jump to its own address, like the `HERE: JMP HERE`
+    /// many programs end with, halts the machine with [`HaltReason::SelfJump`], off by default.
+    /// The program counter stays at the jump. Without it such a program runs until the
+    /// instruction budget runs out.
+    pub fn set_halt_on_self_jump(&mut self, halt: bool) {
+        self.halt_on_self_jump = halt;
+    }
+
+    /// Whether the undocumented opcodes halt the machine, the default, or execute the documented
+    /// instructions they alias, e.g. `RET` for 0xD9, which some existing programs rely on.
+    pub fn set_undocumented_opcodes(&mut self, policy: UndocumentedPolicy) {
+        self.undocumented_opcodes = policy;
+    }
+
+    /// Which processor's instruction set to execute, the 8080 by default. As an 8085 the machine
+    /// executes `RIM` and `SIM` at 0x20 and 0x30, which are otherwise undocumented opcodes handled
+    /// like the rest of them.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// The 8085 interrupt mask in the low 3 bits, for RST 5.5, 6.5 and 7.5 from bit 0 up, as last
+    /// set by `SIM`. Only `RIM` reads it, since those interrupt lines aren't emulated.
+    pub fn interrupt_mask(&self) -> u8 {
+        self.interrupt_mask
+    }
+
+    /// The 8085 serial output line, as last set by `SIM`.
+    pub fn serial_output(&self) -> bool {
+        self.serial_output
+    }
+
+    /// Set the function `IN 0` reads from once the input queue is empty, e.g. the host's stdin.
+    /// Returning `None` signals the end of input, which halts the machine. Without an input source
+    /// the end of the queue is the end of input.
+    pub fn set_input_source(&mut self, source: impl FnMut() -> Option<u8> + Send + 'static) {
+        self.input_source = Some(Box::new(source));
+    }
+
+    /// Send program output to `callback` instead of collecting it in [`Machine::output_buffer`].
+    pub fn set_output_callback(&mut self, callback: impl FnMut(&[u8]) + Send + 'static) {
+        self.output_sink = OutputSink::Callback(Box::new(callback));
+    }
+
+    /// Write program output to `writer` as the program writes it, e.g. to [`std::io::stdout`],
+    /// instead of collecting it in [`Machine::output_buffer`]. Each write is flushed. Once a write
+    /// fails, later output is dropped and the error is kept for [`Machine::take_output_error`].
+    #[cfg(feature = "std")]
+    pub fn set_output(&mut self, writer: impl std::io::Write + Send + 'static) {
+        self.output_sink = OutputSink::Writer(Box::new(writer));
+        self.output_error = None;
+    }
+
+    /// The first write to the writer of [`Machine::set_output`] that failed, if any. Taking it
+    /// lets output be written again.
+    #[cfg(feature = "std")]
+    pub fn take_output_error(&mut self) -> Option<std::io::Error> {
+        self.output_error.take()
+    }
+
+    /// Output the program has written so far, unless it went to an output writer or callback.
+    pub fn output_buffer(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Take the output of [`Machine::output_buffer`], leaving it empty.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.output)
+    }
+
+    /// Take the next byte of input from the queue, falling back to the input source. `None` means
+    /// the input has ended.
+    pub fn read_input(&mut self) -> Option<u8> {
+        let byte = self
+            .input
+            .pop_front()
+            .or_else(|| self.input_source.as_mut().and_then(|source| source()))?;
+        if let Some(history) = &mut self.rewind {
+            history.note_input(byte);
+        }
+        Some(byte)
+    }
+
+    /// Whether [`Machine::read_input`] has a byte to return. If the queue is empty this waits for
+    /// a byte from the input source and queues it.
+    pub fn input_available(&mut self) -> bool {
+        if self.input.is_empty()
+            && let Some(byte) = self.input_source.as_mut().and_then(|source| source())
+        {
+            self.input.push_back(byte);
+        }
+        !self.input.is_empty()
+    }
+
+    /// Write program output to the output writer or callback, or to
+    /// [`Machine::output_buffer`] if there is none.
+    pub fn write_output(&mut self, bytes: &[u8]) {
+        match &mut self.output_sink {
+            OutputSink::Buffer => self.output.extend_from_slice(bytes),
+            OutputSink::Callback(callback) => callback(bytes),
+            #[cfg(feature = "std")]
+            OutputSink::Writer(writer) => {
+                if self.output_error.is_none()
+                    && let Err(err) = writer.write_all(bytes).and_then(|()| writer.flush())
+                {
+                    self.output_error = Some(err);
+                }
+            }
+        }
+    }
+
+    /// Restart the numbers read with `IN 1` from `seed`. Machines start with
+    /// [`devices::DEFAULT_SEED`].
+    pub fn set_random_seed(&mut self, seed: u32) {
+        self.random = Random::new(1, seed);
+    }
+
+    /// Map `device` at `ports`, replacing earlier devices and the built-in behavior of those ports.
+    pub fn attach_device(&mut self, ports: &[Port], device: Box<dyn IoDevice>) {
+        self.io.attach(ports, device);
+    }
+
+    /// Set the value `IN` reads from ports that have no device attached and aren't built in, 0 by
+    /// default. Real hardware typically reads 0xFF from a floating bus.
+    pub fn set_unmapped_input(&mut self, value: Data8) {
+        self.unmapped_input = value;
+    }
+
+    /// The first attached device of type `T`, e.g. to show the screen of a
+    /// [`devices::TextDisplay`].
+    pub fn device<T: IoDevice>(&self) -> Option<&T> {
+        self.io.device()
+    }
+
+    fn device_read(&mut self, port: Port) -> Option<Data8> {
+        let mut io = core::mem::take(&mut self.io);
+        let byte = io.read(port, self);
+        self.io = io;
+        byte
+    }
+
+    fn device_write(&mut self, port: Port, value: Data8) {
+        let mut io = core::mem::take(&mut self.io);
+        io.write(port, value, self);
+        self.io = io;
+    }
+
+    fn tick_devices(&mut self) {
+        if self.io.is_empty() {
+            return;
+        }
+        let mut io = core::mem::take(&mut self.io);
+        io.tick(self);
+        self.io = io;
+    }
+
+    /// Attach an observer that is notified around every executed instruction.
+    pub fn add_observer(&mut self, observer: Box<dyn ExecutionObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Start recording which opcodes are executed, where and how often, see [`Machine::coverage`].
+    /// Does nothing if coverage is already being recorded.
+    pub fn enable_coverage(&mut self) {
+        if self.coverage().is_none() {
+            self.add_observer(Box::new(CoverageObserver(CoverageReport::new())));
+        }
+    }
+
+    /// The opcodes executed since [`Machine::enable_coverage`] was called, or `None` if it wasn't.
+    pub fn coverage(&self) -> Option<&CoverageReport> {
+        self.observers.iter().find_map(|observer| {
+            (observer.as_ref() as &dyn Any)
+                .downcast_ref::<CoverageObserver>()
+                .map(|coverage| &coverage.0)
+        })
+    }
+
+    /// Start counting how often every address and opcode is executed and which way conditional
+    /// instructions go, see [`Machine::profile`]. Does nothing if the machine is already being
+    /// profiled. Without observers, e.g. after [`Machine::disable_profiling`], stepping only pays
+    /// for checking that there are none.
+    pub fn enable_profiling(&mut self) {
+        if self.profile().is_none() {
+            self.add_observer(Box::new(ProfileObserver(ProfileReport::new())));
+        }
+    }
+
+    /// Stop profiling, returning what was recorded, or `None` if the machine wasn't profiled.
+    pub fn disable_profiling(&mut self) -> Option<ProfileReport> {
+        let index = self
+            .observers
+            .iter()
+            .position(|observer| (observer.as_ref() as &dyn Any).is::<ProfileObserver>())?;
+        let observer: Box<dyn Any> = self.observers.remove(index);
+        observer
+            .downcast::<ProfileObserver>()
+            .ok()
+            .map(|profile| profile.0)
+    }
+
+    /// The executions since [`Machine::enable_profiling`] was called, or `None` if it wasn't.
+    pub fn profile(&self) -> Option<&ProfileReport> {
+        self.observers.iter().find_map(|observer| {
+            (observer.as_ref() as &dyn Any)
+                .downcast_ref::<ProfileObserver>()
+                .map(|profile| &profile.0)
+        })
+    }
+
+    /// Detach and return all observers, e.g. to flush their output.
+    pub fn take_observers(&mut self) -> Vec<Box<dyn ExecutionObserver>> {
+        core::mem::take(&mut self.observers)
     }
 
     #[must_use]
     pub fn stack_push(&mut self, data: Data16) -> Option<()> {
-        let new_sp = self.register_16(RegisterPair::Sp).checked_sub(2)?;
+        let sp = self.registers.sp;
+        if let Some(limit) = self.stack_limit
+            && sp.checked_sub(2).is_none_or(|new_sp| new_sp < limit)
+        {
+            return None;
+        }
+        let new_sp = if self.strict_memory {
+            self.registers.sp.checked_sub(2)?
+        } else {
+            self.registers.sp.wrapping_sub(2)
+        };
 
-        self.memory.write_16(new_sp.value(), data)?;
-        self.registers.set_16(RegisterPair::Sp, new_sp);
+        self.store_16(new_sp, data)?;
+        self.registers.sp = new_sp;
 
         Some(())
     }
 
     pub fn stack_pop(&mut self) -> Option<Data16> {
-        let value = self
-            .memory
-            .read_16(self.register_16(RegisterPair::Sp).value())?;
-        self.registers.set_16(
-            RegisterPair::Sp,
-            self.register_16(RegisterPair::Sp).checked_add(2)?,
-        );
+        let sp = self.registers.sp;
+        if let Some(ceiling) = self.stack_ceiling
+            && sp.checked_add(2).is_none_or(|new_sp| new_sp > ceiling)
+        {
+            return None;
+        }
+        let value = self.load_16(self.registers.sp)?;
+        self.registers.sp = if self.strict_memory {
+            self.registers.sp.checked_add(2)?
+        } else {
+            self.registers.sp.wrapping_add(2)
+        };
 
         Some(value)
     }
 
-    fn get_status_word(&self) -> Data16 {
-        let cy_flag = self.conditions.get(ConditionRegister::Carry) as u8;
-        let p_flag = self.conditions.get(ConditionRegister::Parity) as u8;
-        let ac_flag = self.conditions.get(ConditionRegister::AuxiliaryCarry) as u8;
-        let z_flag = self.conditions.get(ConditionRegister::Zero) as u8;
-        let s_flag = self.conditions.get(ConditionRegister::Sign) as u8;
-        let low = 0b0000_0000
-            | cy_flag
-            | (1 << 1)
-            | (p_flag << 2)
-            | (0 << 3)
-            | (ac_flag << 4)
-            | (0 << 5)
-            | (z_flag << 6)
-            | (s_flag << 7);
-        let high = self.registers.get_8(Register::A, &self.memory);
-        Data16 { low, high }
+    /// Jump to `address`, unless it's the jump's own address and the machine halts on those.
+    fn jump(&mut self, address: Address) -> ExecutionResult {
+        if self.halt_on_self_jump && address == self.pc {
+            return ExecutionResult::SelfJump;
+        }
+        self.pc = address;
+        ExecutionResult::ControlTransfer
+    }
+
+    /// Write the flags set by an [`alu`] operation to the condition registers.
+    fn apply_flags(&mut self, flags: Flags) {
+        self.conditions.set(ConditionRegister::Zero, flags.zero);
+        self.conditions.set(ConditionRegister::Sign, flags.sign);
+        self.conditions.set(ConditionRegister::Parity, flags.parity);
+        self.conditions.set(ConditionRegister::AuxiliaryCarry, flags.aux_carry);
+        if let Some(carry) = flags.carry {
+            self.conditions.set(ConditionRegister::Carry, carry);
+        }
+    }
+
+    /// Set A to the result of an [`alu`] operation and apply its flags.
+    fn set_a_with_flags(&mut self, (result, flags): (Data8, Flags)) {
+        self.registers.set_a(result);
+        self.apply_flags(flags);
+    }
+
+    /// The PSW as `PUSH PSW` pushes it: the accumulator in the high byte and the flags in the low
+    /// byte, see [`ConditionRegisters::to_psw_byte`].
+    pub fn get_status_word(&self) -> Data16 {
+        Data16 {
+            low: self.conditions.to_psw_byte(),
+            high: self.registers.a(),
+        }
     }
-    
-    fn set_status_word(&mut self, data: Data16) {
-        let Data16 { low, high } = data;
 
-        let cy_flag = low & 0b0000_0001;
-        let p_flag = (low >> 2) & 0b0000_0001;
-        let ac_flag = (low >> 4) & 0b0000_0001;
-        let z_flag = (low >> 6) & 0b0000_0001;
-        let s_flag = (low >> 7) & 0b0000_0001;
-        self.conditions.set(ConditionRegister::Carry, cy_flag == 1);
-        self.conditions.set(ConditionRegister::Parity, p_flag == 1);
-        self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag == 1);
-        self.conditions.set(ConditionRegister::Zero, z_flag == 1);
-        self.conditions.set(ConditionRegister::Sign, s_flag == 1);
+    /// Set the accumulator and flags from a PSW, like `POP PSW`.
+    pub fn set_status_word(&mut self, data: Data16) {
+        self.conditions = ConditionRegisters::from_psw_byte(data.low);
+        self.registers.set_a(data.high);
+    }
 
-        self.registers.set_8(Register::A, high, &mut self.memory);
+    /// Execute a single instruction and return what happened, like [`Machine::step`].
+    #[inline]
+    pub fn run_cycle(&mut self) -> Option<StepInfo> {
+        self.step()
     }
 
-    pub fn run_cycle(&mut self) {
+    /// Execute a single instruction. Returns `None` without doing anything if the machine has
+    /// already halted, or is waiting for input and [`Machine::input_available`] finds none.
+    #[inline]
+    pub fn step(&mut self) -> Option<StepInfo> {
+        self.stopped_at = None;
+        self.watched_access = None;
+        if self.state == MachineState::WaitingForInput && self.input_available() {
+            self.state = MachineState::Running;
+        }
         match self.state {
-            MachineState::Halted(_) => {}
-            MachineState::Running => {
-                self.state = self.load_execute();
-            }
+            MachineState::Halted(_) | MachineState::WaitingForInput => None,
+            MachineState::Running if self.observers.is_empty() => Some(self.execute_next()),
+            MachineState::Running => Some(self.execute_observed()),
         }
     }
 
-    fn load_execute(&mut self) -> MachineState {
-        let mut stream = Reader::new(&self.memory().0[self.pc().value() as usize..]);
+    /// Execute the instruction at the program counter, notifying the observers before and after.
+    fn execute_observed(&mut self) -> StepInfo {
+        let mut observers = core::mem::take(&mut self.observers);
+        let instruction = self
+            .interrupt
+            .or_else(|| self.cpm_instruction())
+            .or_else(|| self.load());
+        for observer in observers.iter_mut() {
+            observer.before_step(self, instruction.as_ref());
+        }
 
-        let Some(instruction) = coding::decode(&mut stream) else {
-            return MachineState::Halted(HaltReason::InvalidInstruction);
-        };
-        let instruction_len = stream.read_amount_bytes() as u16;
+        let step = self.execute_next();
 
-        let result = self.execute(instruction);
-        if matches!(result, ExecutionResult::Running) {
-            self.pc = self.pc.value().wrapping_add(instruction_len).into();
+        for observer in observers.iter_mut() {
+            observer.after_step(self, &step);
         }
+        self.observers = observers;
 
-        match result {
-            ExecutionResult::Running => MachineState::Running,
-            ExecutionResult::ControlTransfer => MachineState::Running,
-            ExecutionResult::Halt => MachineState::Halted(HaltReason::HaltInstruction),
-            ExecutionResult::StackOverflow => MachineState::Halted(HaltReason::StackOverflow),
-            ExecutionResult::StackUnderflow => MachineState::Halted(HaltReason::StackUnderflow),
-            ExecutionResult::MemoryOverflow => MachineState::Halted(HaltReason::MemoryOverflow),
+        step
+    }
+
+    /// Execute the instruction at the program counter, without notifying observers.
+    #[inline]
+    fn execute_next(&mut self) -> StepInfo {
+        if self.is_plain() {
+            self.execute_plain()
+        } else {
+            self.execute_with_extras()
         }
     }
+
+    /// [`Machine::execute_next`] for a step that isn't plain, see [`Machine::is_plain`].
+    fn execute_with_extras(&mut self) -> StepInfo {
+        let pc_before: Data16 = self.pc.into();
+        self.begin_undo_record();
+        let delayed = self.interrupt_enable == InterruptEnable::Delayed;
+        let (instruction, result) = match self.interrupt.take() {
+            Some(instruction) => (Some(instruction), self.execute_interrupt(instruction)),
+            None => match self.cpm_instruction() {
+                Some(instruction) => (Some(instruction), self.execute_cpm(instruction)),
+                None => self.load_execute(),
+            },
+        };
+        if delayed && self.interrupt_enable == InterruptEnable::Delayed {
+            self.interrupt_enable = InterruptEnable::Enabled;
+        }
+        self.state = result.machine_state();
+        self.tick_devices();
+        self.end_undo_record();
+        if result == ExecutionResult::ControlTransfer
+            && let Some(hook) = &mut self.hooks.control_transfer
+        {
+            hook(pc_before.value(), self.pc);
+        }
+        if let (MachineState::Halted(reason), Some(hook)) = (self.state, &mut self.hooks.halt) {
+            hook(reason);
+        }
+        #[cfg(feature = "trace-log")]
+        self.trace_halt(pc_before);
+        #[cfg(feature = "std")]
+        if let Some(publisher) = &mut self.shared_memory {
+            publisher.after_step(&self.memory, self.state != MachineState::Running);
+        }
+        StepInfo {
+            pc_before,
+            instruction,
+            result,
+        }
+    }
+
+    /// Whether the next step only executes the instruction at the program counter: there's no
+    /// accepted interrupt or CP/M call to execute instead, and no undo record, device, hook or
+    /// shared memory to update.
+    #[inline]
+    fn is_plain(&self) -> bool {
+        #[cfg(feature = "std")]
+        if self.shared_memory.is_some() {
+            return false;
+        }
+        self.interrupt.is_none()
+            && !self.cpm_shim
+            && self.rewind.is_none()
+            && self.io.is_empty()
+            && self.hooks.control_transfer.is_none()
+            && self.hooks.halt.is_none()
+    }
+
+    /// [`Machine::execute_next`] for a plain step, see [`Machine::is_plain`].
+    #[inline]
+    fn execute_plain(&mut self) -> StepInfo {
+        let pc_before: Data16 = self.pc.into();
+        let delayed = self.interrupt_enable == InterruptEnable::Delayed;
+        let (instruction, result) = self.load_execute();
+        if delayed && self.interrupt_enable == InterruptEnable::Delayed {
+            self.interrupt_enable = InterruptEnable::Enabled;
+        }
+        self.state = result.machine_state();
+        #[cfg(feature = "trace-log")]
+        self.trace_halt(pc_before);
+        StepInfo {
+            pc_before,
+            instruction,
+            result,
+        }
+    }
+
+    /// Log the machine halting at `pc`.
+    #[cfg(feature = "trace-log")]
+    fn trace_halt(&self, pc: Data16) {
+        match self.state {
+            MachineState::Running | MachineState::WaitingForInput => {}
+            MachineState::Halted(HaltReason::HaltInstruction) => {
+                tracing::debug!(pc = pc.value(), "machine halted");
+            }
+            MachineState::Halted(reason) => {
+                tracing::warn!(pc = pc.value(), reason = %reason, "machine faulted");
+            }
+        }
+    }
+
+    /// Iterate over executed instructions until the machine halts, or stops at a breakpoint or
+    /// watchpoint, see [`Steps`]. The step that halts the machine is the last one yielded.
+    ///
+    /// ```
+    /// use rsoderh_jonsh_leben_emulator::machine::MachineBuilder;
+    ///
+    /// // 0000: MVI B, 3
+    /// // 0002: DCR B
+    /// // 0003: JNZ 0002H
+    /// // 0006: HLT
+    /// let mut machine = MachineBuilder::new()
+    ///     .program(&[0x06, 0x03, 0x05, 0xC2, 0x02, 0x00, 0x76], 0x0000)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // Run until the first time the loop is left.
+    /// let executed = machine
+    ///     .steps()
+    ///     .take_while(|step| step.pc_before.value() != 0x0006)
+    ///     .count();
+    /// assert_eq!(executed, 1 + 3 * 2);
+    /// ```
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps {
+            machine: self,
+            stopped: None,
+        }
+    }
+
+    /// Execute instructions until the machine halts, waits for input, reaches a breakpoint,
+    /// accesses a watched address or has executed `max_instructions` instructions. Returns why it
+    /// stopped and the number of executed instructions.
+    ///
+    /// A machine that halts with its last instruction of the budget counts as halted. Running
+    /// again after stopping at a breakpoint executes the instruction there, so the breakpoint is
+    /// hit again the next time the program counter reaches it.
+    pub fn run_until_halt(&mut self, max_instructions: u64) -> (RunOutcome, u64) {
+        self.run_until(max_instructions, |_, _| None)
+    }
+
+    /// Execute instructions until the subroutine the machine is in returns, stopping early like
+    /// [`Machine::run_until_halt`]. Lets a debugger step out of a subroutine it stepped into.
+    ///
+    /// The subroutine is taken to have returned once a control transfer leaves SP above its value
+    /// at the start, so that the return address has been popped. This follows the stack rather
+    /// than looking for `RET`, so nested calls are run through and returns through `PCHL` are
+    /// caught too.
+    pub fn run_until_return(&mut self, max_instructions: u64) -> RunOutcome {
+        let entry_sp = self.registers.sp;
+        let (outcome, _) = self.run_until(max_instructions, |machine, step| {
+            // Signed, for stacks wrapping around past 0xFFFF.
+            let depth = machine.registers.sp.wrapping_sub(entry_sp) as i16;
+            (step.result == ExecutionResult::ControlTransfer && depth > 0)
+                .then_some(RunOutcome::Returned(machine.pc))
+        });
+        outcome
+    }
+
+    /// Execute the instruction at the program counter like [`Machine::run_cycle`], except that a
+    /// `CALL`, taken conditional call or `RST` is run until the subroutine returns to the
+    /// instruction after it, stopping early like [`Machine::run_until_halt`].
+    ///
+    /// The call only counts as returned once SP is back where it was, so a recursive call
+    /// returning to the same address deeper in the stack doesn't stop it.
+    pub fn step_over(&mut self, max_instructions: u64) -> RunOutcome {
+        let (outcome, _) = match self.load() {
+            Some(call @ (Instruction::Call(_) | Instruction::Ccc(..) | Instruction::Rst(_))) => {
+                let next = self.pc.wrapping_add(call.length() as u16);
+                let sp = self.registers.sp;
+                self.run_until(max_instructions, |machine, _| {
+                    (machine.pc == next && machine.registers.sp == sp)
+                        .then_some(RunOutcome::Stepped(next))
+                })
+            }
+            _ => self.run_until(max_instructions.min(1), |machine, _| {
+                (machine.state == MachineState::Running).then_some(RunOutcome::Stepped(machine.pc))
+            }),
+        };
+        outcome
+    }
+
+    /// Run like [`Machine::run_until_halt`], but also stop after an instruction for which
+    /// `stop` returns an outcome.
+    fn run_until(
+        &mut self,
+        max_instructions: u64,
+        mut stop: impl FnMut(&Self, &StepInfo) -> Option<RunOutcome>,
+    ) -> (RunOutcome, u64) {
+        let mut executed = 0;
+        while executed < max_instructions {
+            if let Some(address) = self.stop_at_breakpoint() {
+                return (RunOutcome::Breakpoint(address), executed);
+            }
+            let Some(step) = self.step() else {
+                break;
+            };
+            executed += 1;
+            if let Some(hit) = self.watch_hit(&step) {
+                return (RunOutcome::Watchpoint(hit), executed);
+            }
+            if let Some(outcome) = stop(self, &step) {
+                return (outcome, executed);
+            }
+        }
+        let outcome = match self.state {
+            MachineState::Running => RunOutcome::BudgetExhausted,
+            MachineState::WaitingForInput => RunOutcome::WaitingForInput,
+            MachineState::Halted(reason) => RunOutcome::Halted(reason),
+        };
+        (outcome, executed)
+    }
+
+    /// The address of the breakpoint the program counter is at, if the instruction there is to be
+    /// executed next and it hasn't been stopped at yet. Stopping there lets running again execute
+    /// the instruction.
+    fn stop_at_breakpoint(&mut self) -> Option<Address> {
+        if self.state == MachineState::Running
+            && self.interrupt.is_none()
+            && self.stopped_at != Some(self.pc)
+            && self.breakpoints.contains(&self.pc)
+        {
+            self.stopped_at = Some(self.pc);
+            return Some(self.pc);
+        }
+        None
+    }
+
+    /// The access to a watched address by the instruction of `step`, the last executed one.
+    fn watch_hit(&self, step: &StepInfo) -> Option<WatchHit> {
+        let (address, access, value) = self.watched_access?;
+        Some(WatchHit {
+            pc: step.pc_before.value(),
+            instruction: step.instruction?,
+            address,
+            access,
+            value,
+        })
+    }
+
+    /// Execute up to `n` instructions, stopping early like [`Machine::run_until_halt`]. Returns
+    /// the number of executed instructions and the state the machine is left in.
+    ///
+    /// Without observers, breakpoints and watchpoints the instructions run in a loop that only
+    /// checks the state of the machine in between, which is faster than calling
+    /// [`Machine::run_cycle`] `n` times.
+    pub fn run_cycles(&mut self, n: u64) -> (u64, MachineState) {
+        if !self.observers.is_empty()
+            || !self.breakpoints.is_empty()
+            || !self.watched_reads.is_empty()
+            || !self.watched_writes.is_empty()
+        {
+            let (_, executed) = self.run_until_halt(n);
+            return (executed, self.state);
+        }
+
+        self.stopped_at = None;
+        if self.state == MachineState::WaitingForInput && self.input_available() {
+            self.state = MachineState::Running;
+        }
+        // Nothing a plain step executes can attach a device or hook, or request an interrupt.
+        let plain = self.is_plain();
+        let mut executed = 0;
+        while executed < n && self.state == MachineState::Running {
+            if plain {
+                self.execute_plain();
+            } else {
+                self.execute_next();
+            }
+            executed += 1;
+        }
+        (executed, self.state)
+    }
+
+    /// Execute the instruction at the program counter with the handler of its opcode, see
+    /// [`dispatch`].
+    #[inline]
+    fn load_execute(&mut self) -> (Option<Instruction>, ExecutionResult) {
+        // Fetched before executing, as the instruction may overwrite itself.
+        #[cfg(feature = "trace-log")]
+        let pc = self.pc;
+        let bytes = self.memory.fetch(self.pc);
+        let [opcode, low, high] = bytes;
+        let (instruction, result) = dispatch::handler_of(opcode)(self, low, high);
+        #[cfg(feature = "trace-log")]
+        if let Some(instruction) = &instruction
+            && result != ExecutionResult::WaitingForInput
+            && tracing::enabled!(tracing::Level::TRACE)
+        {
+            let bytes = &bytes[..instruction.length() as usize];
+            let line = crate::trace::format_executed(pc, bytes, Some(instruction), self);
+            tracing::trace!("{}", line);
+        }
+
+        (instruction, result)
+    }
+
+    /// Decode and execute the instruction at the program counter, for the opcodes whose
+    /// instruction depends on [`Machine::set_variant`] and [`Machine::set_undocumented_opcodes`].
+    fn decode_execute(&mut self) -> (Option<Instruction>, ExecutionResult) {
+        let Some(instruction) = self.load() else {
+            return (None, ExecutionResult::InvalidInstruction);
+        };
+        let result = self.execute(instruction);
+        if matches!(result, ExecutionResult::Running) {
+            self.pc = self.pc.wrapping_add(instruction.length() as u16);
+        }
+        (Some(instruction), result)
+    }
     
+    /// Execute the instruction of an accepted interrupt. It doesn't come from memory, so the
+    /// program counter isn't moved past it, and `RST` and `CALL` push the address of the
+    /// interrupted instruction.
+    fn execute_interrupt(&mut self, instruction: Instruction) -> ExecutionResult {
+        let pc = self.pc;
+        self.pc = pc.wrapping_sub(instruction.length() as u16);
+        let result = self.execute(instruction);
+        if result != ExecutionResult::ControlTransfer {
+            self.pc = pc;
+        }
+        result
+    }
+
+    /// Decode the instruction at the program counter, following [`Machine::set_variant`] and
+    /// [`Machine::set_undocumented_opcodes`].
     pub fn load(&self) -> Option<Instruction> {
-        let mut stream = Reader::new(&self.memory().0[self.pc().value() as usize..]);
-        coding::decode(&mut stream)
+        // Decoding in place is faster than copying the bytes out, which is only needed where the
+        // instruction wraps around past 0xFFFF.
+        let pc = self.pc as usize;
+        let fetched;
+        let bytes = if pc <= 0xFFFD {
+            &self.memory.0[pc..pc + 3]
+        } else {
+            fetched = self.memory.fetch(self.pc);
+            &fetched[..]
+        };
+        let aliases = self.undocumented_opcodes == UndocumentedPolicy::Alias;
+        coding::decode_variant(&mut Reader::new(bytes), self.variant, aliases)
+    }
+
+    /// Whether an interrupt requested now would be accepted. Interrupts are disabled at the start,
+    /// by `DI` and by accepting an interrupt, and enabled by `EI` after the instruction following
+    /// it.
+    pub fn interrupts_enabled(&self) -> bool {
+        self.interrupt_enable == InterruptEnable::Enabled
+    }
+
+    /// Request an interrupt, which supplies `instruction` in place of the one at the program
+    /// counter, like the device acknowledging an interrupt on a real 8080 does. It's usually an
+    /// `RST`, which pushes the address of the interrupted instruction.
+    ///
+    /// Returns whether the interrupt was accepted. If it was, interrupts are disabled, a machine
+    /// halted by `HLT` or waiting for input starts running again, and `instruction` is executed by
+    /// the next step. The waiting `IN` is executed again when the interrupt returns.
+    pub fn request_interrupt(&mut self, instruction: Instruction) -> bool {
+        if !self.interrupts_enabled() || self.interrupt.is_some() {
+            return false;
+        }
+        match self.state {
+            MachineState::Running => {}
+            MachineState::WaitingForInput => self.state = MachineState::Running,
+            MachineState::Halted(HaltReason::HaltInstruction) => {
+                // The program counter is left at HLT, but the interrupted instruction is the one
+                // after it.
+                self.pc = self.pc.wrapping_add(1);
+                self.state = MachineState::Running;
+            }
+            MachineState::Halted(_) => return false,
+        }
+        self.interrupt_enable = InterruptEnable::Disabled;
+        self.interrupt = Some(instruction);
+        true
+    }
+
+    /// Count the extra clock periods of a conditional call or return whose condition holds.
+    fn add_taken_cycles(&mut self, instruction: Instruction) {
+        if let (cycles, Some(taken)) = instruction.cycles() {
+            self.cycles += (taken - cycles) as u64;
+        }
+    }
+
+    /// Note an access of the executing instruction to memory, if the address is watched.
+    fn note_access(&mut self, address: Address, access: MemoryAccess, value: Data8) {
+        let watched = match access {
+            MemoryAccess::Read => &self.watched_reads,
+            MemoryAccess::Write => &self.watched_writes,
+        };
+        if self.watched_access.is_none() && watched.contains(&address) {
+            self.watched_access = Some((address, access, value));
+        }
+    }
+
+    // Memory accesses of executing instructions go through these, so that watchpoints and mapped
+    // regions see them.
+
+    fn load_8(&mut self, address: Address) -> Data8 {
+        let value = self.read_byte(address);
+        self.note_access(address, MemoryAccess::Read, value);
+        value
+    }
+
+    fn store_8(&mut self, address: Address, value: Data8) {
+        self.note_overwrite(address);
+        self.write_byte(address, value);
+        self.note_access(address, MemoryAccess::Write, value);
+    }
+
+    /// Read 16 bits, or `None` if they run past 0xFFFF in strict mode.
+    fn load_16(&mut self, address: Address) -> Option<Data16> {
+        if self.strict_memory {
+            address.checked_add(1)?;
+        }
+        let low = self.read_byte(address);
+        let value = Data16::new(low, self.read_byte(address.wrapping_add(1)));
+        self.note_access(address, MemoryAccess::Read, value.low);
+        self.note_access(address.wrapping_add(1), MemoryAccess::Read, value.high);
+        Some(value)
+    }
+
+    /// Write 16 bits, or return `None` without writing if they run past 0xFFFF in strict mode.
+    #[must_use]
+    fn store_16(&mut self, address: Address, value: Data16) -> Option<()> {
+        if self.strict_memory {
+            address.checked_add(1)?;
+        }
+        self.note_overwrite(address);
+        self.note_overwrite(address.wrapping_add(1));
+        self.write_byte(address, value.low);
+        self.write_byte(address.wrapping_add(1), value.high);
+        self.note_access(address, MemoryAccess::Write, value.low);
+        self.note_access(address.wrapping_add(1), MemoryAccess::Write, value.high);
+        Some(())
+    }
+
+    /// The value of a register operand, where `M` reads memory at HL.
+    #[inline]
+    fn operand(&mut self, register: Register) -> Data8 {
+        match register {
+            Register::M => self.load_8(self.registers.hl()),
+            register => self.registers.byte(register),
+        }
+    }
+
+    #[inline]
+    fn set_operand(&mut self, register: Register, value: Data8) {
+        match register {
+            Register::M => self.store_8(self.registers.hl(), value),
+            register => self.registers.set_byte(register, value),
+        }
     }
 
     fn execute(&mut self, instruction: Instruction) -> ExecutionResult {
+        self.cycles += instruction.cycles().0 as u64;
+        self.instructions += 1;
+        self.execute_uncounted(instruction)
+    }
+
+    /// Execute `instruction` without adding it to the executed instructions and cycles. Inlined
+    /// into the handlers of [`dispatch`], where `instruction` is a constant, so that only its own
+    /// arm is left.
+    #[inline(always)]
+    fn execute_uncounted(&mut self, instruction: Instruction) -> ExecutionResult {
         match instruction {
             Instruction::Mov(destination, source) => {
-                self.registers.set_8(
-                    destination,
-                    self.registers.get_8(source, &self.memory),
-                    &mut self.memory,
-                );
+                let value = self.operand(source);
+                self.set_operand(destination, value);
                 ExecutionResult::Running
             }
             Instruction::Mvi(destination, data) => {
-                self.registers.set_8(destination, data, &mut self.memory);
+                self.set_operand(destination, data);
                 ExecutionResult::Running
             }
             Instruction::Lxi(register_pair, data) => {
@@ -411,17 +1671,17 @@ impl Machine {
                 ExecutionResult::Running
             }
             Instruction::Lda(address) => {
-                let mem = self.memory.read_8(address);
-                self.registers.set_8(Register::A, mem, &mut self.memory);
+                let mem = self.load_8(address);
+                self.registers.set_a(mem);
                 ExecutionResult::Running
             },
             Instruction::Sta(address) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                self.memory.write_8(address, a);
+                let a = self.registers.a();
+                self.store_8(address, a);
                 ExecutionResult::Running
             },
             Instruction::Lhld(address) => {
-                let Some(mem) = self.memory.read_16(address) else {
+                let Some(mem) = self.load_16(address) else {
                     return ExecutionResult::MemoryOverflow;
                 };
                 self.registers.set_16(RegisterPair::Hl, mem);
@@ -429,20 +1689,20 @@ impl Machine {
             },
             Instruction::Shld(address) => {
                 let hl = self.registers.get_16(RegisterPair::Hl);
-                let res = self.memory.write_16(address, hl);
+                let res = self.store_16(address, hl);
                 if matches!(res, None) { return ExecutionResult::MemoryOverflow }
                 ExecutionResult::Running
             },
             Instruction::Ldax(register_pair_indirect) => {
-                let address = self.registers.get_16(register_pair_indirect.to_register_pair());
-                let mem = self.memory.read_8(address.into());
-                self.registers.set_8(Register::A, mem, &mut self.memory);
+                let address = self.registers.pair(register_pair_indirect.to_register_pair());
+                let mem = self.load_8(address);
+                self.registers.set_a(mem);
                 ExecutionResult::Running
             },
             Instruction::Stax(register_pair_indirect) => {
-                let address = self.registers.get_16(register_pair_indirect.to_register_pair());
-                let a = self.registers.get_8(Register::A, &self.memory);
-                self.memory.write_8(address.into(), a);
+                let address = self.registers.pair(register_pair_indirect.to_register_pair());
+                let a = self.registers.a();
+                self.store_8(address, a);
                 ExecutionResult::Running
             },
             Instruction::Xchg => {
@@ -453,465 +1713,161 @@ impl Machine {
                 ExecutionResult::Running
             },
             Instruction::Add(register) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                let term = self.registers.get_8(register, &self.memory);
-                
-                let result = (a as u16) + (term as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term, false);
-                let cy_flag = (result >> 8) & 0b1 == 1;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let term = self.operand(register);
+                self.set_a_with_flags(alu::add(self.registers.a(), term, false));
                 ExecutionResult::Running
             }
             Instruction::Adi(term) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                
-                let result = (a as u16) + (term as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term, false);
-                let cy_flag = (result >> 8) & 0b1 == 1;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                self.set_a_with_flags(alu::add(self.registers.a(), term, false));
                 ExecutionResult::Running
             }
             Instruction::Adc(register) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                let term = self.registers.get_8(register, &self.memory);
-                
-                let cy_flag = self.conditions.get(ConditionRegister::Carry);
-                let result = (a as u16) + (term as u16) + (cy_flag as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term, cy_flag);
-                let cy_flag = (result >> 8) & 0b1 == 1;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let term = self.operand(register);
+                let carry = self.conditions.get(ConditionRegister::Carry);
+                self.set_a_with_flags(alu::add(self.registers.a(), term, carry));
                 ExecutionResult::Running
             }
             Instruction::Aci(term) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                
-                let cy_flag = self.conditions.get(ConditionRegister::Carry);
-                let result = (a as u16) + (term as u16) + (cy_flag as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term, cy_flag);
-                let cy_flag = (result >> 8) & 0b1 == 1;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let carry = self.conditions.get(ConditionRegister::Carry);
+                self.set_a_with_flags(alu::add(self.registers.a(), term, carry));
                 ExecutionResult::Running
             }
             Instruction::Sub(register) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                let term = self.registers.get_8(register, &self.memory);
-
-                let term_complement = (!term).wrapping_add(1);
-                
-                let result = (a as u16) + (term_complement as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term_complement, false);
-                let cy_flag = (result >> 8) & 0b1 != 1;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let term = self.operand(register);
+                self.set_a_with_flags(alu::sub(self.registers.a(), term, false));
                 ExecutionResult::Running
             }
             Instruction::Sui(term) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-
-                let term_complement = (!term).wrapping_add(1);
-                
-                let result = (a as u16) + (term_complement as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term_complement, false);
-                let cy_flag = (result >> 8) & 0b1 != 1;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                self.set_a_with_flags(alu::sub(self.registers.a(), term, false));
                 ExecutionResult::Running
             }
             Instruction::Sbb(register) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                let term = self.registers.get_8(register, &self.memory);
-
-                let cy_flag = self.conditions.get(ConditionRegister::Carry);
-                let (term, borrow) = term.overflowing_add(cy_flag as u8);
-                let term_complement = (!term).wrapping_add(1);
-                
-                let result = (a as u16) + (term_complement as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term_complement, false);
-                let cy_flag = (result >> 8) & 0b1 != 1 || borrow;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let term = self.operand(register);
+                let borrow = self.conditions.get(ConditionRegister::Carry);
+                self.set_a_with_flags(alu::sub(self.registers.a(), term, borrow));
                 ExecutionResult::Running
             }
             Instruction::Sbi(term) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-
-                let cy_flag = self.conditions.get(ConditionRegister::Carry);
-                let (term, borrow) = term.overflowing_add(cy_flag as u8);
-                let term_complement = (!term).wrapping_add(1);
-                
-                let result = (a as u16) + (term_complement as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term_complement, false);
-                let cy_flag = (result >> 8) & 0b1 != 1 || !borrow;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let borrow = self.conditions.get(ConditionRegister::Carry);
+                self.set_a_with_flags(alu::sub(self.registers.a(), term, borrow));
                 ExecutionResult::Running
             }
             Instruction::Inr(register) => {
-                let value = self.registers.get_8(register, &self.memory);
-                
-                let result = value.wrapping_add(1);
-                let ac_flag = calc_ac_flag_add(value, 1, false);
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-                
-                self.registers.set_8(register, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let (result, flags) = alu::inc(self.operand(register));
+                self.set_operand(register, result);
+                self.apply_flags(flags);
                 ExecutionResult::Running
             }
             Instruction::Dcr(register) => {
-                let value = self.registers.get_8(register, &self.memory);
-                
-                let result = value.wrapping_sub(1);
-                let ac_flag = calc_ac_flag_add(value, 0b1111_1111, false);
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-                
-                self.registers.set_8(register, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let (result, flags) = alu::dec(self.operand(register));
+                self.set_operand(register, result);
+                self.apply_flags(flags);
                 ExecutionResult::Running
             }
             Instruction::Inx(register_pair) => {
-                let value: u16 = self.registers.get_16(register_pair).into();
+                let value = self.registers.pair(register_pair);
                 
                 let result = value.wrapping_add(1);
                 
-                self.registers.set_16(register_pair, result.into());
+                self.registers.set_pair(register_pair, result);
                 ExecutionResult::Running
             }
             Instruction::Dcx(register_pair) => {
-                let value: u16 = self.registers.get_16(register_pair).into();
+                let value = self.registers.pair(register_pair);
                 
                 let result = value.wrapping_sub(1);
                 
-                self.registers.set_16(register_pair, result.into());
+                self.registers.set_pair(register_pair, result);
                 ExecutionResult::Running
             }
             Instruction::Dad(register_pair) => {
-                let hl = self.registers.get_16(RegisterPair::Hl).value();
-                let term = self.registers.get_16(register_pair).value();
+                let hl = self.registers.hl();
+                let term = self.registers.pair(register_pair);
                 
                 let result = (hl as u32) + (term as u32);
                 let cy_flag = (result >> 16) & 0b1 == 1;
 
-                self.registers.set_16(RegisterPair::Hl, (result as u16).into());
+                self.registers.set_pair(RegisterPair::Hl, result as u16);
                 self.conditions.set(ConditionRegister::Carry, cy_flag);
                 ExecutionResult::Running
             }
             Instruction::Daa => {
-                // (Decimal Adjust Accumulator)
-                //
-                // The eight-bit number in the accumulator is adjusted
-                // to form two four-bit Binary-Coded-Decimal digits by
-                // the following process:
-                //
-                // 1. If the value of the least significant 4 bits of the
-                //    accumulator is greater than 9 or if the AC flag
-                //    is set, 6 is added to the accumulator.
-                //
-                // 2. If the value of the most significant 4 bits of the
-                //    accumulator is now greater than 9, or if the CY
-                //    flag is set, 6 is added to the most significant 4
-                //    bits of the accumulator
-                //
-                let mut ac_flag = self.conditions.get(ConditionRegister::AuxiliaryCarry);
-                let cy_flag = self.conditions.get(ConditionRegister::Carry);
-                let mut wrapped = false;
-                let mut a = self.registers.get_8(Register::A, &self.memory);
-                // 1.
-                let lsb = a & 0b0000_1111;
-                if lsb > 9 || ac_flag {
-                    ac_flag = lsb > 9;
-                    if a > 0b1111_1111 - 6 {
-                        wrapped = true;
-                    }
-                    a = a.wrapping_add(6);
-                } else {
-                    ac_flag = false;
-                }
-                // 2.
-                let msb = (a >> 4) & 0b0000_1111;
-                if msb > 9 || cy_flag {
-                    if a > 0b1111_1111 - (6 << 4) {
-                        wrapped = true;
-                    }
-                    a = a.wrapping_add(6 << 4);
-                }
-
-                let z_flag = a == 0;
-                let s_flag = a & 0b1000_0000 == 1;
-                let p_flag = is_even(a.count_ones());
-                let cy_flag = wrapped;
-
-                self.registers.set_8(Register::A, a, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let carry = self.conditions.get(ConditionRegister::Carry);
+                let aux_carry = self.conditions.get(ConditionRegister::AuxiliaryCarry);
+                self.set_a_with_flags(alu::daa(self.registers.a(), carry, aux_carry));
                 ExecutionResult::Running
-            },
+            }
             Instruction::Ana(register) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                let value = self.registers.get_8(register, &self.memory);
-                
-                let result = a & value;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, false);
+                let value = self.operand(register);
+                self.set_a_with_flags(alu::and(self.registers.a(), value));
                 ExecutionResult::Running
             }
             Instruction::Ani(value) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                
-                let result = a & value;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, false);
+                self.set_a_with_flags(alu::and(self.registers.a(), value));
                 ExecutionResult::Running
             }
             Instruction::Xra(register) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                let value = self.registers.get_8(register, &self.memory);
-                
-                let result = a ^ value;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, false);
+                let value = self.operand(register);
+                self.set_a_with_flags(alu::xor(self.registers.a(), value));
                 ExecutionResult::Running
             }
             Instruction::Xri(value) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                
-                let result = a ^ value;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, false);
+                self.set_a_with_flags(alu::xor(self.registers.a(), value));
                 ExecutionResult::Running
             }
             Instruction::Ora(register) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                let value = self.registers.get_8(register, &self.memory);
-                
-                let result = a | value;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, false);
+                let value = self.operand(register);
+                self.set_a_with_flags(alu::or(self.registers.a(), value));
                 ExecutionResult::Running
             }
             Instruction::Ori(value) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                
-                let result = a | value;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                self.registers.set_8(Register::A, result, &mut self.memory);
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, false);
+                self.set_a_with_flags(alu::or(self.registers.a(), value));
                 ExecutionResult::Running
             }
             Instruction::Cmp(register) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-                let term = self.registers.get_8(register, &self.memory);
-
-                let term_complement = (!term).wrapping_add(1);
-                
-                let result = (a as u16) + (term_complement as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term_complement, false);
-                let cy_flag = (result >> 8) & 0b1 != 1;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                // subtraction without actually storing the value
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let term = self.operand(register);
+                // Subtract without storing the result.
+                let (_, flags) = alu::sub(self.registers.a(), term, false);
+                self.apply_flags(flags);
                 ExecutionResult::Running
             }
             Instruction::Cpi(term) => {
-                let a = self.registers.get_8(Register::A, &self.memory);
-
-                let term_complement = (!term).wrapping_add(1);
-                
-                let result = (a as u16) + (term_complement as u16);
-
-                let ac_flag = calc_ac_flag_add(a, term_complement, false);
-                let cy_flag = (result >> 8) & 0b1 != 1;
-                let result = result as u8;
-                let z_flag = result == 0;
-                let s_flag = result & 0b1000_0000 == 1;
-                let p_flag = is_even(result.count_ones());
-
-                // subtraction without actually storing the value
-                self.conditions.set(ConditionRegister::Zero, z_flag);
-                self.conditions.set(ConditionRegister::Sign, s_flag);
-                self.conditions.set(ConditionRegister::Parity, p_flag);
-                self.conditions.set(ConditionRegister::Carry, cy_flag);
-                self.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+                let (_, flags) = alu::sub(self.registers.a(), term, false);
+                self.apply_flags(flags);
                 ExecutionResult::Running
             }
             Instruction::Rlc => {
-                let cy_flag = (self.registers.a >> 7) & 0b1 == 1;
-                self.registers.a = self.registers.a.wrapping_shl(1);
+                let cy_flag = (self.registers.a() >> 7) & 0b1 == 1;
+                self.registers.set_a(self.registers.a().rotate_left(1));
                 self.conditions.set(ConditionRegister::Carry, cy_flag);
                 ExecutionResult::Running
             },
             Instruction::Rrc => {
-                let cy_flag = self.registers.a & 0b1 == 1;
-                self.registers.a = self.registers.a.wrapping_shr(1);
+                let cy_flag = self.registers.a() & 0b1 == 1;
+                self.registers.set_a(self.registers.a().rotate_right(1));
                 self.conditions.set(ConditionRegister::Carry, cy_flag);
                 ExecutionResult::Running
             },
             Instruction::Ral => {
                 let cy_flag = self.conditions.get(ConditionRegister::Carry);
-                let new_cy_flag = (self.registers.a >> 7) & 0b1 == 1;
-                self.registers.a = self.registers.a.wrapping_shl(1);
-                self.registers.a &= cy_flag as u8;
+                let new_cy_flag = (self.registers.a() >> 7) & 0b1 == 1;
+                self.registers.set_a(self.registers.a().wrapping_shl(1));
+                self.registers.set_a(self.registers.a() | cy_flag as u8);
                 self.conditions.set(ConditionRegister::Carry, new_cy_flag);
                 ExecutionResult::Running
             },
             Instruction::Rar => {
                 let cy_flag = self.conditions.get(ConditionRegister::Carry);
-                let new_cy_flag = self.registers.a & 0b1 == 1;
-                self.registers.a = self.registers.a.wrapping_shr(1);
-                self.registers.a &= (cy_flag as u8) << 7;
+                let new_cy_flag = self.registers.a() & 0b1 == 1;
+                self.registers.set_a(self.registers.a().wrapping_shr(1));
+                self.registers.set_a(self.registers.a() | (cy_flag as u8) << 7);
                 self.conditions.set(ConditionRegister::Carry, new_cy_flag);
                 ExecutionResult::Running
             },
             Instruction::Cma => {
-                let a = self.registers.get_8(Register::A, &self.memory);
+                let a = self.registers.a();
                 let result = !a;
-                self.registers.set_8(Register::A, result, &mut self.memory);
+                self.registers.set_a(result);
                 ExecutionResult::Running
             },
             Instruction::Cmc => {
@@ -924,10 +1880,7 @@ impl Machine {
                 self.conditions.set(ConditionRegister::Carry, true);
                 ExecutionResult::Running
             },
-            Instruction::Jmp(address) => {
-                self.pc = address.into();
-                ExecutionResult::ControlTransfer
-            }
+            Instruction::Jmp(address) => self.jump(address),
             Instruction::Jcc(condition, address) => {
                 let should_jump = match condition {
                     Condition::Carry => self.conditions.get(ConditionRegister::Carry),
@@ -940,16 +1893,15 @@ impl Machine {
                     Condition::ParityOdd => !self.conditions.get(ConditionRegister::Parity),
                 };
                 if should_jump {
-                    self.pc = address.into();
-                    ExecutionResult::ControlTransfer
+                    self.jump(address)
                 } else {
                     ExecutionResult::Running
                 }
             }
             Instruction::Call(address) => {
-                let next_address = u16::from(self.pc) + instruction.byte_length();
+                let next_address = self.pc.wrapping_add(instruction.length() as u16);
                 if self.stack_push(next_address.into()).is_some() {
-                    self.pc = address.into();
+                    self.pc = address;
                     ExecutionResult::ControlTransfer
                 } else {
                     ExecutionResult::StackOverflow
@@ -967,9 +1919,10 @@ impl Machine {
                     Condition::ParityOdd => !self.conditions.get(ConditionRegister::Parity),
                 };
                 if should_call {
-                    let next_address = u16::from(self.pc) + instruction.byte_length();
-                    if should_call && self.stack_push(next_address.into()).is_some() {
-                        self.pc = address.into();
+                    self.add_taken_cycles(instruction);
+                    let next_address = self.pc.wrapping_add(instruction.length() as u16);
+                    if self.stack_push(next_address.into()).is_some() {
+                        self.pc = address;
                         ExecutionResult::ControlTransfer
                     } else {
                         ExecutionResult::StackOverflow
@@ -980,7 +1933,7 @@ impl Machine {
             }
             Instruction::Ret => match self.stack_pop() {
                 Some(address) => {
-                    self.pc = address;
+                    self.pc = address.value();
                     ExecutionResult::ControlTransfer
                 }
                 None => ExecutionResult::StackUnderflow,
@@ -998,9 +1951,10 @@ impl Machine {
                 };
 
                 if should_return {
+                    self.add_taken_cycles(instruction);
                     match self.stack_pop() {
                         Some(address) => {
-                            self.pc = address;
+                            self.pc = address.value();
                             ExecutionResult::ControlTransfer
                         }
                         None => ExecutionResult::StackUnderflow,
@@ -1010,15 +1964,16 @@ impl Machine {
                 }
             }
             Instruction::Rst(restart_number) => {
-                if self.stack_push(self.pc).is_some() {
-                    self.pc = (u16::from(restart_number) << 3).into();
+                let next_address = self.pc.wrapping_add(instruction.length() as u16);
+                if self.stack_push(next_address.into()).is_some() {
+                    self.pc = u16::from(restart_number) << 3;
                     ExecutionResult::ControlTransfer
                 } else {
                     ExecutionResult::StackOverflow
                 }
             },
             Instruction::Pchl => {
-                self.pc = self.register_16(RegisterPair::Hl);
+                self.pc = self.registers.hl();
                 ExecutionResult::ControlTransfer
             }
             Instruction::Push(register) => {
@@ -1042,18 +1997,18 @@ impl Machine {
                         }
                         ExecutionResult::Running
                     }
-                    None => ExecutionResult::StackOverflow,
+                    None => ExecutionResult::StackUnderflow,
                 }
             }
             Instruction::Xthl => {
                 let hl = self.registers.get_16(RegisterPair::Hl);
                 let sp = self.registers.get_16(RegisterPair::Sp);
-                let Some(stack_top) = self.memory.read_16(sp.into()) else {
-                    return ExecutionResult::StackOverflow;
+                let Some(stack_top) = self.load_16(sp.into()) else {
+                    return ExecutionResult::StackUnderflow;
                 };
                 self.registers.set_16(RegisterPair::Hl, stack_top);
-                if matches!(self.memory.write_16(sp.into(), hl), None) {
-                    return ExecutionResult::StackOverflow;
+                if self.store_16(sp.into(), hl).is_none() {
+                    return ExecutionResult::StackUnderflow;
                 }
                 ExecutionResult::Running
             },
@@ -1063,115 +2018,118 @@ impl Machine {
                 ExecutionResult::Running
             },
             Instruction::In(port) => {
-                let byte = match port {
-                    0 => {
-                        match io::stdin()
-                            .bytes()
-                            .next()
-                            .map(|res| res.expect("surely io doesn't error"))
-                        {
-                            Some(byte) => byte,
-                            None => return ExecutionResult::Halt,
-                        }
-                    }
-                    1 => {
-                        rand::rng().random()
+                let byte = if self.io.is_mapped(port) {
+                    self.device_read(port)
+                } else {
+                    match port {
+                        devices::CONSOLE_DATA => match Console.read(port, self) {
+                            None if self.wait_for_input => {
+                                // The instruction is executed again once there's input.
+                                self.cycles -= instruction.cycles().0 as u64;
+                                self.instructions -= 1;
+                                return ExecutionResult::WaitingForInput;
+                            }
+                            byte => byte,
+                        },
+                        1 => Some(self.random.next_byte()),
+                        _ => Some(self.unmapped_input),
                     }
-                    _ => 0,
                 };
-                
-                self.registers.set_8(Register::A, byte, &mut self.memory);
+                let Some(byte) = byte else {
+                    return ExecutionResult::Halt;
+                };
+                #[cfg(feature = "trace-log")]
+                tracing::trace!(port, value = byte, "port input");
+
+                self.registers.set_a(byte);
 
                 ExecutionResult::Running
             }
             Instruction::Out(port) => {
-                match port {
-                    0 => {
-                        let byte = self.register_8(Register::A);
-                        self.stdout.push(byte);
-                        ExecutionResult::Running
-                    }
-                    1 => {
-                        let number = self.register_8(Register::A);
-                        self.stdout.extend_from_slice(format!("{}", number).as_bytes());
-                        ExecutionResult::Running
-                    }
-                    2 => {
-                        let number = self.register_16(RegisterPair::Hl).value();
-                        self.stdout.extend_from_slice(format!("{}", number).as_bytes());
-                        ExecutionResult::Running
-                    }
-                    _ => ExecutionResult::Running,
+                #[cfg(feature = "trace-log")]
+                tracing::trace!(port, value = self.register_8(Register::A), "port output");
+                let value = self.register_8(Register::A);
+                if let Some(hook) = &mut self.hooks.output {
+                    hook(port, value);
+                }
+                if self.io.is_mapped(port) {
+                    self.device_write(port, value);
+                } else {
+                    Console.write(port, value, self);
                 }
+                ExecutionResult::Running
             },
-            // We don't support interrupts, equate EI and DI to NOP
-            Instruction::Ei => ExecutionResult::Running,
-            Instruction::Di => ExecutionResult::Running,
+            Instruction::Ei => {
+                if self.interrupt_enable == InterruptEnable::Disabled {
+                    self.interrupt_enable = InterruptEnable::Delayed;
+                }
+                ExecutionResult::Running
+            }
+            Instruction::Di => {
+                self.interrupt_enable = InterruptEnable::Disabled;
+                ExecutionResult::Running
+            }
+            Instruction::Rim => {
+                // No interrupts are pending and the serial input line reads 0.
+                let enabled = (self.interrupt_enable != InterruptEnable::Disabled) as u8;
+                self.registers.set_a(self.interrupt_mask | enabled << 3);
+                ExecutionResult::Running
+            }
+            Instruction::Sim => {
+                let a = self.registers.a();
+                if a & 0b0000_1000 != 0 {
+                    self.interrupt_mask = a & 0b0000_0111;
+                }
+                if a & 0b0100_0000 != 0 {
+                    self.serial_output = a & 0b1000_0000 != 0;
+                }
+                ExecutionResult::Running
+            }
             Instruction::Hlt => ExecutionResult::Halt,
             Instruction::Nop => ExecutionResult::Running,
         }
     }
 }
 
-fn calc_ac_flag_add(a: u8, b: u8, cy_flag: bool) -> bool {
-    let a = a & 0b0000_1111;
-    let b = b & 0b0000_1111;
-    (a + b + (cy_flag as u8)) & 0b0001_0000 == 1
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::instruction::{RegisterPairIndirect, RestartNumber};
     use std::time::Instant;
 
     #[test]
     fn test_add_register() {
         let now = Instant::now();
-        let mut machine = Machine::new();
-
-        machine.conditions.set(ConditionRegister::Carry, true);
-
-        machine
-            .registers
-            .set_8(Register::A, 0x80, &mut machine.memory);
-        machine
-            .registers
-            .set_8(Register::B, 0x00, &mut machine.memory);
+        let mut machine = MachineBuilder::new()
+            .flag(ConditionRegister::Carry, true)
+            .register(Register::A, 0x80)
+            .register(Register::B, 0x00)
+            .build()
+            .unwrap();
 
         let result = machine.execute(Instruction::Add(Register::B));
         let elapsed = now.elapsed();
 
-        // assert_eq!(result, ExecutionResult::Running);
+        assert_eq!(result, ExecutionResult::Running);
+
+        assert_eq!(0x80, machine.register_8(Register::A));
+        assert!(machine.conditions.get(ConditionRegister::Sign));
+        assert!(!machine.conditions.get(ConditionRegister::Carry));
 
-        // assert_eq!(0x6B, machine.register_8(Register::A));
-        println!(
-            "add: Time elapsed: {:?}, A: {:?}, Sign: {:?}",
-            elapsed,
-            machine.register_8(Register::A),
-            machine.conditions.get(ConditionRegister::Sign)
-        );
+        println!("add: Time elapsed: {:?}", elapsed);
     }
     #[test]
     fn test_sub_register() {
-        let now = Instant::now();
-        let mut machine = Machine::new();
-
-        // machine.conditions.set(ConditionRegister::Carry, true);
-
-        machine
-            .registers
-            .set_8(Register::A, 0x20, &mut machine.memory);
-        machine
-            .registers
-            .set_8(Register::B, 0x10, &mut machine.memory);
-        let result = machine.execute(Instruction::Sbi(66));
-        let elapsed = now.elapsed();
+        let mut machine = MachineBuilder::new()
+            .register(Register::A, 0x20)
+            .register(Register::B, 0x10)
+            .build()
+            .unwrap();
+        let result = machine.execute(Instruction::Sub(Register::B));
 
-        // assert_eq!(result, ExecutionResult::Running);
-
-        // assert_eq!(0x10, machine.register_8(Register::A));
+        assert_eq!(result, ExecutionResult::Running);
 
-        println!("sub: Time elapsed: {:?}", elapsed);
+        assert_eq!(0x10, machine.register_8(Register::A));
     }
 
     #[test]
@@ -1192,6 +2150,51 @@ mod tests {
         println!("inr: Time elapsed: {:?}", elapsed);
     }
 
+    #[test]
+    fn test_inr_dcr_flags() {
+        let mut machine = Machine::new();
+        // Value before and (result, Z, S, P, AC) after INR and DCR.
+        for (value, inr, dcr) in [
+            (0x00, (0x01, false, false, false, false), (0xFF, false, true, true, true)),
+            (0x0F, (0x10, false, false, false, true), (0x0E, false, false, false, false)),
+            (0x10, (0x11, false, false, true, false), (0x0F, false, false, true, true)),
+            (0x7F, (0x80, false, true, false, true), (0x7E, false, false, true, false)),
+            (0x80, (0x81, false, true, true, false), (0x7F, false, false, false, true)),
+            (0xFF, (0x00, true, false, true, true), (0xFE, false, true, false, false)),
+        ] {
+            for (instruction, expected) in [
+                (Instruction::Inr(Register::B), inr),
+                (Instruction::Dcr(Register::B), dcr),
+            ] {
+                machine.set_register_8(Register::B, value);
+                machine.execute(instruction);
+                let flags = (
+                    machine.register_8(Register::B),
+                    machine.conditions.get(ConditionRegister::Zero),
+                    machine.conditions.get(ConditionRegister::Sign),
+                    machine.conditions.get(ConditionRegister::Parity),
+                    machine.conditions.get(ConditionRegister::AuxiliaryCarry),
+                );
+                assert_eq!(flags, expected, "{:?} of {:#04X}", instruction, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inr_dcr_preserve_carry() {
+        let mut machine = Machine::new();
+        for carry in [false, true] {
+            for value in [0x00, 0x0F, 0x7F, 0xFF] {
+                for instruction in [Instruction::Inr(Register::C), Instruction::Dcr(Register::C)] {
+                    machine.conditions.set(ConditionRegister::Carry, carry);
+                    machine.set_register_8(Register::C, value);
+                    machine.execute(instruction);
+                    assert_eq!(machine.conditions.get(ConditionRegister::Carry), carry);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_inx_register() {
         let now = Instant::now();
@@ -1222,13 +2225,1785 @@ mod tests {
             .set_8(Register::B, 0x0F, &mut machine.memory);
 
         let result = machine.execute(Instruction::Ana(Register::B));
-
         let elapsed = now.elapsed();
 
-        println!(
-            "inx: Time elapsed: {:?}\nReturn: {:?}",
-            elapsed,
-            machine.registers.get_8(Register::A, &machine.memory)
+        assert_eq!(result, ExecutionResult::Running);
+
+        assert_eq!(0x0C, machine.register_8(Register::A));
+
+        println!("ana: Time elapsed: {:?}", elapsed);
+    }
+
+    // 0000: MVI B, 3
+    // 0002: DCR B
+    // 0003: JNZ 0002H
+    // 0006: HLT
+    const COUNTDOWN: [u8; 7] = [0x06, 0x03, 0x05, 0xC2, 0x02, 0x00, 0x76];
+
+    #[test]
+    fn test_steps_trace() {
+        let mut machine = MachineBuilder::new()
+            .program(&COUNTDOWN, 0x0000)
+            .build()
+            .unwrap();
+
+        let trace: Vec<u16> = machine
+            .steps()
+            .map(|step| step.pc_before.value())
+            .collect();
+
+        assert_eq!(
+            trace,
+            vec![0x0000, 0x0002, 0x0003, 0x0002, 0x0003, 0x0002, 0x0003, 0x0006]
+        );
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert!(machine.step().is_none());
+        assert_eq!(machine.steps().count(), 0);
+    }
+
+    #[test]
+    fn test_steps_resume() {
+        let mut machine = MachineBuilder::new()
+            .program(&COUNTDOWN, 0x0000)
+            .build()
+            .unwrap();
+
+        let step = machine
+            .steps()
+            .find(|step| step.instruction == Some(Instruction::Dcr(Register::B)))
+            .unwrap();
+        assert_eq!(step.pc_before.value(), 0x0002);
+        assert_eq!(step.result, ExecutionResult::Running);
+        assert_eq!(machine.register_8(Register::B), 2);
+
+        let last = machine.steps().last().unwrap();
+        assert_eq!(last.instruction, Some(Instruction::Hlt));
+        assert_eq!(last.result, ExecutionResult::Halt);
+    }
+
+    #[test]
+    fn test_steps_count_calls() {
+        // 0000: LXI SP, 1000H
+        // 0003: MVI B, 3
+        // 0005: CALL 000DH
+        // 0008: DCR B
+        // 0009: JNZ 0005H
+        // 000C: HLT
+        // 000D: RET
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[
+                    0x31, 0x00, 0x10, 0x06, 0x03, 0xCD, 0x0D, 0x00, 0x05, 0xC2, 0x05, 0x00, 0x76,
+                    0xC9,
+                ],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+
+        let calls = machine
+            .steps()
+            .take(1000)
+            .filter(|step| matches!(step.instruction, Some(Instruction::Call(_))))
+            .count();
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn test_steps_stop_at_breakpoint() {
+        let mut machine = MachineBuilder::new()
+            .program(&COUNTDOWN, 0x0000)
+            .build()
+            .unwrap();
+        machine.add_breakpoint(0x0003);
+
+        let mut steps = machine.steps();
+        assert_eq!(steps.by_ref().count(), 2);
+        assert_eq!(steps.stopped(), Some(RunOutcome::Breakpoint(0x0003)));
+        assert_eq!(machine.pc(), Data16::from(0x0003));
+
+        // Resuming executes the instruction at the breakpoint, until it's reached again.
+        let trace: Vec<u16> = machine
+            .steps()
+            .map(|step| step.pc_before.value())
+            .collect();
+        assert_eq!(trace, [0x0003, 0x0002]);
+    }
+
+    #[test]
+    fn test_steps_stop_at_watchpoint() {
+        // 0000: MVI A, 2AH
+        // 0002: STA 0300H
+        // 0005: HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0x3E, 0x2A, 0x32, 0x00, 0x03, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        machine.watch_write(0x0300);
+
+        let mut steps = machine.steps();
+        let last = steps.by_ref().last().unwrap();
+        assert_eq!(last.instruction, Some(Instruction::Sta(0x0300)));
+        assert_eq!(
+            steps.stopped(),
+            Some(RunOutcome::Watchpoint(WatchHit {
+                pc: 0x0002,
+                instruction: Instruction::Sta(0x0300),
+                address: 0x0300,
+                access: MemoryAccess::Write,
+                value: 0x2A,
+            }))
+        );
+
+        let mut steps = machine.steps();
+        assert_eq!(steps.by_ref().count(), 1);
+        assert_eq!(steps.stopped(), None);
+    }
+
+    #[test]
+    fn test_run_cycle() {
+        // 0000: MVI A, 2AH
+        // 0002: JMP 0006H
+        // 0005: NOP
+        // 0006: HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0x3E, 0x2A, 0xC3, 0x06, 0x00, 0x00, 0x76], 0x0000)
+            .build()
+            .unwrap();
+
+        let steps: Vec<_> = core::iter::from_fn(|| machine.run_cycle())
+            .map(|step| (step.pc_before.value(), step.instruction, step.result))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (
+                    0x0000,
+                    Some(Instruction::Mvi(Register::A, 0x2A)),
+                    ExecutionResult::Running
+                ),
+                (
+                    0x0002,
+                    Some(Instruction::Jmp(0x0006)),
+                    ExecutionResult::ControlTransfer
+                ),
+                (0x0006, Some(Instruction::Hlt), ExecutionResult::Halt),
+            ]
+        );
+        assert!(machine.run_cycle().is_none());
+    }
+
+    #[test]
+    fn test_run_cycles() {
+        // 0000: MVI B, 3
+        // 0002: DCR B
+        // 0003: JNZ 0002H
+        // 0006: HLT
+        let program = [0x06, 0x03, 0x05, 0xC2, 0x02, 0x00, 0x76];
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(machine.run_cycles(4), (4, MachineState::Running));
+        assert_eq!(machine.pc().value(), 0x0003);
+        assert_eq!(
+            machine.run_cycles(100),
+            (4, MachineState::Halted(HaltReason::HaltInstruction))
+        );
+        assert_eq!(
+            machine.run_cycles(100),
+            (0, MachineState::Halted(HaltReason::HaltInstruction))
+        );
+
+        // Stops at breakpoints, and goes on past them when run again.
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        machine.add_breakpoint(0x0003);
+        assert_eq!(machine.run_cycles(100), (2, MachineState::Running));
+        assert_eq!(machine.pc().value(), 0x0003);
+        assert_eq!(machine.run_cycles(100), (2, MachineState::Running));
+        assert_eq!(machine.register_8(Register::B), 1);
+    }
+
+    #[test]
+    fn test_step_invalid_instruction() {
+        // 0x08 is an undocumented opcode.
+        let mut machine = MachineBuilder::new()
+            .program(&[0x00, 0x08], 0x0000)
+            .build()
+            .unwrap();
+
+        let steps: Vec<StepInfo> = machine.steps().collect();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].instruction, None);
+        assert_eq!(steps[1].result, ExecutionResult::InvalidInstruction);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::InvalidInstruction)
+        );
+    }
+
+    #[test]
+    fn test_fetch_wraps_around() {
+        // JMP 0200H at 0xFFFF, with the address at 0x0000 and 0x0001.
+        let mut machine = Machine::new();
+        machine.memory_mut().write_8(0xFFFF, 0xC3);
+        machine.memory_mut().write_slice(0x0000, &[0x00, 0x02]).unwrap();
+        machine.set_pc(0xFFFF.into());
+        assert_eq!(machine.load(), Some(Instruction::Jmp(0x0200)));
+        let step = machine.step().unwrap();
+        assert_eq!(step.result, ExecutionResult::ControlTransfer);
+        assert_eq!(machine.pc().value(), 0x0200);
+
+        // JMP 0300H at 0xFFFE, with the high byte of the address at 0x0000.
+        let mut machine = Machine::new();
+        machine.memory_mut().write_slice(0xFFFE, &[0xC3, 0x00]).unwrap();
+        machine.memory_mut().write_8(0x0000, 0x03);
+        machine.set_pc(0xFFFE.into());
+        machine.step().unwrap();
+        assert_eq!(machine.pc().value(), 0x0300);
+
+        // NOP at 0xFFFF, then MVI A, 42H at 0x0000.
+        let mut machine = Machine::new();
+        machine.memory_mut().write_slice(0x0000, &[0x3E, 0x42]).unwrap();
+        machine.set_pc(0xFFFF.into());
+        machine.step().unwrap();
+        assert_eq!(machine.pc().value(), 0x0000);
+        machine.step().unwrap();
+        assert_eq!(machine.register_8(Register::A), 0x42);
+        assert_eq!(machine.pc().value(), 0x0002);
+    }
+
+    #[test]
+    fn test_write_slice_past_end() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.as_raw().len(), 0x10000);
+        assert_eq!(memory.write_slice(0xFFFE, &[0x12, 0x34]), Some(()));
+        assert_eq!(memory.write_slice(0xFFFF, &[0x56, 0x78]), None);
+        assert_eq!(memory.read_8(0xFFFF), 0x34);
+        assert_eq!(memory.read_8(0x0000), 0x00);
+    }
+
+    #[test]
+    fn test_register_pairs() {
+        let mut machine = Machine::new();
+        machine.set_register_16(RegisterPair::Bc, Data16::new(0x01, 0x02));
+        machine.set_register_16(RegisterPair::De, Data16::new(0x03, 0x04));
+        machine.set_register_16(RegisterPair::Hl, Data16::new(0x05, 0x06));
+        machine.set_register_16(RegisterPair::Sp, Data16::new(0x07, 0x08));
+        machine.set_register_8(Register::A, 0x09);
+
+        let values: Vec<Data8> = [
+            Register::B,
+            Register::C,
+            Register::D,
+            Register::E,
+            Register::H,
+            Register::L,
+            Register::A,
+        ]
+        .into_iter()
+        .map(|register| machine.register_8(register))
+        .collect();
+        assert_eq!(values, [0x02, 0x01, 0x04, 0x03, 0x06, 0x05, 0x09]);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x0807);
+
+        // M is the byte HL points to.
+        machine.set_register_8(Register::M, 0xAA);
+        assert_eq!(machine.memory().read_8(0x0605), 0xAA);
+        assert_eq!(machine.register_8(Register::M), 0xAA);
+    }
+
+    #[test]
+    fn test_setters() {
+        // 0000: MOV A, M
+        // 0001: PUSH PSW
+        // 0002: HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0x7E, 0xF5, 0x76], 0x0000)
+            .sp(0x2000)
+            .build()
+            .unwrap();
+        machine.add_breakpoint(0x0001);
+
+        machine.set_register_16(RegisterPair::Hl, 0x1000.into());
+        machine.set_register_8(Register::M, 0x5A);
+        machine.set_register_16(RegisterPair::Hl, 0x1001.into());
+        machine.set_register_8(Register::M, 0xA5);
+        assert_eq!(machine.memory().read_8(0x1000), 0x5A);
+        assert_eq!(machine.register_8(Register::H), 0x10);
+        assert_eq!(machine.register_8(Register::L), 0x01);
+
+        machine.set_flag(ConditionRegister::Carry, true);
+        machine.set_flag(ConditionRegister::Zero, true);
+        machine.set_flag(ConditionRegister::Zero, false);
+        assert!(machine.conditions().get(ConditionRegister::Carry));
+        assert!(!machine.conditions().get(ConditionRegister::Zero));
+
+        assert_eq!(
+            machine.run_until_halt(10),
+            (RunOutcome::Breakpoint(0x0001), 1)
+        );
+        assert_eq!(machine.register_8(Register::A), 0xA5);
+        // Moving the program counter to the breakpoint stops there again.
+        machine.set_pc(0x0001.into());
+        assert_eq!(
+            machine.run_until_halt(10),
+            (RunOutcome::Breakpoint(0x0001), 0)
+        );
+        machine.set_pc(0x0000.into());
+        machine.set_register_16(RegisterPair::Hl, 0x1000.into());
+        assert_eq!(
+            machine.run_until_halt(10),
+            (RunOutcome::Breakpoint(0x0001), 1)
+        );
+        assert_eq!(machine.register_8(Register::A), 0x5A);
+
+        machine.run_until_halt(10);
+        // PUSH PSW stored A and the flags set above.
+        assert_eq!(
+            machine.memory().read_16(0x1FFE).unwrap(),
+            Data16::new(0x03, 0x5A)
+        );
+    }
+
+    #[test]
+    fn test_sign_flag() {
+        let mut machine = Machine::new();
+        machine.set_register_8(Register::A, 0x00);
+        machine.set_register_8(Register::B, 0x01);
+
+        machine.execute(Instruction::Sub(Register::B));
+        assert_eq!(machine.register_8(Register::A), 0xFF);
+        assert!(machine.conditions.get(ConditionRegister::Sign));
+
+        machine.execute(Instruction::Inr(Register::A));
+        assert!(!machine.conditions.get(ConditionRegister::Sign));
+        machine.execute(Instruction::Dcr(Register::A));
+        assert!(machine.conditions.get(ConditionRegister::Sign));
+
+        machine.execute(Instruction::Ani(0x7F));
+        assert!(!machine.conditions.get(ConditionRegister::Sign));
+        machine.execute(Instruction::Adi(0x7F));
+        assert!(machine.conditions.get(ConditionRegister::Sign));
+        machine.execute(Instruction::Cpi(0x00));
+        assert!(machine.conditions.get(ConditionRegister::Sign));
+        machine.execute(Instruction::Xri(0xFF));
+        assert!(!machine.conditions.get(ConditionRegister::Sign));
+        machine.execute(Instruction::Ori(0x80));
+        assert!(machine.conditions.get(ConditionRegister::Sign));
+    }
+
+    #[test]
+    fn test_auxiliary_carry_flag() {
+        let mut machine = Machine::new();
+        for (a, term, ac_flag) in [(0x0F, 0x01, true), (0x08, 0x07, false), (0x08, 0x08, true)] {
+            machine.set_register_8(Register::A, a);
+            machine.execute(Instruction::Adi(term));
+            assert_eq!(
+                machine.conditions.get(ConditionRegister::AuxiliaryCarry),
+                ac_flag,
+                "{:#04X} + {:#04X}",
+                a,
+                term
+            );
+        }
+
+        // Subtraction adds the complement, so there is an auxiliary carry when nothing is
+        // borrowed from bit 4.
+        for (a, term, ac_flag) in [(0x10, 0x01, false), (0x05, 0x10, true), (0x05, 0x03, true)] {
+            machine.set_register_8(Register::A, a);
+            machine.execute(Instruction::Sui(term));
+            assert_eq!(
+                machine.conditions.get(ConditionRegister::AuxiliaryCarry),
+                ac_flag,
+                "{:#04X} - {:#04X}",
+                a,
+                term
+            );
+        }
+
+        machine.set_register_8(Register::B, 0x0F);
+        machine.execute(Instruction::Inr(Register::B));
+        assert!(machine.conditions.get(ConditionRegister::AuxiliaryCarry));
+    }
+
+    #[test]
+    fn test_decimal_addition() {
+        let mut machine = Machine::new();
+        machine.set_register_8(Register::A, 0x08);
+
+        machine.execute(Instruction::Adi(0x09));
+        machine.execute(Instruction::Daa);
+        assert_eq!(machine.register_8(Register::A), 0x17);
+        assert!(!machine.conditions.get(ConditionRegister::Carry));
+
+        // 1999 + 1, a byte at a time: ADD / DAA, then ACI 0 / DAA for the carry.
+        machine.set_register_8(Register::A, 0x99);
+        machine.execute(Instruction::Adi(0x01));
+        machine.execute(Instruction::Daa);
+        assert_eq!(machine.register_8(Register::A), 0x00);
+        assert!(machine.conditions.get(ConditionRegister::Carry));
+        machine.set_register_8(Register::A, 0x19);
+        machine.execute(Instruction::Aci(0x00));
+        machine.execute(Instruction::Daa);
+        assert_eq!(machine.register_8(Register::A), 0x20);
+        assert!(!machine.conditions.get(ConditionRegister::Carry));
+    }
+
+    #[test]
+    fn test_daa() {
+        let mut machine = Machine::new();
+        // (A, CY, AC) before and (A, flags) after, with the flags as PUSH PSW stores them. From
+        // an emulator that passes the 8080 exerciser.
+        for (a, cy_flag, ac_flag, result, flags) in [
+            (0x00, false, false, 0x00, 0x46),
+            (0x00, false, true, 0x06, 0x06),
+            (0x00, true, false, 0x60, 0x07),
+            (0x00, true, true, 0x66, 0x07),
+            (0x09, false, false, 0x09, 0x06),
+            (0x0A, false, false, 0x10, 0x12),
+            (0x0F, false, false, 0x15, 0x12),
+            (0x0F, true, true, 0x75, 0x13),
+            (0x10, false, true, 0x16, 0x02),
+            (0x15, false, false, 0x15, 0x02),
+            (0x19, true, false, 0x79, 0x03),
+            (0x1A, false, false, 0x20, 0x12),
+            (0x42, false, true, 0x48, 0x06),
+            (0x66, false, false, 0x66, 0x06),
+            (0x79, false, false, 0x79, 0x02),
+            (0x7A, false, false, 0x80, 0x92),
+            (0x80, false, false, 0x80, 0x82),
+            (0x8F, false, false, 0x95, 0x96),
+            (0x90, false, false, 0x90, 0x86),
+            (0x99, false, false, 0x99, 0x86),
+            (0x99, true, false, 0xF9, 0x87),
+            (0x9A, false, false, 0x00, 0x57),
+            (0x9A, true, true, 0x00, 0x57),
+            (0xA0, false, false, 0x00, 0x47),
+            (0xA5, false, true, 0x0B, 0x03),
+            (0xAF, false, false, 0x15, 0x13),
+            (0xBB, true, false, 0x21, 0x17),
+            (0xC3, false, false, 0x23, 0x03),
+            (0xD0, true, true, 0x36, 0x07),
+            (0xE9, false, true, 0x4F, 0x03),
+            (0xF9, false, false, 0x59, 0x07),
+            (0xFA, false, false, 0x60, 0x17),
+            (0xFF, false, false, 0x65, 0x17),
+            (0xFF, true, true, 0x65, 0x17),
+        ] {
+            machine.set_register_8(Register::A, a);
+            machine.conditions.set(ConditionRegister::Carry, cy_flag);
+            machine.conditions.set(ConditionRegister::AuxiliaryCarry, ac_flag);
+            machine.execute(Instruction::Daa);
+            assert_eq!(
+                (machine.register_8(Register::A), machine.get_status_word().low),
+                (result, flags),
+                "DAA of {:#04X} with CY={} AC={}",
+                a,
+                cy_flag,
+                ac_flag
+            );
+        }
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut machine = Machine::new();
+        for (instruction, a, result, cy_flag) in [
+            (Instruction::Rlc, 0x81, 0x03, true),
+            (Instruction::Rlc, 0x42, 0x84, false),
+            (Instruction::Rrc, 0x01, 0x80, true),
+            (Instruction::Rrc, 0x42, 0x21, false),
+        ] {
+            machine.set_register_8(Register::A, a);
+            machine.conditions.set(ConditionRegister::Carry, !cy_flag);
+            machine.execute(instruction);
+            assert_eq!(machine.register_8(Register::A), result, "{:?} {:#04X}", instruction, a);
+            assert_eq!(machine.conditions.get(ConditionRegister::Carry), cy_flag);
+        }
+    }
+
+    #[test]
+    fn test_rotate_through_carry() {
+        let mut machine = Machine::new();
+        for (instruction, a, cy_flag, result, new_cy_flag) in [
+            (Instruction::Ral, 0x80, true, 0x01, true),
+            (Instruction::Ral, 0x80, false, 0x00, true),
+            (Instruction::Ral, 0x41, true, 0x83, false),
+            (Instruction::Ral, 0x41, false, 0x82, false),
+            (Instruction::Rar, 0x01, true, 0x80, true),
+            (Instruction::Rar, 0x01, false, 0x00, true),
+            (Instruction::Rar, 0x82, true, 0xC1, false),
+            (Instruction::Rar, 0x82, false, 0x41, false),
+        ] {
+            machine.set_register_8(Register::A, a);
+            machine.conditions.set(ConditionRegister::Carry, cy_flag);
+            machine.execute(instruction);
+            assert_eq!(
+                machine.register_8(Register::A),
+                result,
+                "{:?} {:#04X} with carry {}",
+                instruction,
+                a,
+                cy_flag
+            );
+            assert_eq!(machine.conditions.get(ConditionRegister::Carry), new_cy_flag);
+        }
+    }
+
+    #[test]
+    fn test_subtraction_flags() {
+        // A, operand, carry in, then result and flags of SUB and of SBB, computed from the 8080
+        // datasheet. Flags are the low byte of the status word: S Z 0 AC 0 P 1 CY.
+        let table = [
+            (0x03, 0x05, false, 0xFE, 0x83, 0xFE, 0x83),
+            (0x05, 0x03, false, 0x02, 0x12, 0x02, 0x12),
+            (0x05, 0x05, false, 0x00, 0x56, 0x00, 0x56),
+            (0x00, 0x00, true, 0x00, 0x56, 0xFF, 0x87),
+            (0x10, 0x01, false, 0x0F, 0x06, 0x0F, 0x06),
+            (0x80, 0x01, true, 0x7F, 0x02, 0x7E, 0x06),
+            (0x05, 0x05, true, 0x00, 0x56, 0xFF, 0x87),
+            (0xFF, 0xFF, true, 0x00, 0x56, 0xFF, 0x87),
+            (0x00, 0xFF, false, 0x01, 0x03, 0x01, 0x03),
+            (0x3A, 0x0F, true, 0x2B, 0x06, 0x2A, 0x02),
+        ];
+
+        let mut machine = Machine::new();
+        for (a, term, cy_flag, sub_result, sub_flags, sbb_result, sbb_flags) in table {
+            machine.set_register_8(Register::B, term);
+            for (instruction, result, flags) in [
+                (Instruction::Sub(Register::B), sub_result, sub_flags),
+                (Instruction::Sui(term), sub_result, sub_flags),
+                (Instruction::Sbb(Register::B), sbb_result, sbb_flags),
+                (Instruction::Sbi(term), sbb_result, sbb_flags),
+                (Instruction::Cmp(Register::B), a, sub_flags),
+                (Instruction::Cpi(term), a, sub_flags),
+            ] {
+                machine.set_register_8(Register::A, a);
+                machine.conditions.set(ConditionRegister::Carry, cy_flag);
+                machine.execute(instruction);
+                assert_eq!(
+                    (machine.register_8(Register::A), machine.get_status_word().low),
+                    (result, flags),
+                    "{:?} with A = {:#04X} and carry {}",
+                    instruction,
+                    a,
+                    cy_flag
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_psw_byte_round_trip() {
+        for combination in 0..32u8 {
+            let mut conditions = ConditionRegisters::new();
+            for (index, (condition, _)) in PSW_FLAG_BITS.into_iter().enumerate() {
+                conditions.set(condition, combination >> index & 1 == 1);
+            }
+            let byte = conditions.to_psw_byte();
+            assert_eq!(byte & 0b0010_1010, 0b0000_0010, "{:#04X}", byte);
+
+            let decoded = ConditionRegisters::from_psw_byte(byte);
+            for (condition, bit) in PSW_FLAG_BITS {
+                assert_eq!(decoded.get(condition), conditions.get(condition));
+                assert_eq!(byte >> bit & 1 == 1, conditions.get(condition));
+            }
+            assert_eq!(decoded.to_psw_byte(), byte);
+        }
+    }
+
+    #[test]
+    fn test_psw_byte_fixed_bits() {
+        // Bits 1, 3 and 5 come out as 0, 1 and 0 whatever was popped.
+        for byte in 0..=255u8 {
+            assert_eq!(
+                ConditionRegisters::from_psw_byte(byte).to_psw_byte(),
+                byte & 0b1101_0101 | 0b0000_0010,
+                "{:#04X}",
+                byte
+            );
+        }
+    }
+
+    #[test]
+    fn test_push_pop_psw() {
+        // 0000: LXI SP, 0100H
+        // 0003: MVI A, 0FFH
+        // 0005: ADI 01H
+        // 0007: PUSH PSW
+        // 0008: MVI A, 7FH
+        // 000A: ORA A
+        // 000B: POP PSW
+        // 000C: HLT
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[
+                    0x31, 0x00, 0x01, 0x3E, 0xFF, 0xC6, 0x01, 0xF5, 0x3E, 0x7F, 0xB7, 0xF1, 0x76,
+                ],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+        // Zero, auxiliary carry, parity and carry.
+        let pushed = Data16 {
+            low: 0b0101_0111,
+            high: 0x00,
+        };
+
+        machine.steps().take(4).for_each(drop);
+        assert_eq!(machine.get_status_word(), pushed);
+        assert_eq!(machine.memory().read_8(0x00FE), pushed.low);
+        assert_eq!(machine.memory().read_8(0x00FF), pushed.high);
+
+        machine.steps().take(2).for_each(drop);
+        assert_eq!(machine.register_8(Register::A), 0x7F);
+        assert_eq!(machine.get_status_word().low, 0b0000_0010);
+
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.get_status_word(), pushed);
+        assert!(machine.conditions().get(ConditionRegister::Zero));
+        assert!(machine.conditions().get(ConditionRegister::Carry));
+        assert!(!machine.conditions().get(ConditionRegister::Sign));
+        assert_eq!(machine.register_16(RegisterPair::Sp), Data16::from(0x0100));
+    }
+
+    #[test]
+    fn test_jump_on_minus() {
+        // 0000: MVI A, 0
+        // 0002: SUI 1
+        // 0004: JM 0009H
+        // 0007: HLT
+        // 0008: NOP
+        // 0009: HLT
+        let program = [0x3E, 0x00, 0xD6, 0x01, 0xFA, 0x09, 0x00, 0x76, 0x00, 0x76];
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        let last = machine.steps().last().unwrap();
+        assert_eq!(last.pc_before.value(), 0x0009);
+
+        // 2 - 1 is positive, so the jump isn't taken.
+        let mut program = program;
+        program[1] = 0x02;
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        let last = machine.steps().last().unwrap();
+        assert_eq!(last.pc_before.value(), 0x0007);
+    }
+
+    #[test]
+    fn test_call_returns_after_the_call() {
+        let program = "        ORG 0H
+        LXI SP, 100H
+        CALL SUB
+        HLT
+SUB:    RET
+        END
+";
+        let mut machine = MachineBuilder::new()
+            .assembly(program.as_bytes())
+            .build()
+            .unwrap();
+        let steps: Vec<StepInfo> = machine.steps().collect();
+
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[3].instruction, Some(Instruction::Hlt));
+        assert_eq!(machine.state(), MachineState::Halted(HaltReason::HaltInstruction));
+        assert_eq!(machine.pc().value(), 0x0006);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x0100);
+        // The return address is left below the stack.
+        assert_eq!(machine.memory().read_16(0x00FE).unwrap().value(), 0x0006);
+    }
+
+    #[test]
+    fn test_restart_returns_after_the_restart() {
+        // 0000: LXI SP, 0100H
+        // 0003: RST 1
+        // 0004: HLT
+        // 0008: RET
+        let program = [0x31, 0x00, 0x01, 0xCF, 0x76, 0x00, 0x00, 0x00, 0xC9];
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        let trace: Vec<u16> = machine
+            .steps()
+            .map(|step| step.pc_before.value())
+            .collect();
+
+        assert_eq!(trace, [0x0000, 0x0003, 0x0008, 0x0004]);
+        assert_eq!(machine.state(), MachineState::Halted(HaltReason::HaltInstruction));
+    }
+
+    #[test]
+    fn test_cycles_of_delay_loop() {
+        let program = "        ORG 0H
+        MVI B, 10
+LOOP:   DCR B
+        JNZ LOOP
+        HLT
+        END
+";
+        let mut machine = MachineBuilder::new()
+            .assembly(program.as_bytes())
+            .build()
+            .unwrap();
+        assert_eq!(machine.cycles(), 0);
+        machine.steps().for_each(drop);
+
+        // MVI, 10 times DCR and JNZ, HLT.
+        assert_eq!(machine.cycles(), 7 + 10 * (5 + 10) + 7);
+    }
+
+    #[test]
+    fn test_cycles_of_conditional_call_and_return() {
+        let program = "        ORG 0H
+        LXI SP, 100H
+        XRA A
+        CNZ FUNC
+        CZ FUNC
+        HLT
+FUNC:   RNZ
+        RZ
+        END
+";
+        let mut machine = MachineBuilder::new()
+            .assembly(program.as_bytes())
+            .build()
+            .unwrap();
+        machine.steps().for_each(drop);
+
+        // LXI, XRA, CNZ not taken, CZ taken, RNZ not taken, RZ taken, HLT.
+        assert_eq!(machine.cycles(), 10 + 4 + 11 + 17 + 5 + 11 + 7);
+        assert_eq!(machine.instructions(), 7);
+    }
+
+    #[test]
+    fn test_stack_underflow_and_overflow() {
+        // 0000: LXI SP, 0FFFFH
+        // 0003: POP B
+        let mut machine = MachineBuilder::new()
+            .program(&[0x31, 0xFF, 0xFF, 0xC1], 0x0000)
+            .build()
+            .unwrap();
+        machine.set_strict_memory(true);
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::StackUnderflow)
+        );
+
+        // 0000: LXI SP, 0001H
+        // 0003: PUSH B
+        let mut machine = MachineBuilder::new()
+            .program(&[0x31, 0x01, 0x00, 0xC5], 0x0000)
+            .build()
+            .unwrap();
+        machine.set_strict_memory(true);
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::StackOverflow)
+        );
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        // 0000: LXI SP, 1000H
+        // 0003: CALL 0003H
+        let mut machine = MachineBuilder::new()
+            .program(&[0x31, 0x00, 0x10, 0xCD, 0x03, 0x00], 0x0000)
+            .build()
+            .unwrap();
+        machine.set_stack_limit(0x0FE0);
+        machine.set_stack_ceiling(0x1000);
+        assert_eq!(machine.stack_region(), Some(0x0FE0..0x1000));
+
+        // The 32 bytes hold 16 return addresses, so the 17th call overflows.
+        assert_eq!(machine.steps().count(), 1 + 17);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::StackOverflow)
+        );
+        assert_eq!(machine.pc().value(), 0x0003);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x0FE0);
+        assert_eq!(machine.memory().read_16(0x0FE0), Some(Data16::from(0x0006)));
+        assert_eq!(machine.memory().read_16(0x0FDE), Some(Data16::from(0x0000)));
+    }
+
+    #[test]
+    fn test_stack_ceiling() {
+        // 0000: LXI SP, 0FFEH
+        // 0003: POP B
+        // 0004: POP B
+        let mut machine = MachineBuilder::new()
+            .program(&[0x31, 0xFE, 0x0F, 0xC1, 0xC1], 0x0000)
+            .build()
+            .unwrap();
+        machine.set_stack_ceiling(0x1000);
+        assert_eq!(machine.stack_region(), Some(0x0000..0x1000));
+
+        // The first POP moves SP to the ceiling, and the second would go past it.
+        assert_eq!(machine.steps().count(), 3);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::StackUnderflow)
+        );
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x1000);
+
+        machine.clear_stack_limits();
+        assert_eq!(machine.stack_region(), None);
+    }
+
+    #[test]
+    fn test_undocumented_opcodes() {
+        // 0000: LXI SP, 1000H
+        // 0003: CALL 0008H
+        // 0006: HLT
+        // 0007: NOP
+        // 0008: RET, undocumented
+        let program = [0x31, 0x00, 0x10, 0xCD, 0x08, 0x00, 0x76, 0x00, 0xD9];
+
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .undocumented_opcodes(UndocumentedPolicy::Alias)
+            .build()
+            .unwrap();
+        assert_eq!(machine.steps().count(), 4);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.pc(), Data16::from(0x0006));
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x1000);
+
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::InvalidInstruction)
+        );
+        assert_eq!(machine.pc(), Data16::from(0x0008));
+    }
+
+    #[test]
+    fn test_self_jump() {
+        // 0000: MVI A, 2AH
+        // 0002: JMP 0002H
+        let mut machine = MachineBuilder::new()
+            .program(&[0x3E, 0x2A, 0xC3, 0x02, 0x00], 0x0000)
+            .halt_on_self_jump(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.run_until_halt(100),
+            (RunOutcome::Halted(HaltReason::SelfJump), 2)
+        );
+        assert_eq!(machine.pc(), Data16::from(0x0002));
+        assert_eq!(machine.register_8(Register::A), 0x2A);
+    }
+
+    #[test]
+    fn test_conditional_self_jump() {
+        // 0000: XRA A
+        // 0001: JNZ 0001H, not taken
+        // 0004: JZ 0004H
+        let mut machine = MachineBuilder::new()
+            .program(&[0xAF, 0xC2, 0x01, 0x00, 0xCA, 0x04, 0x00], 0x0000)
+            .halt_on_self_jump(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.run_until_halt(100),
+            (RunOutcome::Halted(HaltReason::SelfJump), 3)
+        );
+        assert_eq!(machine.pc(), Data16::from(0x0004));
+    }
+
+    #[test]
+    fn test_self_jump_keeps_running_by_default() {
+        // 0000: JMP 0000H
+        let mut machine = MachineBuilder::new()
+            .program(&[0xC3, 0x00, 0x00], 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.run_until_halt(100),
+            (RunOutcome::BudgetExhausted, 100)
+        );
+        assert_eq!(machine.state(), MachineState::Running);
+        assert_eq!(machine.pc(), Data16::from(0x0000));
+    }
+
+    #[test]
+    fn test_run_until_return() {
+        // 0000: LXI SP, 1000H
+        // 0003: CALL 000AH
+        // 0006: HLT
+        // 0007: NOP
+        // 0008: NOP
+        // 0009: NOP
+        // 000A: CALL 0011H
+        // 000D: MVI B, 01H
+        // 000F: RET
+        // 0010: NOP
+        // 0011: CALL 0016H
+        // 0014: RET
+        // 0015: NOP
+        // 0016: MVI C, 02H
+        // 0018: RET
+        let program = [
+            0x31, 0x00, 0x10, 0xCD, 0x0A, 0x00, 0x76, 0x00, 0x00, 0x00, 0xCD, 0x11, 0x00, 0x06,
+            0x01, 0xC9, 0x00, 0xCD, 0x16, 0x00, 0xC9, 0x00, 0x0E, 0x02, 0xC9,
+        ];
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        machine.add_breakpoint(0x0011);
+        assert_eq!(
+            machine.run_until_halt(100),
+            (RunOutcome::Breakpoint(0x0011), 3)
+        );
+        machine.remove_breakpoint(0x0011);
+
+        // Out of the routine at 0011H, through the one it calls.
+        assert_eq!(machine.run_until_return(100), RunOutcome::Returned(0x000D));
+        assert_eq!(machine.register_8(Register::C), 0x02);
+        assert_eq!(machine.register_8(Register::B), 0x00);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x0FFE);
+
+        assert_eq!(machine.run_until_return(100), RunOutcome::Returned(0x0006));
+        assert_eq!(machine.register_8(Register::B), 0x01);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x1000);
+    }
+
+    #[test]
+    fn test_run_until_return_through_pchl() {
+        // 0000: LXI SP, 1000H
+        // 0003: CALL 0007H
+        // 0006: HLT
+        // 0007: POP H
+        // 0008: PCHL
+        let mut machine = MachineBuilder::new()
+            .program(&[0x31, 0x00, 0x10, 0xCD, 0x07, 0x00, 0x76, 0xE1, 0xE9], 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(machine.run_until_halt(2), (RunOutcome::BudgetExhausted, 2));
+        assert_eq!(machine.run_until_return(100), RunOutcome::Returned(0x0006));
+    }
+
+    #[test]
+    fn test_run_until_return_halts() {
+        // 0000: LXI SP, 1000H
+        // 0003: CALL 0007H
+        // 0006: RET
+        // 0007: PUSH B
+        // 0008: HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0x31, 0x00, 0x10, 0xCD, 0x07, 0x00, 0xC9, 0xC5, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(machine.run_until_halt(2), (RunOutcome::BudgetExhausted, 2));
+        assert_eq!(
+            machine.run_until_return(100),
+            RunOutcome::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.pc(), Data16::from(0x0008));
+    }
+
+    #[test]
+    fn test_step_over() {
+        // 0000: LXI SP, 1000H
+        // 0003: CALL 000EH
+        // 0006: XRA A
+        // 0007: CNZ 000EH, not taken
+        // 000A: RST 2
+        // 000B: HLT
+        // 000C: NOP
+        // 000D: NOP
+        // 000E: INR B
+        // 000F: RET
+        // 0010: INR C
+        // 0011: RET
+        let program = [
+            0x31, 0x00, 0x10, 0xCD, 0x0E, 0x00, 0xAF, 0xC4, 0x0E, 0x00, 0xD7, 0x76, 0x00, 0x00,
+            0x04, 0xC9, 0x0C, 0xC9,
+        ];
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+
+        assert_eq!(machine.step_over(100), RunOutcome::Stepped(0x0003));
+        assert_eq!(machine.step_over(100), RunOutcome::Stepped(0x0006));
+        assert_eq!(machine.register_8(Register::B), 1);
+        assert_eq!(machine.step_over(100), RunOutcome::Stepped(0x0007));
+        assert_eq!(machine.step_over(100), RunOutcome::Stepped(0x000A));
+        assert_eq!(machine.register_8(Register::B), 1);
+        assert_eq!(machine.step_over(100), RunOutcome::Stepped(0x000B));
+        assert_eq!(machine.register_8(Register::C), 1);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x1000);
+        assert_eq!(
+            machine.step_over(100),
+            RunOutcome::Halted(HaltReason::HaltInstruction)
+        );
+    }
+
+    #[test]
+    fn test_step_over_recursion() {
+        // 0000: LXI SP, 1000H
+        // 0003: MVI B, 03H
+        // 0005: CALL 0009H
+        // 0008: HLT
+        // 0009: DCR B
+        // 000A: CNZ 0009H
+        // 000D: INR C
+        // 000E: RET
+        let program = [
+            0x31, 0x00, 0x10, 0x06, 0x03, 0xCD, 0x09, 0x00, 0x76, 0x05, 0xC4, 0x09, 0x00, 0x0C,
+            0xC9,
+        ];
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(machine.run_until_halt(4), (RunOutcome::BudgetExhausted, 4));
+
+        // The recursive calls return to 000DH deeper in the stack first.
+        assert_eq!(machine.step_over(100), RunOutcome::Stepped(0x000D));
+        assert_eq!(machine.register_8(Register::C), 2);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x0FFE);
+    }
+
+    #[test]
+    fn test_step_over_budget() {
+        // 0000: CALL 0004H
+        // 0003: HLT
+        // 0004: JMP 0004H
+        let mut machine = MachineBuilder::new()
+            .program(&[0xCD, 0x04, 0x00, 0x76, 0xC3, 0x04, 0x00], 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(machine.step_over(10), RunOutcome::BudgetExhausted);
+        assert_eq!(machine.pc(), Data16::from(0x0004));
+    }
+
+    #[test]
+    fn test_stack_wraps_around() {
+        // 0000: LXI SP, 0001H
+        // 0003: LXI B, 1234H
+        // 0006: PUSH B
+        // 0007: POP D
+        // 0008: HLT
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[0x31, 0x01, 0x00, 0x01, 0x34, 0x12, 0xC5, 0xD1, 0x76],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+        machine.steps().take(3).for_each(drop);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0xFFFF);
+        assert_eq!(machine.memory().read_8(0xFFFF), 0x34);
+        assert_eq!(machine.memory().read_8(0x0000), 0x12);
+
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x0001);
+        assert_eq!(machine.register_16(RegisterPair::De).value(), 0x1234);
+    }
+
+    #[test]
+    fn test_16_bit_access_wraps_around() {
+        let mut machine = Machine::new();
+        machine.set_register_16(RegisterPair::Hl, Data16::new(0xCD, 0xAB));
+        assert_eq!(
+            machine.execute(Instruction::Shld(0xFFFF)),
+            ExecutionResult::Running
+        );
+        assert_eq!(machine.memory().read_8(0xFFFF), 0xCD);
+        assert_eq!(machine.memory().read_8(0x0000), 0xAB);
+
+        machine.memory_mut().write_8(0x0000, 0x12);
+        assert_eq!(
+            machine.execute(Instruction::Lhld(0xFFFF)),
+            ExecutionResult::Running
+        );
+        assert_eq!(machine.register_16(RegisterPair::Hl).value(), 0x12CD);
+
+        machine.set_strict_memory(true);
+        assert_eq!(
+            machine.execute(Instruction::Shld(0xFFFF)),
+            ExecutionResult::MemoryOverflow
+        );
+        assert_eq!(machine.memory().read_8(0x0000), 0x12);
+        assert_eq!(
+            machine.execute(Instruction::Lhld(0xFFFF)),
+            ExecutionResult::MemoryOverflow
+        );
+    }
+
+    #[test]
+    fn test_cma_from_memory() {
+        // 0000: CMA
+        // 0001: HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0x2F, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        machine.set_register_8(Register::A, 0x51);
+
+        let step = machine.step().unwrap();
+        assert_eq!(step.instruction, Some(Instruction::Cma));
+        assert_eq!(machine.register_8(Register::A), 0xAE);
+        assert_eq!(machine.pc().value(), 0x0001);
+    }
+
+    /// Encode `instruction` at the program counter, then decode and execute it.
+    fn step_encoded(machine: &mut Machine, instruction: Instruction) -> StepInfo {
+        let mut buffer = [0; 3];
+        let length = coding::encode_into(&mut buffer, instruction).unwrap();
+        let pc = machine.pc();
+        machine
+            .memory_mut()
+            .write_slice(pc.into(), &buffer[..length])
+            .unwrap();
+        let step = machine.step().unwrap();
+        assert_eq!(step.instruction, Some(instruction));
+        step
+    }
+
+    #[test]
+    fn test_exchange_instructions() {
+        let mut machine = Machine::new();
+        machine.set_register_16(RegisterPair::De, Data16::new(0x34, 0x12));
+        machine.set_register_16(RegisterPair::Hl, Data16::new(0x78, 0x56));
+        machine.set_register_16(RegisterPair::Sp, Data16::new(0x00, 0x20));
+        machine.memory_mut().write_16(0x2000, Data16::new(0xBC, 0x9A)).unwrap();
+
+        let step = step_encoded(&mut machine, Instruction::Xchg);
+        assert_eq!(step.result, ExecutionResult::Running);
+        assert_eq!(machine.register_16(RegisterPair::De).value(), 0x5678);
+        assert_eq!(machine.register_16(RegisterPair::Hl).value(), 0x1234);
+        assert_eq!(machine.pc().value(), 0x0001);
+
+        let step = step_encoded(&mut machine, Instruction::Xthl);
+        assert_eq!(step.result, ExecutionResult::Running);
+        assert_eq!(machine.register_16(RegisterPair::Hl).value(), 0x9ABC);
+        assert_eq!(
+            machine.memory().read_16(0x2000).unwrap().value(),
+            0x1234
+        );
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x2000);
+
+        let step = step_encoded(&mut machine, Instruction::Sphl);
+        assert_eq!(step.result, ExecutionResult::Running);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x9ABC);
+        assert_eq!(machine.pc().value(), 0x0003);
+    }
+
+    #[test]
+    fn test_pchl() {
+        let mut machine = Machine::new();
+        machine.set_register_16(RegisterPair::Hl, Data16::new(0x00, 0x01));
+
+        let step = step_encoded(&mut machine, Instruction::Pchl);
+        assert_eq!(step.result, ExecutionResult::ControlTransfer);
+        assert_eq!(machine.pc().value(), 0x0100);
+    }
+
+    #[test]
+    fn test_interrupt_enable() {
+        let mut machine = Machine::new();
+
+        // Both only move on to the next instruction.
+        for instruction in [Instruction::Ei, Instruction::Di] {
+            let step = step_encoded(&mut machine, instruction);
+            assert_eq!(step.result, ExecutionResult::Running);
+        }
+        assert_eq!(machine.pc().value(), 0x0002);
+        assert_eq!(machine.state(), MachineState::Running);
+    }
+
+    #[test]
+    fn test_rim_sim() {
+        // 0000: MVI A, 0CDH
+        // 0002: SIM
+        // 0003: MVI A, 02H
+        // 0005: SIM
+        // 0006: EI
+        // 0007: RIM
+        // 0008: HLT
+        let program = [0x3E, 0xCD, 0x30, 0x3E, 0x02, 0x30, 0xFB, 0x20, 0x76];
+
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .variant(Variant::Intel8085)
+            .build()
+            .unwrap();
+        machine.step();
+        machine.step();
+        // Mask set enable and serial output enable are both set.
+        assert_eq!(machine.interrupt_mask(), 0b101);
+        assert!(machine.serial_output());
+        // Neither is set, so nothing changes.
+        machine.step();
+        machine.step();
+        assert_eq!(machine.interrupt_mask(), 0b101);
+        assert!(machine.serial_output());
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.register_8(Register::A), 0b0000_1101);
+
+        // The 8080 doesn't have them.
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::InvalidInstruction)
+        );
+        assert_eq!(machine.pc(), Data16::from(0x0002));
+    }
+
+    /// 0000: LXI SP, 0100H
+    /// 0003: EI
+    /// 0004: NOP
+    /// 0005: DI
+    /// 0006: HLT
+    /// 0007: MVI B, 1
+    /// 0009: HLT
+    /// 0010: INR A
+    /// 0011: EI
+    /// 0012: RET
+    const INTERRUPT_PROGRAM: [u8; 19] = [
+        0x31, 0x00, 0x01, 0xFB, 0x00, 0xF3, 0x76, 0x06, 0x01, 0x76, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x3C, 0xFB, 0xC9,
+    ];
+
+    fn interrupt_machine() -> Machine {
+        MachineBuilder::new()
+            .program(&INTERRUPT_PROGRAM, 0x0000)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_interrupt_pushes_the_interrupted_address() {
+        let mut machine = interrupt_machine();
+        assert!(!machine.request_interrupt(Instruction::Rst(RestartNumber::R2)));
+
+        machine.step().unwrap();
+        machine.step().unwrap();
+        // Not until the instruction after EI has been executed.
+        assert!(!machine.interrupts_enabled());
+        assert!(!machine.request_interrupt(Instruction::Rst(RestartNumber::R2)));
+        machine.step().unwrap();
+        assert!(machine.interrupts_enabled());
+
+        assert!(machine.request_interrupt(Instruction::Rst(RestartNumber::R2)));
+        assert!(!machine.interrupts_enabled());
+        let step = machine.step().unwrap();
+        assert_eq!(step.pc_before.value(), 0x0005);
+        assert_eq!(step.instruction, Some(Instruction::Rst(RestartNumber::R2)));
+        assert_eq!(step.result, ExecutionResult::ControlTransfer);
+        assert_eq!(machine.pc().value(), 0x0010);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x00FE);
+        assert_eq!(machine.memory().read_16(0x00FE).unwrap().value(), 0x0005);
+        assert_eq!(machine.cycles(), 10 + 4 + 4 + 11);
+    }
+
+    #[test]
+    fn test_di_blocks_interrupts() {
+        let mut machine = interrupt_machine();
+        machine.steps().take(4).for_each(drop);
+
+        assert!(!machine.interrupts_enabled());
+        assert!(!machine.request_interrupt(Instruction::Rst(RestartNumber::R2)));
+        let step = machine.step().unwrap();
+        assert_eq!(step.instruction, Some(Instruction::Hlt));
+        assert_eq!(machine.state(), MachineState::Halted(HaltReason::HaltInstruction));
+        assert!(!machine.request_interrupt(Instruction::Rst(RestartNumber::R2)));
+        assert_eq!(machine.state(), MachineState::Halted(HaltReason::HaltInstruction));
+    }
+
+    #[test]
+    fn test_interrupt_wakes_halted_machine() {
+        // Skip DI, so that the machine halts with interrupts enabled.
+        let mut program = INTERRUPT_PROGRAM;
+        program[5] = 0x00;
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        machine.steps().for_each(drop);
+        assert_eq!(machine.state(), MachineState::Halted(HaltReason::HaltInstruction));
+        assert_eq!(machine.pc().value(), 0x0006);
+
+        assert!(machine.request_interrupt(Instruction::Rst(RestartNumber::R2)));
+        assert_eq!(machine.state(), MachineState::Running);
+        let trace: Vec<u16> = machine
+            .steps()
+            .map(|step| step.pc_before.value())
+            .collect();
+
+        assert_eq!(trace, [0x0007, 0x0010, 0x0011, 0x0012, 0x0007, 0x0009]);
+        assert_eq!(machine.register_8(Register::A), 1);
+        assert_eq!(machine.register_8(Register::B), 1);
+        assert!(machine.interrupts_enabled());
+    }
+
+    /// Count B down from 3, printing and pushing it every time, then store A and halt.
+    const RESET_PROGRAM: [u8; 19] = [
+        0x31, 0x00, 0x02, // 0100: LXI SP, 0200H
+        0x06, 0x03, //       0103: MVI B, 3
+        0x78, //             0105: MOV A, B
+        0xC6, 0x30, //       0106: ADI 30H
+        0xD3, 0x00, //       0108: OUT 0
+        0xC5, //             010A: PUSH B
+        0x05, //             010B: DCR B
+        0xC2, 0x05, 0x01, // 010C: JNZ 0105H
+        0x32, 0x00, 0x03, // 010F: STA 0300H
+        0x76, //             0112: HLT
+    ];
+
+    fn reset_machine() -> Machine {
+        MachineBuilder::new()
+            .program(&RESET_PROGRAM, 0x0100)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reset_reruns_to_the_same_halt() {
+        let mut machine = reset_machine();
+        assert_eq!(machine.entry(), 0x0100);
+        machine.steps().for_each(drop);
+        let halted = (
+            machine.state(),
+            machine.pc(),
+            machine.register_8(Register::A),
+            machine.register_16(RegisterPair::Sp),
+            machine.cycles(),
+            machine.output_buffer().to_vec(),
+        );
+        assert_eq!(halted.0, MachineState::Halted(HaltReason::HaltInstruction));
+
+        machine.reset();
+        assert_eq!(machine.state(), MachineState::Running);
+        assert_eq!(machine.pc().value(), 0x0100);
+        assert_eq!(machine.register_8(Register::A), 0);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0);
+        assert!(!machine.conditions().get(ConditionRegister::Zero));
+        assert_eq!(machine.cycles(), 0);
+        assert!(machine.output_buffer().is_empty());
+        // Memory is kept, including what the program wrote.
+        assert_eq!(machine.memory().read_8(0x0300), 0x31);
+        assert_eq!(&machine.memory().as_raw()[0x0100..0x0113], RESET_PROGRAM);
+
+        machine.steps().for_each(drop);
+        assert_eq!(
+            (
+                machine.state(),
+                machine.pc(),
+                machine.register_8(Register::A),
+                machine.register_16(RegisterPair::Sp),
+                machine.cycles(),
+                machine.output_buffer().to_vec(),
+            ),
+            halted
+        );
+        assert_eq!(machine.output_buffer(), b"321");
+    }
+
+    #[test]
+    fn test_reset_full_zeroes_memory() {
+        let mut machine = reset_machine();
+        machine.steps().for_each(drop);
+
+        machine.reset_full();
+        assert_eq!(machine.state(), MachineState::Running);
+        assert_eq!(machine.pc().value(), 0x0100);
+        assert!(machine.memory().as_raw().iter().all(|&byte| byte == 0));
+        assert!(machine.loaded_ranges().is_empty());
+        assert!(machine.output_buffer().is_empty());
+    }
+
+    #[test]
+    fn test_run_until_halt() {
+        let mut machine = reset_machine();
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Halted(HaltReason::HaltInstruction), 2 + 6 * 3 + 2)
+        );
+        assert_eq!(machine.output_buffer(), b"321");
+        // A halted machine executes nothing.
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Halted(HaltReason::HaltInstruction), 0)
+        );
+
+        // Exactly the instructions up to and including HLT.
+        machine.reset();
+        assert_eq!(
+            machine.run_until_halt(22),
+            (RunOutcome::Halted(HaltReason::HaltInstruction), 22)
+        );
+    }
+
+    #[test]
+    fn test_run_until_halt_exhausts_budget() {
+        // 0000: JMP 0000H
+        let mut machine = MachineBuilder::new()
+            .program(&[0xC3, 0x00, 0x00], 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.run_until_halt(500),
+            (RunOutcome::BudgetExhausted, 500)
+        );
+        assert_eq!(machine.state(), MachineState::Running);
+        assert_eq!(machine.run_until_halt(0), (RunOutcome::BudgetExhausted, 0));
+    }
+
+    #[test]
+    fn test_run_until_halt_on_invalid_instruction() {
+        // 0000: NOP, 0001: an undocumented opcode
+        let mut machine = MachineBuilder::new()
+            .program(&[0x00, 0x08], 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.run_until_halt(100),
+            (RunOutcome::Halted(HaltReason::InvalidInstruction), 2)
+        );
+        assert_eq!(machine.pc().value(), 0x0001);
+    }
+
+    #[test]
+    fn test_run_until_halt_waiting_for_input() {
+        let mut machine = MachineBuilder::new()
+            .program(&ECHO_PROGRAM, 0x0000)
+            .build()
+            .unwrap();
+        machine.set_wait_for_input(true);
+        machine.push_input(b"a");
+        assert_eq!(
+            machine.run_until_halt(100),
+            (RunOutcome::WaitingForInput, 4)
+        );
+    }
+
+    #[test]
+    fn test_breakpoint_in_loop_is_hit_every_iteration() {
+        let mut machine = reset_machine();
+        // OUT 0
+        assert!(machine.add_breakpoint(0x0108));
+        assert!(!machine.add_breakpoint(0x0108));
+
+        // LXI, MVI, MOV and ADI before the first OUT.
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Breakpoint(0x0108), 4)
+        );
+        assert_eq!(machine.state(), MachineState::Running);
+        assert!(machine.output_buffer().is_empty());
+        for digit in [b"3", b"2"] {
+            // The OUT is executed once, then the loop comes around to it again.
+            assert_eq!(
+                machine.run_until_halt(1000),
+                (RunOutcome::Breakpoint(0x0108), 6)
+            );
+            assert!(machine.output_buffer().ends_with(digit));
+        }
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Halted(HaltReason::HaltInstruction), 6)
+        );
+        assert_eq!(machine.output_buffer(), b"321");
+        assert!(machine.breakpoints().contains(&0x0108));
+    }
+
+    #[test]
+    fn test_breakpoint_removed_mid_run() {
+        let mut machine = reset_machine();
+        // MOV A, B
+        machine.add_breakpoint(0x0105);
+        machine.add_breakpoint(0x010F);
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Breakpoint(0x0105), 2)
+        );
+
+        assert!(machine.remove_breakpoint(0x0105));
+        assert!(!machine.remove_breakpoint(0x0105));
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Breakpoint(0x010F), 6 * 3)
+        );
+        assert_eq!(machine.output_buffer(), b"321");
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Halted(HaltReason::HaltInstruction), 2)
+        );
+    }
+
+    /// LXI D, 0300H; MVI A, 2AH; STAX D; MVI A, 0; LDAX D; HLT.
+    const WATCH_PROGRAM: [u8; 10] = [0x11, 0x00, 0x03, 0x3E, 0x2A, 0x12, 0x3E, 0x00, 0x1A, 0x76];
+
+    fn watch_machine() -> Machine {
+        MachineBuilder::new()
+            .program(&WATCH_PROGRAM, 0x0100)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_write_watchpoint() {
+        let mut machine = watch_machine();
+        assert!(machine.watch_write(0x0300));
+        assert!(!machine.watch_write(0x0300));
+        let hit = WatchHit {
+            pc: 0x0105,
+            instruction: Instruction::Stax(RegisterPairIndirect::De),
+            address: 0x0300,
+            access: MemoryAccess::Write,
+            value: 0x2A,
+        };
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Watchpoint(hit), 3)
+        );
+        assert_eq!(machine.pc().value(), 0x0106);
+        assert_eq!(machine.memory().read_8(0x0300), 0x2A);
+
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Halted(HaltReason::HaltInstruction), 3)
+        );
+    }
+
+    #[test]
+    fn test_read_watchpoint() {
+        let mut machine = watch_machine();
+        machine.watch_read(0x0300);
+        let hit = WatchHit {
+            pc: 0x0108,
+            instruction: Instruction::Ldax(RegisterPairIndirect::De),
+            address: 0x0300,
+            access: MemoryAccess::Read,
+            value: 0x2A,
+        };
+        assert_eq!(
+            machine.run_until_halt(1000),
+            (RunOutcome::Watchpoint(hit), 5)
+        );
+        assert_eq!(machine.pc().value(), 0x0109);
+        assert_eq!(machine.register_8(Register::A), 0x2A);
+
+        assert!(machine.unwatch(0x0300));
+        assert!(!machine.unwatch(0x0300));
+    }
+
+    /// Echo input to the output: IN 0, OUT 0, JMP 0000H.
+    const ECHO_PROGRAM: [u8; 7] = [0xDB, 0x00, 0xD3, 0x00, 0xC3, 0x00, 0x00];
+
+    #[test]
+    fn test_input_is_read_in_pushed_order() {
+        let mut machine = MachineBuilder::new()
+            .program(&ECHO_PROGRAM, 0x0000)
+            .build()
+            .unwrap();
+        machine.push_input(b"ab");
+        machine.push_input(b"c");
+        machine.steps().for_each(drop);
+
+        assert_eq!(machine.output_buffer(), b"abc");
+        assert_eq!(machine.state(), MachineState::Halted(HaltReason::HaltInstruction));
+    }
+
+    #[test]
+    fn test_waiting_for_input() {
+        let mut machine = MachineBuilder::new()
+            .program(&ECHO_PROGRAM, 0x0000)
+            .build()
+            .unwrap();
+        machine.set_wait_for_input(true);
+        machine.push_input(b"a");
+        let last = machine.steps().last().unwrap();
+
+        assert_eq!(last.result, ExecutionResult::WaitingForInput);
+        assert_eq!(machine.state(), MachineState::WaitingForInput);
+        assert_eq!(machine.pc().value(), 0x0000);
+        assert_eq!(machine.output_buffer(), b"a");
+        // The waiting IN isn't counted until it's executed again.
+        assert_eq!(machine.cycles(), 10 + 10 + 10);
+        assert!(machine.step().is_none());
+
+        machine.push_input(b"bc");
+        assert_eq!(machine.state(), MachineState::Running);
+        machine.steps().for_each(drop);
+        assert_eq!(machine.output_buffer(), b"abc");
+        assert_eq!(machine.state(), MachineState::WaitingForInput);
+    }
+
+    #[test]
+    fn test_output_writer() {
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut machine = MachineBuilder::new()
+            .program(&ECHO_PROGRAM, 0x0000)
+            .input(b"ab")
+            .build()
+            .unwrap();
+        let sink = Shared::default();
+        machine.set_output(sink.clone());
+        machine.steps().for_each(drop);
+
+        assert_eq!(*sink.0.lock().unwrap(), b"ab");
+        assert!(machine.output_buffer().is_empty());
+        assert!(machine.take_output_error().is_none());
+    }
+
+    #[test]
+    fn test_failing_output_writer() {
+        struct Broken;
+
+        impl std::io::Write for Broken {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut machine = MachineBuilder::new()
+            .program(&ECHO_PROGRAM, 0x0000)
+            .input(b"ab")
+            .build()
+            .unwrap();
+        machine.set_output(Broken);
+        machine.steps().for_each(drop);
+
+        // The program runs on, its output dropped.
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert!(machine.output_buffer().is_empty());
+        let err = machine.take_output_error().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+        assert!(machine.take_output_error().is_none());
+    }
+
+    #[cfg(feature = "trace-log")]
+    #[test]
+    fn test_fault_emits_warn_event() {
+        use std::{
+            fmt::Debug,
+            sync::{Arc, Mutex},
+        };
+        use tracing::{
+            Event, Level, Subscriber,
+            field::{Field, Visit},
+        };
+        use tracing_subscriber::{
+            Layer, Registry,
+            layer::{Context, SubscriberExt},
+        };
+
+        type Fields = Vec<(String, String)>;
+
+        #[derive(Clone, Default)]
+        struct Capture(Arc<Mutex<Vec<(Level, Fields)>>>);
+
+        struct Recorder(Fields);
+
+        impl Visit for Recorder {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0.push((field.name().to_owned(), format!("{:?}", value)));
+            }
+        }
+
+        impl<S: Subscriber> Layer<S> for Capture {
+            fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+                let mut recorder = Recorder(Vec::new());
+                event.record(&mut recorder);
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((*event.metadata().level(), recorder.0));
+            }
+        }
+
+        let capture = Capture::default();
+        let subscriber = Registry::default().with(capture.clone());
+
+        // 0000: NOP
+        // 0001: (invalid)
+        let mut machine = MachineBuilder::new()
+            .program(&[0x00, 0x08], 0x0000)
+            .build()
+            .unwrap();
+        tracing::subscriber::with_default(subscriber, || machine.steps().for_each(drop));
+
+        let events = capture.0.lock().unwrap();
+        let warnings: Vec<&Fields> = events
+            .iter()
+            .filter(|(level, _)| *level == Level::WARN)
+            .map(|(_, fields)| fields)
+            .collect();
+        assert_eq!(
+            warnings,
+            vec![&vec![
+                (String::from("message"), String::from("machine faulted")),
+                (String::from("pc"), String::from("1")),
+                (
+                    String::from("reason"),
+                    String::from("Encountered invalid instruction")
+                ),
+            ]]
         );
     }
 }
@@ -0,0 +1,212 @@
+//! Results and flags of the arithmetic and logic instructions, independent of the machine. Every
+//! function returns the result with the [`Flags`] the instruction sets, which
+//! [`Machine::apply_flags`](super::Machine::apply_flags) writes to the condition registers.
+//!
+//! The 8080 quirks live here:
+//!
+//! - Subtraction adds the complement of the operand, so the auxiliary carry of `a - b` is the
+//!   carry out of bit 3 of `a + !b + 1`, set when the low nibble doesn't borrow. The carry flag is
+//!   the borrow.
+//! - `ANA` and `ANI` set the auxiliary carry to the OR of bit 3 of the operands, while the other
+//!   logical instructions clear it. All of them clear the carry.
+//! - `INR` and `DCR` leave the carry alone.
+
+use crate::instruction::Data8;
+
+/// Flags set by an ALU operation. Zero, sign and parity always follow the result.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub(super) struct Flags {
+    pub(super) zero: bool,
+    pub(super) sign: bool,
+    pub(super) parity: bool,
+    pub(super) aux_carry: bool,
+    /// `None` if the operation leaves the carry as it was.
+    pub(super) carry: Option<bool>,
+}
+
+impl Flags {
+    fn of(result: Data8, aux_carry: bool, carry: Option<bool>) -> Self {
+        Self {
+            zero: result == 0,
+            sign: result & 0b1000_0000 != 0,
+            parity: result.count_ones().is_multiple_of(2),
+            aux_carry,
+            carry,
+        }
+    }
+}
+
+/// `a + b + carry`, for `ADD`, `ADC`, `ADI` and `ACI`.
+pub(super) fn add(a: Data8, b: Data8, carry: bool) -> (Data8, Flags) {
+    let sum = a as u16 + b as u16 + carry as u16;
+    let aux_carry = (a & 0x0F) + (b & 0x0F) + carry as u8 > 0x0F;
+    let result = sum as u8;
+    (result, Flags::of(result, aux_carry, Some(sum > 0xFF)))
+}
+
+/// `a - b - borrow`, for `SUB`, `SBB`, `SUI`, `SBI`, `CMP` and `CPI`.
+pub(super) fn sub(a: Data8, b: Data8, borrow: bool) -> (Data8, Flags) {
+    let (result, flags) = add(a, !b, !borrow);
+    let carry = flags.carry.map(|carry| !carry);
+    (result, Flags { carry, ..flags })
+}
+
+/// `a & b`, for `ANA` and `ANI`.
+pub(super) fn and(a: Data8, b: Data8) -> (Data8, Flags) {
+    let result = a & b;
+    let aux_carry = (a | b) & 0b0000_1000 != 0;
+    (result, Flags::of(result, aux_carry, Some(false)))
+}
+
+/// `a | b`, for `ORA` and `ORI`.
+pub(super) fn or(a: Data8, b: Data8) -> (Data8, Flags) {
+    let result = a | b;
+    (result, Flags::of(result, false, Some(false)))
+}
+
+/// `a ^ b`, for `XRA` and `XRI`.
+pub(super) fn xor(a: Data8, b: Data8) -> (Data8, Flags) {
+    let result = a ^ b;
+    (result, Flags::of(result, false, Some(false)))
+}
+
+/// `value + 1`, for `INR`.
+pub(super) fn inc(value: Data8) -> (Data8, Flags) {
+    let result = value.wrapping_add(1);
+    (result, Flags::of(result, value & 0x0F == 0x0F, None))
+}
+
+/// `value - 1`, for `DCR`. The auxiliary carry is set if the low nibble borrows.
+pub(super) fn dec(value: Data8) -> (Data8, Flags) {
+    let result = value.wrapping_sub(1);
+    (result, Flags::of(result, value & 0x0F == 0, None))
+}
+
+/// Adjust `a` to two BCD digits after adding BCD numbers, for `DAA`.
+///
+/// 6 is added if the low nibble is above 9 or the auxiliary carry is set, and 0x60 if the high
+/// nibble then is above 9 or the carry is set. Both corrections are one addition, which sets the
+/// auxiliary carry like `ADD` does. The carry is set by the second correction and never cleared.
+pub(super) fn daa(a: Data8, carry: bool, aux_carry: bool) -> (Data8, Flags) {
+    let mut correction = 0;
+    if a & 0x0F > 9 || aux_carry {
+        correction |= 0x06;
+    }
+    // Without wrapping, so that a carry out of the first correction counts.
+    let carry = carry || (a as u16 + correction as u16) >> 4 > 9;
+    if carry {
+        correction |= 0x60;
+    }
+    let (result, flags) = add(a, correction, false);
+    (
+        result,
+        Flags {
+            carry: Some(carry),
+            ..flags
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs() -> impl Iterator<Item = (Data8, Data8, bool)> {
+        (0..=255).flat_map(|a| (0..=255).flat_map(move |b| [(a, b, false), (a, b, true)]))
+    }
+
+    /// Check the flags that follow the result against a bit by bit count.
+    fn assert_zsp(result: Data8, flags: Flags) {
+        let ones = (0..8).filter(|bit| result >> bit & 1 == 1).count();
+        assert_eq!(flags.zero, result == 0, "zero of {:#04X}", result);
+        assert_eq!(flags.sign, result >= 0x80, "sign of {:#04X}", result);
+        assert_eq!(flags.parity, ones % 2 == 0, "parity of {:#04X}", result);
+    }
+
+    #[test]
+    fn add_exhaustive() {
+        for (a, b, carry) in inputs() {
+            let (result, flags) = add(a, b, carry);
+            let sum = a as u32 + b as u32 + carry as u32;
+            let context = format!("{:#04X} + {:#04X} + {}", a, b, carry as u8);
+            assert_eq!(result as u32, sum % 256, "{}", context);
+            assert_eq!(flags.carry, Some(sum >= 256), "{}", context);
+            let low = (a % 16 + b % 16) as u32 + carry as u32;
+            assert_eq!(flags.aux_carry, low >= 16, "{}", context);
+            assert_zsp(result, flags);
+        }
+    }
+
+    #[test]
+    fn sub_exhaustive() {
+        for (a, b, borrow) in inputs() {
+            let (result, flags) = sub(a, b, borrow);
+            let difference = a as i32 - b as i32 - borrow as i32;
+            let context = format!("{:#04X} - {:#04X} - {}", a, b, borrow as u8);
+            assert_eq!(result as i32, difference.rem_euclid(256), "{}", context);
+            assert_eq!(flags.carry, Some(difference < 0), "{}", context);
+            let low = (a % 16) as i32 - (b % 16) as i32 - borrow as i32;
+            assert_eq!(flags.aux_carry, low >= 0, "{}", context);
+            assert_zsp(result, flags);
+        }
+    }
+
+    #[test]
+    fn logical_exhaustive() {
+        for (a, b, _) in inputs().filter(|(_, _, carry)| !carry) {
+            let (result, flags) = and(a, b);
+            assert_eq!(result, a & b);
+            assert_eq!(flags.aux_carry, a & 0x08 != 0 || b & 0x08 != 0);
+            assert_eq!(flags.carry, Some(false));
+            assert_zsp(result, flags);
+
+            for (operation, expected) in [(or as fn(_, _) -> _, a | b), (xor, a ^ b)] {
+                let (result, flags) = operation(a, b);
+                assert_eq!(result, expected);
+                assert!(!flags.aux_carry);
+                assert_eq!(flags.carry, Some(false));
+                assert_zsp(result, flags);
+            }
+        }
+    }
+
+    #[test]
+    fn inc_dec_exhaustive() {
+        for value in 0..=255u8 {
+            let (result, flags) = inc(value);
+            assert_eq!(result, value.wrapping_add(1));
+            assert_eq!(flags.aux_carry, result % 16 == 0);
+            assert_eq!(flags.carry, None);
+            assert_zsp(result, flags);
+
+            let (result, flags) = dec(value);
+            assert_eq!(result, value.wrapping_sub(1));
+            assert_eq!(flags.aux_carry, value % 16 == 0);
+            assert_eq!(flags.carry, None);
+            assert_zsp(result, flags);
+        }
+    }
+
+    #[test]
+    fn daa_adds_bcd() {
+        let bcd = |value: u32| (value / 10 * 16 + value % 10) as Data8;
+        for x in 0..100 {
+            for y in 0..100 {
+                let (sum, flags) = add(bcd(x), bcd(y), false);
+                let (result, flags) = daa(sum, flags.carry.unwrap(), flags.aux_carry);
+                assert_eq!(result, bcd((x + y) % 100), "{} + {}", x, y);
+                assert_eq!(flags.carry, Some(x + y >= 100), "{} + {}", x, y);
+                assert_zsp(result, flags);
+            }
+        }
+    }
+
+    #[test]
+    fn daa_datasheet_example() {
+        // 0x9B has both nibbles above 9, so both corrections apply.
+        let (result, flags) = daa(0x9B, false, false);
+        assert_eq!(result, 0x01);
+        assert_eq!(flags.carry, Some(true));
+        assert!(flags.aux_carry);
+    }
+}
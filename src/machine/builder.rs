@@ -0,0 +1,1055 @@
+use alloc::{borrow::ToOwned, boxed::Box, string::String, vec::Vec};
+use core::{
+    fmt::{Debug, Display},
+    ops::Range,
+};
+
+#[cfg(feature = "std")]
+use std::{io::Write, sync::Arc};
+
+#[cfg(feature = "std")]
+use crate::{
+    assembler, coding,
+    instruction::InstructionOrData,
+    loader::{ADDRESS_SPACE, LoadError, MemoryImage},
+    machine::ExecutionObserver,
+    trace::TraceWriter,
+};
+use crate::{
+    coding::ihex::{self, IhexError},
+    instruction::{Address, Data8, Data16, Port, Register, RegisterPair, Variant},
+    machine::{ConditionRegister, IoDevice, Machine, MemoryModel, UndocumentedPolicy},
+};
+
+/// Error returned by [`MachineBuilder::build`] when the requested configuration is invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// Both a binary program and an assembly source were given.
+    ProgramAndAssembly,
+    /// The assembly source couldn't be assembled.
+    Assembly(String),
+    /// The program doesn't fit in memory when placed at `origin`.
+    ProgramTooLarge { origin: Address, length: usize },
+    /// Two segments (or the program and a segment) share the addresses of both ranges.
+    SegmentsOverlap(Range<usize>, Range<usize>),
+    /// Reading a loaded segment back from memory didn't return the loaded bytes, first at
+    /// `address`.
+    VerificationFailed { address: Address },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BuildError::ProgramAndAssembly => {
+                write!(f, "Both a binary program and an assembly source were given")
+            }
+            BuildError::Assembly(message) => write!(f, "Couldn't assemble program: {}", message),
+            BuildError::ProgramTooLarge { origin, length } => write!(
+                f,
+                "Program doesn't fit in memory. It is {} bytes large, but only {} bytes are available after 0x{:04X}",
+                length,
+                0x10000 - *origin as usize,
+                origin,
+            ),
+            BuildError::SegmentsOverlap(first, second) => write!(
+                f,
+                "Segments at 0x{:04X}-0x{:04X} and 0x{:04X}-0x{:04X} overlap",
+                first.start,
+                first.end - 1,
+                second.start,
+                second.end - 1,
+            ),
+            BuildError::VerificationFailed { address } => write!(
+                f,
+                "Memory at 0x{:04X} doesn't hold the loaded bytes after loading",
+                address
+            ),
+        }
+    }
+}
+
+impl core::error::Error for BuildError {}
+
+/// Error returned by [`Machine::load_assembly`].
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AssembleLoadError {
+    /// The source couldn't be assembled.
+    Assembly(String),
+    /// The assembled program doesn't fit in memory when placed at `origin`.
+    TooLarge { origin: Address, length: usize },
+}
+
+#[cfg(feature = "std")]
+impl Display for AssembleLoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AssembleLoadError::Assembly(message) => {
+                write!(f, "Couldn't assemble program: {}", message)
+            }
+            AssembleLoadError::TooLarge { origin, length } => write!(
+                f,
+                "Program doesn't fit in memory. It is {} bytes large, but only {} bytes are available after 0x{:04X}",
+                length,
+                0x10000 - *origin as usize,
+                origin,
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::error::Error for AssembleLoadError {}
+
+/// Chainable configuration for a [`Machine`], validated when calling [`MachineBuilder::build`].
+///
+/// ```
+/// use rsoderh_jonsh_leben_emulator::machine::{BuildError, MachineBuilder, MemoryModel};
+///
+/// let machine = MachineBuilder::new()
+///     .program(&[0x3E, 0x2A, 0x76], 0x0100)
+///     .sp(0xFFFE)
+///     .stack_region(0xF000..0xFFFE)
+///     .memory_model(MemoryModel::Strict)
+///     .build()?;
+/// assert_eq!(machine.pc().value(), 0x0100);
+/// # Ok::<(), BuildError>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MachineBuilder {
+    program: Option<(Vec<u8>, Address)>,
+    segments: Vec<(Vec<u8>, Address)>,
+    #[cfg(feature = "std")]
+    assembly: Option<Vec<u8>>,
+    sp: Option<Address>,
+    pc: Option<Address>,
+    input: Vec<u8>,
+    random_seed: Option<u32>,
+    unmapped_input: Option<Data8>,
+    undocumented_opcodes: Option<UndocumentedPolicy>,
+    variant: Variant,
+    allow_overlap: bool,
+    halt_on_self_jump: bool,
+    registers: Vec<(Register, Data8)>,
+    register_pairs: Vec<(RegisterPair, Data16)>,
+    flags: Vec<(ConditionRegister, bool)>,
+    devices: DeviceBus,
+    stack_region: Option<Range<Address>>,
+    memory_model: MemoryModel,
+    #[cfg(feature = "std")]
+    trace: Option<TraceTemplate>,
+}
+
+/// Devices and the ports they're attached at, for [`MachineBuilder::io_bus`]. Only shows the
+/// ports when debug printed.
+///
+/// The devices are templates: every machine built gets clones of them.
+#[derive(Default)]
+pub struct DeviceBus(Vec<(Vec<Port>, Box<dyn DeviceTemplate>)>);
+
+impl DeviceBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach `device` at `ports`. Later devices replace earlier ones at the ports they share.
+    pub fn device<T: IoDevice + Clone>(mut self, ports: &[Port], device: T) -> Self {
+        self.0.push((ports.to_owned(), Box::new(device)));
+        self
+    }
+}
+
+/// A device every built machine gets a clone of.
+trait DeviceTemplate: Send {
+    fn instantiate(&self) -> Box<dyn IoDevice>;
+    fn clone_template(&self) -> Box<dyn DeviceTemplate>;
+}
+
+impl<T: IoDevice + Clone> DeviceTemplate for T {
+    fn instantiate(&self) -> Box<dyn IoDevice> {
+        Box::new(self.clone())
+    }
+
+    fn clone_template(&self) -> Box<dyn DeviceTemplate> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for DeviceBus {
+    fn clone(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|(ports, device)| (ports.clone(), device.clone_template()))
+                .collect(),
+        )
+    }
+}
+
+impl Debug for DeviceBus {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(ports, _)| ports))
+            .finish()
+    }
+}
+
+/// Creates the trace writer of every machine built, see [`MachineBuilder::trace`].
+#[cfg(feature = "std")]
+#[derive(Clone)]
+struct TraceTemplate(Arc<dyn Fn() -> Box<dyn ExecutionObserver> + Send + Sync>);
+
+#[cfg(feature = "std")]
+impl Debug for TraceTemplate {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("TraceWriter")
+    }
+}
+
+impl MachineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a binary program at `origin`. The program counter starts at `origin` unless
+    /// [`MachineBuilder::pc`] is given.
+    pub fn program(mut self, bytes: &[u8], origin: Address) -> Self {
+        self.program = Some((bytes.to_owned(), origin));
+        self
+    }
+
+    /// Load `bytes` at `origin` in addition to the program and earlier segments, e.g. data next to
+    /// the code. Can be called several times. Without a program, the program counter starts at the
+    /// origin of the first segment unless [`MachineBuilder::pc`] is given.
+    pub fn segment(mut self, bytes: &[u8], origin: Address) -> Self {
+        self.segments.push((bytes.to_owned(), origin));
+        self
+    }
+
+    /// Load `image` at its origin, starting execution at its entry point unless
+    /// [`MachineBuilder::pc`] is given.
+    #[cfg(feature = "std")]
+    pub fn image(self, image: &MemoryImage) -> Self {
+        let builder = self.program(&image.bytes, image.origin);
+        match builder.pc {
+            Some(_) => builder,
+            None => builder.pc(image.entry),
+        }
+    }
+
+    /// Assemble `source` and load it at the address given by its `ORG` statement. The program
+    /// counter starts at that address unless [`MachineBuilder::pc`] is given.
+    #[cfg(feature = "std")]
+    pub fn assembly(mut self, source: &[u8]) -> Self {
+        self.assembly = Some(source.to_owned());
+        self
+    }
+
+    /// Initial value of the stack pointer.
+    pub fn sp(mut self, address: Address) -> Self {
+        self.sp = Some(address);
+        self
+    }
+
+    /// Initial value of the program counter.
+    pub fn pc(mut self, address: Address) -> Self {
+        self.pc = Some(address);
+        self
+    }
+
+    /// Initial value of an 8-bit register. `M` writes memory at the initial HL, after the program
+    /// and segments are loaded.
+    pub fn register(mut self, register: Register, value: Data8) -> Self {
+        self.registers.push((register, value));
+        self
+    }
+
+    /// Initial value of a register pair, like [`MachineBuilder::sp`] for
+    /// [`RegisterPair::Sp`].
+    pub fn register_pair(mut self, register_pair: RegisterPair, value: u16) -> Self {
+        self.register_pairs.push((register_pair, value.into()));
+        self
+    }
+
+    /// Initial value of a flag. All flags are cleared by default.
+    pub fn flag(mut self, flag: ConditionRegister, value: bool) -> Self {
+        self.flags.push((flag, value));
+        self
+    }
+
+    /// Attach `device` at `ports`, see [`Machine::attach_device`]. Later devices replace earlier
+    /// ones at the ports they share. Every machine built gets a clone of `device`.
+    pub fn io_device<T: IoDevice + Clone>(mut self, ports: &[Port], device: T) -> Self {
+        self.devices = self.devices.device(ports, device);
+        self
+    }
+
+    /// Attach all devices of `bus` at their ports, after the devices given so far. Every machine
+    /// built gets clones of the devices.
+    pub fn io_bus(mut self, bus: DeviceBus) -> Self {
+        self.devices.0.extend(bus.0);
+        self
+    }
+
+    /// Halt when the stack grows below `range.start` or is popped above `range.end`, see
+    /// [`Machine::set_stack_limit`] and [`Machine::set_stack_ceiling`].
+    pub fn stack_region(mut self, range: Range<Address>) -> Self {
+        self.stack_region = Some(range);
+        self
+    }
+
+    /// Whether 16-bit accesses past 0xFFFF wrap around or halt, see
+    /// [`Machine::set_strict_memory`].
+    pub fn memory_model(mut self, model: MemoryModel) -> Self {
+        self.memory_model = model;
+        self
+    }
+
+    /// Write a trace line before every executed instruction, see [`TraceWriter`]. `writer` is
+    /// called once for every machine built and returns where its trace goes, e.g. `io::stderr`.
+    #[cfg(feature = "std")]
+    pub fn trace<W, F>(mut self, writer: F) -> Self
+    where
+        W: Write + Send + 'static,
+        F: Fn() -> W + Send + Sync + 'static,
+    {
+        self.trace = Some(TraceTemplate(Arc::new(move || {
+            Box::new(TraceWriter::new(writer()))
+        })));
+        self
+    }
+
+    /// Seed of the numbers read with `IN 1`, see [`Machine::set_random_seed`].
+    pub fn random_seed(mut self, seed: u32) -> Self {
+        self.random_seed = Some(seed);
+        self
+    }
+
+    /// Value read from ports without a device, see [`Machine::set_unmapped_input`].
+    pub fn unmapped_input(mut self, value: Data8) -> Self {
+        self.unmapped_input = Some(value);
+        self
+    }
+
+    /// What to do with undocumented opcodes, see [`Machine::set_undocumented_opcodes`].
+    pub fn undocumented_opcodes(mut self, policy: UndocumentedPolicy) -> Self {
+        self.undocumented_opcodes = Some(policy);
+        self
+    }
+
+    /// Which processor's instruction set to execute, see [`Machine::set_variant`].
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Halt on jumps to their own address, see [`Machine::set_halt_on_self_jump`].
+    pub fn halt_on_self_jump(mut self, halt: bool) -> Self {
+        self.halt_on_self_jump = halt;
+        self
+    }
+
+    /// Bytes queued for the program to read through `IN 0`.
+    pub fn input(mut self, bytes: &[u8]) -> Self {
+        self.input.extend_from_slice(bytes);
+        self
+    }
+
+    /// Let segments overlap each other and the program, later ones overwriting earlier ones.
+    /// Overlaps are an error by default.
+    pub fn allow_overlap(mut self, allow: bool) -> Self {
+        self.allow_overlap = allow;
+        self
+    }
+
+    pub fn build(self) -> Result<Machine, BuildError> {
+        #[cfg(feature = "std")]
+        let program = match (self.program, self.assembly) {
+            (Some(_), Some(_)) => return Err(BuildError::ProgramAndAssembly),
+            (Some(program), None) => Some(program),
+            (None, Some(source)) => {
+                let (instructions, origin) =
+                    assembler::parse_assembly(&source).map_err(BuildError::Assembly)?;
+                let mut bytes = Vec::new();
+                coding::encode_program(&mut bytes, &instructions)
+                    .expect("writing to Vec can't error");
+                Some((bytes, origin))
+            }
+            (None, None) => None,
+        };
+        #[cfg(not(feature = "std"))]
+        let program = self.program;
+
+        let mut machine = Machine::new();
+
+        for (index, (bytes, origin)) in program.iter().chain(&self.segments).enumerate() {
+            machine.load_segment(bytes, *origin, self.allow_overlap)?;
+            if index == 0 {
+                machine.set_pc((*origin).into());
+            }
+        }
+
+        if let Some(pc) = self.pc {
+            machine.set_pc(pc.into());
+        }
+        machine.set_entry(machine.pc);
+        if let Some(sp) = self.sp {
+            machine.registers.set_16(RegisterPair::Sp, Data16::from(sp));
+        }
+        for (register_pair, value) in self.register_pairs {
+            machine.set_register_16(register_pair, value);
+        }
+        // `M` last, so that it goes to the final HL.
+        let (memory, registers): (Vec<_>, Vec<_>) = self
+            .registers
+            .into_iter()
+            .partition(|&(register, _)| register == Register::M);
+        for (register, value) in registers.into_iter().chain(memory) {
+            machine.set_register_8(register, value);
+        }
+        for (flag, value) in self.flags {
+            machine.conditions.set(flag, value);
+        }
+        for (ports, device) in &self.devices.0 {
+            machine.attach_device(ports, device.instantiate());
+        }
+        if let Some(seed) = self.random_seed {
+            machine.set_random_seed(seed);
+        }
+        if let Some(value) = self.unmapped_input {
+            machine.set_unmapped_input(value);
+        }
+        if let Some(policy) = self.undocumented_opcodes {
+            machine.set_undocumented_opcodes(policy);
+        }
+        if let Some(range) = self.stack_region {
+            machine.set_stack_limit(range.start);
+            machine.set_stack_ceiling(range.end);
+        }
+        machine.set_strict_memory(self.memory_model == MemoryModel::Strict);
+        #[cfg(feature = "std")]
+        if let Some(trace) = &self.trace {
+            machine.add_observer((trace.0)());
+        }
+        machine.set_variant(self.variant);
+        machine.set_halt_on_self_jump(self.halt_on_self_jump);
+        machine.push_input(&self.input);
+
+        Ok(machine)
+    }
+}
+
+impl Machine {
+    /// Copy `bytes` to memory at `origin` and read them back to make sure they arrived. Unless
+    /// `allow_overlap` is set, fails if they overlap an earlier load. Returns the addresses of the
+    /// bytes, which are added to [`Machine::loaded_ranges`] unless there are none.
+    ///
+    /// Memory is left unchanged if the bytes don't fit below 0x10000 or overlap.
+    pub fn load_segment(
+        &mut self,
+        bytes: &[u8],
+        origin: Address,
+        allow_overlap: bool,
+    ) -> Result<Range<usize>, BuildError> {
+        let range = origin as usize..origin as usize + bytes.len();
+        let too_large = BuildError::ProgramTooLarge {
+            origin,
+            length: bytes.len(),
+        };
+        if range.end > 0x10000 {
+            return Err(too_large);
+        }
+        if range.is_empty() {
+            return Ok(range);
+        }
+        if !allow_overlap
+            && let Some(other) = self
+                .loaded
+                .iter()
+                .find(|other| other.start < range.end && range.start < other.end)
+        {
+            return Err(BuildError::SegmentsOverlap(other.clone(), range));
+        }
+
+        self.memory.write_slice(origin, bytes).ok_or(too_large)?;
+        if let Some(offset) = (0..bytes.len())
+            .find(|&offset| self.memory.read_8(origin + offset as Address) != bytes[offset])
+        {
+            return Err(BuildError::VerificationFailed {
+                address: origin + offset as Address,
+            });
+        }
+
+        self.loaded.push(range.clone());
+        Ok(range)
+    }
+
+    /// Copy the raw binary `image` to memory at `origin` and start execution there, also after
+    /// [`Machine::reset`]. The image is added to [`Machine::loaded_ranges`].
+    ///
+    /// Fails without changing the machine if the image doesn't fit below 0x10000.
+    #[cfg(feature = "std")]
+    pub fn load_program(&mut self, image: &[u8], origin: Address) -> Result<(), LoadError> {
+        let range = Self::fit(origin, image.len())?;
+        self.memory.0[range.clone()].copy_from_slice(image);
+        self.start_loaded(range);
+        Ok(())
+    }
+
+    /// Like [`Machine::load_program`], encoding assembled `items` directly into memory.
+    #[cfg(feature = "std")]
+    pub fn load_assembled(
+        &mut self,
+        items: &[InstructionOrData],
+        origin: Address,
+    ) -> Result<(), LoadError> {
+        let length = items.iter().map(InstructionOrData::length).sum();
+        let range = Self::fit(origin, length)?;
+        coding::encode_program(&mut &mut self.memory.0[range.clone()], items)
+            .expect("the items were measured to fit");
+        self.start_loaded(range);
+        Ok(())
+    }
+
+    /// Assemble `source` and load it like [`Machine::load_assembled`], at the origin given with
+    /// `ORG` or 0. Returns the origin, where execution starts.
+    ///
+    /// Fails without changing the machine if the source doesn't assemble or the program doesn't
+    /// fit below 0x10000.
+    #[cfg(feature = "std")]
+    pub fn load_assembly(&mut self, source: &[u8]) -> Result<Address, AssembleLoadError> {
+        let (items, origin) =
+            assembler::parse_assembly(source).map_err(AssembleLoadError::Assembly)?;
+        match self.load_assembled(&items, origin) {
+            Ok(()) => Ok(origin),
+            Err(LoadError::TooLarge { origin, length }) => {
+                Err(AssembleLoadError::TooLarge { origin, length })
+            }
+            Err(err) => unreachable!("loading assembled items can't fail with {}", err),
+        }
+    }
+
+    /// The addresses of `length` bytes loaded at `origin`, if they fit below 0x10000.
+    #[cfg(feature = "std")]
+    fn fit(origin: Address, length: usize) -> Result<Range<usize>, LoadError> {
+        let range = origin as usize..origin as usize + length;
+        if range.end > ADDRESS_SPACE {
+            return Err(LoadError::TooLarge { origin, length });
+        }
+        Ok(range)
+    }
+
+    /// Record `range` as loaded and make its start the entry point.
+    #[cfg(feature = "std")]
+    fn start_loaded(&mut self, range: Range<usize>) {
+        let origin = range.start as Address;
+        if !range.is_empty() {
+            self.loaded.push(range);
+        }
+        self.set_pc(origin.into());
+        self.set_entry(origin);
+    }
+
+    /// Copy the data records of the Intel HEX `text` to memory, adding their addresses to
+    /// [`Machine::loaded_ranges`]. Returns the lowest loaded address as a suggested entry point,
+    /// or 0 if there's no data.
+    ///
+    /// Memory is left unchanged if the text isn't valid.
+    pub fn load_ihex(&mut self, text: &str) -> Result<Address, IhexError> {
+        let mut records = ihex::parse(text)?;
+        records.retain(|(_, bytes)| !bytes.is_empty());
+        for (address, bytes) in &records {
+            self.memory
+                .write_slice(*address, bytes)
+                .expect("records end below 0x10000");
+            self.loaded
+                .push(*address as usize..*address as usize + bytes.len());
+        }
+        Ok(records.iter().map(|(address, _)| *address).min().unwrap_or(0))
+    }
+
+    /// The addresses of the segments loaded with [`Machine::load_segment`], e.g. by
+    /// [`MachineBuilder::build`], in the order they were loaded.
+    pub fn loaded_ranges(&self) -> &[Range<usize>] {
+        &self.loaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::trace::tests::SharedBuffer;
+    use crate::{
+        instruction::Instruction,
+        machine::{HaltReason, MachineState, RunOutcome},
+    };
+
+    #[test]
+    fn empty() {
+        let machine = MachineBuilder::new().build().unwrap();
+        assert_eq!(machine.pc().value(), 0);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0);
+    }
+
+    #[test]
+    fn program() {
+        let machine = MachineBuilder::new()
+            .program(&[0x3E, 0x2A, 0x76], 0x0100)
+            .build()
+            .unwrap();
+        assert_eq!(&machine.memory().as_raw()[0x0100..0x0103], &[0x3E, 0x2A, 0x76]);
+        assert_eq!(machine.pc().value(), 0x0100);
+    }
+
+    #[test]
+    fn program_exact_fit() {
+        let machine = MachineBuilder::new()
+            .program(&[0x76; 16], 0xFFF0)
+            .build()
+            .unwrap();
+        assert_eq!(machine.memory().read_8(0xFFFF), 0x76);
+    }
+
+    #[test]
+    fn program_too_large() {
+        let result = MachineBuilder::new().program(&[0; 17], 0xFFF0).build();
+        assert_eq!(
+            result.err(),
+            Some(BuildError::ProgramTooLarge {
+                origin: 0xFFF0,
+                length: 17
+            })
+        );
+    }
+
+    #[test]
+    fn disjoint_segments() {
+        let machine = MachineBuilder::new()
+            .segment(&[0xC3, 0x00, 0x20], 0x0000)
+            .segment(&[0x11, 0x22], 0x2000)
+            .segment(&[], 0x0001)
+            .build()
+            .unwrap();
+        assert_eq!(&machine.memory().as_raw()[0x0000..0x0003], &[0xC3, 0x00, 0x20]);
+        assert_eq!(&machine.memory().as_raw()[0x2000..0x2002], &[0x11, 0x22]);
+        assert_eq!(machine.pc().value(), 0x0000);
+
+        let machine = MachineBuilder::new()
+            .segment(&[0x11, 0x22], 0x2000)
+            .program(&[0x76], 0x0100)
+            .build()
+            .unwrap();
+        assert_eq!(machine.memory().read_8(0x2001), 0x22);
+        assert_eq!(machine.pc().value(), 0x0100);
+    }
+
+    #[test]
+    fn overlapping_segments() {
+        let result = MachineBuilder::new()
+            .program(&[0x00; 0x100], 0x0000)
+            .segment(&[0x00; 0x10], 0x1000)
+            .segment(&[0x00; 0x10], 0x00F8)
+            .build();
+        let err = result.err().unwrap();
+        assert_eq!(err, BuildError::SegmentsOverlap(0x0000..0x0100, 0x00F8..0x0108));
+        assert_eq!(
+            err.to_string(),
+            "Segments at 0x0000-0x00FF and 0x00F8-0x0107 overlap"
+        );
+    }
+
+    #[test]
+    fn allowed_overlap() {
+        let machine = MachineBuilder::new()
+            .program(&[0x11; 0x10], 0x0100)
+            .segment(&[0x22; 0x10], 0x0108)
+            .allow_overlap(true)
+            .build()
+            .unwrap();
+        assert_eq!(machine.memory().read_8(0x0107), 0x11);
+        assert_eq!(machine.memory().read_8(0x0108), 0x22);
+        assert_eq!(machine.loaded_ranges(), [0x0100..0x0110, 0x0108..0x0118]);
+    }
+
+    #[test]
+    fn loaded_ranges() {
+        let machine = MachineBuilder::new()
+            .segment(&[0x11, 0x22], 0x2000)
+            .program(&[0x76], 0x0100)
+            .segment(&[], 0x0000)
+            .build()
+            .unwrap();
+        assert_eq!(machine.loaded_ranges(), [0x0100..0x0101, 0x2000..0x2002]);
+        assert!(Machine::new().loaded_ranges().is_empty());
+    }
+
+    #[test]
+    fn load_segment_near_the_end() {
+        let mut machine = Machine::new();
+        assert_eq!(machine.load_segment(&[0x76], 0xFFFF, false), Ok(0xFFFF..0x10000));
+
+        let err = machine.load_segment(&[0x3E, 0x2A], 0xFFFF, true).unwrap_err();
+        assert_eq!(
+            err,
+            BuildError::ProgramTooLarge {
+                origin: 0xFFFF,
+                length: 2
+            }
+        );
+        assert_eq!(machine.memory().read_8(0xFFFF), 0x76);
+        assert_eq!(machine.loaded_ranges(), [0xFFFF..0x10000]);
+    }
+
+    #[test]
+    fn load_segment_overlap() {
+        let mut machine = Machine::new();
+        machine.load_segment(&[0x11; 4], 0x0100, false).unwrap();
+        assert_eq!(
+            machine.load_segment(&[0x22; 4], 0x0102, false),
+            Err(BuildError::SegmentsOverlap(0x0100..0x0104, 0x0102..0x0106))
+        );
+        assert_eq!(machine.memory().read_8(0x0102), 0x11);
+        assert_eq!(machine.load_segment(&[0x22; 4], 0x0104, false), Ok(0x0104..0x0108));
+    }
+
+    #[test]
+    fn registers_and_flags() {
+        let machine = MachineBuilder::new()
+            .register(Register::A, 0x12)
+            .register(Register::M, 0x34)
+            .register_pair(RegisterPair::Sp, 0xFF00)
+            .register_pair(RegisterPair::Hl, 0x2000)
+            .register(Register::L, 0x01)
+            .flag(ConditionRegister::Carry, true)
+            .flag(ConditionRegister::Zero, true)
+            .flag(ConditionRegister::Zero, false)
+            .build()
+            .unwrap();
+        assert_eq!(machine.register_8(Register::A), 0x12);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0xFF00);
+        assert_eq!(machine.register_16(RegisterPair::Hl).value(), 0x2001);
+        assert_eq!(machine.memory().read_8(0x2001), 0x34);
+        assert!(machine.conditions().get(ConditionRegister::Carry));
+        assert!(!machine.conditions().get(ConditionRegister::Zero));
+    }
+
+    /// Device reading back the last value written to it.
+    #[derive(Clone, Default)]
+    struct Latch(Option<Data8>);
+
+    impl IoDevice for Latch {
+        fn read(&mut self, _port: Port, _machine: &mut Machine) -> Option<Data8> {
+            self.0
+        }
+
+        fn write(&mut self, _port: Port, value: Data8, _machine: &mut Machine) {
+            self.0 = Some(value);
+        }
+    }
+
+    #[test]
+    fn io_device() {
+        // OUT 7; MVI A, 0; IN 7; HLT
+        let builder = MachineBuilder::new()
+            .program(&[0xD3, 0x07, 0x3E, 0x00, 0xDB, 0x07, 0x76], 0x0000)
+            .register(Register::A, 0x2A)
+            .io_device(&[7], Latch::default());
+        assert_eq!(format!("{:?}", builder.devices), "[[7]]");
+
+        let mut machine = builder.clone().build().unwrap();
+        machine.steps().for_each(drop);
+        assert_eq!(machine.register_8(Register::A), 0x2A);
+        assert_eq!(machine.device::<Latch>().unwrap().0, Some(0x2A));
+
+        // Every machine gets its own device.
+        let machine = builder.build().unwrap();
+        assert_eq!(machine.device::<Latch>().unwrap().0, None);
+    }
+
+    #[test]
+    fn io_bus() {
+        // OUT 7; IN 8; HLT
+        let bus = DeviceBus::new()
+            .device(&[7], Latch::default())
+            .device(&[8], Latch(Some(0x11)));
+        let builder = MachineBuilder::new()
+            .program(&[0xD3, 0x07, 0xDB, 0x08, 0x76], 0x0000)
+            .register(Register::A, 0x2A)
+            .io_bus(bus);
+        assert_eq!(format!("{:?}", builder.devices), "[[7], [8]]");
+
+        let mut machine = builder.build().unwrap();
+        machine.steps().for_each(drop);
+        assert_eq!(machine.register_8(Register::A), 0x11);
+        assert_eq!(machine.device::<Latch>().unwrap().0, Some(0x2A));
+    }
+
+    #[test]
+    fn stack_region() {
+        // 0000: CALL 0000H
+        let mut machine = MachineBuilder::new()
+            .program(&[0xCD, 0x00, 0x00], 0x0000)
+            .sp(0x1000)
+            .stack_region(0x0FE0..0x1000)
+            .build()
+            .unwrap();
+        assert_eq!(machine.stack_region(), Some(0x0FE0..0x1000));
+
+        // The 32 bytes hold 16 return addresses, so the 17th call overflows.
+        assert_eq!(machine.steps().count(), 17);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::StackOverflow)
+        );
+    }
+
+    #[test]
+    fn memory_model() {
+        // LXI SP, 0FFFFH; POP B; HLT
+        let builder = MachineBuilder::new().program(&[0x31, 0xFF, 0xFF, 0xC1, 0x76], 0x0000);
+
+        let mut machine = builder.clone().build().unwrap();
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+
+        let mut machine = builder.memory_model(MemoryModel::Strict).build().unwrap();
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::StackUnderflow)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn trace() {
+        let buffer = SharedBuffer::default();
+        let writer = buffer.clone();
+        // MVI A, 2AH; HLT
+        let builder = MachineBuilder::new()
+            .program(&[0x3E, 0x2A, 0x76], 0x0000)
+            .trace(move || writer.clone());
+        assert!(format!("{:?}", builder).contains("trace: Some(TraceWriter)"));
+
+        let mut machine = builder.build().unwrap();
+        machine.steps().for_each(drop);
+        let trace = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            trace.lines().collect::<Vec<_>>(),
+            vec![
+                "PC=0000 AF=00 02 BC=0000 DE=0000 HL=0000 SP=0000  MVI A,2AH",
+                "PC=0002 AF=2A 02 BC=0000 DE=0000 HL=0000 SP=0000  HLT",
+            ]
+        );
+    }
+
+    #[test]
+    fn load_program() {
+        let mut machine = Machine::new();
+        // MVI A, 2AH; HLT
+        machine.load_program(&[0x3E, 0x2A, 0x76], 0x0100).unwrap();
+        assert_eq!(machine.pc().value(), 0x0100);
+        assert_eq!(machine.load(), Some(Instruction::Mvi(Register::A, 0x2A)));
+        assert_eq!(machine.loaded_ranges().len(), 1);
+        assert_eq!(machine.loaded_ranges()[0], 0x0100..0x0103);
+
+        machine.steps().for_each(drop);
+        machine.reset();
+        assert_eq!(machine.pc().value(), 0x0100);
+
+        let err = machine.load_program(&[0x00; 0x102], 0xFF00).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Image is 258 bytes large, 2 more than the 256 bytes that fit between 0xFF00 and 0xFFFF"
+        );
+        assert_eq!(machine.pc().value(), 0x0100);
+        assert!(machine.load_program(&[0x76], 0xFFFF).is_ok());
+    }
+
+    #[test]
+    fn load_assembled() {
+        let mut machine = Machine::new();
+        let items = [
+            InstructionOrData::Instruction(Instruction::Lxi(
+                RegisterPair::Hl,
+                Data16::new(0x03, 0x01),
+            )),
+            InstructionOrData::Instruction(Instruction::Hlt),
+            InstructionOrData::Slice(Box::new(*b"Hi")),
+            InstructionOrData::Byte(0x00),
+        ];
+        machine.load_assembled(&items, 0x0100).unwrap();
+        assert_eq!(machine.pc().value(), 0x0100);
+        assert_eq!(
+            machine.load(),
+            Some(Instruction::Lxi(RegisterPair::Hl, Data16::new(0x03, 0x01)))
+        );
+        assert_eq!(
+            &machine.memory().as_raw()[0x0100..0x0107],
+            &[0x21, 0x03, 0x01, 0x76, b'H', b'i', 0x00]
+        );
+        assert_eq!(machine.loaded_ranges().len(), 1);
+        assert_eq!(machine.loaded_ranges()[0], 0x0100..0x0107);
+
+        assert!(matches!(
+            machine.load_assembled(&items, 0xFFFA),
+            Err(LoadError::TooLarge {
+                origin: 0xFFFA,
+                length: 7
+            })
+        ));
+        assert_eq!(machine.memory().read_8(0xFFFA), 0x00);
+    }
+
+    #[test]
+    fn load_assembly() {
+        // The program of `forward_references_and_invalid_numbers` in the assembler.
+        let source = b"
+                ORG 100H
+                LXI H, TABLE
+                MVI A, 2
+                CALL DONE
+        TABLE:  DB 1
+        DONE:   HLT
+                END
+        ";
+        let mut machine = Machine::new();
+        assert_eq!(machine.load_assembly(source), Ok(0x0100));
+        assert_eq!(machine.pc().value(), 0x0100);
+        assert_eq!(
+            machine.run_until_halt(100),
+            (RunOutcome::Halted(HaltReason::HaltInstruction), 4)
+        );
+        assert_eq!(machine.pc().value(), 0x0109);
+        assert_eq!(machine.register_16(RegisterPair::Hl).value(), 0x0108);
+        assert_eq!(machine.register_8(Register::A), 2);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0xFFFE);
+        assert_eq!(machine.loaded_ranges()[0], 0x0100..0x010A);
+
+        let mut machine = Machine::new();
+        assert!(matches!(
+            machine.load_assembly(b"        MVI A, 100H\n        END\n"),
+            Err(AssembleLoadError::Assembly(_))
+        ));
+        assert_eq!(
+            machine.load_assembly(b"        ORG 0FFFFH\n        MVI A, 1\n        END\n"),
+            Err(AssembleLoadError::TooLarge {
+                origin: 0xFFFF,
+                length: 2
+            })
+        );
+        assert!(machine.loaded_ranges().is_empty());
+    }
+
+    #[test]
+    fn load_ihex() {
+        let mut machine = Machine::new();
+        let entry = machine
+            .load_ihex(include_str!("../../tests/data/hello.hex"))
+            .unwrap();
+        assert_eq!(entry, 0x0100);
+        assert_eq!(
+            machine.loaded_ranges(),
+            [0x0100..0x0108, 0x0108..0x010F, 0x0200..0x0203]
+        );
+        assert_eq!(&machine.memory().as_raw()[0x0200..0x0203], b"Hi\0");
+
+        machine.set_pc(entry.into());
+        machine.steps().for_each(drop);
+        assert_eq!(machine.output_buffer(), b"Hi");
+
+        let mut machine = Machine::new();
+        let err = machine
+            .load_ihex(include_str!("../../tests/data/bad_checksum.hex"))
+            .unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(machine.memory().as_raw().iter().all(|&byte| byte == 0));
+        assert!(machine.loaded_ranges().is_empty());
+    }
+
+    #[test]
+    fn sp_and_pc() {
+        let machine = MachineBuilder::new()
+            .program(&[0x00], 0x0100)
+            .sp(0xFFFE)
+            .pc(0x0200)
+            .build()
+            .unwrap();
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0xFFFE);
+        assert_eq!(machine.pc().value(), 0x0200);
+    }
+
+    #[test]
+    fn input() {
+        // IN 0, MOV B, A, IN 0, HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0xDB, 0x00, 0x47, 0xDB, 0x00, 0x76], 0x0000)
+            .input(b"x")
+            .input(b"y")
+            .build()
+            .unwrap();
+        assert_eq!(
+            machine.run_until_halt(100),
+            (RunOutcome::Halted(HaltReason::HaltInstruction), 4)
+        );
+        assert_eq!(machine.register_8(crate::instruction::Register::B), b'x');
+        assert_eq!(machine.register_8(crate::instruction::Register::A), b'y');
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn assembly() {
+        let machine = MachineBuilder::new()
+            .assembly(b"        ORG 100H\n        MVI A, 2AH\n        HLT\n        END\n")
+            .build()
+            .unwrap();
+        assert_eq!(&machine.memory().as_raw()[0x0100..0x0103], &[0x3E, 0x2A, 0x76]);
+        assert_eq!(machine.pc().value(), 0x0100);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn assembly_error() {
+        let result = MachineBuilder::new().assembly(b"FOO\n").build();
+        assert!(matches!(result, Err(BuildError::Assembly(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn program_and_assembly_conflict() {
+        let result = MachineBuilder::new()
+            .program(&[0x76], 0)
+            .assembly(b"        HLT\n        END\n")
+            .build();
+        assert_eq!(result.err(), Some(BuildError::ProgramAndAssembly));
+    }
+
+    #[test]
+    fn random_seed() {
+        let read_random = |builder: MachineBuilder| {
+            // IN 1; MOV B, A; IN 1; HLT
+            let mut machine = builder
+                .program(&[0xDB, 0x01, 0x47, 0xDB, 0x01, 0x76], 0)
+                .build()
+                .unwrap();
+            machine.steps().count();
+            (
+                machine.register_8(crate::instruction::Register::B),
+                machine.register_8(crate::instruction::Register::A),
+            )
+        };
+
+        let default = read_random(MachineBuilder::new());
+        assert_eq!(default, read_random(MachineBuilder::new()));
+        assert_eq!(default, (0xE1, 0x8B));
+        assert_eq!(
+            read_random(MachineBuilder::new().random_seed(7)),
+            read_random(MachineBuilder::new().random_seed(7))
+        );
+        assert_ne!(read_random(MachineBuilder::new().random_seed(7)), default);
+    }
+}
@@ -0,0 +1,154 @@
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::any::Any;
+
+use crate::instruction::{Data8, Port};
+
+use super::Machine;
+
+/// A device answering `IN` and `OUT` on the ports it's attached to with
+/// [`Machine::attach_device`], taking precedence over the built-in ports.
+///
+/// Like observers, a device is detached from the machine while it's called, so it can use the
+/// machine's input queue and output through [`Machine::read_input`], [`Machine::input_available`]
+/// and [`Machine::write_output`]. Attached devices can be looked up by type with
+/// [`Machine::device`].
+pub trait IoDevice: Any + Send {
+    /// Value read by `IN port`. Returning `None` halts the machine, like reaching the end of input
+    /// on port 0.
+    fn read(&mut self, port: Port, machine: &mut Machine) -> Option<Data8>;
+
+    /// Handle `OUT port` with the accumulator holding `value`.
+    fn write(&mut self, port: Port, value: Data8, machine: &mut Machine);
+
+    /// Called after every executed instruction, e.g. to request an interrupt with
+    /// [`Machine::request_interrupt`] once enough [cycles](Machine::cycles) have passed.
+    fn tick(&mut self, _machine: &mut Machine) {}
+}
+
+/// Devices attached to a machine and the ports they're mapped at.
+#[derive(Default)]
+pub(super) struct IoBus {
+    devices: Vec<Box<dyn IoDevice>>,
+    ports: BTreeMap<Port, usize>,
+}
+
+impl IoBus {
+    pub(super) fn attach(&mut self, ports: &[Port], device: Box<dyn IoDevice>) {
+        let index = self.devices.len();
+        self.devices.push(device);
+        for port in ports {
+            self.ports.insert(*port, index);
+        }
+    }
+
+    /// The first attached device of type `T`.
+    pub(super) fn device<T: IoDevice>(&self) -> Option<&T> {
+        self.devices
+            .iter()
+            .find_map(|device| (device.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    pub(super) fn is_mapped(&self, port: Port) -> bool {
+        self.ports.contains_key(&port)
+    }
+
+    pub(super) fn read(&mut self, port: Port, machine: &mut Machine) -> Option<Data8> {
+        match self.ports.get(&port) {
+            Some(&index) => self.devices[index].read(port, machine),
+            None => Some(0),
+        }
+    }
+
+    pub(super) fn write(&mut self, port: Port, value: Data8, machine: &mut Machine) {
+        if let Some(&index) = self.ports.get(&port) {
+            self.devices[index].write(port, value, machine);
+        }
+    }
+
+    pub(super) fn tick(&mut self, machine: &mut Machine) {
+        for device in &mut self.devices {
+            device.tick(machine);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{collections::VecDeque, vec};
+
+    use super::*;
+    use crate::{instruction::Register, machine::MachineBuilder};
+
+    /// Device that answers reads from a script and records every write.
+    #[derive(Clone, Default)]
+    struct Script {
+        reads: VecDeque<Data8>,
+        writes: Vec<(Port, Data8)>,
+    }
+
+    impl IoDevice for Script {
+        fn read(&mut self, _port: Port, _machine: &mut Machine) -> Option<Data8> {
+            self.reads.pop_front()
+        }
+
+        fn write(&mut self, port: Port, value: Data8, _machine: &mut Machine) {
+            self.writes.push((port, value));
+        }
+    }
+
+    #[rustfmt::skip]
+    const PROGRAM: [u8; 18] = [
+        0xDB, 0x05, // IN 5
+        0xC6, 0x01, // ADI 1
+        0xD3, 0x06, // OUT 6
+        0xDB, 0x00, // IN 0
+        0xD3, 0x00, // OUT 0
+        0xDB, 0x09, // IN 9
+        0xD3, 0x06, // OUT 6
+        0xDB, 0x05, // IN 5
+        0xD3, 0x06, // OUT 6, never reached
+    ];
+
+    fn machine() -> Machine {
+        let script = Script {
+            reads: VecDeque::from([0x41, 0x42]),
+            ..Script::default()
+        };
+        MachineBuilder::new()
+            .program(&PROGRAM, 0x0000)
+            .unmapped_input(0xFF)
+            .io_device(&[0, 5, 6], script)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn program_talks_to_attached_device() {
+        let mut machine = machine();
+        machine.steps().count();
+
+        let script = machine.device::<Script>().unwrap();
+        // Port 0 is taken from the console, and port 9 has no device.
+        assert_eq!(script.writes, vec![(6, 0x42), (0, 0x42), (6, 0xFF)]);
+        assert!(script.reads.is_empty());
+        assert!(machine.output_buffer().is_empty());
+        // The script ran out of reads at the second `IN 5`, which halts like running out of input.
+        assert_eq!(machine.pc().value(), 0x000E);
+        assert_eq!(machine.register_8(Register::A), 0xFF);
+    }
+
+    #[test]
+    fn unmapped_ports_read_zero_by_default() {
+        let mut machine = MachineBuilder::new()
+            .program(&[0xDB, 0x09, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        machine.set_register_8(Register::A, 0x12);
+        machine.steps().count();
+        assert_eq!(machine.register_8(Register::A), 0);
+    }
+}
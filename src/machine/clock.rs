@@ -0,0 +1,159 @@
+//! Running at the speed of a real 8080, by executing instructions as their clock periods fall due
+//! and sleeping in between.
+
+use core::time::Duration;
+
+use crate::machine::{Machine, MachineState};
+
+/// Longest sleep between checks of the time, which bounds how far execution lags behind.
+const SLICE: Duration = Duration::from_millis(1);
+
+/// Source of time for [`Machine::run_realtime_with`], so that tests can run without sleeping.
+pub trait Clock {
+    /// Time since some fixed point, e.g. when the clock was created.
+    fn now(&self) -> Duration;
+
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// The host's monotonic clock, sleeping the calling thread.
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug)]
+pub struct SystemClock {
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+impl Machine {
+    /// Execute instructions for `wall_duration` at the speed of an 8080 clocked at `clock_hz`,
+    /// e.g. 2 MHz, so that delay loops take as long as on real hardware. Returns the number of
+    /// clock periods the executed instructions took, see [`Machine::cycles`].
+    ///
+    /// Returns early if the machine halts, waits for input or reaches a breakpoint.
+    #[cfg(feature = "std")]
+    pub fn run_realtime(&mut self, clock_hz: u32, wall_duration: Duration) -> u64 {
+        self.run_realtime_with(&mut SystemClock::new(), clock_hz, wall_duration)
+    }
+
+    /// [`Machine::run_realtime`] with the time taken from `clock`.
+    pub fn run_realtime_with(
+        &mut self,
+        clock: &mut impl Clock,
+        clock_hz: u32,
+        wall_duration: Duration,
+    ) -> u64 {
+        let start = clock.now();
+        let start_cycles = self.cycles;
+        loop {
+            let elapsed = clock.now().saturating_sub(start);
+            if elapsed >= wall_duration {
+                break;
+            }
+            // Catch up with the clock periods that have passed.
+            let due = (elapsed.as_nanos() * clock_hz as u128 / 1_000_000_000) as u64;
+            while self.cycles - start_cycles < due {
+                if self.run_cycles(1).0 == 0 {
+                    return self.cycles - start_cycles;
+                }
+            }
+            if self.state != MachineState::Running {
+                break;
+            }
+            clock.sleep(SLICE.min(wall_duration - elapsed));
+        }
+        self.cycles - start_cycles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{HaltReason, MachineBuilder};
+
+    /// Clock that only moves when sleeping.
+    #[derive(Default)]
+    struct MockClock {
+        now: Duration,
+        sleeps: usize,
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Duration {
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.now += duration;
+            self.sleeps += 1;
+        }
+    }
+
+    #[test]
+    fn keeps_to_the_clock() {
+        // JMP 0000H, 10 clock periods.
+        let mut machine = MachineBuilder::new()
+            .program(&[0xC3, 0x00, 0x00], 0x0000)
+            .build()
+            .unwrap();
+        let mut clock = MockClock::default();
+
+        // 1 kHz for 100 ms is 100 clock periods.
+        let cycles = machine.run_realtime_with(&mut clock, 1_000, Duration::from_millis(100));
+        assert_eq!(cycles, 100);
+        assert_eq!(machine.cycles(), 100);
+        assert_eq!(clock.now, Duration::from_millis(100));
+        assert_eq!(clock.sleeps, 100);
+
+        // 2 MHz for 5 ms, continuing from the clock's current time.
+        let cycles = machine.run_realtime_with(&mut clock, 2_000_000, Duration::from_millis(5));
+        assert_eq!(cycles, 8_000);
+        assert_eq!(clock.now, Duration::from_millis(105));
+    }
+
+    #[test]
+    fn stops_when_halted() {
+        // MVI A, 1; HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0x3E, 0x01, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        let mut clock = MockClock::default();
+
+        let cycles = machine.run_realtime_with(&mut clock, 1_000, Duration::from_secs(1));
+        assert_eq!(cycles, 7 + 7);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert!(clock.now < Duration::from_millis(20));
+
+        let cycles = machine.run_realtime_with(&mut clock, 1_000, Duration::from_secs(1));
+        assert_eq!(cycles, 0);
+    }
+}
@@ -0,0 +1,218 @@
+//! Which opcodes a run executed, recorded with [`Machine::enable_coverage`].
+//!
+//! [`CoverageReport::write`] prints the executed opcodes as a table followed by the documented
+//! opcodes that never ran:
+//!
+//! ```text
+//! OP  INSTRUCTION  EXECUTED  ADDRESSES
+//! 3E  MVI A,d8            1  0000
+//! 76  HLT                 1  0002
+//!
+//! 2 of 244 documented opcodes executed, missing:
+//! 00 NOP
+//! 01 LXI B,d16
+//! ...
+//! ```
+
+use alloc::{collections::BTreeSet, vec::Vec};
+use core::fmt::{self, Write};
+
+use crate::instruction::{Address, Instruction, OPCODES};
+
+use super::{ExecutionObserver, Machine};
+
+/// Addresses listed per opcode before the rest are summarized.
+const ADDRESS_PREVIEW: usize = 8;
+
+/// How often one opcode was executed and where.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct OpcodeCoverage {
+    pub count: u64,
+    /// The distinct addresses the opcode was executed at.
+    pub addresses: BTreeSet<Address>,
+}
+
+/// Executed opcodes, indexed by the first byte of the instruction.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct CoverageReport {
+    opcodes: Vec<OpcodeCoverage>,
+}
+
+impl Default for CoverageReport {
+    fn default() -> Self {
+        Self {
+            opcodes: (0..256).map(|_| OpcodeCoverage::default()).collect(),
+        }
+    }
+}
+
+impl CoverageReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count an execution of `opcode` at `address`.
+    pub fn record(&mut self, opcode: u8, address: Address) {
+        let coverage = &mut self.opcodes[opcode as usize];
+        coverage.count += 1;
+        coverage.addresses.insert(address);
+    }
+
+    pub fn opcode(&self, opcode: u8) -> &OpcodeCoverage {
+        &self.opcodes[opcode as usize]
+    }
+
+    /// The opcodes executed at least once, in ascending order.
+    pub fn covered(&self) -> Vec<u8> {
+        (0..=255)
+            .filter(|&opcode| self.opcode(opcode).count > 0)
+            .collect()
+    }
+
+    /// The documented opcodes that were never executed, in ascending order.
+    pub fn missing_documented_opcodes(&self) -> Vec<u8> {
+        (0..=255)
+            .filter(|&opcode| OPCODES[opcode as usize].documented && self.opcode(opcode).count == 0)
+            .collect()
+    }
+
+    /// Write the report as text, see the [module documentation](self).
+    pub fn write(&self, mut writer: impl Write) -> fmt::Result {
+        writeln!(writer, "OP  INSTRUCTION  EXECUTED  ADDRESSES")?;
+        for opcode in self.covered() {
+            let coverage = self.opcode(opcode);
+            write!(
+                writer,
+                "{:02X}  {:<11} {:>9} ",
+                opcode, OPCODES[opcode as usize].mnemonic, coverage.count
+            )?;
+            for address in coverage.addresses.iter().take(ADDRESS_PREVIEW) {
+                write!(writer, " {:04X}", address)?;
+            }
+            if coverage.addresses.len() > ADDRESS_PREVIEW {
+                write!(
+                    writer,
+                    " and {} more",
+                    coverage.addresses.len() - ADDRESS_PREVIEW
+                )?;
+            }
+            writeln!(writer)?;
+        }
+
+        let missing = self.missing_documented_opcodes();
+        let documented = OPCODES.iter().filter(|info| info.documented).count();
+        write!(
+            writer,
+            "\n{} of {} documented opcodes executed",
+            documented - missing.len(),
+            documented
+        )?;
+        if missing.is_empty() {
+            return writeln!(writer);
+        }
+        writeln!(writer, ", missing:")?;
+        for opcode in missing {
+            writeln!(
+                writer,
+                "{:02X} {}",
+                opcode, OPCODES[opcode as usize].mnemonic
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Observer filling the report of [`Machine::enable_coverage`].
+pub(super) struct CoverageObserver(pub(super) CoverageReport);
+
+impl ExecutionObserver for CoverageObserver {
+    fn before_step(&mut self, machine: &Machine, instruction: Option<&Instruction>) {
+        // Bytes that can't be decoded halt the machine without executing anything.
+        if instruction.is_some() {
+            let pc = machine.pc().value();
+            self.0.record(machine.memory().read_8(pc), pc);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    /// MVI A, 3, then DCR A until it's zero and halt.
+    const COUNTDOWN: [u8; 6] = [0x3E, 0x03, 0x3D, 0xC2, 0x02, 0x00];
+
+    fn run(program: &[u8]) -> CoverageReport {
+        let mut program = program.to_vec();
+        program.push(0x76);
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        machine.enable_coverage();
+        while machine.step().is_some() {}
+        machine.coverage().unwrap().clone()
+    }
+
+    #[test]
+    fn covered_opcodes() {
+        let report = run(&COUNTDOWN);
+        assert_eq!(report.covered(), [0x3D, 0x3E, 0x76, 0xC2]);
+        assert_eq!(report.opcode(0x3D).count, 3);
+        assert_eq!(report.opcode(0xC2).addresses, BTreeSet::from([0x0003]));
+        assert_eq!(report.opcode(0x76).addresses, BTreeSet::from([0x0006]));
+        assert_eq!(report.missing_documented_opcodes().len(), 244 - 4);
+    }
+
+    #[test]
+    fn missing_opcodes_shrink() {
+        let before = run(&COUNTDOWN);
+        // INR B in front of the loop.
+        let mut program = [0x04].to_vec();
+        program.extend([0x3E, 0x03, 0x3D, 0xC2, 0x03, 0x00]);
+        let after = run(&program);
+
+        assert!(before.missing_documented_opcodes().contains(&0x04));
+        let mut expected = before.missing_documented_opcodes();
+        expected.retain(|&opcode| opcode != 0x04);
+        assert_eq!(after.missing_documented_opcodes(), expected);
+    }
+
+    #[test]
+    fn undocumented_opcodes_are_not_missing() {
+        let mut report = CoverageReport::new();
+        report.record(0x08, 0x0000);
+        report.record(0x3E, 0x0001);
+        assert_eq!(report.covered(), [0x08, 0x3E]);
+        assert_eq!(report.missing_documented_opcodes().len(), 244 - 1);
+    }
+
+    #[test]
+    fn table() {
+        let mut text = String::new();
+        run(&COUNTDOWN).write(&mut text).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[..6],
+            [
+                "OP  INSTRUCTION  EXECUTED  ADDRESSES",
+                "3D  DCR A               3  0002",
+                "3E  MVI A,d8            1  0000",
+                "76  HLT                 1  0006",
+                "C2  JNZ a16             3  0003",
+                "",
+            ]
+        );
+        assert_eq!(lines[6], "4 of 244 documented opcodes executed, missing:");
+        assert_eq!(lines[7], "00 NOP");
+        assert_eq!(lines.len(), 7 + 240);
+    }
+
+    #[test]
+    fn coverage_is_off_by_default() {
+        assert!(Machine::new().coverage().is_none());
+    }
+}
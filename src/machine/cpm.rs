@@ -0,0 +1,138 @@
+//! Just enough of CP/M for programs like the classic 8080 diagnostics (cpudiag, TST8080), which
+//! print through the BDOS and exit by jumping to the warm boot address.
+
+use alloc::vec::Vec;
+
+use crate::{
+    instruction::{Address, Instruction, Register, RegisterPair},
+    machine::{ExecutionResult, Machine},
+};
+
+/// Address of the warm boot, which ends the program.
+const WARM_BOOT: Address = 0x0000;
+/// Address programs call the BDOS at, with the function number in C.
+const BDOS: Address = 0x0005;
+
+/// BDOS function ending the program, like a jump to the warm boot.
+const SYSTEM_RESET: u8 = 0;
+/// BDOS function writing the character in E.
+const CONSOLE_OUTPUT: u8 = 2;
+/// BDOS function writing the string at DE, up to a `$`.
+const PRINT_STRING: u8 = 9;
+
+impl Machine {
+    /// Emulate the parts of CP/M that programs built for it need to run to completion when loaded
+    /// at 0x0100:
+    ///
+    /// - Reaching 0x0005 calls the BDOS and returns like `RET`. Function 2 (C = 2) writes the
+    ///   character in E to the output and function 9 the string at DE up to a `$`. Function 0
+    ///   halts the machine, and other functions do nothing.
+    /// - Reaching 0x0000, the warm boot, halts the machine as if it executed `HLT`.
+    ///
+    /// Whatever is in memory at those addresses isn't executed.
+    pub fn enable_cpm_shim(&mut self) {
+        self.cpm_shim = true;
+    }
+
+    /// The instruction the shim executes in place of the one at the program counter, if any.
+    pub(super) fn cpm_instruction(&self) -> Option<Instruction> {
+        if !self.cpm_shim {
+            return None;
+        }
+        match self.pc {
+            WARM_BOOT => Some(Instruction::Hlt),
+            BDOS if self.registers.get_8(Register::C, &self.memory) == SYSTEM_RESET => {
+                Some(Instruction::Hlt)
+            }
+            BDOS => Some(Instruction::Ret),
+            _ => None,
+        }
+    }
+
+    /// Execute `instruction` from [`Machine::cpm_instruction`], calling the BDOS first if it's
+    /// the return from it.
+    pub(super) fn execute_cpm(&mut self, instruction: Instruction) -> ExecutionResult {
+        if instruction == Instruction::Ret {
+            self.call_bdos();
+        }
+        self.execute(instruction)
+    }
+
+    fn call_bdos(&mut self) {
+        match self.registers.get_8(Register::C, &self.memory) {
+            CONSOLE_OUTPUT => {
+                let character = self.registers.get_8(Register::E, &self.memory);
+                self.write_output(&[character]);
+            }
+            PRINT_STRING => {
+                let start = self.registers.get_16(RegisterPair::De).value();
+                let text: Vec<u8> = (0..=u16::MAX)
+                    .map(|offset| self.memory.read_8(start.wrapping_add(offset)))
+                    .take_while(|&byte| byte != b'$')
+                    .collect();
+                self.write_output(&text);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::{HaltReason, MachineBuilder, MachineState};
+
+    fn run(program: &[u8]) -> Machine {
+        let mut machine = MachineBuilder::new()
+            .program(program, 0x0100)
+            .sp(0x2000)
+            .build()
+            .unwrap();
+        machine.enable_cpm_shim();
+        machine.steps().for_each(drop);
+        machine
+    }
+
+    #[test]
+    fn console_output_and_warm_boot() {
+        let machine = run(&[
+            0x0E, 0x02, // MVI C, 2
+            0x1E, b'A', // MVI E, 'A'
+            0xCD, 0x05, 0x00, // CALL 0005H
+            0x1E, b'B', // MVI E, 'B'
+            0xCD, 0x05, 0x00, // CALL 0005H
+            0xC3, 0x00, 0x00, // JMP 0000H
+        ]);
+        assert_eq!(machine.output_buffer(), b"AB");
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.pc().value(), 0x0000);
+        assert_eq!(machine.register_16(RegisterPair::Sp).value(), 0x2000);
+    }
+
+    #[test]
+    fn system_reset() {
+        let machine = run(&[
+            0x0E, 0x00, // MVI C, 0
+            0xCD, 0x05, 0x00, // CALL 0005H
+            0x3E, 0x01, // MVI A, 1
+            0x76, // HLT
+        ]);
+        assert_eq!(machine.pc().value(), 0x0005);
+        assert_eq!(machine.register_8(Register::A), 0x00);
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        // CALL 0005H, which runs into the zeroed memory.
+        let mut machine = MachineBuilder::new()
+            .program(&[0xCD, 0x05, 0x00, 0x76], 0x0100)
+            .sp(0x2000)
+            .build()
+            .unwrap();
+        machine.steps().take(3).for_each(drop);
+        assert_eq!(machine.pc().value(), 0x0007);
+    }
+}
@@ -0,0 +1,295 @@
+//! What an instruction changed, returned by [`Machine::step_with_diff`].
+//!
+//! The [`Display`](fmt::Display) output of a [`MachineDiff`] is a single line, e.g. for `PUSH B`:
+//!
+//! ```text
+//! SP 1000->0FFE, PC 0000->0001, [0FFE] 00->34, [0FFF] 00->12
+//! ```
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::fmt;
+
+use crate::instruction::{Address, Data8, Register};
+
+use super::{ConditionRegister, Machine, StepInfo};
+#[cfg(feature = "serde")]
+use super::{MachineSnapshot, snapshot};
+
+/// The registers in [`MachineDiff::registers`], in order.
+const REGISTERS: [Register; 7] = [
+    Register::A,
+    Register::B,
+    Register::C,
+    Register::D,
+    Register::E,
+    Register::H,
+    Register::L,
+];
+
+/// The flags in [`MachineDiff::flags`], in order, and their names.
+const FLAGS: [(ConditionRegister, &str); 5] = [
+    (ConditionRegister::Sign, "S"),
+    (ConditionRegister::Zero, "Z"),
+    (ConditionRegister::AuxiliaryCarry, "AC"),
+    (ConditionRegister::Parity, "P"),
+    (ConditionRegister::Carry, "CY"),
+];
+
+/// Registers, flags, SP and memory bytes that changed, each with the old and new value.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct MachineDiff {
+    /// The 8-bit registers, A first.
+    pub registers: Vec<(Register, Data8, Data8)>,
+    /// The flags in the order S, Z, AC, P and CY.
+    pub flags: Vec<(ConditionRegister, bool, bool)>,
+    pub sp: Option<(Address, Address)>,
+    pub pc: Option<(Address, Address)>,
+    /// The bytes of memory by address.
+    pub memory: Vec<(Address, Data8, Data8)>,
+}
+
+/// Everything a diff compares except memory.
+struct Registers {
+    /// In the order of [`REGISTERS`].
+    bytes: [Data8; 7],
+    /// In the order of [`FLAGS`].
+    flags: [bool; 5],
+    sp: Address,
+    pc: Address,
+}
+
+impl Registers {
+    fn of(machine: &Machine) -> Self {
+        Self {
+            bytes: REGISTERS.map(|register| machine.register_8(register)),
+            flags: FLAGS.map(|(flag, _)| machine.conditions.get(flag)),
+            sp: machine.registers.sp,
+            pc: machine.pc,
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    fn of_snapshot(snapshot: &MachineSnapshot) -> Self {
+        let find = |register| {
+            snapshot::REGISTERS
+                .iter()
+                .position(|&other| other == register)
+        };
+        let find_flag = |flag| snapshot::CONDITIONS.iter().position(|&other| other == flag);
+        Self {
+            bytes: REGISTERS.map(|register| snapshot.registers[find(register).unwrap()]),
+            flags: FLAGS.map(|(flag, _)| snapshot.conditions[find_flag(flag).unwrap()]),
+            sp: snapshot.sp,
+            pc: snapshot.pc,
+        }
+    }
+
+    /// The changes from `self` to `after`, without memory.
+    fn diff(&self, after: &Registers) -> MachineDiff {
+        let changed = |old, new| (old != new).then_some((old, new));
+        MachineDiff {
+            registers: REGISTERS
+                .iter()
+                .zip(self.bytes.iter().zip(after.bytes))
+                .filter(|&(_, (&old, new))| old != new)
+                .map(|(&register, (&old, new))| (register, old, new))
+                .collect(),
+            flags: FLAGS
+                .iter()
+                .zip(self.flags.iter().zip(after.flags))
+                .filter(|&(_, (&old, new))| old != new)
+                .map(|(&(flag, _), (&old, new))| (flag, old, new))
+                .collect(),
+            sp: changed(self.sp, after.sp),
+            pc: changed(self.pc, after.pc),
+            memory: Vec::new(),
+        }
+    }
+}
+
+impl MachineDiff {
+    /// The changes between two snapshots, comparing all of memory.
+    #[cfg(feature = "serde")]
+    pub fn between(before: &MachineSnapshot, after: &MachineSnapshot) -> MachineDiff {
+        let mut diff = Registers::of_snapshot(before).diff(&Registers::of_snapshot(after));
+        diff.memory = before
+            .memory
+            .iter()
+            .zip(&after.memory)
+            .enumerate()
+            .filter(|&(_, (old, new))| old != new)
+            .map(|(address, (&old, &new))| (address as Address, old, new))
+            .collect();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+            && self.flags.is_empty()
+            && self.sp.is_none()
+            && self.pc.is_none()
+            && self.memory.is_empty()
+    }
+}
+
+impl fmt::Display for MachineDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("no changes");
+        }
+        let mut separator = "";
+        let mut next = |f: &mut fmt::Formatter<'_>| {
+            let result = f.write_str(separator);
+            separator = ", ";
+            result
+        };
+        for &(register, old, new) in &self.registers {
+            next(f)?;
+            write!(f, "{} {:02X}->{:02X}", register, old, new)?;
+        }
+        for &(flag, old, new) in &self.flags {
+            next(f)?;
+            let name = FLAGS.iter().find(|&&(other, _)| other == flag).unwrap().1;
+            write!(f, "{} {}->{}", name, old as u8, new as u8)?;
+        }
+        let pointers = [("SP", self.sp), ("PC", self.pc)];
+        for (name, (old, new)) in pointers
+            .into_iter()
+            .filter_map(|(name, change)| Some((name, change?)))
+        {
+            next(f)?;
+            write!(f, "{} {:04X}->{:04X}", name, old, new)?;
+        }
+        for &(address, old, new) in &self.memory {
+            next(f)?;
+            write!(f, "[{:04X}] {:02X}->{:02X}", address, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+impl Machine {
+    /// Execute a single instruction like [`Machine::step`], and also return what it changed.
+    ///
+    /// Only the bytes the instruction wrote are compared, not all of memory. Writes to
+    /// [mapped](Machine::map_region) addresses don't change memory, so they aren't listed.
+    pub fn step_with_diff(&mut self) -> Option<(StepInfo, MachineDiff)> {
+        let before = Registers::of(self);
+        self.written = Some(Vec::new());
+        let step = self.step();
+        let written = self.written.take().unwrap_or_default();
+        let step = step?;
+
+        let mut diff = before.diff(&Registers::of(self));
+        let mut old_values = BTreeMap::new();
+        for (address, old) in written {
+            old_values.entry(address).or_insert(old);
+        }
+        diff.memory = old_values
+            .into_iter()
+            .map(|(address, old)| (address, old, self.memory.read_8(address)))
+            .filter(|&(_, old, new)| old != new)
+            .collect();
+        Some((step, diff))
+    }
+
+    /// Keep the value at `address` before an instruction overwrites it, for
+    /// [`Machine::step_back`] and [`Machine::step_with_diff`].
+    pub(super) fn note_overwrite(&mut self, address: Address) {
+        if self.rewind.is_none() && self.written.is_none() {
+            return;
+        }
+        let old = self.memory.read_8(address);
+        if let Some(history) = &mut self.rewind {
+            history.note_write(address, old);
+        }
+        if let Some(written) = &mut self.written {
+            written.push((address, old));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instruction::RegisterPair, machine::MachineBuilder};
+
+    fn step(program: &[u8], setup: impl FnOnce(MachineBuilder) -> MachineBuilder) -> MachineDiff {
+        let mut machine = setup(MachineBuilder::new().program(program, 0x0000))
+            .build()
+            .unwrap();
+        machine.step_with_diff().unwrap().1
+    }
+
+    #[test]
+    fn mov() {
+        // MOV B, A
+        let diff = step(&[0x47], |builder| builder.register(Register::A, 0x2A));
+        assert_eq!(diff.registers, [(Register::B, 0x00, 0x2A)]);
+        assert!(diff.flags.is_empty());
+        assert_eq!(diff.sp, None);
+        assert_eq!(diff.pc, Some((0x0000, 0x0001)));
+        assert!(diff.memory.is_empty());
+        assert_eq!(diff.to_string(), "B 00->2A, PC 0000->0001");
+    }
+
+    #[test]
+    fn push() {
+        // PUSH B
+        let diff = step(&[0xC5], |builder| {
+            builder.sp(0x1000).register_pair(RegisterPair::Bc, 0x1234)
+        });
+        assert!(diff.registers.is_empty());
+        assert_eq!(diff.sp, Some((0x1000, 0x0FFE)));
+        assert_eq!(diff.memory, [(0x0FFE, 0x00, 0x34), (0x0FFF, 0x00, 0x12)]);
+        assert_eq!(
+            diff.to_string(),
+            "SP 1000->0FFE, PC 0000->0001, [0FFE] 00->34, [0FFF] 00->12"
+        );
+    }
+
+    #[test]
+    fn daa() {
+        // DAA
+        let diff = step(&[0x27], |builder| builder.register(Register::A, 0x9B));
+        assert_eq!(diff.registers, [(Register::A, 0x9B, 0x01)]);
+        assert_eq!(
+            diff.flags,
+            [
+                (ConditionRegister::AuxiliaryCarry, false, true),
+                (ConditionRegister::Carry, false, true),
+            ]
+        );
+        assert_eq!(diff.sp, None);
+        assert!(diff.memory.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            "A 9B->01, AC 0->1, CY 0->1, PC 0000->0001"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn between_snapshots() {
+        // PUSH B
+        let mut machine = MachineBuilder::new()
+            .program(&[0xC5], 0x0000)
+            .sp(0x1000)
+            .register_pair(RegisterPair::Bc, 0x1234)
+            .build()
+            .unwrap();
+        let before = machine.snapshot();
+        let (_, diff) = machine.step_with_diff().unwrap();
+        assert_eq!(MachineDiff::between(&before, &machine.snapshot()), diff);
+    }
+
+    #[test]
+    fn unchanged_write() {
+        // MVI M, 00H
+        let diff = step(&[0x36, 0x00], |builder| {
+            builder.register_pair(RegisterPair::Hl, 0x1000)
+        });
+        assert!(diff.memory.is_empty());
+        assert_eq!(diff.to_string(), "PC 0000->0002");
+    }
+}
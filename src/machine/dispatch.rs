@@ -0,0 +1,119 @@
+//! Executing the instruction at the program counter with a handler for its opcode, instead of
+//! decoding it first and matching on the decoded instruction.
+//!
+//! Every documented opcode gets its own instance of [`handler`], in which the instruction is a
+//! constant. [`Machine::execute_uncounted`] is inlined into it, so only the arm of that instruction
+//! is left, without looking up the opcode in the decoding table or matching on its operands. The
+//! undocumented opcodes depend on [`Machine::set_variant`] and
+//! [`Machine::set_undocumented_opcodes`], so their handlers decode as usual.
+
+use crate::{coding::table, instruction::Instruction};
+
+use super::{ExecutionResult, Machine};
+
+/// Executes the instruction of one opcode, given the two bytes after it.
+pub(super) type Handler = fn(&mut Machine, u8, u8) -> (Option<Instruction>, ExecutionResult);
+
+fn handler<const OPCODE: u8>(
+    machine: &mut Machine,
+    low: u8,
+    high: u8,
+) -> (Option<Instruction>, ExecutionResult) {
+    let entry = const { table::documented(OPCODE) };
+    let Some(entry) = entry else {
+        return machine.decode_execute();
+    };
+    let instruction = table::with_operands(entry.instruction, low, high);
+    machine.cycles += entry.cycles as u64;
+    machine.instructions += 1;
+    let result = machine.execute_uncounted(instruction);
+    if result == ExecutionResult::Running {
+        machine.pc = machine.pc.wrapping_add(entry.length as u16);
+    }
+    (Some(instruction), result)
+}
+
+/// The handlers of the opcodes `high` × 16 to `high` × 16 + 15.
+macro_rules! row {
+    ($high:literal) => {
+        [
+            handler::<{ $high * 16 }>,
+            handler::<{ $high * 16 + 1 }>,
+            handler::<{ $high * 16 + 2 }>,
+            handler::<{ $high * 16 + 3 }>,
+            handler::<{ $high * 16 + 4 }>,
+            handler::<{ $high * 16 + 5 }>,
+            handler::<{ $high * 16 + 6 }>,
+            handler::<{ $high * 16 + 7 }>,
+            handler::<{ $high * 16 + 8 }>,
+            handler::<{ $high * 16 + 9 }>,
+            handler::<{ $high * 16 + 10 }>,
+            handler::<{ $high * 16 + 11 }>,
+            handler::<{ $high * 16 + 12 }>,
+            handler::<{ $high * 16 + 13 }>,
+            handler::<{ $high * 16 + 14 }>,
+            handler::<{ $high * 16 + 15 }>,
+        ]
+    };
+}
+
+/// Indexed by the high and low nibble of the opcode.
+static HANDLERS: [[Handler; 16]; 16] = [
+    row!(0),
+    row!(1),
+    row!(2),
+    row!(3),
+    row!(4),
+    row!(5),
+    row!(6),
+    row!(7),
+    row!(8),
+    row!(9),
+    row!(10),
+    row!(11),
+    row!(12),
+    row!(13),
+    row!(14),
+    row!(15),
+];
+
+/// The handler of `opcode`.
+#[inline]
+pub(super) fn handler_of(opcode: u8) -> Handler {
+    HANDLERS[opcode as usize >> 4][opcode as usize & 0xF]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    /// Every handler does what decoding its opcode and executing the instruction does.
+    #[test]
+    fn handlers_match_decoding() {
+        for opcode in 0..=0xFF {
+            let machine = || {
+                MachineBuilder::new()
+                    .program(&[opcode, 0x34, 0x12], 0x0100)
+                    .build()
+                    .unwrap()
+            };
+            let mut decoded = machine();
+            let mut dispatched = machine();
+            assert_eq!(
+                handler_of(opcode)(&mut dispatched, 0x34, 0x12),
+                decoded.decode_execute(),
+                "{:02X}",
+                opcode
+            );
+            assert_eq!(dispatched.pc(), decoded.pc(), "{:02X}", opcode);
+            assert_eq!(dispatched.cycles(), decoded.cycles(), "{:02X}", opcode);
+            assert_eq!(
+                dispatched.instructions(),
+                decoded.instructions(),
+                "{:02X}",
+                opcode
+            );
+        }
+    }
+}
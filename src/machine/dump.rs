@@ -0,0 +1,190 @@
+//! Writing a region of memory out, with [`Memory::dump`].
+//!
+//! [`DumpFormat::HexDump`] writes 16 bytes per line, like `hexdump -C` but with the 16-bit
+//! addresses of the machine. Lines start at multiples of 16, so that the columns line up with the
+//! addresses, and the bytes before and after the region are left blank:
+//!
+//! ```text
+//! 0100                 48 65 6C  6C 6F 0A 00 FF 01 02 03  |     Hello......|
+//! 0110  04 05                                             |..              |
+//! ```
+
+use std::{
+    fmt::{self, Display},
+    io::{self, Write},
+    ops::Range,
+};
+
+use super::Memory;
+
+/// Bytes per line of [`DumpFormat::HexDump`].
+const LINE_BYTES: usize = 16;
+
+/// How [`Memory::dump`] writes memory.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// The bytes as they are.
+    Binary,
+    /// Lines of hexadecimal bytes with the address and the bytes as ASCII, see
+    /// `src/machine/dump.rs`.
+    HexDump,
+}
+
+/// Error returned by [`Memory::dump`].
+#[derive(Debug)]
+pub enum DumpError {
+    Io(io::Error),
+    /// The range is reversed or runs past 0xFFFF.
+    InvalidRange(Range<usize>),
+}
+
+impl Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpError::Io(err) => write!(f, "Couldn't write memory dump: {}", err),
+            DumpError::InvalidRange(range) => write!(
+                f,
+                "0x{:04X}..0x{:04X} is not a range of memory",
+                range.start, range.end
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {}
+
+impl From<io::Error> for DumpError {
+    fn from(err: io::Error) -> Self {
+        DumpError::Io(err)
+    }
+}
+
+impl Memory {
+    /// Write the bytes at the addresses in `range` to `writer`. The range may end at 0x10000 to
+    /// include 0xFFFF.
+    pub fn dump(
+        &self,
+        range: Range<usize>,
+        mut writer: impl Write,
+        format: DumpFormat,
+    ) -> Result<(), DumpError> {
+        if range.start > range.end || range.end > 0x10000 {
+            return Err(DumpError::InvalidRange(range));
+        }
+        let bytes = &self.0[range.clone()];
+        match format {
+            DumpFormat::Binary => writer.write_all(bytes)?,
+            DumpFormat::HexDump => {
+                let mut line_start = range.start - range.start % LINE_BYTES;
+                while line_start < range.end {
+                    write_line(&mut writer, line_start, |address| {
+                        range.contains(&address).then(|| self.0[address])
+                    })?;
+                    line_start += LINE_BYTES;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write the hex dump line of the 16 bytes from `start`, where `byte` returns `None` for the
+/// addresses outside of the dumped range.
+fn write_line(
+    writer: &mut impl Write,
+    start: usize,
+    byte: impl Fn(usize) -> Option<u8>,
+) -> io::Result<()> {
+    write!(writer, "{:04X} ", start)?;
+    for offset in 0..LINE_BYTES {
+        if offset % 8 == 0 {
+            write!(writer, " ")?;
+        }
+        match byte(start + offset) {
+            Some(value) => write!(writer, "{:02X} ", value)?,
+            None => write!(writer, "   ")?,
+        }
+    }
+    write!(writer, " |")?;
+    for offset in 0..LINE_BYTES {
+        let shown = match byte(start + offset) {
+            Some(value @ 0x20..=0x7E) => value,
+            Some(_) => b'.',
+            None => b' ',
+        };
+        writer.write_all(&[shown])?;
+    }
+    writeln!(writer, "|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(address: usize, bytes: &[u8]) -> Memory {
+        let mut memory = Memory::new();
+        memory.write_slice(address as u16, bytes).unwrap();
+        memory
+    }
+
+    fn hex_dump(memory: &Memory, range: Range<usize>) -> String {
+        let mut out = Vec::new();
+        memory.dump(range, &mut out, DumpFormat::HexDump).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn binary() {
+        let memory = memory(0xFFFE, &[0x12, 0x34]);
+        let mut out = Vec::new();
+        memory
+            .dump(0xFFFD..0x10000, &mut out, DumpFormat::Binary)
+            .unwrap();
+        assert_eq!(out, [0x00, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn full_lines() {
+        let memory = memory(0x0200, b"0123456789ABCDEFGHIJKLMNOPQRSTUV");
+        assert_eq!(
+            hex_dump(&memory, 0x0200..0x0220),
+            "0200  30 31 32 33 34 35 36 37  38 39 41 42 43 44 45 46  |0123456789ABCDEF|\n\
+             0210  47 48 49 4A 4B 4C 4D 4E  4F 50 51 52 53 54 55 56  |GHIJKLMNOPQRSTUV|\n"
+        );
+    }
+
+    #[test]
+    fn crossing_line_boundary() {
+        let memory = memory(0x0105, b"Hello\n\x00\xFF\x01\x02\x03\x04\x05");
+        assert_eq!(
+            hex_dump(&memory, 0x0105..0x0112),
+            "0100                 48 65 6C  6C 6F 0A 00 FF 01 02 03  |     Hello......|\n\
+             0110  04 05                                             |..              |\n"
+        );
+    }
+
+    #[test]
+    fn non_printable_bytes() {
+        let memory = memory(0x0000, &[0x1F, 0x20, 0x7E, 0x7F, 0x80, 0xFF, b'~', b'a']);
+        assert_eq!(
+            hex_dump(&memory, 0x0000..0x0008),
+            "0000  1F 20 7E 7F 80 FF 7E 61                           |. ~...~a        |\n"
+        );
+    }
+
+    #[test]
+    fn empty_and_invalid_ranges() {
+        let memory = Memory::new();
+        assert_eq!(hex_dump(&memory, 0x0100..0x0100), "");
+        let reversed = Range {
+            start: 0x0100,
+            end: 0x00FF,
+        };
+        for range in [reversed, 0xFFFF..0x10001] {
+            let err = memory
+                .dump(range.clone(), &mut Vec::new(), DumpFormat::Binary)
+                .unwrap_err();
+            assert!(matches!(err, DumpError::InvalidRange(invalid) if invalid == range));
+        }
+    }
+}
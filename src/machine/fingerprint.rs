@@ -0,0 +1,127 @@
+//! Hashes of memory and of the whole machine state, for tests comparing against known-good runs.
+//!
+//! Both are 64-bit FNV-1a hashes computed by hand, so they stay the same across platforms and
+//! versions unless the hashed bytes change on purpose:
+//!
+//! - [`Memory::checksum`] hashes the bytes of a range of memory in order.
+//! - [`Machine::state_fingerprint`] hashes B, C, D, E, H and L, the flags byte and A as
+//!   `PUSH PSW` stores them, SP and PC in little-endian, and the [`Memory::checksum`] of all
+//!   64 KiB in little-endian.
+
+use core::ops::Range;
+
+use crate::instruction::RegisterPair;
+
+use super::{Machine, Memory};
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// 64-bit FNV-1a hash of `bytes`.
+pub(crate) fn fnv1a<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u64 {
+    bytes.into_iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+impl Memory {
+    /// Hash of the bytes at the addresses in `range`, see `src/machine/fingerprint.rs`. The range
+    /// may end at 0x10000 to include 0xFFFF.
+    ///
+    /// # Panics
+    ///
+    /// If the range is reversed or runs past 0xFFFF.
+    pub fn checksum(&self, range: Range<usize>) -> u64 {
+        assert!(
+            range.start <= range.end && range.end <= 0x10000,
+            "0x{:04X}..0x{:04X} is not a range of memory",
+            range.start,
+            range.end
+        );
+        fnv1a(&self.0[range])
+    }
+}
+
+impl Machine {
+    /// Hash of the registers, flags, SP, PC and all of memory, see `src/machine/fingerprint.rs`.
+    /// Two machines with the same fingerprint are in the same state as far as a program can tell,
+    /// apart from the cycle count, input, output and devices.
+    pub fn state_fingerprint(&self) -> u64 {
+        let pairs = [RegisterPair::Bc, RegisterPair::De, RegisterPair::Hl]
+            .map(|pair| self.register_16(pair))
+            .map(|value| [value.high, value.low]);
+        let psw = self.get_status_word();
+        let sp = self.registers.sp.to_le_bytes();
+        let pc = self.pc.to_le_bytes();
+        let memory = self.memory.checksum(0..0x10000).to_le_bytes();
+        fnv1a(
+            pairs
+                .iter()
+                .flatten()
+                .chain(&[psw.low, psw.high])
+                .chain(&sp)
+                .chain(&pc)
+                .chain(&memory),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    #[test]
+    fn fnv1a_reference_values() {
+        assert_eq!(fnv1a(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_F739_67E8);
+    }
+
+    #[test]
+    fn checksum() {
+        let mut memory = Memory::new();
+        memory.write_slice(0x0100, b"foobar").unwrap();
+        assert_eq!(memory.checksum(0x0100..0x0106), fnv1a(b"foobar"));
+        assert_eq!(memory.checksum(0x0100..0x0100), fnv1a(b""));
+        assert_ne!(
+            memory.checksum(0x0000..0x10000),
+            Memory::new().checksum(0x0000..0x10000)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "0xFFFF..0x10001 is not a range of memory")]
+    fn checksum_past_end() {
+        Memory::new().checksum(0xFFFF..0x10001);
+    }
+
+    #[test]
+    fn new_machine() {
+        assert_eq!(Machine::new().state_fingerprint(), 0x1110_1E0C_635B_6C33);
+    }
+
+    #[test]
+    fn after_program() {
+        // 0000: LXI SP, 1000H
+        // 0003: MVI A, 2AH
+        // 0005: PUSH PSW
+        // 0006: ADI 0D6H
+        // 0008: LXI H, 1234H
+        // 000B: HLT
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[
+                    0x31, 0x00, 0x10, 0x3E, 0x2A, 0xF5, 0xC6, 0xD6, 0x21, 0x34, 0x12, 0x76,
+                ],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+        let before = machine.state_fingerprint();
+        machine.steps().for_each(drop);
+        // A 00, flags 57, HL 1234H, SP 0FFEH, PC 000BH and the pushed 2A02H at 0FFEH.
+        assert_eq!(machine.state_fingerprint(), 0xFE5E_E565_BC32_0BF7);
+        assert_ne!(machine.state_fingerprint(), before);
+    }
+}
@@ -0,0 +1,121 @@
+use alloc::boxed::Box;
+
+use crate::instruction::{Address, Data8, Port};
+
+use super::{HaltReason, Machine};
+
+type HaltHook = Box<dyn FnMut(HaltReason) + Send>;
+type OutputHook = Box<dyn FnMut(Port, Data8) + Send>;
+type ControlTransferHook = Box<dyn FnMut(Address, Address) + Send>;
+
+/// Callbacks for machine events, see [`Machine::on_halt`]. Unlike
+/// [observers](super::ExecutionObserver) they get the event instead of the machine, so they can
+/// be called in the middle of an instruction.
+#[derive(Default)]
+pub(super) struct Hooks {
+    pub(super) halt: Option<HaltHook>,
+    pub(super) output: Option<OutputHook>,
+    pub(super) control_transfer: Option<ControlTransferHook>,
+}
+
+impl Machine {
+    /// Call `hook` with the reason whenever an instruction halts the machine, replacing the
+    /// previous halt hook.
+    pub fn on_halt(&mut self, hook: impl FnMut(HaltReason) + Send + 'static) {
+        self.hooks.halt = Some(Box::new(hook));
+    }
+
+    /// Call `hook` with the port and value of every executed `OUT`, before the port's device or
+    /// the console handles it, replacing the previous output hook.
+    pub fn on_output(&mut self, hook: impl FnMut(Port, Data8) + Send + 'static) {
+        self.hooks.output = Some(Box::new(hook));
+    }
+
+    /// Call `hook` with the address of the instruction and the address execution continues at
+    /// whenever a jump, call, return, `RST` or `PCHL` is taken, replacing the previous control
+    /// transfer hook.
+    pub fn on_control_transfer(&mut self, hook: impl FnMut(Address, Address) + Send + 'static) {
+        self.hooks.control_transfer = Some(Box::new(hook));
+    }
+
+    /// Remove the hooks set with [`Machine::on_halt`], [`Machine::on_output`] and
+    /// [`Machine::on_control_transfer`].
+    pub fn clear_hooks(&mut self) {
+        self.hooks = Hooks::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Event {
+        Halt(HaltReason),
+        Output(Port, Data8),
+        ControlTransfer(Address, Address),
+    }
+
+    #[test]
+    fn event_sequence() {
+        // 0000: LXI SP, 1000H
+        // 0003: CALL 000DH
+        // 0006: CALL 0010H
+        // 0009: HLT
+        // 000A: NOP
+        // 000B: NOP
+        // 000C: NOP
+        // 000D: OUT 1
+        // 000F: RET
+        // 0010: INR A
+        // 0011: RET
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[
+                    0x31, 0x00, 0x10, 0xCD, 0x0D, 0x00, 0xCD, 0x10, 0x00, 0x76, 0x00, 0x00, 0x00,
+                    0xD3, 0x01, 0xC9, 0x3C, 0xC9,
+                ],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let log = events.clone();
+        machine.on_halt(move |reason| log.lock().unwrap().push(Event::Halt(reason)));
+        let log = events.clone();
+        machine.on_output(move |port, value| log.lock().unwrap().push(Event::Output(port, value)));
+        let log = events.clone();
+        machine.on_control_transfer(move |from, to| {
+            log.lock().unwrap().push(Event::ControlTransfer(from, to))
+        });
+        machine.steps().for_each(drop);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                Event::ControlTransfer(0x0003, 0x000D),
+                Event::Output(1, 0x00),
+                Event::ControlTransfer(0x000F, 0x0006),
+                Event::ControlTransfer(0x0006, 0x0010),
+                Event::ControlTransfer(0x0011, 0x0009),
+                Event::Halt(HaltReason::HaltInstruction),
+            ]
+        );
+        assert_eq!(machine.take_output(), b"0");
+    }
+
+    #[test]
+    fn cleared_hooks() {
+        let mut machine = MachineBuilder::new()
+            .program(&[0x76], 0x0000)
+            .build()
+            .unwrap();
+        machine.on_halt(|_| panic!("hook called after clearing it"));
+        machine.clear_hooks();
+        machine.steps().for_each(drop);
+    }
+}
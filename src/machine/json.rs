@@ -0,0 +1,363 @@
+//! Human-readable JSON dump of the machine state.
+//!
+//! The format is stable and meant to be diffed, so all object keys are sorted and memory is split
+//! into chunks at fixed addresses. A dump looks like this (with `registers` collapsed to one line):
+//!
+//! ```json
+//! {
+//!   "conditions": {
+//!     "auxiliary_carry": false,
+//!     "carry": true,
+//!     "parity": false,
+//!     "sign": false,
+//!     "zero": false
+//!   },
+//!   "format": "leben-state",
+//!   "halt_reason": "halt_instruction",
+//!   "interrupts": { "delayed": false, "enabled": false, "pending": null },
+//!   "memory": {
+//!     "0100": "3E2A760000000000000000000000000000000000000000000000000000000000"
+//!   },
+//!   "pc": "0102",
+//!   "registers": { "a": "2A", "b": "00", "c": "00", "d": "00", "e": "00", "h": "00", "l": "00", "sp": "FFFE" },
+//!   "state": "halted",
+//!   "version": 1
+//! }
+//! ```
+//!
+//! - All numbers are uppercase hexadecimal strings: 2 digits for 8-bit registers, 4 for 16-bit
+//!   registers and addresses.
+//! - `state` is `"running"`, `"waiting_for_input"` or `"halted"`. `halt_reason` is `null` unless
+//!   halted, otherwise one of `"halt_instruction"`, `"invalid_instruction"`, `"stack_overflow"`,
+//!   `"stack_underflow"` and `"memory_overflow"`.
+//! - `interrupts` has `enabled`, whether interrupts are accepted, `delayed`, whether `EI` was just
+//!   executed so that they are accepted after the next instruction, and `pending`, the encoded
+//!   instruction of an accepted interrupt the next step executes, or `null`. A dump without it
+//!   restores with interrupts disabled.
+//! - `memory` maps the address of each [`CHUNK_SIZE`]-byte chunk to its contents. Chunks missing
+//!   from the object are all zero, so a dump may contain all chunks or only the non-zero ones.
+//!
+//! Queued input, program output and observers are not part of the dump.
+
+use std::{
+    fmt::Display,
+    io::{self, Read, Write},
+};
+
+use serde_json::{Map, Value, json};
+
+use crate::{
+    coding::{self, reader::Reader},
+    instruction::{Data16, Register, RegisterPair},
+    machine::{ConditionRegister, HaltReason, InterruptEnable, Machine, MachineState},
+};
+
+/// Number of bytes per entry of the `memory` object.
+pub const CHUNK_SIZE: usize = 32;
+
+const FORMAT_NAME: &str = "leben-state";
+const FORMAT_VERSION: u64 = 1;
+
+pub(crate) const REGISTERS: [(&str, Register); 7] = [
+    ("a", Register::A),
+    ("b", Register::B),
+    ("c", Register::C),
+    ("d", Register::D),
+    ("e", Register::E),
+    ("h", Register::H),
+    ("l", Register::L),
+];
+
+pub(crate) const CONDITIONS: [(&str, ConditionRegister); 5] = [
+    ("auxiliary_carry", ConditionRegister::AuxiliaryCarry),
+    ("carry", ConditionRegister::Carry),
+    ("parity", ConditionRegister::Parity),
+    ("sign", ConditionRegister::Sign),
+    ("zero", ConditionRegister::Zero),
+];
+
+const HALT_REASONS: [(&str, HaltReason); 6] = [
+    ("halt_instruction", HaltReason::HaltInstruction),
+    ("invalid_instruction", HaltReason::InvalidInstruction),
+    ("stack_overflow", HaltReason::StackOverflow),
+    ("stack_underflow", HaltReason::StackUnderflow),
+    ("memory_overflow", HaltReason::MemoryOverflow),
+    ("self_jump", HaltReason::SelfJump),
+];
+
+/// Which memory chunks [`Machine::dump_json`] writes.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum MemoryDump {
+    /// Every chunk of the 64 KiB address space.
+    Full,
+    /// Only chunks containing at least one non-zero byte.
+    NonZero,
+}
+
+/// Error returned by [`Machine::restore_json`].
+#[derive(Debug)]
+pub enum StateJsonError {
+    /// The input isn't valid JSON, or couldn't be read.
+    Json(serde_json::Error),
+    /// The JSON doesn't describe a machine state.
+    Invalid(String),
+}
+
+impl Display for StateJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateJsonError::Json(err) => write!(f, "Couldn't parse state: {}", err),
+            StateJsonError::Invalid(message) => write!(f, "Invalid state: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StateJsonError {}
+
+pub(crate) fn invalid(message: impl Into<String>) -> StateJsonError {
+    StateJsonError::Invalid(message.into())
+}
+
+pub(crate) fn field<'a>(object: &'a Map<String, Value>, key: &str) -> Result<&'a Value, StateJsonError> {
+    object
+        .get(key)
+        .ok_or_else(|| invalid(format!("missing '{}'", key)))
+}
+
+pub(crate) fn object<'a>(value: &'a Value, key: &str) -> Result<&'a Map<String, Value>, StateJsonError> {
+    value
+        .as_object()
+        .ok_or_else(|| invalid(format!("'{}' is not an object", key)))
+}
+
+pub(crate) fn parse_hex(text: &str, digits: usize) -> Option<u16> {
+    if text.len() != digits {
+        return None;
+    }
+    u16::from_str_radix(text, 16).ok()
+}
+
+pub(crate) fn hex(value: &Value, key: &str, digits: usize) -> Result<u16, StateJsonError> {
+    value
+        .as_str()
+        .and_then(|text| parse_hex(text, digits))
+        .ok_or_else(|| {
+            invalid(format!(
+                "'{}' is not a {}-digit hexadecimal string",
+                key, digits
+            ))
+        })
+}
+
+/// `bytes` as a hexadecimal string, two digits per byte.
+pub(crate) fn hex_bytes(bytes: impl IntoIterator<Item = u8>) -> String {
+    bytes
+        .into_iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect()
+}
+
+/// Bytes written as a hexadecimal string, or none if `key` is missing.
+pub(crate) fn bytes(object: &Map<String, Value>, key: &str) -> Result<Vec<u8>, StateJsonError> {
+    let Some(value) = object.get(key) else {
+        return Ok(Vec::new());
+    };
+    value
+        .as_str()
+        .filter(|text| text.len().is_multiple_of(2))
+        .and_then(|text| {
+            (0..text.len() / 2)
+                .map(|i| u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok())
+                .collect()
+        })
+        .ok_or_else(|| invalid(format!("'{}' is not a hexadecimal string", key)))
+}
+
+impl Machine {
+    /// Write the machine state as pretty-printed JSON, in the format documented in
+    /// `src/machine/json.rs`.
+    pub fn dump_json(&self, mut writer: impl Write, memory: MemoryDump) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, &self.state_json(memory))?;
+        writeln!(writer)
+    }
+
+    /// The object written by [`Machine::dump_json`].
+    pub(super) fn state_json(&self, memory: MemoryDump) -> Value {
+        let registers: Map<String, Value> = REGISTERS
+            .iter()
+            .map(|(name, register)| {
+                (
+                    name.to_string(),
+                    format!("{:02X}", self.register_8(*register)).into(),
+                )
+            })
+            .chain([(
+                String::from("sp"),
+                format!("{:04X}", self.register_16(RegisterPair::Sp).value()).into(),
+            )])
+            .collect();
+
+        let conditions: Map<String, Value> = CONDITIONS
+            .iter()
+            .map(|(name, condition)| (name.to_string(), self.conditions.get(*condition).into()))
+            .collect();
+
+        let chunks: Map<String, Value> = self
+            .memory
+            .as_raw()
+            .chunks(CHUNK_SIZE)
+            .enumerate()
+            .filter(|(_, chunk)| memory == MemoryDump::Full || chunk.iter().any(|&byte| byte != 0))
+            .map(|(index, chunk)| {
+                let contents: String = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+                (format!("{:04X}", index * CHUNK_SIZE), contents.into())
+            })
+            .collect();
+
+        let (state, halt_reason) = match self.state {
+            MachineState::Running => ("running", Value::Null),
+            MachineState::WaitingForInput => ("waiting_for_input", Value::Null),
+            MachineState::Halted(reason) => {
+                let name = HALT_REASONS
+                    .iter()
+                    .find(|(_, candidate)| *candidate == reason)
+                    .map(|(name, _)| *name)
+                    .unwrap_or_default();
+                ("halted", name.into())
+            }
+        };
+
+        let (enabled, delayed) = self.interrupt_enable.flags();
+        let pending = self.interrupt.map(|instruction| {
+            let mut encoded = [0; 3];
+            let length = coding::encode_into(&mut encoded, instruction).unwrap_or_default();
+            hex_bytes(encoded[..length].iter().copied())
+        });
+
+        json!({
+            "format": FORMAT_NAME,
+            "version": FORMAT_VERSION,
+            "state": state,
+            "halt_reason": halt_reason,
+            "interrupts": {
+                "enabled": enabled,
+                "delayed": delayed,
+                "pending": pending,
+            },
+            "pc": format!("{:04X}", self.pc),
+            "registers": registers,
+            "conditions": conditions,
+            "memory": chunks,
+        })
+    }
+
+    /// Read a machine state written by [`Machine::dump_json`].
+    pub fn restore_json(reader: impl Read) -> Result<Machine, StateJsonError> {
+        let value: Value = serde_json::from_reader(reader).map_err(StateJsonError::Json)?;
+        Self::from_state_json(&value)
+    }
+
+    /// Inverse of [`Machine::state_json`].
+    pub(super) fn from_state_json(value: &Value) -> Result<Machine, StateJsonError> {
+        let root = object(value, "state")?;
+
+        if field(root, "format")?.as_str() != Some(FORMAT_NAME) {
+            return Err(invalid(format!("'format' is not '{}'", FORMAT_NAME)));
+        }
+        match field(root, "version")?.as_u64() {
+            Some(FORMAT_VERSION) => {}
+            _ => return Err(invalid("unsupported 'version'")),
+        }
+
+        let mut machine = Machine::new();
+
+        let registers = object(field(root, "registers")?, "registers")?;
+        for (name, register) in REGISTERS {
+            let value = hex(field(registers, name)?, name, 2)?;
+            machine.set_register_8(register, value as u8);
+        }
+        let sp = hex(field(registers, "sp")?, "sp", 4)?;
+        machine.set_register_16(RegisterPair::Sp, Data16::from(sp));
+
+        let conditions = object(field(root, "conditions")?, "conditions")?;
+        for (name, condition) in CONDITIONS {
+            let value = field(conditions, name)?
+                .as_bool()
+                .ok_or_else(|| invalid(format!("'{}' is not a boolean", name)))?;
+            machine.conditions.set(condition, value);
+        }
+
+        machine.pc = hex(field(root, "pc")?, "pc", 4)?;
+
+        machine.state = match field(root, "state")?.as_str() {
+            Some("running") => MachineState::Running,
+            Some("waiting_for_input") => MachineState::WaitingForInput,
+            Some("halted") => {
+                let name = field(root, "halt_reason")?.as_str();
+                let reason = HALT_REASONS
+                    .iter()
+                    .find(|(candidate, _)| Some(*candidate) == name)
+                    .map(|(_, reason)| *reason)
+                    .ok_or_else(|| invalid("unknown 'halt_reason'"))?;
+                MachineState::Halted(reason)
+            }
+            _ => {
+                return Err(invalid(
+                    "'state' is not 'running', 'waiting_for_input' or 'halted'",
+                ));
+            }
+        };
+
+        if let Some(interrupts) = root.get("interrupts") {
+            let interrupts = object(interrupts, "interrupts")?;
+            let flag = |name: &str| {
+                field(interrupts, name)?
+                    .as_bool()
+                    .ok_or_else(|| invalid(format!("'{}' is not a boolean", name)))
+            };
+            machine.interrupt_enable =
+                InterruptEnable::from_flags(flag("enabled")?, flag("delayed")?);
+            machine.interrupt = match interrupts.get("pending") {
+                None | Some(Value::Null) => None,
+                Some(_) => {
+                    let encoded = bytes(interrupts, "pending")?;
+                    let mut stream = Reader::new(&encoded);
+                    let instruction = coding::decode(&mut stream)
+                        .filter(|instruction| instruction.length() as usize == encoded.len())
+                        .ok_or_else(|| invalid("'pending' is not an instruction"))?;
+                    Some(instruction)
+                }
+            };
+        }
+
+        let chunks = object(field(root, "memory")?, "memory")?;
+        for (address, contents) in chunks {
+            let start = parse_hex(address, 4).ok_or_else(|| {
+                invalid(format!("memory chunk {} has an invalid address", address))
+            })?;
+            if !(start as usize).is_multiple_of(CHUNK_SIZE) {
+                return Err(invalid(format!(
+                    "memory chunk {} is not aligned to {} bytes",
+                    address, CHUNK_SIZE
+                )));
+            }
+            let bytes = contents
+                .as_str()
+                .filter(|text| text.len() == CHUNK_SIZE * 2)
+                .and_then(|text| {
+                    (0..CHUNK_SIZE)
+                        .map(|i| u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok())
+                        .collect::<Option<Vec<u8>>>()
+                })
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "memory chunk {} is not {} bytes of hexadecimal",
+                        address, CHUNK_SIZE
+                    ))
+                })?;
+            let _ = machine.memory.write_slice(start, &bytes);
+        }
+
+        Ok(machine)
+    }
+}
@@ -0,0 +1,170 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, ops::Range};
+
+use crate::instruction::{Address, Data8};
+
+use super::Machine;
+
+/// A device answering memory reads and writes of instructions in the address range it's mapped at
+/// with [`Machine::map_region`], e.g. a memory-mapped display or keyboard latch. The memory behind
+/// the range is left untouched.
+///
+/// Instructions are still fetched from memory, as are the views of [`Machine::memory`]. Mapped
+/// handlers can be looked up by type with [`Machine::memory_handler`].
+pub trait MemoryHandler: Any + Send {
+    /// Value read by an instruction from `address`.
+    fn read(&mut self, address: Address) -> Data8;
+
+    /// Handle an instruction writing `value` to `address`.
+    fn write(&mut self, address: Address, value: Data8);
+}
+
+/// Handlers mapped at address ranges of a machine.
+#[derive(Default)]
+pub(super) struct MemoryMap {
+    regions: Vec<(Range<usize>, Box<dyn MemoryHandler>)>,
+}
+
+impl MemoryMap {
+    pub(super) fn map(&mut self, range: Range<usize>, handler: Box<dyn MemoryHandler>) {
+        self.regions.push((range, handler));
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// The handler mapped at `address`, the last mapped one if regions overlap.
+    pub(super) fn handler(&mut self, address: Address) -> Option<&mut dyn MemoryHandler> {
+        self.regions
+            .iter_mut()
+            .rev()
+            .find(|(range, _)| range.contains(&(address as usize)))
+            .map(|(_, handler)| handler.as_mut())
+    }
+
+    /// The first mapped handler of type `T`.
+    pub(super) fn find<T: MemoryHandler>(&self) -> Option<&T> {
+        self.regions
+            .iter()
+            .find_map(|(_, handler)| (handler.as_ref() as &dyn Any).downcast_ref())
+    }
+}
+
+impl Machine {
+    /// Route the memory accesses of instructions to addresses in `range` to `handler` instead of
+    /// memory, replacing earlier handlers where the ranges overlap. The range may end at 0x10000 to
+    /// include 0xFFFF.
+    pub fn map_region(&mut self, range: Range<usize>, handler: Box<dyn MemoryHandler>) {
+        self.memory_map.map(range, handler);
+    }
+
+    /// The first mapped memory handler of type `T`.
+    pub fn memory_handler<T: MemoryHandler>(&self) -> Option<&T> {
+        self.memory_map.find()
+    }
+
+    /// Read a byte for an executing instruction, from the handler mapped at `address` if any.
+    pub(super) fn read_byte(&mut self, address: Address) -> Data8 {
+        if !self.memory_map.is_empty()
+            && let Some(handler) = self.memory_map.handler(address)
+        {
+            return handler.read(address);
+        }
+        self.memory.read_8(address)
+    }
+
+    /// Write a byte for an executing instruction, to the handler mapped at `address` if any.
+    pub(super) fn write_byte(&mut self, address: Address, value: Data8) {
+        if !self.memory_map.is_empty()
+            && let Some(handler) = self.memory_map.handler(address)
+        {
+            handler.write(address, value);
+            return;
+        }
+        self.memory.write_8(address, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::Register,
+        machine::{HaltReason, MachineBuilder, MachineState},
+    };
+
+    /// Handler that counts the reads, answering each with the count so far, and records writes.
+    #[derive(Default)]
+    struct Counter {
+        reads: Data8,
+        writes: Vec<(Address, Data8)>,
+    }
+
+    impl MemoryHandler for Counter {
+        fn read(&mut self, _address: Address) -> Data8 {
+            self.reads += 1;
+            self.reads
+        }
+
+        fn write(&mut self, address: Address, value: Data8) {
+            self.writes.push((address, value));
+        }
+    }
+
+    #[test]
+    fn lda_and_sta() {
+        // LDA F000H
+        // MOV B, A
+        // LDA F7FFH
+        // STA F001H
+        // MVI A, 42H
+        // STA F800H
+        // HLT
+        let mut machine = MachineBuilder::new()
+            .program(
+                &[
+                    0x3A, 0x00, 0xF0, 0x47, 0x3A, 0xFF, 0xF7, 0x32, 0x01, 0xF0, 0x3E, 0x42, 0x32,
+                    0x00, 0xF8, 0x76,
+                ],
+                0x0000,
+            )
+            .build()
+            .unwrap();
+        machine.map_region(0xF000..0xF800, Box::new(Counter::default()));
+        machine.steps().for_each(drop);
+
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.register_8(Register::B), 1);
+        assert_eq!(machine.register_8(Register::A), 0x42);
+        let counter = machine.memory_handler::<Counter>().unwrap();
+        assert_eq!(counter.reads, 2);
+        assert_eq!(counter.writes, [(0xF001, 2)]);
+        // Mapped writes leave memory alone, and the rest of it still works.
+        assert_eq!(machine.memory().read_8(0xF001), 0);
+        assert_eq!(machine.memory().read_8(0xF800), 0x42);
+    }
+
+    #[test]
+    fn sixteen_bit_access() {
+        // LHLD FFFFH
+        // SHLD FFFFH
+        // HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0x2A, 0xFF, 0xFF, 0x22, 0xFF, 0xFF, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        machine.map_region(0xFFFF..0x10000, Box::new(Counter::default()));
+        machine.steps().for_each(drop);
+
+        // L comes from the handler, H from memory at 0x0000 after wrapping around.
+        assert_eq!(machine.register_8(Register::L), 1);
+        assert_eq!(machine.register_8(Register::H), 0x2A);
+        let counter = machine.memory_handler::<Counter>().unwrap();
+        assert_eq!(counter.writes, [(0xFFFF, 1)]);
+        assert_eq!(machine.memory().read_8(0x0000), 0x2A);
+    }
+}
@@ -0,0 +1,19 @@
+use core::any::Any;
+
+use crate::instruction::Instruction;
+
+use super::{Machine, StepInfo};
+
+/// Hooks called by [`Machine::step`] around every executed instruction.
+///
+/// Observers are added with [`Machine::add_observer`]. While a hook runs the observer is detached
+/// from the machine, so the machine passed to it never lists the observer itself. Observers must be
+/// `Send` since the UI runs the machine on its own thread.
+pub trait ExecutionObserver: Any + Send {
+    /// Called before `instruction` is executed, with the machine in its state before the step.
+    /// `instruction` is `None` if the bytes at the program counter can't be decoded.
+    fn before_step(&mut self, _machine: &Machine, _instruction: Option<&Instruction>) {}
+
+    /// Called after an instruction has been executed.
+    fn after_step(&mut self, _machine: &Machine, _step: &StepInfo) {}
+}
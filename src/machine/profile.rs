@@ -0,0 +1,332 @@
+//! How often every address and opcode was executed, recorded with [`Machine::enable_profiling`],
+//! to find the hot spots of a program.
+//!
+//! The [`Display`](fmt::Display) implementation of [`ProfileReport`] prints the hottest addresses,
+//! the executed opcodes and the conditional jumps, calls and returns:
+//!
+//! ```text
+//! 14 instructions executed
+//!
+//! ADDR  EXECUTED
+//! 0005         3
+//! 0006         3
+//! ...
+//!
+//! OP  INSTRUCTION  EXECUTED
+//! 05  DCR B               3
+//! ...
+//!
+//! ADDR  INSTRUCTION  TAKEN  NOT TAKEN
+//! 0006  CZ a16           1          2
+//! ...
+//! ```
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::fmt;
+
+use crate::instruction::{Address, Instruction, OPCODES};
+
+use super::{ExecutionObserver, ExecutionResult, Machine, StepInfo};
+
+/// Addresses listed by the [`Display`](fmt::Display) implementation of [`ProfileReport`].
+const HOTTEST: usize = 10;
+
+/// How often a conditional jump, call or return went each way.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct BranchCount {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Executions per address and opcode, and the branches of conditional instructions.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ProfileReport {
+    addresses: BTreeMap<Address, u64>,
+    /// Executions of every opcode, indexed by the first byte of the instruction.
+    opcodes: Vec<u64>,
+    /// Branches of the conditional instructions, with the opcode, by address.
+    branches: BTreeMap<Address, (u8, BranchCount)>,
+}
+
+impl Default for ProfileReport {
+    fn default() -> Self {
+        Self {
+            addresses: BTreeMap::new(),
+            opcodes: alloc::vec![0; 256],
+            branches: BTreeMap::new(),
+        }
+    }
+}
+
+impl ProfileReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count an execution of `opcode` at `address`.
+    pub fn record(&mut self, opcode: u8, address: Address) {
+        *self.addresses.entry(address).or_default() += 1;
+        self.opcodes[opcode as usize] += 1;
+    }
+
+    /// Count a conditional instruction with `opcode` at `address` that went one way or the other.
+    pub fn record_branch(&mut self, opcode: u8, address: Address, taken: bool) {
+        let (_, count) = self
+            .branches
+            .entry(address)
+            .or_insert((opcode, BranchCount::default()));
+        if taken {
+            count.taken += 1;
+        } else {
+            count.not_taken += 1;
+        }
+    }
+
+    /// Number of executed instructions.
+    pub fn total(&self) -> u64 {
+        self.opcodes.iter().sum()
+    }
+
+    /// Number of times the instruction at `address` was executed.
+    pub fn executions(&self, address: Address) -> u64 {
+        self.addresses.get(&address).copied().unwrap_or(0)
+    }
+
+    /// The `n` most executed addresses with their counts, the most executed first and ties in
+    /// ascending order of address.
+    pub fn hottest(&self, n: usize) -> Vec<(Address, u64)> {
+        let mut addresses: Vec<(Address, u64)> = self
+            .addresses
+            .iter()
+            .map(|(&address, &count)| (address, count))
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses.truncate(n);
+        addresses
+    }
+
+    /// Number of times `opcode` was executed.
+    pub fn opcode(&self, opcode: u8) -> u64 {
+        self.opcodes[opcode as usize]
+    }
+
+    /// The executed opcodes with their counts, in ascending order of opcode.
+    pub fn opcodes(&self) -> Vec<(u8, u64)> {
+        (0..=255)
+            .map(|opcode| (opcode, self.opcode(opcode)))
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+
+    /// How often the conditional instruction at `address` went each way, all zero if none was
+    /// executed there.
+    pub fn branch(&self, address: Address) -> BranchCount {
+        self.branches
+            .get(&address)
+            .map_or_else(BranchCount::default, |&(_, count)| count)
+    }
+
+    /// The executed conditional instructions as the address, opcode and branches, in ascending
+    /// order of address.
+    pub fn branches(&self) -> Vec<(Address, u8, BranchCount)> {
+        self.branches
+            .iter()
+            .map(|(&address, &(opcode, count))| (address, opcode, count))
+            .collect()
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} instructions executed", self.total())?;
+
+        writeln!(f, "\nADDR  EXECUTED")?;
+        for (address, count) in self.hottest(HOTTEST) {
+            writeln!(f, "{:04X}  {:>8}", address, count)?;
+        }
+        if self.addresses.len() > HOTTEST {
+            writeln!(f, "and {} more", self.addresses.len() - HOTTEST)?;
+        }
+
+        writeln!(f, "\nOP  INSTRUCTION  EXECUTED")?;
+        for (opcode, count) in self.opcodes() {
+            writeln!(
+                f,
+                "{:02X}  {:<11} {:>9}",
+                opcode, OPCODES[opcode as usize].mnemonic, count
+            )?;
+        }
+
+        if !self.branches.is_empty() {
+            writeln!(f, "\nADDR  INSTRUCTION  TAKEN  NOT TAKEN")?;
+            for (address, opcode, count) in self.branches() {
+                writeln!(
+                    f,
+                    "{:04X}  {:<11} {:>6} {:>10}",
+                    address, OPCODES[opcode as usize].mnemonic, count.taken, count.not_taken
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Observer filling the report of [`Machine::enable_profiling`].
+pub(super) struct ProfileObserver(pub(super) ProfileReport);
+
+impl ExecutionObserver for ProfileObserver {
+    fn before_step(&mut self, machine: &Machine, instruction: Option<&Instruction>) {
+        // Bytes that can't be decoded halt the machine without executing anything.
+        if instruction.is_some() {
+            let pc = machine.pc().value();
+            self.0.record(machine.memory().read_8(pc), pc);
+        }
+    }
+
+    fn after_step(&mut self, machine: &Machine, step: &StepInfo) {
+        if let Some(Instruction::Jcc(..) | Instruction::Ccc(..) | Instruction::Rcc(..)) =
+            step.instruction
+        {
+            let pc = step.pc_before.value();
+            let opcode = machine.memory().read_8(pc);
+            match step.result {
+                ExecutionResult::ControlTransfer => self.0.record_branch(opcode, pc, true),
+                ExecutionResult::Running => self.0.record_branch(opcode, pc, false),
+                // Calls and returns that fault don't go either way.
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    /// Counts B down from 3, calling a subroutine once it reaches zero:
+    ///
+    /// ```text
+    /// 0000: LXI SP, 1000H
+    /// 0003: MVI B, 3
+    /// 0005: DCR B
+    /// 0006: CZ 000EH
+    /// 0009: JNZ 0005H
+    /// 000C: HLT
+    /// 000D: NOP
+    /// 000E: RNZ
+    /// 000F: RET
+    /// ```
+    const PROGRAM: [u8; 16] = [
+        0x31, 0x00, 0x10, 0x06, 0x03, 0x05, 0xCC, 0x0E, 0x00, 0xC2, 0x05, 0x00, 0x76, 0x00, 0xC0,
+        0xC9,
+    ];
+
+    fn run() -> Machine {
+        let mut machine = MachineBuilder::new()
+            .program(&PROGRAM, 0x0000)
+            .build()
+            .unwrap();
+        machine.enable_profiling();
+        machine.steps().for_each(drop);
+        machine
+    }
+
+    #[test]
+    fn counters() {
+        let machine = run();
+        let report = machine.profile().unwrap();
+        assert_eq!(report.total(), 14);
+        assert_eq!(report.executions(0x0000), 1);
+        assert_eq!(report.executions(0x0005), 3);
+        assert_eq!(report.executions(0x000D), 0);
+        assert_eq!(
+            report.hottest(4),
+            [(0x0005, 3), (0x0006, 3), (0x0009, 3), (0x0000, 1)]
+        );
+        assert_eq!(
+            report.opcodes(),
+            [
+                (0x05, 3),
+                (0x06, 1),
+                (0x31, 1),
+                (0x76, 1),
+                (0xC0, 1),
+                (0xC2, 3),
+                (0xC9, 1),
+                (0xCC, 3),
+            ]
+        );
+        assert_eq!(
+            report.branches(),
+            [
+                (
+                    0x0006,
+                    0xCC,
+                    BranchCount {
+                        taken: 1,
+                        not_taken: 2
+                    }
+                ),
+                (
+                    0x0009,
+                    0xC2,
+                    BranchCount {
+                        taken: 2,
+                        not_taken: 1
+                    }
+                ),
+                (
+                    0x000E,
+                    0xC0,
+                    BranchCount {
+                        taken: 0,
+                        not_taken: 1
+                    }
+                ),
+            ]
+        );
+        assert_eq!(report.branch(0x0005), BranchCount::default());
+    }
+
+    #[test]
+    fn report() {
+        let text = run().profile().unwrap().to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "14 instructions executed");
+        assert_eq!(lines[1..4], ["", "ADDR  EXECUTED", "0005         3"]);
+        assert_eq!(
+            lines[11..14],
+            ["", "OP  INSTRUCTION  EXECUTED", "05  DCR B               3"]
+        );
+        assert_eq!(
+            lines[lines.len() - 4..],
+            [
+                "ADDR  INSTRUCTION  TAKEN  NOT TAKEN",
+                "0006  CZ a16           1          2",
+                "0009  JNZ a16          2          1",
+                "000E  RNZ              0          1",
+            ]
+        );
+    }
+
+    #[test]
+    fn enable_and_disable() {
+        let mut machine = MachineBuilder::new()
+            .program(&PROGRAM, 0x0000)
+            .build()
+            .unwrap();
+        assert!(machine.profile().is_none());
+        machine.steps().take(2).for_each(drop);
+        machine.enable_profiling();
+        machine.steps().take(3).for_each(drop);
+        assert_eq!(machine.profile().unwrap().total(), 3);
+
+        let report = machine.disable_profiling().unwrap();
+        assert_eq!(report.executions(0x0005), 1);
+        assert!(machine.profile().is_none());
+        assert!(machine.disable_profiling().is_none());
+    }
+}
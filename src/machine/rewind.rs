@@ -0,0 +1,314 @@
+//! Stepping backwards, by keeping what every executed instruction changed.
+
+use alloc::{collections::VecDeque, vec::Vec};
+
+use crate::{
+    devices::Random,
+    instruction::{Address, Data8, Instruction},
+    machine::{ConditionRegisters, InterruptEnable, Machine, MachineState, RegisterMap},
+};
+
+/// State of [`Machine::enable_rewind`] kept by the machine.
+pub(super) struct History {
+    capacity: usize,
+    /// Records of the executed instructions, the latest last.
+    records: VecDeque<UndoRecord>,
+    /// Record of the instruction being executed.
+    current: Option<UndoRecord>,
+}
+
+/// The state of the machine before an instruction, except memory, of which only the bytes the
+/// instruction overwrote are kept.
+struct UndoRecord {
+    state: MachineState,
+    pc: Address,
+    registers: RegisterMap,
+    conditions: ConditionRegisters,
+    cycles: u64,
+    instructions: u64,
+    interrupt_enable: InterruptEnable,
+    interrupt: Option<Instruction>,
+    interrupt_mask: u8,
+    serial_output: bool,
+    random: Random,
+    /// Length of [`Machine::output_buffer`].
+    output: usize,
+    /// Input bytes the instruction read, in order.
+    input: Vec<u8>,
+    /// Overwritten memory as the address and old value, in the order of the writes.
+    memory: Vec<(Address, Data8)>,
+}
+
+impl History {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::new(),
+            current: None,
+        }
+    }
+
+    pub(super) fn note_write(&mut self, address: Address, old: Data8) {
+        if let Some(record) = &mut self.current {
+            record.memory.push((address, old));
+        }
+    }
+
+    pub(super) fn note_input(&mut self, byte: u8) {
+        if let Some(record) = &mut self.current {
+            record.input.push(byte);
+        }
+    }
+}
+
+impl Machine {
+    /// Keep undo records of the last `history_len` executed instructions, so that
+    /// [`Machine::step_back`] can undo them. Changing the length keeps the latest records.
+    pub fn enable_rewind(&mut self, history_len: usize) {
+        let history = self.rewind.get_or_insert_with(|| History::new(history_len));
+        history.capacity = history_len;
+        while history.records.len() > history_len {
+            history.records.pop_front();
+        }
+    }
+
+    /// Stop keeping undo records and forget the kept ones.
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Undo the last executed instruction, restoring the registers, flags, program counter, the
+    /// memory it wrote, the input it read and the output it wrote to [`Machine::output_buffer`].
+    /// Returns `false` if there's nothing to undo.
+    ///
+    /// Only instructions are undone: changes made through the machine's methods between steps,
+    /// output sent to an output writer or callback and the state of devices stay as they are.
+    pub fn step_back(&mut self) -> bool {
+        let Some(record) = self
+            .rewind
+            .as_mut()
+            .and_then(|history| history.records.pop_back())
+        else {
+            return false;
+        };
+        // In reverse, in case the instruction wrote the same address twice.
+        for &(address, value) in record.memory.iter().rev() {
+            self.memory.write_8(address, value);
+        }
+        for &byte in record.input.iter().rev() {
+            self.input.push_front(byte);
+        }
+        self.output.truncate(record.output);
+        self.state = record.state;
+        self.pc = record.pc;
+        self.registers = record.registers;
+        self.conditions = record.conditions;
+        self.cycles = record.cycles;
+        self.instructions = record.instructions;
+        self.interrupt_enable = record.interrupt_enable;
+        self.interrupt = record.interrupt;
+        self.interrupt_mask = record.interrupt_mask;
+        self.serial_output = record.serial_output;
+        self.random = record.random;
+        self.stopped_at = None;
+        true
+    }
+
+    /// Start the undo record of the instruction about to be executed, if rewinding is enabled.
+    pub(super) fn begin_undo_record(&mut self) {
+        if self.rewind.is_none() {
+            return;
+        }
+        let record = UndoRecord {
+            state: self.state,
+            pc: self.pc,
+            registers: self.registers.clone(),
+            conditions: self.conditions.clone(),
+            cycles: self.cycles,
+            instructions: self.instructions,
+            interrupt_enable: self.interrupt_enable,
+            interrupt: self.interrupt,
+            interrupt_mask: self.interrupt_mask,
+            serial_output: self.serial_output,
+            random: self.random,
+            output: self.output.len(),
+            input: Vec::new(),
+            memory: Vec::new(),
+        };
+        if let Some(history) = &mut self.rewind {
+            history.current = Some(record);
+        }
+    }
+
+    /// Keep the undo record of the executed instruction, dropping the oldest if the history is
+    /// full.
+    pub(super) fn end_undo_record(&mut self) {
+        let Some(history) = &mut self.rewind else {
+            return;
+        };
+        let Some(record) = history.current.take() else {
+            return;
+        };
+        if history.capacity == 0 {
+            return;
+        }
+        if history.records.len() == history.capacity {
+            history.records.pop_front();
+        }
+        history.records.push_back(record);
+    }
+
+    /// Forget the undo records, after the machine changed in a way they don't cover.
+    pub(super) fn clear_undo_records(&mut self) {
+        if let Some(history) = &mut self.rewind {
+            history.records.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::{Register, RegisterPair},
+        machine::{ConditionRegister, HaltReason, MachineBuilder},
+    };
+
+    /// Ten instructions writing memory in every way, then HLT:
+    ///
+    /// ```text
+    /// LXI SP, 2000H
+    /// LXI H, 0300H
+    /// MVI A, 22H
+    /// MOV M, A
+    /// OUT 0
+    /// STA 0301H
+    /// SHLD 0302H
+    /// PUSH H
+    /// XTHL
+    /// STAX D
+    /// HLT
+    /// ```
+    const PROGRAM: [u8; 21] = [
+        0x31, 0x00, 0x20, 0x21, 0x00, 0x03, 0x3E, 0x22, 0x77, 0xD3, 0x00, 0x32, 0x01, 0x03, 0x22,
+        0x02, 0x03, 0xE5, 0xE3, 0x12, 0x76,
+    ];
+
+    fn machine() -> Machine {
+        let mut machine = MachineBuilder::new()
+            .program(&PROGRAM, 0x0100)
+            .build()
+            .unwrap();
+        machine.enable_rewind(100);
+        machine
+    }
+
+    /// Everything an instruction can change, to compare machines with.
+    fn state(machine: &Machine) -> impl PartialEq + core::fmt::Debug + use<> {
+        let registers = [
+            Register::A,
+            Register::B,
+            Register::C,
+            Register::D,
+            Register::E,
+            Register::H,
+            Register::L,
+        ]
+        .map(|register| machine.register_8(register));
+        let conditions = [
+            ConditionRegister::Carry,
+            ConditionRegister::AuxiliaryCarry,
+            ConditionRegister::Sign,
+            ConditionRegister::Zero,
+            ConditionRegister::Parity,
+        ]
+        .map(|condition| machine.conditions().get(condition));
+        (
+            machine.state(),
+            machine.pc().value(),
+            machine.register_16(RegisterPair::Sp).value(),
+            registers,
+            conditions,
+            machine.cycles(),
+            machine.output_buffer().to_vec(),
+            machine.memory().as_raw().to_vec(),
+        )
+    }
+
+    #[test]
+    fn forward_and_back() {
+        let mut machine = machine();
+        let start = state(&machine);
+
+        let mut states = Vec::new();
+        for _ in 0..10 {
+            states.push(state(&machine));
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.output_buffer(), b"\"");
+        assert_ne!(state(&machine), start);
+
+        while let Some(before) = states.pop() {
+            assert!(machine.step_back());
+            assert_eq!(state(&machine), before);
+        }
+        assert!(!machine.step_back());
+        assert_eq!(state(&machine), start);
+
+        machine.steps().for_each(drop);
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(machine.memory().read_8(0x0000), 0x22);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_after_rewinding() {
+        let mut machine = machine();
+        let start = machine.snapshot();
+        machine.steps().take(10).for_each(drop);
+        for _ in 0..10 {
+            assert!(machine.step_back());
+        }
+        assert_eq!(machine.snapshot(), start);
+    }
+
+    #[test]
+    fn input_is_read_again() {
+        // IN 0; OUT 0; HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0xDB, 0x00, 0xD3, 0x00, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        machine.enable_rewind(10);
+        machine.push_input(b"xy");
+        machine.steps().for_each(drop);
+        assert_eq!(machine.output_buffer(), b"x");
+
+        for _ in 0..3 {
+            assert!(machine.step_back());
+        }
+        assert_eq!(machine.pc().value(), 0x0000);
+        assert!(machine.output_buffer().is_empty());
+        assert_eq!(machine.read_input(), Some(b'x'));
+        assert_eq!(machine.read_input(), Some(b'y'));
+    }
+
+    #[test]
+    fn history_is_limited() {
+        let mut machine = machine();
+        machine.enable_rewind(3);
+        machine.steps().take(5).for_each(drop);
+        for _ in 0..3 {
+            assert!(machine.step_back());
+        }
+        assert!(!machine.step_back());
+        assert_eq!(machine.pc().value(), 0x0106);
+
+        machine.disable_rewind();
+        machine.step();
+        assert!(!machine.step_back());
+    }
+}
@@ -0,0 +1,257 @@
+//! Save states: everything needed to stop a program and continue it later, in another process.
+//!
+//! A save state is a header line with the magic `LEBEN-SAVE` and the format version, followed by
+//! a JSON object:
+//!
+//! ```text
+//! LEBEN-SAVE 1
+//! {
+//!   "input": "",
+//!   "output": "4869",
+//!   "program_hash": "9E3F1C0A5B7D2E48",
+//!   "random": "2545F491",
+//!   "entry": "0100",
+//!   "state": { "format": "leben-state", ... }
+//! }
+//! ```
+//!
+//! - `state` is the machine state in the format of [`Machine::dump_json`].
+//! - `input` and `output` are the queued input and the program output so far, as hexadecimal
+//!   strings, `random` is the state of the generator behind `IN 1`, `entry` is the
+//!   [`Machine::entry`] a reset restarts at.
+//! - `program_hash` is the [`MemoryImage::hash`](crate::loader::MemoryImage::hash) of the program
+//!   the state was saved from, or `null` if it's unknown.
+//!
+//! Decoding is forward compatible: only `state` is required and unknown keys are ignored, so keys
+//! can be added without breaking older saves or older readers. The version only changes when a
+//! save can no longer be read by older versions, which then refuse to load it.
+
+use std::io::{self, Read, Write};
+
+use serde_json::{Value, json};
+
+use crate::{
+    devices::Random,
+    instruction::Address,
+    machine::{
+        Machine, MemoryDump, StateJsonError,
+        json::{bytes, field, hex_bytes, invalid, object},
+    },
+};
+
+/// Version written to the header of save states, the only version [`Machine::load_state`]
+/// accepts.
+pub const SAVE_VERSION: u64 = 1;
+
+/// First word of the header line of save states.
+pub const SAVE_MAGIC: &str = "LEBEN-SAVE";
+
+/// What a save state records about where it came from, besides the machine.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct SaveInfo {
+    /// Hash of the program the machine was loaded with, used to warn when a state is resumed with
+    /// a different program.
+    pub program_hash: Option<u64>,
+}
+
+/// A hexadecimal number of up to 64 bits, written with exactly `digits` digits.
+fn number(value: &Value, key: &str, digits: usize) -> Result<u64, StateJsonError> {
+    value
+        .as_str()
+        .filter(|text| text.len() == digits)
+        .and_then(|text| u64::from_str_radix(text, 16).ok())
+        .ok_or_else(|| {
+            invalid(format!(
+                "'{}' is not a {}-digit hexadecimal string",
+                key, digits
+            ))
+        })
+}
+
+impl Machine {
+    /// Write a save state of the machine, see `src/machine/save.rs` for the format.
+    pub fn save_state(&self, mut writer: impl Write, info: &SaveInfo) -> io::Result<()> {
+        let value = json!({
+            "program_hash": info.program_hash.map(|hash| format!("{:016X}", hash)),
+            "state": self.state_json(MemoryDump::NonZero),
+            "input": hex_bytes(self.input.iter().copied()),
+            "output": hex_bytes(self.output.iter().copied()),
+            "random": format!("{:08X}", self.random.state()),
+            "entry": format!("{:04X}", self.entry),
+        });
+
+        writeln!(writer, "{} {}", SAVE_MAGIC, SAVE_VERSION)?;
+        serde_json::to_writer_pretty(&mut writer, &value)?;
+        writeln!(writer)
+    }
+
+    /// Read a save state written by [`Machine::save_state`].
+    pub fn load_state(mut reader: impl Read) -> Result<(Machine, SaveInfo), StateJsonError> {
+        let mut contents = Vec::new();
+        reader
+            .read_to_end(&mut contents)
+            .map_err(|err| StateJsonError::Json(serde_json::Error::io(err)))?;
+
+        let header_end = contents
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .unwrap_or(contents.len());
+        let header = String::from_utf8_lossy(&contents[..header_end]);
+        let version = header
+            .trim_end()
+            .strip_prefix(SAVE_MAGIC)
+            .and_then(|rest| rest.strip_prefix(' '))
+            .ok_or_else(|| invalid("not a save state, the header is missing"))?;
+        match version.parse::<u64>() {
+            Ok(SAVE_VERSION) => {}
+            _ => {
+                return Err(invalid(format!(
+                    "unsupported save state version '{}', only {} can be loaded",
+                    version, SAVE_VERSION
+                )));
+            }
+        }
+
+        let value: Value = serde_json::from_slice(contents.get(header_end + 1..).unwrap_or(&[]))
+            .map_err(StateJsonError::Json)?;
+        let root = object(&value, "save state")?;
+
+        let mut machine = Machine::from_state_json(field(root, "state")?)?;
+        machine.input = bytes(root, "input")?.into();
+        machine.output = bytes(root, "output")?;
+        if let Some(random) = root.get("random") {
+            machine.random = Random::new(1, number(random, "random", 8)? as u32);
+        }
+        if let Some(entry) = root.get("entry") {
+            machine.entry = number(entry, "entry", 4)? as Address;
+        }
+        let program_hash = match root.get("program_hash") {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(number(value, "program_hash", 16)?),
+        };
+
+        Ok((machine, SaveInfo { program_hash }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::{Instruction, RestartNumber},
+        machine::{HaltReason, MachineBuilder, MachineState},
+    };
+
+    /// Prints the numbers read from `IN 1` until its input runs out, then halts.
+    fn program() -> MachineBuilder {
+        MachineBuilder::new()
+            .program(
+                &[
+                    0xDB, 0x01, //       0000: IN 1
+                    0xD3, 0x01, //       0002: OUT 1
+                    0xDB, 0x00, //       0004: IN 0
+                    0xB7, //             0006: ORA A
+                    0xC2, 0x00, 0x00, // 0007: JNZ 0000H
+                    0x76, //             000A: HLT
+                ],
+                0x0000,
+            )
+            .input(b"abc\0")
+            .random_seed(1234)
+    }
+
+    fn finish(mut machine: Machine) -> Vec<u8> {
+        machine.steps().count();
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        machine.take_output()
+    }
+
+    #[test]
+    fn resume_matches_uninterrupted_run() {
+        let expected = finish(program().build().unwrap());
+
+        let mut machine = program().build().unwrap();
+        machine.steps().take(7).count();
+        let info = SaveInfo {
+            program_hash: Some(0x0123_4567_89AB_CDEF),
+        };
+        let mut save = Vec::new();
+        machine.save_state(&mut save, &info).unwrap();
+        assert!(save.starts_with(b"LEBEN-SAVE 1\n{"));
+
+        let (resumed, loaded_info) = Machine::load_state(&save[..]).unwrap();
+        assert_eq!(loaded_info, info);
+        assert_eq!(resumed.pc(), machine.pc());
+        assert_eq!(resumed.entry(), machine.entry());
+        assert_eq!(finish(resumed), expected);
+    }
+
+    #[test]
+    fn resume_with_interrupts_enabled() {
+        // 0000: EI
+        // 0001: NOP
+        // 0002: JMP 0001H
+        // 0038: HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0xFB, 0x00, 0xC3, 0x01, 0x00], 0x0000)
+            .segment(&[0x76], 0x0038)
+            .build()
+            .unwrap();
+        machine.steps().take(3).count();
+        let mut save = Vec::new();
+        machine.save_state(&mut save, &SaveInfo::default()).unwrap();
+
+        let (mut resumed, _) = Machine::load_state(&save[..]).unwrap();
+        assert!(resumed.interrupts_enabled());
+        assert!(resumed.request_interrupt(Instruction::Rst(RestartNumber::R7)));
+
+        // The accepted interrupt is saved too.
+        let mut save = Vec::new();
+        resumed.save_state(&mut save, &SaveInfo::default()).unwrap();
+        let (mut resumed, _) = Machine::load_state(&save[..]).unwrap();
+        assert!(!resumed.interrupts_enabled());
+        resumed.steps().count();
+        assert_eq!(
+            resumed.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(resumed.pc(), 0x0038.into());
+    }
+
+    #[test]
+    fn unknown_and_missing_keys() {
+        let mut state = Vec::new();
+        program()
+            .build()
+            .unwrap()
+            .dump_json(&mut state, MemoryDump::NonZero)
+            .unwrap();
+        let save = format!(
+            "LEBEN-SAVE 1\n{{\"state\": {}, \"added_later\": [1, 2]}}",
+            String::from_utf8(state).unwrap()
+        );
+
+        let (machine, info) = Machine::load_state(save.as_bytes()).unwrap();
+        assert_eq!(info.program_hash, None);
+        assert!(machine.output_buffer().is_empty());
+        assert_eq!(machine.memory().read_8(0x0000), 0xDB);
+    }
+
+    #[test]
+    fn bad_header() {
+        let err = Machine::load_state(&b"{}"[..]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid state: not a save state, the header is missing"
+        );
+
+        let err = Machine::load_state(&b"LEBEN-SAVE 2\n{}"[..]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid state: unsupported save state version '2', only 1 can be loaded"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+//! Read access to the memory of a running machine from other threads, e.g. for an external
+//! visualizer.
+//!
+//! The machine keeps sole ownership of its memory. Once sharing is enabled with
+//! [`Machine::share_memory`], it copies its memory into a second buffer behind a lock every
+//! `interval` instructions, and readers on other threads read that copy through [`SharedMemory`]
+//! handles. Machines that don't share their memory never take the lock or copy anything.
+//!
+//! Consistency guarantees:
+//!
+//! - A published copy is always the complete memory between two instructions, never a copy taken
+//!   in the middle of an instruction or a mix of two publishes.
+//! - A write by an instruction is visible to readers at the latest after `interval` more
+//!   instructions, and the memory at a halt is always published.
+//! - Memory changed outside of [`Machine::step`], e.g. through [`Machine::memory_mut`], is only
+//!   visible after the next publish. Call [`Machine::publish_memory`] to publish it right away.
+//! - The copy is only as fresh as the last publish, so reading it never blocks the machine for
+//!   longer than a publish blocks readers: the time it takes to copy the memory once.
+
+use std::{
+    ops::Range,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    instruction::{Address, Data8},
+    machine::{Machine, Memory},
+};
+
+struct Published {
+    bytes: Box<[u8]>,
+    generation: u64,
+}
+
+/// Clonable read handle to the memory of a machine, see the [module documentation](self).
+#[derive(Clone)]
+pub struct SharedMemory {
+    published: Arc<RwLock<Published>>,
+}
+
+impl SharedMemory {
+    fn new(memory: &Memory) -> Self {
+        Self {
+            published: Arc::new(RwLock::new(Published {
+                bytes: memory.as_raw().to_vec().into_boxed_slice(),
+                generation: 0,
+            })),
+        }
+    }
+
+    fn publish(&self, memory: &Memory) {
+        let mut published = self
+            .published
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        published.bytes.copy_from_slice(memory.as_raw());
+        published.generation += 1;
+    }
+
+    /// Call `f` with the last published memory. The machine can't publish while `f` runs.
+    pub fn with_bytes<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        let published = self.published.read().unwrap_or_else(|err| err.into_inner());
+        f(&published.bytes)
+    }
+
+    pub fn read_8(&self, address: Address) -> Data8 {
+        self.with_bytes(|bytes| bytes[address as usize])
+    }
+
+    /// Copy of `range` of the last published memory, cut off at the end of the memory.
+    pub fn read(&self, range: Range<usize>) -> Vec<u8> {
+        self.with_bytes(|bytes| {
+            let end = range.end.min(bytes.len());
+            bytes[range.start.min(end)..end].to_vec()
+        })
+    }
+
+    /// Number of times the memory has been published, which changes whenever readers may see new
+    /// contents.
+    pub fn generation(&self) -> u64 {
+        self.published
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .generation
+    }
+}
+
+/// State of [`Machine::share_memory`] kept by the machine.
+pub(super) struct Publisher {
+    handle: SharedMemory,
+    interval: usize,
+    /// Instructions executed since the last publish.
+    pending: usize,
+}
+
+impl Publisher {
+    /// Called after every executed instruction.
+    pub(super) fn after_step(&mut self, memory: &Memory, halted: bool) {
+        self.pending += 1;
+        if self.pending >= self.interval || halted {
+            self.handle.publish(memory);
+            self.pending = 0;
+        }
+    }
+}
+
+impl Machine {
+    /// Start publishing the memory every `interval` instructions (at least one), returning a
+    /// handle to read it from other threads. The current memory is published right away. Calling
+    /// this again only changes the interval, handles from earlier calls stay valid.
+    pub fn share_memory(&mut self, interval: usize) -> SharedMemory {
+        let interval = interval.max(1);
+        match &mut self.shared_memory {
+            Some(publisher) => publisher.interval = interval,
+            None => {
+                self.shared_memory = Some(Publisher {
+                    handle: SharedMemory::new(&self.memory),
+                    interval,
+                    pending: 0,
+                })
+            }
+        }
+        self.publish_memory();
+        self.shared_memory_handle()
+            .expect("memory sharing was just enabled")
+    }
+
+    /// A new handle to the shared memory, or `None` if [`Machine::share_memory`] hasn't been
+    /// called.
+    pub fn shared_memory_handle(&self) -> Option<SharedMemory> {
+        self.shared_memory
+            .as_ref()
+            .map(|publisher| publisher.handle.clone())
+    }
+
+    /// Publish the memory now instead of waiting for the interval. Does nothing if the memory
+    /// isn't shared.
+    pub fn publish_memory(&mut self) {
+        if let Some(publisher) = &mut self.shared_memory {
+            publisher.handle.publish(&self.memory);
+            publisher.pending = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::machine::{HaltReason, MachineBuilder, MachineState};
+
+    /// Stores 1, 2, 3, ... at 0100H, one store every 36 instructions.
+    fn counter() -> MachineBuilder {
+        MachineBuilder::new().program(
+            &[
+                0x3C, //             0000: INR A
+                0x32, 0x00, 0x01, // 0001: STA 0100H
+                0x0E, 0x10, //       0004: MVI C, 10H
+                0x0D, //             0006: DCR C
+                0xC2, 0x06, 0x00, // 0007: JNZ 0006H
+                0xC3, 0x00, 0x00, // 000A: JMP 0000H
+            ],
+            0x0000,
+        )
+    }
+
+    #[test]
+    fn publish_interval() {
+        let mut machine = counter().build().unwrap();
+        let shared = machine.share_memory(8);
+        assert_eq!(shared.generation(), 1);
+        assert_eq!(shared.read_8(0x0000), 0x3C);
+
+        // INR A, STA: written, but not published yet.
+        machine.step();
+        machine.step();
+        assert_eq!(machine.memory().read_8(0x0100), 1);
+        assert_eq!(shared.read_8(0x0100), 0);
+
+        for _ in 0..6 {
+            machine.step();
+        }
+        assert_eq!(shared.generation(), 2);
+        assert_eq!(shared.read(0x0100..0x0102), [1, 0]);
+
+        machine.memory_mut().write_8(0x0101, 0xAA);
+        machine.publish_memory();
+        assert_eq!(shared.generation(), 3);
+        assert_eq!(machine.shared_memory_handle().unwrap().read_8(0x0101), 0xAA);
+    }
+
+    #[test]
+    fn halt_is_published() {
+        let mut machine = MachineBuilder::new()
+            .program(&[0x3E, 0x42, 0x32, 0x00, 0x01, 0x76], 0x0000)
+            .build()
+            .unwrap();
+        let shared = machine.share_memory(1000);
+        machine.steps().count();
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(shared.read_8(0x0100), 0x42);
+    }
+
+    #[test]
+    fn not_shared_by_default() {
+        let mut machine = counter().build().unwrap();
+        assert!(machine.shared_memory_handle().is_none());
+        machine.publish_memory();
+        assert!(machine.shared_memory_handle().is_none());
+    }
+
+    #[test]
+    fn reader_thread() {
+        const INTERVAL: usize = 64;
+        let mut machine = counter().build().unwrap();
+        let shared = machine.share_memory(INTERVAL);
+        let (seen, seen_receiver) = mpsc::channel();
+
+        let reader = thread::spawn(move || {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while Instant::now() < deadline {
+                let value = shared.read_8(0x0100);
+                if value >= 3 {
+                    seen.send(value).unwrap();
+                    return;
+                }
+                thread::yield_now();
+            }
+        });
+
+        // Run until the reader has seen the third store. Every store is followed by a publish
+        // within `INTERVAL` instructions, so the value can't be older than that.
+        let mut executed = 0;
+        let value = loop {
+            machine.step();
+            executed += 1;
+            if reader.is_finished() {
+                break seen_receiver.try_recv().expect("reader gave up");
+            }
+            if executed % INTERVAL == 0 {
+                thread::yield_now();
+            }
+        };
+        reader.join().unwrap();
+
+        let current = machine.memory().read_8(0x0100);
+        assert!((3..=current).contains(&value), "{} > {}", value, current);
+    }
+}
@@ -0,0 +1,423 @@
+//! Snapshots of the machine as plain data, with `serde` support and a binary format.
+//!
+//! A binary snapshot written by [`Machine::save_to`] is the magic `LEBEN-SNAP`, a version byte
+//! ([`SNAPSHOT_VERSION`]) and the [`MachineSnapshot`] encoded with `bincode`'s standard
+//! configuration. Unlike save states (`src/machine/save.rs`) the format isn't meant to be read by
+//! people or by other versions of the emulator, but it's compact and quick to write.
+
+use std::{
+    fmt::{self, Display},
+    io::{self, Read, Write},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::{
+    devices::Random,
+    instruction::{Address, Data8, Data16, Instruction, Register, RegisterPair},
+    machine::{ConditionRegister, InterruptEnable, MEMORY_SIZE_BYTES, Machine, MachineState},
+};
+
+/// Version written after the magic of binary snapshots, the only version
+/// [`Machine::load_from`] accepts.
+pub const SNAPSHOT_VERSION: u8 = 2;
+
+const MAGIC: &[u8] = b"LEBEN-SNAP";
+
+/// The registers in [`MachineSnapshot::registers`].
+pub(super) const REGISTERS: [Register; 7] = [
+    Register::B,
+    Register::C,
+    Register::D,
+    Register::E,
+    Register::H,
+    Register::L,
+    Register::A,
+];
+
+/// The flags in [`MachineSnapshot::conditions`].
+pub(super) const CONDITIONS: [ConditionRegister; 5] = [
+    ConditionRegister::Carry,
+    ConditionRegister::AuxiliaryCarry,
+    ConditionRegister::Sign,
+    ConditionRegister::Zero,
+    ConditionRegister::Parity,
+];
+
+/// Everything needed to continue a program later: the state of the machine without its input
+/// source, output callback, observers and devices.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineSnapshot {
+    pub state: MachineState,
+    pub pc: Address,
+    pub sp: Address,
+    /// B, C, D, E, H, L and A.
+    pub registers: [Data8; 7],
+    /// Carry, auxiliary carry, sign, zero and parity.
+    pub conditions: [bool; 5],
+    /// All of memory. Serialized as bytes, or as a hexadecimal string in human-readable formats.
+    #[serde(with = "memory")]
+    pub memory: Vec<u8>,
+    /// Queued input, not yet read by `IN 0`.
+    pub input: Vec<u8>,
+    /// Program output so far, see [`Machine::output_buffer`].
+    pub output: Vec<u8>,
+    pub cycles: u64,
+    /// Executed instructions, see [`Machine::instructions`].
+    pub instructions: u64,
+    pub entry: Address,
+    /// State of the generator behind `IN 1`.
+    pub random: u32,
+    /// Whether interrupts are accepted, see [`Machine::interrupts_enabled`].
+    pub interrupts_enabled: bool,
+    /// Whether `EI` was just executed, so that interrupts are accepted after the next instruction.
+    pub interrupts_delayed: bool,
+    /// An accepted interrupt, executed by the next step, see [`Machine::request_interrupt`].
+    pub interrupt: Option<Instruction>,
+    /// Interrupt mask set by the 8085's `SIM`, see [`Machine::interrupt_mask`].
+    pub interrupt_mask: u8,
+    /// Serial output line set by the 8085's `SIM`, see [`Machine::serial_output`].
+    pub serial_output: bool,
+}
+
+/// Error returned by [`Machine::save_to`], [`Machine::load_from`] and
+/// [`Machine::from_snapshot`].
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Encode(bincode::error::EncodeError),
+    Decode(bincode::error::DecodeError),
+    /// The input doesn't start with the magic and a supported version.
+    NotASnapshot,
+    /// The snapshot has this many bytes of memory instead of the size of the machine's memory.
+    MemorySize(usize),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "Couldn't read or write snapshot: {}", err),
+            SnapshotError::Encode(err) => write!(f, "Couldn't encode snapshot: {}", err),
+            SnapshotError::Decode(err) => write!(f, "Couldn't decode snapshot: {}", err),
+            SnapshotError::NotASnapshot => write!(
+                f,
+                "Not a snapshot of version {}, the header is missing or differs",
+                SNAPSHOT_VERSION
+            ),
+            SnapshotError::MemorySize(size) => write!(
+                f,
+                "The snapshot has {} bytes of memory instead of {}",
+                size, MEMORY_SIZE_BYTES
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Machine {
+    /// The state of the machine as plain data, see [`MachineSnapshot`].
+    pub fn snapshot(&self) -> MachineSnapshot {
+        let (interrupts_enabled, interrupts_delayed) = self.interrupt_enable.flags();
+        MachineSnapshot {
+            state: self.state,
+            pc: self.pc,
+            sp: self.register_16(RegisterPair::Sp).value(),
+            registers: REGISTERS.map(|register| self.register_8(register)),
+            conditions: CONDITIONS.map(|condition| self.conditions.get(condition)),
+            memory: self.memory.0.to_vec(),
+            input: self.input.iter().copied().collect(),
+            output: self.output.clone(),
+            cycles: self.cycles,
+            instructions: self.instructions,
+            entry: self.entry,
+            random: self.random.state(),
+            interrupts_enabled,
+            interrupts_delayed,
+            interrupt: self.interrupt,
+            interrupt_mask: self.interrupt_mask,
+            serial_output: self.serial_output,
+        }
+    }
+
+    /// A new machine in the state of `snapshot`, without devices or observers.
+    pub fn from_snapshot(snapshot: MachineSnapshot) -> Result<Machine, SnapshotError> {
+        if snapshot.memory.len() != MEMORY_SIZE_BYTES {
+            return Err(SnapshotError::MemorySize(snapshot.memory.len()));
+        }
+
+        let mut machine = Machine::new();
+        machine.memory.0.copy_from_slice(&snapshot.memory);
+        for (register, value) in REGISTERS.into_iter().zip(snapshot.registers) {
+            machine.set_register_8(register, value);
+        }
+        machine.set_register_16(RegisterPair::Sp, Data16::from(snapshot.sp));
+        for (condition, value) in CONDITIONS.into_iter().zip(snapshot.conditions) {
+            machine.conditions.set(condition, value);
+        }
+        machine.state = snapshot.state;
+        machine.pc = snapshot.pc;
+        machine.input = snapshot.input.into();
+        machine.output = snapshot.output;
+        machine.cycles = snapshot.cycles;
+        machine.instructions = snapshot.instructions;
+        machine.entry = snapshot.entry;
+        machine.random = Random::new(1, snapshot.random);
+        machine.interrupt_enable =
+            InterruptEnable::from_flags(snapshot.interrupts_enabled, snapshot.interrupts_delayed);
+        machine.interrupt = snapshot.interrupt;
+        machine.interrupt_mask = snapshot.interrupt_mask;
+        machine.serial_output = snapshot.serial_output;
+        Ok(machine)
+    }
+
+    /// Write a binary snapshot of the machine, see `src/machine/snapshot.rs` for the format.
+    pub fn save_to(&self, mut writer: impl Write) -> Result<(), SnapshotError> {
+        writer.write_all(MAGIC).map_err(SnapshotError::Io)?;
+        writer
+            .write_all(&[SNAPSHOT_VERSION])
+            .map_err(SnapshotError::Io)?;
+        bincode::serde::encode_into_std_write(
+            self.snapshot(),
+            &mut writer,
+            bincode::config::standard(),
+        )
+        .map_err(SnapshotError::Encode)?;
+        Ok(())
+    }
+
+    /// Read a binary snapshot written by [`Machine::save_to`].
+    pub fn load_from(mut reader: impl Read) -> Result<Machine, SnapshotError> {
+        let mut header = [0; MAGIC.len() + 1];
+        reader
+            .read_exact(&mut header)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => SnapshotError::NotASnapshot,
+                _ => SnapshotError::Io(err),
+            })?;
+        if header[..MAGIC.len()] != *MAGIC || header[MAGIC.len()] != SNAPSHOT_VERSION {
+            return Err(SnapshotError::NotASnapshot);
+        }
+
+        let snapshot: MachineSnapshot =
+            bincode::serde::decode_from_std_read(&mut reader, bincode::config::standard())
+                .map_err(SnapshotError::Decode)?;
+        Machine::from_snapshot(snapshot)
+    }
+}
+
+/// Memory as bytes instead of a sequence of numbers, or as a hexadecimal string in
+/// human-readable formats like JSON.
+mod memory {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(memory: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let hex: String = memory.iter().map(|byte| format!("{:02X}", byte)).collect();
+            serializer.serialize_str(&hex)
+        } else {
+            serializer.serialize_bytes(memory)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(MemoryVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(MemoryVisitor)
+        }
+    }
+
+    struct MemoryVisitor;
+
+    impl<'de> de::Visitor<'de> for MemoryVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "bytes or a hexadecimal string")
+        }
+
+        fn visit_str<E: de::Error>(self, hex: &str) -> Result<Vec<u8>, E> {
+            if !hex.len().is_multiple_of(2) {
+                return Err(E::invalid_length(hex.len(), &self));
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|start| {
+                    hex.get(start..start + 2)
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                        .ok_or_else(|| E::invalid_value(de::Unexpected::Str(hex), &self))
+                })
+                .collect()
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+            Ok(bytes)
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::RestartNumber,
+        machine::{HaltReason, MachineBuilder},
+    };
+
+    /// Print the numbers read from the input until a 0, then halt.
+    fn program() -> MachineBuilder {
+        MachineBuilder::new()
+            .program(
+                &[
+                    0xDB, 0x00, //       0000: IN 0
+                    0xD3, 0x01, //       0002: OUT 1
+                    0xB7, //             0004: ORA A
+                    0xC2, 0x00, 0x00, // 0005: JNZ 0000H
+                    0x76, //             0008: HLT
+                ],
+                0x0000,
+            )
+            .input(b"abc\0")
+            .random_seed(1234)
+    }
+
+    fn finish(mut machine: Machine) -> Vec<u8> {
+        machine.steps().count();
+        assert_eq!(
+            machine.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        machine.take_output()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut machine = program().build().unwrap();
+        machine.steps().take(5).count();
+        machine.set_register_8(Register::E, 0x5E);
+        machine
+            .conditions
+            .set(ConditionRegister::AuxiliaryCarry, true);
+        machine.memory_mut().write_8(0x4000, 0x40);
+        let snapshot = machine.snapshot();
+
+        let mut saved = Vec::new();
+        machine.save_to(&mut saved).unwrap();
+        assert!(saved.starts_with(b"LEBEN-SNAP"));
+        assert_eq!(saved[10], SNAPSHOT_VERSION);
+        // Memory is written as bytes, not one number per byte.
+        assert!(saved.len() < MEMORY_SIZE_BYTES + 64);
+
+        let loaded = Machine::load_from(&saved[..]).unwrap();
+        assert_eq!(loaded.snapshot(), snapshot);
+        assert_eq!(
+            Machine::from_snapshot(snapshot.clone()).unwrap().snapshot(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn round_trip_after_ei() {
+        // 0000: EI
+        // 0001: NOP
+        // 0002: JMP 0001H
+        // 0038: HLT
+        let mut machine = MachineBuilder::new()
+            .program(&[0xFB, 0x00, 0xC3, 0x01, 0x00], 0x0000)
+            .segment(&[0x76], 0x0038)
+            .build()
+            .unwrap();
+        machine.step();
+        assert!(!machine.interrupts_enabled());
+        let delayed = Machine::from_snapshot(machine.snapshot()).unwrap();
+        assert_eq!(delayed.snapshot(), machine.snapshot());
+        assert!(delayed.snapshot().interrupts_delayed);
+
+        machine.step();
+        let mut resumed = Machine::from_snapshot(machine.snapshot()).unwrap();
+        assert!(resumed.interrupts_enabled());
+        assert_eq!(resumed.instructions(), 2);
+        assert!(resumed.request_interrupt(Instruction::Rst(RestartNumber::R7)));
+
+        let mut saved = Vec::new();
+        resumed.save_to(&mut saved).unwrap();
+        let mut loaded = Machine::load_from(&saved[..]).unwrap();
+        assert_eq!(
+            loaded.snapshot().interrupt,
+            Some(Instruction::Rst(RestartNumber::R7))
+        );
+        assert!(!loaded.interrupts_enabled());
+        loaded.steps().count();
+        assert_eq!(
+            loaded.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(loaded.pc(), Data16::from(0x0038));
+    }
+
+    #[test]
+    fn resume_matches_uninterrupted_run() {
+        let expected = finish(program().build().unwrap());
+
+        let mut machine = program().build().unwrap();
+        machine.steps().take(6).count();
+        let mut saved = Vec::new();
+        machine.save_to(&mut saved).unwrap();
+
+        let resumed = Machine::load_from(&saved[..]).unwrap();
+        assert_eq!(resumed.pc(), machine.pc());
+        assert_eq!(finish(resumed), expected);
+    }
+
+    #[test]
+    fn memory_is_a_hex_string_in_json() {
+        let snapshot = program().build().unwrap().snapshot();
+        let json = serde_json::to_value(&snapshot).unwrap();
+        let memory = json["memory"].as_str().unwrap();
+        assert!(memory.starts_with("DB00D301B7C2000076"));
+        assert_eq!(memory.len(), 2 * MEMORY_SIZE_BYTES);
+
+        let parsed: MachineSnapshot = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, snapshot);
+    }
+
+    #[test]
+    fn load_errors() {
+        assert!(matches!(
+            Machine::load_from(&b"LEBEN-SAVE 1\n{}"[..]),
+            Err(SnapshotError::NotASnapshot)
+        ));
+        assert!(matches!(
+            Machine::load_from(&b"LEBEN"[..]),
+            Err(SnapshotError::NotASnapshot)
+        ));
+
+        let mut saved = Vec::new();
+        Machine::new().save_to(&mut saved).unwrap();
+        saved.truncate(saved.len() / 2);
+        assert!(matches!(
+            Machine::load_from(&saved[..]),
+            Err(SnapshotError::Decode(_))
+        ));
+
+        let mut snapshot = Machine::new().snapshot();
+        snapshot.memory.truncate(0x100);
+        assert!(matches!(
+            Machine::from_snapshot(snapshot),
+            Err(SnapshotError::MemorySize(0x100))
+        ));
+    }
+}
@@ -0,0 +1,207 @@
+use core::ops::ControlFlow;
+
+use crate::{
+    instruction::Address,
+    machine::{HaltReason, Machine, MachineState},
+};
+
+/// Number of instructions between two [`Event::Progress`] events.
+pub const PROGRESS_INTERVAL: usize = 10_000;
+
+/// Something that happened during [`Machine::run_streaming`].
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// The last instruction wrote these bytes to an output port.
+    Output(&'a [u8]),
+    /// Sent every [`PROGRESS_INTERVAL`] instructions.
+    Progress {
+        /// Instructions executed by this call so far.
+        instructions: usize,
+        pc: Address,
+    },
+    /// The machine halted. This is always the last event.
+    Halted(HaltReason),
+}
+
+impl Machine {
+    /// Execute up to `budget` instructions, reporting output, progress and halting to `on_event`
+    /// as it happens. Execution stops early when the machine halts, starts
+    /// [waiting for input](MachineState::WaitingForInput) or `on_event` returns
+    /// [`ControlFlow::Break`]. Returns the number of executed instructions.
+    ///
+    /// Output is passed to `on_event` instead of the output writer, callback or
+    /// [`Machine::output_buffer`].
+    /// Events borrow from the machine, so no memory is allocated per event.
+    pub fn run_streaming(
+        &mut self,
+        budget: usize,
+        mut on_event: impl FnMut(Event<'_>) -> ControlFlow<()>,
+    ) -> usize {
+        if let MachineState::Halted(reason) = self.state {
+            let _ = on_event(Event::Halted(reason));
+            return 0;
+        }
+        if self.state == MachineState::WaitingForInput {
+            return 0;
+        }
+
+        // Collect output at the end of the buffer, handing it out and dropping it after every
+        // instruction.
+        let sink = core::mem::take(&mut self.output_sink);
+        let start = self.output.len();
+
+        let mut executed = 0;
+        while executed < budget {
+            self.run_cycle();
+            executed += 1;
+
+            if self.output.len() > start {
+                let flow = on_event(Event::Output(&self.output[start..]));
+                self.output.truncate(start);
+                if flow.is_break() {
+                    break;
+                }
+            }
+            if let MachineState::Halted(reason) = self.state {
+                let _ = on_event(Event::Halted(reason));
+                break;
+            }
+            if self.state == MachineState::WaitingForInput {
+                break;
+            }
+            if executed.is_multiple_of(PROGRESS_INTERVAL) {
+                let flow = on_event(Event::Progress {
+                    instructions: executed,
+                    pc: self.pc,
+                });
+                if flow.is_break() {
+                    break;
+                }
+            }
+        }
+
+        self.output_sink = sink;
+        executed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::ToOwned, vec::Vec};
+
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    enum Owned {
+        Output(Vec<u8>),
+        Progress(usize, Address),
+        Halted(HaltReason),
+    }
+
+    fn record(event: Event<'_>) -> Owned {
+        match event {
+            Event::Output(bytes) => Owned::Output(bytes.to_owned()),
+            Event::Progress { instructions, pc } => Owned::Progress(instructions, pc),
+            Event::Halted(reason) => Owned::Halted(reason),
+        }
+    }
+
+    /// Prints 'C', 'B', 'A' from a loop, then halts.
+    fn countdown() -> Machine {
+        MachineBuilder::new()
+            .program(
+                &[
+                    0x06, 0x03, // 0000: MVI B, 3
+                    0x78, //       0002: MOV A, B
+                    0xC6, 0x40, // 0003: ADI 40H
+                    0xD3, 0x00, // 0005: OUT 0
+                    0x05, //       0007: DCR B
+                    0xC2, 0x02, 0x00, // 0008: JNZ 0002H
+                    0x76, //       000B: HLT
+                ],
+                0x0000,
+            )
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn output_before_halt() {
+        let mut machine = countdown();
+        let mut events = Vec::new();
+        let executed = machine.run_streaming(1000, |event| {
+            events.push(record(event));
+            ControlFlow::Continue(())
+        });
+
+        assert_eq!(executed, 1 + 3 * 5 + 1);
+        assert_eq!(
+            events,
+            [
+                Owned::Output(b"C".to_vec()),
+                Owned::Output(b"B".to_vec()),
+                Owned::Output(b"A".to_vec()),
+                Owned::Halted(HaltReason::HaltInstruction),
+            ]
+        );
+        assert!(machine.output_buffer().is_empty());
+    }
+
+    #[test]
+    fn break_stops_execution() {
+        let mut machine = countdown();
+        let mut outputs = 0;
+        let executed = machine.run_streaming(1000, |event| {
+            if let Event::Output(_) = event {
+                outputs += 1;
+            }
+            if outputs == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+
+        // Stopped right after the second OUT.
+        assert_eq!(executed, 1 + 5 + 3);
+        assert_eq!(machine.pc().value(), 0x0007);
+        assert_eq!(machine.state(), MachineState::Running);
+    }
+
+    #[test]
+    fn progress_and_budget() {
+        // JMP 0000H
+        let mut machine = MachineBuilder::new()
+            .program(&[0xC3, 0x00, 0x00], 0x0000)
+            .build()
+            .unwrap();
+        let mut events = Vec::new();
+        let executed = machine.run_streaming(PROGRESS_INTERVAL * 2 + 1, |event| {
+            events.push(record(event));
+            ControlFlow::Continue(())
+        });
+
+        assert_eq!(executed, PROGRESS_INTERVAL * 2 + 1);
+        assert_eq!(
+            events,
+            [
+                Owned::Progress(PROGRESS_INTERVAL, 0x0000),
+                Owned::Progress(PROGRESS_INTERVAL * 2, 0x0000),
+            ]
+        );
+    }
+
+    #[test]
+    fn already_halted() {
+        let mut machine = MachineBuilder::new().program(&[0x76], 0).build().unwrap();
+        machine.step();
+        let mut events = Vec::new();
+        let executed = machine.run_streaming(10, |event| {
+            events.push(record(event));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(executed, 0);
+        assert_eq!(events, [Owned::Halted(HaltReason::HaltInstruction)]);
+    }
+}
@@ -0,0 +1,303 @@
+//! Truth tables of the arithmetic and logic instructions, to compare the emulator with captures
+//! from real hardware.
+//!
+//! [`alu_table`] executes an operation for every combination of its inputs on a scratch machine
+//! and [`write_alu_csv`] writes the rows as CSV:
+//!
+//! ```text
+//! a,b,cy,ac,result,flags
+//! 00,00,0,0,00,46
+//! 00,01,0,0,01,02
+//! ```
+//!
+//! - `a` and `b` are the accumulator and register B before the instruction, `result` is the
+//!   accumulator after it. Operations that don't use B always have `b` set to `00`.
+//! - `cy` and `ac` are the carry and auxiliary carry flags before the instruction. They are only
+//!   varied for operations whose result depends on them, and are 0 otherwise. All other flags are
+//!   clear before the instruction.
+//! - `flags` is the flags byte as `PUSH PSW` stores it after the instruction: `S Z 0 AC 0 P 1 CY`
+//!   from bit 7 to bit 0.
+//!
+//! All numbers are uppercase hexadecimal, rows are sorted by `a`, `b`, `cy` and `ac`.
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Display, Write},
+    str::FromStr,
+};
+
+use crate::{
+    instruction::{Instruction, Register},
+    machine::{ConditionRegister, ConditionRegisters, Machine},
+};
+
+/// An instruction [`alu_table`] can tabulate. Binary operations use register B as the operand.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum AluOperation {
+    Add,
+    Adc,
+    Sub,
+    Sbb,
+    Ana,
+    Xra,
+    Ora,
+    Cmp,
+    Inr,
+    Dcr,
+    Daa,
+    Rlc,
+    Rrc,
+    Ral,
+    Rar,
+}
+
+impl AluOperation {
+    pub const ALL: [AluOperation; 15] = [
+        AluOperation::Add,
+        AluOperation::Adc,
+        AluOperation::Sub,
+        AluOperation::Sbb,
+        AluOperation::Ana,
+        AluOperation::Xra,
+        AluOperation::Ora,
+        AluOperation::Cmp,
+        AluOperation::Inr,
+        AluOperation::Dcr,
+        AluOperation::Daa,
+        AluOperation::Rlc,
+        AluOperation::Rrc,
+        AluOperation::Ral,
+        AluOperation::Rar,
+    ];
+
+    /// The mnemonic, e.g. `"ADD"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            AluOperation::Add => "ADD",
+            AluOperation::Adc => "ADC",
+            AluOperation::Sub => "SUB",
+            AluOperation::Sbb => "SBB",
+            AluOperation::Ana => "ANA",
+            AluOperation::Xra => "XRA",
+            AluOperation::Ora => "ORA",
+            AluOperation::Cmp => "CMP",
+            AluOperation::Inr => "INR",
+            AluOperation::Dcr => "DCR",
+            AluOperation::Daa => "DAA",
+            AluOperation::Rlc => "RLC",
+            AluOperation::Rrc => "RRC",
+            AluOperation::Ral => "RAL",
+            AluOperation::Rar => "RAR",
+        }
+    }
+
+    fn instruction(self) -> Instruction {
+        match self {
+            AluOperation::Add => Instruction::Add(Register::B),
+            AluOperation::Adc => Instruction::Adc(Register::B),
+            AluOperation::Sub => Instruction::Sub(Register::B),
+            AluOperation::Sbb => Instruction::Sbb(Register::B),
+            AluOperation::Ana => Instruction::Ana(Register::B),
+            AluOperation::Xra => Instruction::Xra(Register::B),
+            AluOperation::Ora => Instruction::Ora(Register::B),
+            AluOperation::Cmp => Instruction::Cmp(Register::B),
+            AluOperation::Inr => Instruction::Inr(Register::A),
+            AluOperation::Dcr => Instruction::Dcr(Register::A),
+            AluOperation::Daa => Instruction::Daa,
+            AluOperation::Rlc => Instruction::Rlc,
+            AluOperation::Rrc => Instruction::Rrc,
+            AluOperation::Ral => Instruction::Ral,
+            AluOperation::Rar => Instruction::Rar,
+        }
+    }
+
+    fn uses_b(self) -> bool {
+        matches!(
+            self,
+            AluOperation::Add
+                | AluOperation::Adc
+                | AluOperation::Sub
+                | AluOperation::Sbb
+                | AluOperation::Ana
+                | AluOperation::Xra
+                | AluOperation::Ora
+                | AluOperation::Cmp
+        )
+    }
+
+    /// Whether the outcome depends on the carry flag, because it's used or kept unchanged.
+    fn uses_carry(self) -> bool {
+        matches!(
+            self,
+            AluOperation::Adc
+                | AluOperation::Sbb
+                | AluOperation::Inr
+                | AluOperation::Dcr
+                | AluOperation::Daa
+                | AluOperation::Ral
+                | AluOperation::Rar
+        )
+    }
+
+    /// Whether the outcome depends on the auxiliary carry flag, because it's used or kept
+    /// unchanged.
+    fn uses_aux_carry(self) -> bool {
+        matches!(
+            self,
+            AluOperation::Daa
+                | AluOperation::Rlc
+                | AluOperation::Rrc
+                | AluOperation::Ral
+                | AluOperation::Rar
+        )
+    }
+}
+
+impl Display for AluOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Error returned when parsing an unknown [`AluOperation`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownAluOperation;
+
+impl Display for UnknownAluOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("unknown operation, expected one of ")?;
+        for (i, operation) in AluOperation::ALL.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(operation.name())?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for UnknownAluOperation {}
+
+impl FromStr for AluOperation {
+    type Err = UnknownAluOperation;
+
+    /// Parse a mnemonic, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AluOperation::ALL
+            .into_iter()
+            .find(|operation| operation.name().eq_ignore_ascii_case(s))
+            .ok_or(UnknownAluOperation)
+    }
+}
+
+/// One combination of inputs of an [`AluOperation`] and its outcome.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct AluRow {
+    pub a: u8,
+    pub b: u8,
+    pub carry: bool,
+    pub aux_carry: bool,
+    pub result: u8,
+    /// Flags byte after the instruction, as stored by `PUSH PSW`.
+    pub flags: u8,
+}
+
+/// Execute `operation` for every combination of its inputs, see the
+/// [module documentation](self).
+pub fn alu_table(operation: AluOperation) -> Vec<AluRow> {
+    let bs = if operation.uses_b() { 0..=255 } else { 0..=0 };
+    let carries: &[bool] = if operation.uses_carry() {
+        &[false, true]
+    } else {
+        &[false]
+    };
+    let aux_carries: &[bool] = if operation.uses_aux_carry() {
+        &[false, true]
+    } else {
+        &[false]
+    };
+
+    let mut machine = Machine::new();
+    let mut rows = Vec::new();
+    for a in 0..=255 {
+        for b in bs.clone() {
+            for &carry in carries {
+                for &aux_carry in aux_carries {
+                    machine.set_register_8(Register::A, a);
+                    machine.set_register_8(Register::B, b);
+                    machine.conditions = ConditionRegisters::new();
+                    machine.conditions.set(ConditionRegister::Carry, carry);
+                    machine
+                        .conditions
+                        .set(ConditionRegister::AuxiliaryCarry, aux_carry);
+
+                    let _ = machine.execute(operation.instruction());
+
+                    rows.push(AluRow {
+                        a,
+                        b,
+                        carry,
+                        aux_carry,
+                        result: machine.register_8(Register::A),
+                        flags: machine.get_status_word().low,
+                    });
+                }
+            }
+        }
+    }
+    rows
+}
+
+/// Write `rows` as CSV with a header line, see the [module documentation](self).
+pub fn write_alu_csv(rows: &[AluRow], mut writer: impl Write) -> fmt::Result {
+    writeln!(writer, "a,b,cy,ac,result,flags")?;
+    for row in rows {
+        writeln!(
+            writer,
+            "{:02X},{:02X},{},{},{:02X},{:02X}",
+            row.a, row.b, row.carry as u8, row.aux_carry as u8, row.result, row.flags
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::*;
+
+    #[test]
+    fn table_sizes() {
+        assert_eq!(alu_table(AluOperation::Add).len(), 256 * 256);
+        assert_eq!(alu_table(AluOperation::Adc).len(), 256 * 256 * 2);
+        assert_eq!(alu_table(AluOperation::Daa).len(), 256 * 4);
+        assert_eq!(alu_table(AluOperation::Rlc).len(), 256 * 2);
+        assert_eq!(alu_table(AluOperation::Inr).len(), 256 * 2);
+    }
+
+    #[test]
+    fn csv() {
+        let rows = alu_table(AluOperation::Xra);
+        let mut text = String::new();
+        write_alu_csv(&rows[..2], &mut text).unwrap();
+        // A XOR A and A XOR 1 with A = 0: zero with even parity, then odd parity.
+        assert_eq!(
+            text,
+            "a,b,cy,ac,result,flags\n00,00,0,0,00,46\n00,01,0,0,01,02\n"
+        );
+    }
+
+    #[test]
+    fn parse_operation() {
+        assert_eq!("daa".parse(), Ok(AluOperation::Daa));
+        assert_eq!("SbB".parse(), Ok(AluOperation::Sbb));
+        assert!(
+            "ADI"
+                .parse::<AluOperation>()
+                .unwrap_err()
+                .to_string()
+                .starts_with("unknown operation, expected one of ADD, ADC")
+        );
+    }
+}
@@ -1,5 +1,7 @@
-use rsoderh_jonsh_leben_emulator::{self, cli};
+use std::process::ExitCode;
 
-fn main() -> anyhow::Result<()> {
+use rsoderh_jonsh_leben_emulator::cli;
+
+fn main() -> ExitCode {
     cli::start()
 }
@@ -0,0 +1,359 @@
+//! Python bindings, enabled with the `python` feature and built into a `leben` extension module
+//! with maturin, see the README.
+//!
+//! Memory, output and assembled programs always cross into Python as new `bytes` objects. The
+//! machine owns its memory and keeps changing it, so handing out a view would let Python read it
+//! while a `run` that released the GIL is writing to it. Bytes passed in are copied into the
+//! machine before the call returns.
+//!
+//! Loading errors raise `ValueError`, or its subclass `AssemblyError` when the source doesn't
+//! assemble. A halted machine isn't an error: `step` and `run` report it, and `halt_reason` tells
+//! why as a [`HaltReason`].
+
+use std::collections::BTreeMap;
+
+use pyo3::{create_exception, exceptions::PyValueError, prelude::*, types::PyBytes};
+
+use crate::{
+    assembler, coding,
+    disasm::{Listing, ListingLine, Symbols},
+    instruction::{Address, Register, RegisterPair},
+    machine::{self, BuildError, ConditionRegister, Machine, MachineBuilder, MachineState},
+};
+
+create_exception!(
+    leben,
+    AssemblyError,
+    PyValueError,
+    "The assembly source couldn't be assembled."
+);
+
+/// Why a machine stopped, mirroring [`machine::HaltReason`].
+#[pyclass(module = "leben", eq, eq_int, frozen)]
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum HaltReason {
+    HaltInstruction,
+    InvalidInstruction,
+    StackOverflow,
+    StackUnderflow,
+    MemoryOverflow,
+    SelfJump,
+}
+
+impl From<machine::HaltReason> for HaltReason {
+    fn from(value: machine::HaltReason) -> Self {
+        match value {
+            machine::HaltReason::HaltInstruction => HaltReason::HaltInstruction,
+            machine::HaltReason::InvalidInstruction => HaltReason::InvalidInstruction,
+            machine::HaltReason::StackOverflow => HaltReason::StackOverflow,
+            machine::HaltReason::StackUnderflow => HaltReason::StackUnderflow,
+            machine::HaltReason::MemoryOverflow => HaltReason::MemoryOverflow,
+            machine::HaltReason::SelfJump => HaltReason::SelfJump,
+        }
+    }
+}
+
+/// A register as named from Python, case-insensitively: `a` to `l`, the pairs `bc`, `de`, `hl` and
+/// `sp`, and `pc`.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+enum RegisterName {
+    Single(Register),
+    Pair(RegisterPair),
+    Pc,
+}
+
+impl RegisterName {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "a" => RegisterName::Single(Register::A),
+            "b" => RegisterName::Single(Register::B),
+            "c" => RegisterName::Single(Register::C),
+            "d" => RegisterName::Single(Register::D),
+            "e" => RegisterName::Single(Register::E),
+            "h" => RegisterName::Single(Register::H),
+            "l" => RegisterName::Single(Register::L),
+            "bc" => RegisterName::Pair(RegisterPair::Bc),
+            "de" => RegisterName::Pair(RegisterPair::De),
+            "hl" => RegisterName::Pair(RegisterPair::Hl),
+            "sp" => RegisterName::Pair(RegisterPair::Sp),
+            "pc" => RegisterName::Pc,
+            _ => return None,
+        })
+    }
+
+    fn get(self, machine: &Machine) -> u16 {
+        match self {
+            RegisterName::Single(register) => machine.register_8(register) as u16,
+            RegisterName::Pair(pair) => machine.register_16(pair).value(),
+            RegisterName::Pc => machine.pc().value(),
+        }
+    }
+
+    /// Set the register, failing if `value` doesn't fit in it.
+    fn set(self, machine: &mut Machine, value: u16) -> Option<()> {
+        match self {
+            RegisterName::Single(register) => {
+                machine.set_register_8(register, u8::try_from(value).ok()?)
+            }
+            RegisterName::Pair(pair) => machine.set_register_16(pair, value.into()),
+            RegisterName::Pc => machine.set_pc(value.into()),
+        }
+        Some(())
+    }
+}
+
+fn register_name(name: &str) -> PyResult<RegisterName> {
+    RegisterName::parse(name)
+        .ok_or_else(|| PyValueError::new_err(format!("unknown register '{}'", name)))
+}
+
+fn build_error(err: BuildError) -> PyErr {
+    match err {
+        BuildError::Assembly(message) => AssemblyError::new_err(message),
+        err => PyValueError::new_err(err.to_string()),
+    }
+}
+
+/// Assemble `source` into its bytes, origin and labels.
+fn assemble_source(source: &str) -> Result<(Vec<u8>, Address, Vec<(String, Address)>), String> {
+    let (items, origin, labels) = assembler::parse_assembly_with_labels(source.as_bytes())?;
+    let mut bytes = Vec::new();
+    coding::encode_program(&mut bytes, &items).expect("writing to Vec can't error");
+    Ok((bytes, origin, labels))
+}
+
+fn disassembly(bytes: &[u8], origin: Address) -> Vec<ListingLine> {
+    Listing::new(bytes, origin, &[], &Symbols::new()).lines(8)
+}
+
+/// An emulated Intel 8080 for use from Python.
+#[pyclass(module = "leben", name = "Machine")]
+pub struct PyMachine {
+    machine: Machine,
+}
+
+#[pymethods]
+impl PyMachine {
+    /// Create a machine with empty memory.
+    #[new]
+    fn new() -> Self {
+        Self {
+            machine: Machine::new(),
+        }
+    }
+
+    /// Replace the machine with one running `program` loaded at `origin`.
+    #[pyo3(signature = (program, origin = 0))]
+    fn load_bytes(&mut self, program: &[u8], origin: Address) -> PyResult<()> {
+        self.machine = MachineBuilder::new()
+            .program(program, origin)
+            .build()
+            .map_err(build_error)?;
+        Ok(())
+    }
+
+    /// Replace the machine with one running the assembled `source`.
+    fn load_assembly(&mut self, source: &str) -> PyResult<()> {
+        self.machine = MachineBuilder::new()
+            .assembly(source.as_bytes())
+            .build()
+            .map_err(build_error)?;
+        Ok(())
+    }
+
+    /// Execute a single instruction. Returns whether the machine is still running.
+    fn step(&mut self) -> bool {
+        self.machine.step();
+        self.running()
+    }
+
+    /// Execute up to `budget` instructions, stopping early if the machine halts. Returns the
+    /// number of executed instructions. Other Python threads run in the meantime.
+    fn run(&mut self, py: Python<'_>, budget: usize) -> usize {
+        let machine = &mut self.machine;
+        py.allow_threads(|| machine.steps().take(budget).count())
+    }
+
+    #[getter]
+    fn running(&self) -> bool {
+        self.machine.state() == MachineState::Running
+    }
+
+    /// Why the machine halted, or `None` while it's running.
+    #[getter]
+    fn halt_reason(&self) -> Option<HaltReason> {
+        match self.machine.state() {
+            MachineState::Running | MachineState::WaitingForInput => None,
+            MachineState::Halted(reason) => Some(reason.into()),
+        }
+    }
+
+    /// Value of a register by name, e.g. `"a"`, `"hl"` or `"pc"`.
+    fn register(&self, name: &str) -> PyResult<u16> {
+        Ok(register_name(name)?.get(&self.machine))
+    }
+
+    fn set_register(&mut self, name: &str, value: u16) -> PyResult<()> {
+        register_name(name)?
+            .set(&mut self.machine, value)
+            .ok_or_else(|| PyValueError::new_err(format!("{} doesn't fit in {}", value, name)))
+    }
+
+    /// The flags as a dict from `"sign"`, `"zero"`, `"auxiliary_carry"`, `"parity"` and
+    /// `"carry"` to booleans.
+    fn flags(&self) -> BTreeMap<&'static str, bool> {
+        let conditions = self.machine.conditions();
+        [
+            ("sign", ConditionRegister::Sign),
+            ("zero", ConditionRegister::Zero),
+            ("auxiliary_carry", ConditionRegister::AuxiliaryCarry),
+            ("parity", ConditionRegister::Parity),
+            ("carry", ConditionRegister::Carry),
+        ]
+        .into_iter()
+        .map(|(name, condition)| (name, conditions.get(condition)))
+        .collect()
+    }
+
+    /// Copy `length` bytes of memory starting at `start`. The range is cut off at the end of the
+    /// address space.
+    fn read_memory<'py>(
+        &self,
+        py: Python<'py>,
+        start: Address,
+        length: usize,
+    ) -> Bound<'py, PyBytes> {
+        let memory = &self.machine.memory().as_raw()[start as usize..];
+        PyBytes::new(py, &memory[..length.min(memory.len())])
+    }
+
+    /// Copy `data` into memory starting at `start`.
+    fn write_memory(&mut self, start: Address, data: &[u8]) -> PyResult<()> {
+        self.machine
+            .memory_mut()
+            .write_slice(start, data)
+            .ok_or_else(|| {
+                PyValueError::new_err(format!(
+                    "{} bytes at 0x{:04X} don't fit in memory",
+                    data.len(),
+                    start
+                ))
+            })
+    }
+
+    /// Queue bytes to be read by `IN 0`. Reading from an empty queue halts the machine.
+    fn push_input(&mut self, data: &[u8]) {
+        self.machine.push_input(data);
+    }
+
+    /// Take all output written by the program since the last call.
+    fn drain_output<'py>(&mut self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.machine.take_output())
+    }
+}
+
+/// Assemble `source`, returning `(bytes, origin, symbols)` with `symbols` a dict from label to
+/// address.
+#[pyfunction]
+fn assemble<'py>(
+    py: Python<'py>,
+    source: &str,
+) -> PyResult<(Bound<'py, PyBytes>, Address, BTreeMap<String, Address>)> {
+    let (bytes, origin, labels) = assemble_source(source).map_err(AssemblyError::new_err)?;
+    Ok((
+        PyBytes::new(py, &bytes),
+        origin,
+        labels.into_iter().collect(),
+    ))
+}
+
+/// Disassemble `program` placed at `origin` into a list of `(address, bytes, label, statement)`
+/// tuples, following the control flow from `origin`. `label` is `None` where there is none.
+#[pyfunction]
+#[pyo3(signature = (program, origin = 0))]
+fn disassemble<'py>(
+    py: Python<'py>,
+    program: &[u8],
+    origin: Address,
+) -> Vec<(Address, Bound<'py, PyBytes>, Option<String>, String)> {
+    disassembly(program, origin)
+        .into_iter()
+        .map(|line| {
+            (
+                line.address,
+                PyBytes::new(py, &line.bytes),
+                line.label,
+                line.statement,
+            )
+        })
+        .collect()
+}
+
+#[pymodule]
+fn leben(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyMachine>()?;
+    module.add_class::<HaltReason>()?;
+    module.add("AssemblyError", module.py().get_type::<AssemblyError>())?;
+    module.add_function(wrap_pyfunction!(assemble, module)?)?;
+    module.add_function(wrap_pyfunction!(disassemble, module)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_names() {
+        assert_eq!(
+            RegisterName::parse("A"),
+            Some(RegisterName::Single(Register::A))
+        );
+        assert_eq!(
+            RegisterName::parse("hl"),
+            Some(RegisterName::Pair(RegisterPair::Hl))
+        );
+        assert_eq!(RegisterName::parse("Pc"), Some(RegisterName::Pc));
+        assert_eq!(RegisterName::parse("m"), None);
+        assert_eq!(RegisterName::parse("psw"), None);
+    }
+
+    #[test]
+    fn registers_round_trip() {
+        let mut machine = Machine::new();
+        let hl = RegisterName::parse("hl").unwrap();
+        let h = RegisterName::parse("h").unwrap();
+        assert_eq!(hl.set(&mut machine, 0x1234), Some(()));
+        assert_eq!(h.get(&machine), 0x12);
+        assert_eq!(h.set(&mut machine, 0x100), None);
+        assert_eq!(hl.get(&machine), 0x1234);
+    }
+
+    #[test]
+    fn halt_reasons() {
+        assert_eq!(
+            HaltReason::from(machine::HaltReason::StackUnderflow),
+            HaltReason::StackUnderflow
+        );
+    }
+
+    #[test]
+    fn assemble_and_disassemble() {
+        let (bytes, origin, labels) =
+            assemble_source("        ORG 10H\nLOOP:   JMP LOOP\n        END\n").unwrap();
+        assert_eq!(bytes, [0xC3, 0x10, 0x00]);
+        assert_eq!(origin, 0x0010);
+        assert_eq!(labels, [(String::from("LOOP"), 0x0010)]);
+
+        assert_eq!(
+            disassembly(&bytes, origin),
+            [ListingLine {
+                address: 0x0010,
+                bytes: vec![0xC3, 0x10, 0x00],
+                label: Some(String::from("L0010")),
+                statement: String::from("JMP L0010"),
+            }]
+        );
+        assert!(assemble_source("        FOO\n        END\n").is_err());
+    }
+}
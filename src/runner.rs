@@ -0,0 +1,248 @@
+//! Running many independent programs in parallel, e.g. a directory of test programs.
+//!
+//! Every [`ProgramJob`] gets its own [`Machine`](crate::machine::Machine), built and run on one of
+//! a fixed number of worker threads. [`run_batch`] returns a [`ProgramReport`] per job, in the
+//! order of the jobs, and [`Summary`] formats them as a pass/fail table.
+
+use std::{
+    fmt::Display,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::machine::{BuildError, HaltReason, MachineBuilder, MachineState};
+
+/// Instruction budget of a job unless [`ProgramJob::budget`] is given.
+pub const DEFAULT_BUDGET: usize = 10_000_000;
+
+/// A program to run, see [`run_batch`].
+#[derive(Clone, Debug)]
+pub struct ProgramJob {
+    pub name: String,
+    pub machine: MachineBuilder,
+    /// Most instructions to execute before giving up.
+    pub budget: usize,
+    /// Output the program must produce to pass. Any output passes if `None`.
+    pub expected: Option<Vec<u8>>,
+}
+
+impl ProgramJob {
+    pub fn new(name: impl Into<String>, machine: MachineBuilder) -> Self {
+        Self {
+            name: name.into(),
+            machine,
+            budget: DEFAULT_BUDGET,
+            expected: None,
+        }
+    }
+
+    pub fn budget(mut self, instructions: usize) -> Self {
+        self.budget = instructions;
+        self
+    }
+
+    pub fn expected(mut self, output: &[u8]) -> Self {
+        self.expected = Some(output.to_owned());
+        self
+    }
+}
+
+/// Outcome of a [`ProgramJob`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramReport {
+    pub name: String,
+    /// State of the machine after the run, or why it couldn't be built.
+    pub result: Result<MachineState, BuildError>,
+    /// Number of executed instructions.
+    pub instructions: usize,
+    /// Wall time of building and running the machine.
+    pub elapsed: Duration,
+    /// Everything the program wrote to its output ports.
+    pub output: Vec<u8>,
+    /// Copied from the job.
+    pub expected: Option<Vec<u8>>,
+}
+
+impl ProgramReport {
+    /// Whether the program stopped at a `HLT` with the expected output.
+    pub fn passed(&self) -> bool {
+        self.failure().is_none()
+    }
+
+    /// Why the program didn't pass, or `None` if it did.
+    pub fn failure(&self) -> Option<String> {
+        match &self.result {
+            Err(err) => Some(err.to_string()),
+            Ok(MachineState::Running) => Some(format!(
+                "still running after {} instructions",
+                self.instructions
+            )),
+            Ok(MachineState::WaitingForInput) => Some(String::from("waiting for input")),
+            Ok(MachineState::Halted(HaltReason::HaltInstruction)) => match &self.expected {
+                Some(expected) if *expected != self.output => {
+                    Some(String::from("output differs from the expected output"))
+                }
+                _ => None,
+            },
+            Ok(MachineState::Halted(reason)) => Some(format!("halted: {}", reason)),
+        }
+    }
+}
+
+fn run_job(job: ProgramJob) -> ProgramReport {
+    let start = Instant::now();
+    let (result, instructions, output) = match job.machine.build() {
+        Ok(mut machine) => {
+            let (_, instructions) = machine.run_until_halt(job.budget as u64);
+            (Ok(machine.state()), instructions as usize, machine.take_output())
+        }
+        Err(err) => (Err(err), 0, Vec::new()),
+    };
+    ProgramReport {
+        name: job.name,
+        result,
+        instructions,
+        elapsed: start.elapsed(),
+        output,
+        expected: job.expected,
+    }
+}
+
+/// Run `jobs` on `parallelism` threads (at least one), returning their reports in the same order.
+pub fn run_batch(jobs: Vec<ProgramJob>, parallelism: usize) -> Vec<ProgramReport> {
+    let count = jobs.len();
+    let queue = Mutex::new(jobs.into_iter().enumerate());
+    let reports: Mutex<Vec<Option<ProgramReport>>> = Mutex::new(vec![None; count]);
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.clamp(1, count.max(1)) {
+            scope.spawn(|| {
+                loop {
+                    // Take the next job without holding the lock while running it.
+                    let next = queue.lock().unwrap().next();
+                    let Some((index, job)) = next else {
+                        break;
+                    };
+                    let report = run_job(job);
+                    reports.lock().unwrap()[index] = Some(report);
+                }
+            });
+        }
+    });
+
+    reports
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|report| report.expect("every job is run"))
+        .collect()
+}
+
+/// Table with one line per report and a total, for printing.
+pub struct Summary<'a>(pub &'a [ProgramReport]);
+
+impl Display for Summary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name_width = self
+            .0
+            .iter()
+            .map(|report| report.name.len())
+            .max()
+            .unwrap_or(0);
+        for report in self.0 {
+            let failure = report.failure();
+            write!(
+                f,
+                "{}  {:name_width$}  {:>10} instructions  {:>10.2?}",
+                if failure.is_none() { "PASS" } else { "FAIL" },
+                report.name,
+                report.instructions,
+                report.elapsed,
+                name_width = name_width,
+            )?;
+            match failure {
+                Some(failure) => writeln!(f, "  {}", failure)?,
+                None => writeln!(f)?,
+            }
+        }
+        let passed = self.0.iter().filter(|report| report.passed()).count();
+        writeln!(
+            f,
+            "{} programs, {} passed, {} failed",
+            self.0.len(),
+            passed,
+            self.0.len() - passed
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(name: &str, program: &[u8]) -> ProgramJob {
+        ProgramJob::new(name, MachineBuilder::new().program(program, 0x0000))
+    }
+
+    #[test]
+    fn batch() {
+        let jobs = vec![
+            // MVI A, 'A'; OUT 0; HLT
+            job("print", &[0x3E, b'A', 0xD3, 0x00, 0x76]).expected(b"A"),
+            // JMP 0000H
+            job("spin", &[0xC3, 0x00, 0x00]).budget(1000),
+            // IN 0; OUT 0; HLT
+            ProgramJob::new(
+                "echo",
+                MachineBuilder::new()
+                    .program(&[0xDB, 0x00, 0xD3, 0x00, 0x76], 0x0000)
+                    .input(b"x"),
+            )
+            .expected(b"x")
+            .budget(10),
+        ];
+
+        let reports = run_batch(jobs, 2);
+        let names: Vec<&str> = reports.iter().map(|report| report.name.as_str()).collect();
+        assert_eq!(names, ["print", "spin", "echo"]);
+
+        assert_eq!(
+            reports[0].result,
+            Ok(MachineState::Halted(HaltReason::HaltInstruction))
+        );
+        assert_eq!(reports[0].instructions, 3);
+        assert_eq!(reports[0].output, b"A");
+        assert!(reports[0].passed());
+
+        assert_eq!(reports[1].result, Ok(MachineState::Running));
+        assert_eq!(reports[1].instructions, 1000);
+        assert_eq!(
+            reports[1].failure().as_deref(),
+            Some("still running after 1000 instructions")
+        );
+
+        assert_eq!(reports[2].instructions, 3);
+        assert_eq!(reports[2].output, b"x");
+        assert!(reports[2].passed());
+
+        let summary = Summary(&reports).to_string();
+        assert!(summary.starts_with("PASS  print"));
+        assert!(summary.contains("FAIL  spin "));
+        assert!(summary.ends_with("3 programs, 2 passed, 1 failed\n"));
+    }
+
+    #[test]
+    fn wrong_output_fails() {
+        let reports = run_batch(vec![job("halt", &[0x76]).expected(b"?")], 4);
+        assert_eq!(
+            reports[0].failure().as_deref(),
+            Some("output differs from the expected output")
+        );
+    }
+
+    #[test]
+    fn empty_batch() {
+        assert!(run_batch(Vec::new(), 0).is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+//! Helpers for tests that compare machines, e.g. the emulator against a reference or a run
+//! against the same run resumed from a save state.
+//!
+//! [`diff_machines`] lists everything that differs between two machines, and its [`Display`]
+//! output is meant for assertion messages:
+//!
+//! ```text
+//! A: 12 vs 34
+//! Z flag: 1 vs 0
+//! memory 0100-0102: 01 02 03 vs AA BB CC
+//! ```
+
+use std::{
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+};
+
+use crate::{
+    instruction::{Address, Register, RegisterPair},
+    machine::{ConditionRegister, Machine},
+};
+
+/// Bytes of a memory range shown before the rest is summarized.
+const MEMORY_PREVIEW_BYTES: usize = 16;
+/// Memory ranges shown before the rest are summarized.
+const MEMORY_PREVIEW_RANGES: usize = 8;
+
+const REGISTERS: [(&str, Register); 7] = [
+    ("A", Register::A),
+    ("B", Register::B),
+    ("C", Register::C),
+    ("D", Register::D),
+    ("E", Register::E),
+    ("H", Register::H),
+    ("L", Register::L),
+];
+
+const FLAGS: [(&str, ConditionRegister); 5] = [
+    ("S", ConditionRegister::Sign),
+    ("Z", ConditionRegister::Zero),
+    ("AC", ConditionRegister::AuxiliaryCarry),
+    ("P", ConditionRegister::Parity),
+    ("CY", ConditionRegister::Carry),
+];
+
+/// A named value that differs between the two machines.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Difference<T> {
+    pub name: &'static str,
+    pub left: T,
+    pub right: T,
+}
+
+/// A run of consecutive bytes that differ between the two memories.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct MemoryDifference {
+    pub start: Address,
+    pub left: Vec<u8>,
+    pub right: Vec<u8>,
+}
+
+impl MemoryDifference {
+    /// Address of the last differing byte.
+    pub fn end(&self) -> Address {
+        self.start + (self.left.len() - 1) as Address
+    }
+}
+
+/// Everything that differs between two machines, see [`diff_machines`].
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct StateDiff {
+    /// The 8-bit registers, A first.
+    pub registers: Vec<Difference<u8>>,
+    pub flags: Vec<Difference<bool>>,
+    /// The program counter and the stack pointer.
+    pub pointers: Vec<Difference<u16>>,
+    pub memory: Vec<MemoryDifference>,
+    colored: bool,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+            && self.flags.is_empty()
+            && self.pointers.is_empty()
+            && self.memory.is_empty()
+    }
+
+    /// Show the values of the left machine in red and those of the right one in green when
+    /// displayed.
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+
+    fn write_pair(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        left: impl Display,
+        right: impl Display,
+    ) -> fmt::Result {
+        if self.colored {
+            write!(f, "\x1b[31m{}\x1b[0m vs \x1b[32m{}\x1b[0m", left, right)
+        } else {
+            write!(f, "{} vs {}", left, right)
+        }
+    }
+}
+
+/// Bytes as hexadecimal separated by spaces, cut off after [`MEMORY_PREVIEW_BYTES`].
+struct Preview<'a>(&'a [u8]);
+
+impl Display for Preview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().take(MEMORY_PREVIEW_BYTES).enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        if self.0.len() > MEMORY_PREVIEW_BYTES {
+            write!(f, " ...")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no differences");
+        }
+        for difference in &self.registers {
+            write!(f, "{}: ", difference.name)?;
+            self.write_pair(
+                f,
+                format_args!("{:02X}", difference.left),
+                format_args!("{:02X}", difference.right),
+            )?;
+            writeln!(f)?;
+        }
+        for difference in &self.flags {
+            write!(f, "{} flag: ", difference.name)?;
+            self.write_pair(f, difference.left as u8, difference.right as u8)?;
+            writeln!(f)?;
+        }
+        for difference in &self.pointers {
+            write!(f, "{}: ", difference.name)?;
+            self.write_pair(
+                f,
+                format_args!("{:04X}", difference.left),
+                format_args!("{:04X}", difference.right),
+            )?;
+            writeln!(f)?;
+        }
+        for difference in self.memory.iter().take(MEMORY_PREVIEW_RANGES) {
+            write!(
+                f,
+                "memory {:04X}-{:04X}: ",
+                difference.start,
+                difference.end()
+            )?;
+            self.write_pair(f, Preview(&difference.left), Preview(&difference.right))?;
+            writeln!(f)?;
+        }
+        if self.memory.len() > MEMORY_PREVIEW_RANGES {
+            writeln!(
+                f,
+                "... and {} more memory ranges",
+                self.memory.len() - MEMORY_PREVIEW_RANGES
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare the registers, flags, program counter, stack pointer and the 64 KiB of addressable
+/// memory of two machines.
+pub fn diff_machines(left: &Machine, right: &Machine) -> StateDiff {
+    let mut diff = StateDiff::default();
+
+    for (name, register) in REGISTERS {
+        let (left, right) = (left.register_8(register), right.register_8(register));
+        if left != right {
+            diff.registers.push(Difference { name, left, right });
+        }
+    }
+    for (name, flag) in FLAGS {
+        let (left, right) = (left.conditions().get(flag), right.conditions().get(flag));
+        if left != right {
+            diff.flags.push(Difference { name, left, right });
+        }
+    }
+    let pointers = [
+        ("PC", left.pc().value(), right.pc().value()),
+        (
+            "SP",
+            left.register_16(RegisterPair::Sp).value(),
+            right.register_16(RegisterPair::Sp).value(),
+        ),
+    ];
+    for (name, left, right) in pointers {
+        if left != right {
+            diff.pointers.push(Difference { name, left, right });
+        }
+    }
+
+    let left = &left.memory().as_raw()[..=Address::MAX as usize];
+    let right = &right.memory().as_raw()[..=Address::MAX as usize];
+    let mut address = 0;
+    while address < left.len() {
+        if left[address] == right[address] {
+            address += 1;
+            continue;
+        }
+        let start = address;
+        while address < left.len() && left[address] != right[address] {
+            address += 1;
+        }
+        diff.memory.push(MemoryDifference {
+            start: start as Address,
+            left: left[start..address].to_vec(),
+            right: right[start..address].to_vec(),
+        });
+    }
+
+    diff
+}
+
+/// Panic with the differences if the machines aren't in the same state. The values are colored
+/// when stderr, where the panic message goes, is a terminal.
+#[track_caller]
+pub fn assert_machines_eq(left: &Machine, right: &Machine) {
+    let diff = diff_machines(left, right);
+    if !diff.is_empty() {
+        panic!(
+            "machines differ, left vs right:\n{}",
+            diff.colored(io::stderr().is_terminal())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    fn machine() -> Machine {
+        MachineBuilder::new()
+            .program(&[0x3E, 0x12, 0x76], 0x0000)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn identical() {
+        let diff = diff_machines(&machine(), &machine());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no differences\n");
+        assert_machines_eq(&machine(), &machine());
+    }
+
+    #[test]
+    fn register_flag_and_memory() {
+        let left = machine();
+        let mut right = machine();
+        right.set_register_8(Register::A, 0x34);
+        let mut status = right.get_status_word();
+        status.low |= 0b0100_0000;
+        right.set_status_word(status);
+        right.memory_mut().write_slice(0x0100, &[0xAA, 0xBB, 0xCC]);
+
+        let diff = diff_machines(&left, &right);
+        assert_eq!(
+            diff.registers,
+            [Difference {
+                name: "A",
+                left: 0x00,
+                right: 0x34
+            }]
+        );
+        assert_eq!(diff.memory.len(), 1);
+        assert_eq!(diff.memory[0].end(), 0x0102);
+        assert_eq!(
+            diff.to_string(),
+            "A: 00 vs 34\nZ flag: 0 vs 1\nmemory 0100-0102: 00 00 00 vs AA BB CC\n"
+        );
+        assert_eq!(
+            diff.colored(true).to_string().lines().next(),
+            Some("A: \x1b[31m00\x1b[0m vs \x1b[32m34\x1b[0m")
+        );
+    }
+
+    #[test]
+    fn long_memory_differences_are_capped() {
+        let left = machine();
+        let mut right = machine();
+        right.set_pc(0x0002.into());
+        right.memory_mut().write_slice(0x1000, &[0xFF; 20]);
+        for range in 0..10 {
+            right.memory_mut().write_8(0x2000 + range * 2, 1);
+        }
+
+        let text = diff_machines(&left, &right).to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "PC: 0000 vs 0002");
+        assert_eq!(
+            lines[1],
+            "memory 1000-1013: 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 ... vs \
+             FF FF FF FF FF FF FF FF FF FF FF FF FF FF FF FF ..."
+        );
+        assert_eq!(lines.len(), 1 + 8 + 1);
+        assert_eq!(lines[9], "... and 3 more memory ranges");
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    fmt::{Display, Write as _},
+    io::{self, Write},
+};
+
+use crate::{
+    instruction::{Address, Instruction, RegisterPair},
+    machine::{ExecutionObserver, Machine},
+};
+
+mod jsonl;
+mod replay;
+
+pub use jsonl::{JsonTraceWriter, TraceRecord};
+pub use replay::{RecordedTrace, Replay, TraceReadError};
+
+/// Number of matching lines shown before the first difference in a [`Divergence`].
+const CONTEXT_LINES: usize = 3;
+
+/// A column of a trace line.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum TraceField {
+    /// `PC=0100`
+    Pc,
+    /// Accumulator and flags byte, `AF=02 02`
+    Af,
+    /// `BC=0000`
+    Bc,
+    /// `DE=0000`
+    De,
+    /// `HL=0000`
+    Hl,
+    /// `SP=FFFE`
+    Sp,
+    /// Mnemonic of the instruction about to be executed, `MVI A,2AH`
+    Instruction,
+}
+
+impl TraceField {
+    pub const ALL: [TraceField; 7] = [
+        TraceField::Pc,
+        TraceField::Af,
+        TraceField::Bc,
+        TraceField::De,
+        TraceField::Hl,
+        TraceField::Sp,
+        TraceField::Instruction,
+    ];
+}
+
+/// Format the trace line for the instruction at the program counter of `machine`.
+///
+/// With all fields the line looks like
+/// `PC=0100 AF=02 02 BC=0000 DE=0000 HL=0000 SP=FFFE  MVI A,2AH`.
+pub fn format_line(
+    machine: &Machine,
+    instruction: Option<&Instruction>,
+    fields: &[TraceField],
+) -> String {
+    let mut line = String::new();
+    for field in fields {
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        let _ = match field {
+            TraceField::Pc => write!(line, "PC={:04X}", machine.pc().value()),
+            TraceField::Af => {
+                let status = machine.get_status_word();
+                write!(line, "AF={:02X} {:02X}", status.high, status.low)
+            }
+            TraceField::Bc => write!(
+                line,
+                "BC={:04X}",
+                machine.register_16(RegisterPair::Bc).value()
+            ),
+            TraceField::De => write!(
+                line,
+                "DE={:04X}",
+                machine.register_16(RegisterPair::De).value()
+            ),
+            TraceField::Hl => write!(
+                line,
+                "HL={:04X}",
+                machine.register_16(RegisterPair::Hl).value()
+            ),
+            TraceField::Sp => write!(
+                line,
+                "SP={:04X}",
+                machine.register_16(RegisterPair::Sp).value()
+            ),
+            TraceField::Instruction => {
+                // The mnemonic is separated from the registers by two spaces.
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                match instruction {
+                    Some(instruction) => write!(line, "{}", instruction),
+                    None => write!(line, "???"),
+                }
+            }
+        };
+    }
+    line
+}
+
+/// Format the log record of an executed instruction: its address, bytes and mnemonic in columns
+/// of fixed width, followed by the registers of `machine` after executing it, like
+/// `0100  3E 2A     MVI A,2AH        AF=2A 02 BC=0000 DE=0000 HL=0000 SP=FFFE`.
+///
+/// The registers are formatted like [`format_line`] does, so the log and trace files agree.
+pub fn format_executed(
+    pc: Address,
+    bytes: &[u8],
+    instruction: Option<&Instruction>,
+    machine: &Machine,
+) -> String {
+    let mut hex = String::new();
+    for byte in bytes {
+        if !hex.is_empty() {
+            hex.push(' ');
+        }
+        let _ = write!(hex, "{:02X}", byte);
+    }
+    let mnemonic = match instruction {
+        Some(instruction) => instruction.to_string(),
+        None => "???".into(),
+    };
+    let registers = format_line(machine, None, &TraceField::ALL[1..6]);
+    format!("{:04X}  {:<8}  {:<16} {}", pc, hex, mnemonic, registers)
+}
+
+/// Execution observer writing one trace line per instruction, before it's executed.
+///
+/// Writing stops at the first I/O error, which can be retrieved with [`TraceWriter::error`].
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    fields: Vec<TraceField>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Create a trace writer emitting all fields.
+    pub fn new(writer: W) -> Self {
+        Self::with_fields(writer, &TraceField::ALL)
+    }
+
+    /// Create a trace writer emitting only `fields`, in the given order.
+    pub fn with_fields(writer: W, fields: &[TraceField]) -> Self {
+        Self {
+            writer,
+            fields: fields.to_vec(),
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send + 'static> ExecutionObserver for TraceWriter<W> {
+    fn before_step(&mut self, machine: &Machine, instruction: Option<&Instruction>) {
+        if self.error.is_some() {
+            return;
+        }
+        let line = format_line(machine, instruction, &self.fields);
+        if let Err(err) = writeln!(self.writer, "{}", line) {
+            self.error = Some(err);
+        }
+    }
+}
+
+/// The first point where two traces differ, as reported by [`compare_traces`].
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Divergence {
+    /// Line number of the first differing line, starting at 1.
+    pub line: usize,
+    /// The line in the first trace, or `None` if it ended before the second one.
+    pub left: Option<String>,
+    /// The line in the second trace, or `None` if it ended before the first one.
+    pub right: Option<String>,
+    /// The matching lines directly preceding the divergence.
+    pub context: Vec<String>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Traces diverge at line {}:", self.line)?;
+        for (offset, line) in self.context.iter().enumerate() {
+            let number = self.line - self.context.len() + offset;
+            writeln!(f, "  {:>6}  {}", number, line)?;
+        }
+        writeln!(
+            f,
+            "< {:>6}  {}",
+            self.line,
+            self.left.as_deref().unwrap_or("<end of trace>")
+        )?;
+        write!(
+            f,
+            "> {:>6}  {}",
+            self.line,
+            self.right.as_deref().unwrap_or("<end of trace>")
+        )
+    }
+}
+
+/// Compare two traces line by line, returning the first line where they differ. Trailing
+/// whitespace on each line is ignored. Returns `None` if the traces are identical.
+pub fn compare_traces(a: &str, b: &str) -> Option<Divergence> {
+    let mut left = a.lines().map(str::trim_end);
+    let mut right = b.lines().map(str::trim_end);
+    let mut context = Vec::new();
+    let mut line = 1;
+
+    loop {
+        match (left.next(), right.next()) {
+            (None, None) => return None,
+            (l, r) if l == r => {
+                if context.len() == CONTEXT_LINES {
+                    context.remove(0);
+                }
+                context.push(l.unwrap_or_default().to_owned());
+            }
+            (l, r) => {
+                return Some(Divergence {
+                    line,
+                    left: l.map(str::to_owned),
+                    right: r.map(str::to_owned),
+                    context,
+                });
+            }
+        }
+        line += 1;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::machine::MachineBuilder;
+
+    /// Writer whose contents can be read back after handing it to a machine.
+    #[derive(Clone, Default)]
+    pub(crate) struct SharedBuffer(pub(crate) Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 0100: MVI A, 2AH
+    // 0102: LXI B, 1234H
+    // 0105: INR A
+    // 0106: HLT
+    const PROGRAM: [u8; 7] = [0x3E, 0x2A, 0x01, 0x34, 0x12, 0x3C, 0x76];
+
+    fn trace(fields: &[TraceField]) -> String {
+        let buffer = SharedBuffer::default();
+        let mut machine = MachineBuilder::new()
+            .program(&PROGRAM, 0x0100)
+            .sp(0xFFFE)
+            .build()
+            .unwrap();
+        machine.add_observer(Box::new(TraceWriter::with_fields(buffer.clone(), fields)));
+        machine.steps().for_each(drop);
+
+        String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn trace_first_lines() {
+        let trace = trace(&TraceField::ALL);
+        let lines: Vec<&str> = trace.lines().take(3).collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "PC=0100 AF=00 02 BC=0000 DE=0000 HL=0000 SP=FFFE  MVI A,2AH",
+                "PC=0102 AF=2A 02 BC=0000 DE=0000 HL=0000 SP=FFFE  LXI B,1234H",
+                "PC=0105 AF=2A 02 BC=1234 DE=0000 HL=0000 SP=FFFE  INR A",
+            ]
+        );
+        assert_eq!(trace.lines().count(), 4);
+    }
+
+    #[test]
+    fn trace_selected_fields() {
+        let trace = trace(&[TraceField::Pc, TraceField::Instruction]);
+
+        assert_eq!(
+            trace,
+            "PC=0100  MVI A,2AH\nPC=0102  LXI B,1234H\nPC=0105  INR A\nPC=0106  HLT\n"
+        );
+    }
+
+    #[test]
+    fn executed_columns() {
+        let mut machine = MachineBuilder::new()
+            .program(&PROGRAM, 0x0100)
+            .sp(0xFFFE)
+            .build()
+            .unwrap();
+        machine.step().unwrap();
+        let instruction = machine.load();
+        machine.step().unwrap();
+
+        assert_eq!(
+            format_executed(0x0102, &PROGRAM[2..5], instruction.as_ref(), &machine),
+            "0102  01 34 12  LXI B,1234H      AF=2A 02 BC=1234 DE=0000 HL=0000 SP=FFFE"
+        );
+        assert_eq!(
+            format_executed(0x0105, &[0xFF, 0xFF], None, &machine),
+            "0105  FF FF     ???              AF=2A 02 BC=1234 DE=0000 HL=0000 SP=FFFE"
+        );
+    }
+
+    #[test]
+    fn compare_identical() {
+        let trace = trace(&TraceField::ALL);
+        assert_eq!(compare_traces(&trace, &trace), None);
+    }
+
+    #[test]
+    fn compare_divergence() {
+        let trace = trace(&TraceField::ALL);
+        let perturbed = trace.replace("BC=1234", "BC=1235");
+
+        let divergence = compare_traces(&trace, &perturbed).unwrap();
+        assert_eq!(divergence.line, 3);
+        assert_eq!(
+            divergence.left.as_deref(),
+            Some("PC=0105 AF=2A 02 BC=1234 DE=0000 HL=0000 SP=FFFE  INR A")
+        );
+        assert_eq!(
+            divergence.right.as_deref(),
+            Some("PC=0105 AF=2A 02 BC=1235 DE=0000 HL=0000 SP=FFFE  INR A")
+        );
+        assert_eq!(divergence.context.len(), 2);
+        assert!(divergence.context[0].starts_with("PC=0100"));
+    }
+
+    #[test]
+    fn compare_truncated() {
+        let trace = trace(&TraceField::ALL);
+        let truncated: String = trace
+            .lines()
+            .take(2)
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        let divergence = compare_traces(&trace, &truncated).unwrap();
+        assert_eq!(divergence.line, 3);
+        assert!(divergence.left.is_some());
+        assert_eq!(divergence.right, None);
+    }
+}
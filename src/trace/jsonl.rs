@@ -0,0 +1,369 @@
+//! Traces with one JSON object per executed instruction, written by [`JsonTraceWriter`] and read
+//! back by [`RecordedTrace`](super::RecordedTrace).
+//!
+//! Every line records the CPU state before the instruction at `pc` is executed, and what executing
+//! it changed besides the registers:
+//!
+//! ```json
+//! {"bytes":"D5","conditions":{"auxiliary_carry":false,"carry":false,"parity":false,"sign":false,"zero":false},"output":"","pc":"0105","registers":{"a":"2A","b":"00","c":"00","d":"12","e":"34","h":"00","l":"00","sp":"FFFE"},"writes":{"FFFC":"34","FFFD":"12"}}
+//! ```
+//!
+//! - `pc`, `registers` and `conditions` are written like in the JSON state dump of
+//!   [`Machine::dump_json`], and `bytes` is the instruction, as a hexadecimal string.
+//! - `output` is the program output the instruction produced, as a hexadecimal string.
+//! - `writes` maps the addresses of the memory bytes the instruction changed to their new values.
+//!   It's optional: traces from other tools may leave it out, then only the registers of each
+//!   step can be replayed. Memory changed by I/O devices isn't recorded.
+
+use std::io::{self, Write};
+
+use serde_json::{Map, Value, json};
+
+use crate::{
+    instruction::{Address, Data8, Data16, Instruction, OPCODES, Register, RegisterPair},
+    machine::{
+        ConditionRegister, ExecutionObserver, Machine, StateJsonError, StepInfo,
+        json::{CONDITIONS, REGISTERS, bytes, field, hex, hex_bytes, invalid, object, parse_hex},
+    },
+};
+
+/// One line of a JSON trace.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: Address,
+    /// The instruction at `pc`, a single byte if it couldn't be decoded.
+    pub bytes: Vec<u8>,
+    /// A, B, C, D, E, H and L, in the order of the state dump.
+    registers: [Data8; 7],
+    sp: Address,
+    conditions: [bool; 5],
+    /// Program output produced by the instruction.
+    pub output: Vec<u8>,
+    /// Memory bytes changed by the instruction, by address, or `None` if the trace doesn't record
+    /// memory.
+    pub writes: Option<Vec<(Address, Data8)>>,
+}
+
+impl TraceRecord {
+    /// The state of `machine` before it executes `instruction`, with no output or writes yet.
+    fn capture(machine: &Machine, instruction: Option<&Instruction>) -> Self {
+        let pc = machine.pc().value();
+        let opcode = machine.memory().read_8(pc);
+        let length = match instruction {
+            Some(_) => OPCODES[opcode as usize].length,
+            None => 1,
+        };
+        Self {
+            pc,
+            bytes: (0..length as u16)
+                .map(|offset| machine.memory().read_8(pc.wrapping_add(offset)))
+                .collect(),
+            registers: REGISTERS.map(|(_, register)| machine.register_8(register)),
+            sp: machine.register_16(RegisterPair::Sp).value(),
+            conditions: CONDITIONS.map(|(_, condition)| machine.conditions().get(condition)),
+            output: Vec::new(),
+            writes: Some(Vec::new()),
+        }
+    }
+
+    /// The value of `register`, or `None` for M, which isn't recorded.
+    pub fn register(&self, register: Register) -> Option<Data8> {
+        REGISTERS
+            .iter()
+            .position(|(_, candidate)| *candidate == register)
+            .map(|index| self.registers[index])
+    }
+
+    pub fn sp(&self) -> Address {
+        self.sp
+    }
+
+    pub fn condition(&self, condition: ConditionRegister) -> bool {
+        let index = CONDITIONS
+            .iter()
+            .position(|(_, candidate)| *candidate == condition)
+            .expect("all conditions are recorded");
+        self.conditions[index]
+    }
+
+    /// Set the program counter, registers and flags of `machine` to the recorded ones.
+    pub fn apply(&self, machine: &mut Machine) {
+        machine.set_pc(Data16::from(self.pc));
+        for ((_, register), value) in REGISTERS.iter().zip(self.registers) {
+            machine.set_register_8(*register, value);
+        }
+        machine.set_register_16(RegisterPair::Sp, Data16::from(self.sp));
+        for ((_, condition), value) in CONDITIONS.iter().zip(self.conditions) {
+            machine.conditions_mut().set(*condition, value);
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let registers: Map<String, Value> = REGISTERS
+            .iter()
+            .zip(self.registers)
+            .map(|((name, _), value)| (name.to_string(), format!("{:02X}", value).into()))
+            .chain([(String::from("sp"), format!("{:04X}", self.sp).into())])
+            .collect();
+        let conditions: Map<String, Value> = CONDITIONS
+            .iter()
+            .zip(self.conditions)
+            .map(|((name, _), value)| (name.to_string(), value.into()))
+            .collect();
+
+        let mut value = json!({
+            "pc": format!("{:04X}", self.pc),
+            "bytes": hex_bytes(self.bytes.iter().copied()),
+            "registers": registers,
+            "conditions": conditions,
+            "output": hex_bytes(self.output.iter().copied()),
+        });
+        if let Some(writes) = &self.writes {
+            let writes: Map<String, Value> = writes
+                .iter()
+                .map(|(address, value)| {
+                    (format!("{:04X}", address), format!("{:02X}", value).into())
+                })
+                .collect();
+            value["writes"] = writes.into();
+        }
+        value
+    }
+
+    /// Inverse of [`TraceRecord::to_json`].
+    pub fn from_json(value: &Value) -> Result<Self, StateJsonError> {
+        let root = object(value, "record")?;
+
+        let registers = object(field(root, "registers")?, "registers")?;
+        let mut recorded = [0; 7];
+        for (slot, (name, _)) in recorded.iter_mut().zip(REGISTERS) {
+            *slot = hex(field(registers, name)?, name, 2)? as Data8;
+        }
+
+        let conditions = object(field(root, "conditions")?, "conditions")?;
+        let mut flags = [false; 5];
+        for (slot, (name, _)) in flags.iter_mut().zip(CONDITIONS) {
+            *slot = field(conditions, name)?
+                .as_bool()
+                .ok_or_else(|| invalid(format!("'{}' is not a boolean", name)))?;
+        }
+
+        let instruction = bytes(root, "bytes")?;
+        if instruction.is_empty() || instruction.len() > 3 {
+            return Err(invalid("'bytes' is not an instruction of 1 to 3 bytes"));
+        }
+
+        let writes = match root.get("writes") {
+            None => None,
+            Some(writes) => Some(
+                object(writes, "writes")?
+                    .iter()
+                    .map(|(address, value)| {
+                        let address = parse_hex(address, 4).ok_or_else(|| {
+                            invalid(format!("write to {} has an invalid address", address))
+                        })?;
+                        Ok((address, hex(value, "writes", 2)? as Data8))
+                    })
+                    .collect::<Result<Vec<_>, StateJsonError>>()?,
+            ),
+        };
+
+        Ok(Self {
+            pc: hex(field(root, "pc")?, "pc", 4)?,
+            bytes: instruction,
+            registers: recorded,
+            sp: hex(field(registers, "sp")?, "sp", 4)?,
+            conditions: flags,
+            output: bytes(root, "output")?,
+            writes,
+        })
+    }
+}
+
+/// Addresses `instruction` can write to when executed by `machine`. Instructions only write
+/// memory at BC, DE or HL, next to the stack pointer, or at the address of STA and SHLD.
+fn write_candidates(machine: &Machine, instruction: Option<&Instruction>) -> Vec<Address> {
+    let pair = |pair| machine.register_16(pair).value();
+    let sp = pair(RegisterPair::Sp);
+    let mut addresses = vec![
+        pair(RegisterPair::Bc),
+        pair(RegisterPair::De),
+        pair(RegisterPair::Hl),
+        sp.wrapping_sub(2),
+        sp.wrapping_sub(1),
+        sp,
+        sp.wrapping_add(1),
+    ];
+    if let Some(Instruction::Sta(address) | Instruction::Shld(address)) = instruction {
+        addresses.extend([*address, address.wrapping_add(1)]);
+    }
+    addresses.sort_unstable();
+    addresses.dedup();
+    addresses
+}
+
+/// An instruction whose record is completed once it has been executed.
+struct Pending {
+    record: TraceRecord,
+    /// Length of the program output before the instruction.
+    output_start: usize,
+    /// The memory the instruction can write to, with its contents before the instruction.
+    memory: Vec<(Address, Data8)>,
+}
+
+/// Execution observer writing one [`TraceRecord`] per instruction as a line of JSON, in the
+/// format of the [module documentation](self).
+///
+/// Writing stops at the first I/O error, which can be retrieved with [`JsonTraceWriter::error`].
+pub struct JsonTraceWriter<W: Write> {
+    writer: W,
+    pending: Option<Pending>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonTraceWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            pending: None,
+            error: None,
+        }
+    }
+
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send + 'static> ExecutionObserver for JsonTraceWriter<W> {
+    fn before_step(&mut self, machine: &Machine, instruction: Option<&Instruction>) {
+        if self.error.is_some() {
+            return;
+        }
+        self.pending = Some(Pending {
+            record: TraceRecord::capture(machine, instruction),
+            output_start: machine.output.len(),
+            memory: write_candidates(machine, instruction)
+                .into_iter()
+                .map(|address| (address, machine.memory().read_8(address)))
+                .collect(),
+        });
+    }
+
+    fn after_step(&mut self, machine: &Machine, _step: &StepInfo) {
+        let Some(Pending {
+            mut record,
+            output_start,
+            memory,
+        }) = self.pending.take()
+        else {
+            return;
+        };
+        record.output = machine
+            .output
+            .get(output_start..)
+            .unwrap_or_default()
+            .to_vec();
+        record.writes = Some(
+            memory
+                .into_iter()
+                .map(|(address, before)| (address, before, machine.memory().read_8(address)))
+                .filter(|(_, before, after)| before != after)
+                .map(|(address, _, after)| (address, after))
+                .collect(),
+        );
+
+        let result = serde_json::to_writer(&mut self.writer, &record.to_json())
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(self.writer));
+        if let Err(err) = result {
+            self.error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{machine::MachineBuilder, trace::tests::SharedBuffer};
+
+    // 0100: MVI A, 2AH
+    // 0102: LXI D, 1234H
+    // 0105: PUSH D
+    // 0106: OUT 0
+    // 0108: HLT
+    const PROGRAM: [u8; 9] = [0x3E, 0x2A, 0x11, 0x34, 0x12, 0xD5, 0xD3, 0x00, 0x76];
+
+    fn trace() -> Vec<Value> {
+        let buffer = SharedBuffer::default();
+        let mut machine = MachineBuilder::new()
+            .program(&PROGRAM, 0x0100)
+            .sp(0xFFFE)
+            .build()
+            .unwrap();
+        machine.add_observer(Box::new(JsonTraceWriter::new(buffer.clone())));
+        machine.steps().for_each(drop);
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        text.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn one_line_per_instruction() {
+        let lines = trace();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[2].to_string(),
+            concat!(
+                r#"{"bytes":"D5","conditions":{"auxiliary_carry":false,"carry":false,"parity":false,"sign":false,"zero":false},"#,
+                r#""output":"","pc":"0105","registers":{"a":"2A","b":"00","c":"00","d":"12","e":"34","h":"00","l":"00","sp":"FFFE"},"#,
+                r#""writes":{"FFFC":"34","FFFD":"12"}}"#,
+            )
+        );
+        assert_eq!(lines[1]["bytes"], "113412");
+        assert_eq!(lines[1]["writes"], json!({}));
+        assert_eq!(lines[3]["output"], "2A");
+    }
+
+    #[test]
+    fn records_round_trip() {
+        for line in trace() {
+            let record = TraceRecord::from_json(&line).unwrap();
+            assert_eq!(record.to_json(), line);
+        }
+    }
+
+    #[test]
+    fn writes_are_optional() {
+        let mut line = trace().remove(2);
+        line.as_object_mut().unwrap().remove("writes");
+
+        let record = TraceRecord::from_json(&line).unwrap();
+        assert_eq!(record.writes, None);
+        assert_eq!(record.register(Register::D), Some(0x12));
+        assert_eq!(record.register(Register::M), None);
+        assert_eq!(record.sp(), 0xFFFE);
+        assert_eq!(record.to_json(), line);
+    }
+
+    #[test]
+    fn invalid_records() {
+        let line = trace().remove(0);
+        let mut missing = line.clone();
+        missing.as_object_mut().unwrap().remove("pc");
+        assert_eq!(
+            TraceRecord::from_json(&missing).unwrap_err().to_string(),
+            "Invalid state: missing 'pc'"
+        );
+
+        let mut bytes = line;
+        bytes["bytes"] = json!("");
+        assert!(TraceRecord::from_json(&bytes).is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    fmt::Display,
+    io::{self, BufRead},
+};
+
+use serde_json::Value;
+
+use crate::{
+    instruction::{Address, Data8},
+    machine::{Machine, StateJsonError},
+};
+
+use super::TraceRecord;
+
+/// Error returned by [`RecordedTrace::read`].
+#[derive(Debug)]
+pub enum TraceReadError {
+    Io(io::Error),
+    /// Line `line`, starting at 1, isn't a trace record.
+    Record {
+        line: usize,
+        error: StateJsonError,
+    },
+    /// The trace has no records.
+    Empty,
+}
+
+impl Display for TraceReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceReadError::Io(err) => write!(f, "Couldn't read trace: {}", err),
+            TraceReadError::Record { line, error } => write!(f, "Line {}: {}", line, error),
+            TraceReadError::Empty => write!(f, "The trace has no records"),
+        }
+    }
+}
+
+impl std::error::Error for TraceReadError {}
+
+/// A JSON trace read into memory, with random access to the record of every step.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedTrace {
+    records: Vec<TraceRecord>,
+}
+
+impl RecordedTrace {
+    /// Read a trace in the format of [`JsonTraceWriter`](super::JsonTraceWriter). Empty lines are
+    /// skipped.
+    pub fn read(reader: impl BufRead) -> Result<Self, TraceReadError> {
+        let mut records = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(TraceReadError::Io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str::<Value>(&line)
+                .map_err(StateJsonError::Json)
+                .and_then(|value| TraceRecord::from_json(&value))
+                .map_err(|error| TraceReadError::Record {
+                    line: index + 1,
+                    error,
+                })?;
+            records.push(record);
+        }
+        if records.is_empty() {
+            return Err(TraceReadError::Empty);
+        }
+        Ok(Self { records })
+    }
+
+    /// Number of recorded steps, never 0.
+    pub fn steps(&self) -> usize {
+        self.records.len()
+    }
+
+    /// The record of the instruction executed at `step`, starting at 0.
+    pub fn record(&self, step: usize) -> Option<&TraceRecord> {
+        self.records.get(step)
+    }
+
+    pub fn records(&self) -> &[TraceRecord] {
+        &self.records
+    }
+
+    /// Whether every record lists its memory writes, so the memory of every step can be
+    /// reconstructed from the program image.
+    pub fn has_memory(&self) -> bool {
+        self.records.iter().all(|record| record.writes.is_some())
+    }
+}
+
+/// A machine moved forward and backward through the steps of a [`RecordedTrace`], without
+/// executing anything.
+///
+/// At every step the machine has the recorded registers and flags of the instruction about to be
+/// executed, the output of the earlier steps in [`Machine::output_buffer`], and, if the trace
+/// [has memory](RecordedTrace::has_memory), the memory it was created with plus the writes of the
+/// earlier steps. Otherwise the memory stays as it was.
+pub struct Replay {
+    trace: RecordedTrace,
+    machine: Machine,
+    step: usize,
+    /// For every step before the current one, the memory its writes replaced.
+    undo: Vec<Vec<(Address, Data8)>>,
+}
+
+impl Replay {
+    /// Start replaying `trace` at its first step, with `machine` holding the program image the
+    /// trace was recorded with.
+    pub fn new(trace: RecordedTrace, mut machine: Machine) -> Self {
+        trace.records[0].apply(&mut machine);
+        Self {
+            trace,
+            machine,
+            step: 0,
+            undo: Vec::new(),
+        }
+    }
+
+    pub fn trace(&self) -> &RecordedTrace {
+        &self.trace
+    }
+
+    /// The machine in the state of the current step.
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// The record of the current step.
+    pub fn record(&self) -> &TraceRecord {
+        &self.trace.records[self.step]
+    }
+
+    /// Move to the next step. Returns `false` without doing anything at the last step.
+    pub fn forward(&mut self) -> bool {
+        let Some(next) = self.trace.records.get(self.step + 1) else {
+            return false;
+        };
+        let record = &self.trace.records[self.step];
+        let memory = self.machine.memory_mut();
+        let mut replaced = Vec::new();
+        for &(address, value) in record.writes.iter().flatten() {
+            replaced.push((address, memory.read_8(address)));
+            memory.write_8(address, value);
+        }
+        self.undo.push(replaced);
+        self.machine.output.extend_from_slice(&record.output);
+
+        next.apply(&mut self.machine);
+        self.step += 1;
+        true
+    }
+
+    /// Move to the previous step. Returns `false` without doing anything at the first step.
+    pub fn back(&mut self) -> bool {
+        let Some(replaced) = self.undo.pop() else {
+            return false;
+        };
+        self.step -= 1;
+        let record = &self.trace.records[self.step];
+        let memory = self.machine.memory_mut();
+        // In reverse, in case a step wrote the same address twice.
+        for &(address, value) in replaced.iter().rev() {
+            memory.write_8(address, value);
+        }
+        let output = self.machine.output.len() - record.output.len();
+        self.machine.output.truncate(output);
+
+        record.apply(&mut self.machine);
+        true
+    }
+
+    /// Move to `step`, or the last step if the trace is shorter.
+    pub fn seek(&mut self, step: usize) {
+        while self.step > step && self.back() {}
+        while self.step < step && self.forward() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::{Register, RegisterPair},
+        machine::{ConditionRegister, MachineBuilder},
+        trace::{JsonTraceWriter, tests::SharedBuffer},
+    };
+
+    /// Count B down from 3, printing the digit and pushing it every time, then halt.
+    const PROGRAM: &str = "        ORG 100H
+        LXI SP, 2000H
+        MVI B, 3
+LOOP:   MOV A, B
+        ADI 30H
+        OUT 0
+        PUSH B
+        DCR B
+        JNZ LOOP
+        HLT
+        END
+";
+
+    fn machine() -> Machine {
+        MachineBuilder::new()
+            .assembly(PROGRAM.as_bytes())
+            .build()
+            .unwrap()
+    }
+
+    fn record() -> String {
+        let buffer = SharedBuffer::default();
+        let mut machine = machine();
+        machine.add_observer(Box::new(JsonTraceWriter::new(buffer.clone())));
+        machine.steps().for_each(drop);
+        String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap()
+    }
+
+    /// A live machine after executing `steps` instructions.
+    fn live(steps: usize) -> Machine {
+        let mut machine = machine();
+        machine.steps().take(steps).for_each(drop);
+        machine
+    }
+
+    fn assert_same_state(replayed: &Machine, live: &Machine, memory: bool) {
+        assert_eq!(replayed.pc(), live.pc());
+        for register in [
+            Register::A,
+            Register::B,
+            Register::C,
+            Register::D,
+            Register::E,
+            Register::H,
+            Register::L,
+        ] {
+            assert_eq!(replayed.register_8(register), live.register_8(register));
+        }
+        assert_eq!(
+            replayed.register_16(RegisterPair::Sp),
+            live.register_16(RegisterPair::Sp)
+        );
+        assert_eq!(
+            replayed.conditions().get(ConditionRegister::Zero),
+            live.conditions().get(ConditionRegister::Zero)
+        );
+        assert_eq!(replayed.output_buffer(), live.output_buffer());
+        if memory {
+            assert_eq!(replayed.memory().as_raw(), live.memory().as_raw());
+        }
+    }
+
+    #[test]
+    fn random_access() {
+        let trace = RecordedTrace::read(record().as_bytes()).unwrap();
+        assert_eq!(trace.steps(), 2 + 6 * 3 + 1);
+        assert!(trace.has_memory());
+        assert_eq!(trace.record(0).unwrap().pc, 0x0100);
+        assert_eq!(trace.record(4).unwrap().bytes, [0xD3, 0x00]);
+        assert_eq!(trace.record(4).unwrap().output, b"3");
+        assert!(trace.record(trace.steps()).is_none());
+    }
+
+    #[test]
+    fn replay_matches_the_live_run() {
+        let trace = RecordedTrace::read(record().as_bytes()).unwrap();
+        let last = trace.steps() - 1;
+        let mut replay = Replay::new(trace, machine());
+        assert_same_state(replay.machine(), &live(0), true);
+
+        for step in [7, 3, last, 0, 12, 11] {
+            replay.seek(step);
+            assert_eq!(replay.step(), step);
+            assert_same_state(replay.machine(), &live(step), true);
+        }
+
+        replay.seek(last);
+        assert!(!replay.forward());
+        assert_eq!(replay.step(), last);
+        assert_eq!(replay.machine().output_buffer(), b"321");
+        replay.seek(0);
+        assert!(!replay.back());
+        assert!(replay.machine().output_buffer().is_empty());
+    }
+
+    #[test]
+    fn registers_only() {
+        let text: String = record()
+            .lines()
+            .map(|line| {
+                let mut value: Value = serde_json::from_str(line).unwrap();
+                value.as_object_mut().unwrap().remove("writes");
+                format!("{}\n", value)
+            })
+            .collect();
+        let trace = RecordedTrace::read(text.as_bytes()).unwrap();
+        assert!(!trace.has_memory());
+
+        let mut replay = Replay::new(trace, machine());
+        replay.seek(9);
+        assert_same_state(replay.machine(), &live(9), false);
+        assert_eq!(
+            replay.machine().memory().as_raw(),
+            machine().memory().as_raw()
+        );
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut text = record();
+        text.insert_str(0, "\n");
+        text.push_str("{\"pc\": \"0100\"}\n");
+        let lines = text.lines().count();
+        match RecordedTrace::read(text.as_bytes()) {
+            Err(TraceReadError::Record { line, .. }) => assert_eq!(line, lines),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        assert!(matches!(
+            RecordedTrace::read(&b"\n"[..]),
+            Err(TraceReadError::Empty)
+        ));
+    }
+}
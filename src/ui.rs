@@ -1,7 +1,11 @@
 use std::{
+    cell::Cell,
     fmt::Display,
-    io,
-    sync::{LazyLock, mpsc::{self, TryRecvError}},
+    fs,
+    io::{self, Write},
+    ops::Range,
+    path::PathBuf,
+    sync::mpsc::{self, TryRecvError},
     time::{Duration, Instant},
 };
 
@@ -21,16 +25,22 @@ use tui::{
 };
 
 use crate::{
-    coding,
-    instruction::{Register, RegisterPair},
-    machine::{ConditionRegister, Machine, MachineState},
-    ui::memory_view::MemoryView,
+    coding::{self, reader::Reader},
+    devices::{FRAMEBUFFER_ROWS, Framebuffer, TEXT_ROWS, TextDisplay},
+    disasm::{self, DisassembledLine, Listing, ListingColumns, Symbols},
+    instruction::{Address, Data16, Instruction, Register, RegisterPair},
+    machine::{ConditionRegister, Machine, MachineState, SaveInfo},
+    trace::Replay,
+    ui::{memory_view::MemoryView, text_display_view::TextDisplayView},
 };
 
 mod memory_view;
+mod text_display_view;
 
 static DRAW_TIMEOUT: Duration = Duration::from_millis(33);
 static INPUT_TIMEOUT: Duration = Duration::from_millis(100);
+/// Time [`Speed::Clock`] runs the machine for per tick.
+static REALTIME_SLICE: Duration = Duration::from_millis(10);
 
 fn parse_hex(hex: &str) -> anyhow::Result<Color> {
     let digits = hex
@@ -71,45 +81,130 @@ impl Display for RegisterDisplay {
     }
 }
 
-#[allow(unused)]
-static COLOR_TEXT: LazyLock<Color> = LazyLock::new(|| parse_hex("#cdd6f4").unwrap());
-#[allow(unused)]
-static COLOR_SUBTEXT_1: LazyLock<Color> = LazyLock::new(|| parse_hex("#bac2de").unwrap());
-#[allow(unused)]
-static COLOR_SUBTEXT_0: LazyLock<Color> = LazyLock::new(|| parse_hex("#a6adc8").unwrap());
-#[allow(unused)]
-static COLOR_OVERLAY_2: LazyLock<Color> = LazyLock::new(|| parse_hex("#6c7086").unwrap());
-#[allow(unused)]
-static COLOR_GREEN: LazyLock<Color> = LazyLock::new(|| parse_hex("#A7DFA2").unwrap());
-#[allow(unused)]
-static COLOR_PEACH: LazyLock<Color> = LazyLock::new(|| parse_hex("#fab387").unwrap());
-#[allow(unused)]
-static COLOR_RED: LazyLock<Color> = LazyLock::new(|| parse_hex("#f38ba8").unwrap());
-#[allow(unused)]
-static COLOR_MAROON: LazyLock<Color> = LazyLock::new(|| parse_hex("#eba0ac").unwrap());
-#[allow(unused)]
-static COLOR_LAVENDER: LazyLock<Color> = LazyLock::new(|| parse_hex("#b4befe").unwrap());
-
-static STYLE_BLOCK_BORDER: LazyLock<Style> =
-    LazyLock::new(|| Style::default().fg(*COLOR_OVERLAY_2));
-static STYLE_BLOCK_LABEL: LazyLock<Style> = LazyLock::new(|| {
-    Style::default()
-        .fg(*COLOR_OVERLAY_2)
-        .add_modifier(Modifier::BOLD)
-});
-static STYLE_ADDRESS: LazyLock<Style> = LazyLock::new(|| Style::default().fg(*COLOR_GREEN));
-static STYLE_LABEL: LazyLock<Style> = LazyLock::new(|| {
-    Style::default()
-        .fg(*COLOR_TEXT)
-        .add_modifier(Modifier::empty())
-});
-static STYLE_VALUE: LazyLock<Style> = LazyLock::new(|| Style::default().fg(*COLOR_PEACH));
-static STYLE_DATA: LazyLock<Style> = LazyLock::new(|| Style::default().fg(*COLOR_SUBTEXT_0));
-static STYLE_PC: LazyLock<Style> = LazyLock::new(|| {
-    Style::default()
-        .fg(*COLOR_MAROON)
-        .add_modifier(Modifier::BOLD)
-});
+/// Color palette used to draw the UI.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub text: Color,
+    pub subtext: Color,
+    pub overlay: Color,
+    pub address: Color,
+    pub value: Color,
+    pub highlight: Color,
+}
+
+impl Theme {
+    /// Dark theme based on the Catppuccin Mocha palette. This is the default.
+    pub fn mocha() -> Self {
+        Self {
+            text: parse_hex("#cdd6f4").unwrap(),
+            subtext: parse_hex("#a6adc8").unwrap(),
+            overlay: parse_hex("#6c7086").unwrap(),
+            address: parse_hex("#A7DFA2").unwrap(),
+            value: parse_hex("#fab387").unwrap(),
+            highlight: parse_hex("#eba0ac").unwrap(),
+        }
+    }
+
+    /// Light theme based on the Catppuccin Latte palette.
+    pub fn latte() -> Self {
+        Self {
+            text: parse_hex("#4c4f69").unwrap(),
+            subtext: parse_hex("#6c6f85").unwrap(),
+            overlay: parse_hex("#9ca0b0").unwrap(),
+            address: parse_hex("#40a02b").unwrap(),
+            value: parse_hex("#fe640b").unwrap(),
+            highlight: parse_hex("#e64553").unwrap(),
+        }
+    }
+
+    /// Uses the terminal's default colors, for terminals without true color support.
+    pub fn plain() -> Self {
+        Self {
+            text: Color::Reset,
+            subtext: Color::Reset,
+            overlay: Color::Reset,
+            address: Color::Reset,
+            value: Color::Reset,
+            highlight: Color::Reset,
+        }
+    }
+
+    fn block_border(&self) -> Style {
+        Style::default().fg(self.overlay)
+    }
+
+    fn block_label(&self) -> Style {
+        Style::default().fg(self.overlay).add_modifier(Modifier::BOLD)
+    }
+
+    fn address(&self) -> Style {
+        Style::default().fg(self.address)
+    }
+
+    fn label(&self) -> Style {
+        Style::default().fg(self.text).add_modifier(Modifier::empty())
+    }
+
+    fn value(&self) -> Style {
+        Style::default().fg(self.value)
+    }
+
+    fn data(&self) -> Style {
+        Style::default().fg(self.subtext)
+    }
+
+    fn region(&self) -> Style {
+        Style::default().fg(self.text)
+    }
+
+    fn stack(&self) -> Style {
+        Style::default().fg(self.address)
+    }
+
+    fn pc(&self) -> Style {
+        Style::default().fg(self.highlight).add_modifier(Modifier::BOLD)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::mocha()
+    }
+}
+
+/// What the export keys of the UI write: `X` writes a disassembly listing of the loaded image, `V`
+/// one of the memory currently shown.
+#[derive(Clone, Debug, Default)]
+pub struct ListingExport {
+    /// File the listing is written to, replacing it if it exists.
+    pub path: PathBuf,
+    /// Addresses of the loaded image. The whole address space is exported if this is `None`.
+    pub image: Option<Range<usize>>,
+    /// Where execution of the loaded image starts, used to tell code from data.
+    pub entry: Address,
+    /// Labels of the loaded program, if it was assembled.
+    pub symbols: Symbols,
+    pub columns: ListingColumns,
+}
+
+/// Where the save key of the UI, `S`, writes a save state.
+#[derive(Clone, Debug, Default)]
+pub struct StateSave {
+    /// File the state is written to, replacing it if it exists.
+    pub path: PathBuf,
+    pub info: SaveInfo,
+    /// Also save when the machine halts.
+    pub on_halt: bool,
+}
+
+/// How fast the UI runs the machine while running.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Speed {
+    /// This many instructions between checks for input, as fast as the host allows.
+    Batch(u64),
+    /// At the speed of an 8080 clocked at this many hertz, see [`Machine::run_realtime`].
+    Clock(u32),
+}
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 enum UiState {
@@ -117,25 +212,55 @@ enum UiState {
     Paused,
 }
 
+/// Where the panels get the machine they show from.
+enum Source {
+    /// A machine executing the program.
+    Live(Machine),
+    /// The recorded states of an earlier run. Running plays the steps forward instead of executing
+    /// anything.
+    Replay(Replay),
+}
+
 struct Ui {
-    machine: Machine,
+    source: Source,
     input_receiver: mpsc::Receiver<KeyEvent>,
     quit_sender: mpsc::Sender<Option<String>>,
     state: UiState,
+    theme: Theme,
+    /// Addresses of the loaded segments.
+    regions: Vec<Range<usize>>,
+    export: ListingExport,
+    save: StateSave,
+    speed: Speed,
+    /// Addresses shown in the memory view when it was last drawn.
+    visible_memory: Cell<(usize, usize)>,
+    /// Message shown next to the keys, e.g. the result of an export.
+    status: Option<String>,
 }
 
 impl Ui {
     fn new(
-        machine: Machine,
+        source: Source,
         input_receiver: mpsc::Receiver<KeyEvent>,
-        quit_sender: mpsc::Sender<Option<String>>) 
+        quit_sender: mpsc::Sender<Option<String>>,
+        theme: Theme,
+        regions: Vec<Range<usize>>,
+        export: ListingExport,
+        save: StateSave)
         -> Self 
     {
         Self {
-            machine,
+            source,
             input_receiver,
             quit_sender,
             state: UiState::Paused,
+            theme,
+            regions,
+            export,
+            save,
+            speed: Speed::Batch(1),
+            visible_memory: Cell::new((0, 0)),
+            status: None,
         }
     }
 
@@ -147,21 +272,78 @@ impl Ui {
                 err => return Err(anyhow!(err)),
             },
         }
-        match self.state {
-            UiState::Running => {
-                self.machine.run_cycle();
+        let machine = match (&mut self.source, self.state) {
+            (Source::Live(machine), UiState::Running) => {
+                match self.speed {
+                    Speed::Batch(batch) => {
+                        machine.run_cycles(batch);
+                    }
+                    Speed::Clock(clock_hz) => {
+                        machine.run_realtime(clock_hz, REALTIME_SLICE);
+                    }
+                }
+                machine
             }
-            UiState::Paused => {}
-        }
-        match self.machine.state() {
-            MachineState::Running => {}
+            (Source::Live(machine), UiState::Paused) => machine,
+            (Source::Replay(replay), UiState::Running) => {
+                if !replay.forward() {
+                    self.state = UiState::Paused;
+                }
+                return Ok(());
+            }
+            (Source::Replay(_), UiState::Paused) => return Ok(()),
+        };
+        match machine.state() {
+            MachineState::Running | MachineState::WaitingForInput => {}
             MachineState::Halted(halt_reason) => {
-                self.quit_sender.send(Some(format!("State machine halted: {}", halt_reason)));
+                let mut message = format!("State machine halted: {}", halt_reason);
+                // A halting instruction leaves the program counter at itself.
+                message = format!(
+                    "{} at {}",
+                    message,
+                    describe_instruction(machine.pc(), machine.load())
+                );
+                if self.save.on_halt {
+                    // Only once, the machine stays halted until the UI quits.
+                    self.save.on_halt = false;
+                    self.save_state();
+                    message = format!("{}\n{}", message, self.status.as_deref().unwrap_or_default());
+                }
+                self.quit_sender.send(Some(message))?;
             }
         }
         Ok(())
     }
 
+    /// The machine the panels show: the running one, or the current step of a replay.
+    fn machine(&self) -> &Machine {
+        match &self.source {
+            Source::Live(machine) => machine,
+            Source::Replay(replay) => replay.machine(),
+        }
+    }
+
+    /// Up to `count` instructions from the program counter. A replay only has the recorded bytes
+    /// of the current instruction, since its memory may not hold them.
+    fn instructions(&self, count: usize) -> Vec<DisassembledLine> {
+        match &self.source {
+            Source::Live(machine) => {
+                let pc = machine.pc().value();
+                let mut lines = disasm::disassemble(machine.memory().as_raw(), pc, count * 3);
+                lines.truncate(count);
+                lines
+            }
+            Source::Replay(replay) => {
+                let record = replay.record();
+                vec![DisassembledLine::new(
+                    record.pc,
+                    record.bytes.clone(),
+                    coding::decode(&mut Reader::new(&record.bytes)),
+                )]
+            }
+        }
+    }
+
     fn draw(&self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> anyhow::Result<()> {
         terminal.draw(|f| {
             static REGISTERS_HEIGHT: u16 = 5 + 2;
@@ -207,6 +389,23 @@ impl Ui {
 
             self.draw_keys(f, keys_area);
 
+            // An attached text display or framebuffer takes the top of the output column, as much
+            // of it as the screen needs.
+            if let Some(display) = self.machine().device::<TextDisplay>() {
+                let mut display_area = stdout_area;
+                display_area.height = stdout_area.height.min(TEXT_ROWS as u16 + 2);
+                stdout_area.y = display_area.bottom();
+                stdout_area.height -= display_area.height;
+                self.draw_text_display(f, display_area, display);
+            }
+            if let Some(framebuffer) = self.machine().memory_handler::<Framebuffer>() {
+                let mut framebuffer_area = stdout_area;
+                framebuffer_area.height = stdout_area.height.min(FRAMEBUFFER_ROWS as u16 + 2);
+                stdout_area.y = framebuffer_area.bottom();
+                stdout_area.height -= framebuffer_area.height;
+                self.draw_framebuffer(f, framebuffer_area, framebuffer);
+            }
+
             self.draw_stdout(f, stdout_area);
         })?;
         Ok(())
@@ -214,33 +413,51 @@ impl Ui {
 
     fn draw_memory(&self, f: &mut Frame<'_, CrosstermBackend<io::Stdout>>, area: Rect) {
         let block = Block::default()
-            .title(Span::styled("Memory", *STYLE_BLOCK_LABEL))
+            .title(Span::styled("Memory", self.theme.block_label()))
             .borders(Borders::all())
             .border_type(BorderType::Rounded)
-            .border_style(*STYLE_BLOCK_BORDER);
+            .border_style(self.theme.block_border());
         let widget_area = block.inner(area).inner(&Margin {
             vertical: 0,
             horizontal: 1,
         });
         f.render_widget(block, area);
 
-        let memory_view = MemoryView::new(self.machine.memory().as_raw())
-            .shown_address(0)
-            .highlighted_address(Some(self.machine.pc().value()))
-            .label_style(*STYLE_LABEL)
-            .address_style(*STYLE_ADDRESS)
-            .data_style(*STYLE_DATA)
-            .highlighted_style(*STYLE_PC);
+        if let Source::Replay(replay) = &self.source
+            && !replay.trace().has_memory()
+        {
+            let par = Paragraph::new(
+                "The trace doesn't record memory writes, only registers are replayed.",
+            )
+            .style(self.theme.data())
+            .wrap(Wrap { trim: true });
+            f.render_widget(par, widget_area);
+            return;
+        }
 
+        let memory_view = MemoryView::new(self.machine().memory().as_raw())
+            .shown_address(0)
+            .highlighted_address(Some(self.machine().pc().value()))
+            .label_style(self.theme.label())
+            .address_style(self.theme.address())
+            .data_style(self.theme.data())
+            .regions(&self.regions)
+            .region_style(self.theme.region())
+            .stack(self.machine().stack_region())
+            .stack_style(self.theme.stack())
+            .highlighted_style(self.theme.pc());
+
+        let visible = memory_view.visible_range(widget_area);
+        self.visible_memory.set((visible.start, visible.end));
         f.render_widget(memory_view, widget_area);
     }
 
     fn draw_registers(&self, f: &mut Frame<'_, CrosstermBackend<io::Stdout>>, area: Rect) {
         let block = Block::default()
-            .title(Span::styled("Registers", *STYLE_BLOCK_LABEL))
+            .title(Span::styled("Registers", self.theme.block_label()))
             .borders(Borders::all())
             .border_type(BorderType::Rounded)
-            .border_style(*STYLE_BLOCK_BORDER);
+            .border_style(self.theme.block_border());
         let list_area = block.inner(area).inner(&Margin {
             vertical: 0,
             horizontal: 1,
@@ -301,17 +518,17 @@ impl Ui {
                 let value_string = match register {
                     RegisterDisplay::Single(register) => {
                         let value = self
-                            .machine
+                            .machine()
                             .registers()
-                            .get_8(register, self.machine.memory());
+                            .get_8(register, self.machine().memory());
                         format!("0x{:02x}", value)
                     }
                     RegisterDisplay::Pair(register) => {
-                        let value = self.machine.registers().get_16(register);
+                        let value = self.machine().registers().get_16(register);
                         format!("0x{:04x}", value.value())
                     }
                     RegisterDisplay::Flags => {
-                        let flags = self.machine.conditions();
+                        let flags = self.machine().conditions();
                         fn to_binary(b: bool) -> u8 {
                             if b { 1 } else { 0 }
                         }
@@ -326,9 +543,9 @@ impl Ui {
                     }
                 };
                 let par = Paragraph::new(vec![Spans::from(vec![
-                    Span::styled(format!("{}", register), *STYLE_LABEL),
+                    Span::styled(format!("{}", register), self.theme.label()),
                     Span::raw(": "),
-                    Span::styled(value_string, *STYLE_VALUE),
+                    Span::styled(value_string, self.theme.value()),
                 ])]);
 
                 f.render_widget(par, areas[row_index]);
@@ -338,10 +555,10 @@ impl Ui {
 
     fn draw_instructions(&self, f: &mut Frame<'_, CrosstermBackend<io::Stdout>>, area: Rect) {
         let block = Block::default()
-            .title(Span::styled("Instructions", *STYLE_BLOCK_LABEL))
+            .title(Span::styled("Instructions", self.theme.block_label()))
             .borders(Borders::all())
             .border_type(BorderType::Rounded)
-            .border_style(*STYLE_BLOCK_BORDER);
+            .border_style(self.theme.block_border());
         let block_area = block.inner(area).inner(&Margin {
             vertical: 0,
             horizontal: 1,
@@ -349,13 +566,22 @@ impl Ui {
         f.render_widget(block, area);
 
         {
-            let value = self.machine.pc();
-            let pc = Paragraph::new(Spans::from(vec![
-                Span::styled("PC", *STYLE_LABEL),
+            let value = self.machine().pc();
+            let mut spans = vec![
+                Span::styled("PC", self.theme.label()),
                 Span::raw(": "),
-                Span::styled(format!("0x{:04x}", value.value()), *STYLE_PC),
-            ]));
-            f.render_widget(pc, block_area);
+                Span::styled(format!("0x{:04x}", value.value()), self.theme.pc()),
+            ];
+            // A replay doesn't record the cycles.
+            if let Source::Live(machine) = &self.source {
+                spans.extend([
+                    Span::raw("  "),
+                    Span::styled("Cycles", self.theme.label()),
+                    Span::raw(": "),
+                    Span::styled(machine.cycles().to_string(), self.theme.value()),
+                ]);
+            }
+            f.render_widget(Paragraph::new(Spans::from(spans)), block_area);
         }
 
         let mut instructions_area = block_area;
@@ -364,48 +590,83 @@ impl Ui {
         instructions_area.x += 1;
         instructions_area.width -= 1;
 
-        if let Some(instruction) = self.machine.load() {
-            let mut instruction_bytes = Vec::new();
-            coding::encode(&mut instruction_bytes, instruction)
-                .expect("writing to Vec can't error");
-
-            // This is actually terrible
-            fn join_bytes(bytes: &[u8]) -> String {
-                bytes
-                    .iter()
-                    .map(|byte| format!("{:02x}", byte))
-                    .collect::<Vec<_>>()
-                    .join(" ")
-            }
-
-            let par = Paragraph::new(Spans::from(vec![
-                Span::styled(join_bytes(&instruction_bytes), *STYLE_VALUE),
-                Span::raw(" "),
-                Span::styled(format!("{:?}", instruction), *STYLE_DATA),
-            ]));
-            
-            f.render_widget(par, instructions_area);
-        }
+        let lines: Vec<Spans> = self
+            .instructions(instructions_area.height as usize)
+            .iter()
+            .map(|line| {
+                let bytes: Vec<String> =
+                    line.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let label = match &line.label {
+                    Some(label) => format!("{}: ", label),
+                    None => String::new(),
+                };
+                Spans::from(vec![
+                    Span::styled(bytes.join(" "), self.theme.value()),
+                    Span::raw(" "),
+                    Span::styled(label, self.theme.label()),
+                    Span::styled(line.statement.clone(), self.theme.data()),
+                ])
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines), instructions_area);
     }
 
     fn draw_keys(&self, f: &mut Frame<'_, CrosstermBackend<io::Stdout>>, area: Rect) {
-        let par = Paragraph::new(Spans::from(vec![
-            Span::styled(" pause: ", *STYLE_BLOCK_BORDER),
-            Span::styled("P", *STYLE_BLOCK_LABEL),
-            Span::styled("  step instruction: ", *STYLE_BLOCK_BORDER),
-            Span::styled("Space", *STYLE_BLOCK_LABEL),
-            Span::styled("  quit: ", *STYLE_BLOCK_BORDER),
-            Span::styled("Q", *STYLE_BLOCK_LABEL),
-        ]));
-        f.render_widget(par, area);
+        let mut spans = match &self.source {
+            Source::Live(_) if self.waiting_for_input() => vec![
+                Span::styled(" waiting for input, type it or press ", self.theme.block_border()),
+                Span::styled("Enter", self.theme.block_label()),
+                Span::styled(" for a newline", self.theme.block_border()),
+            ],
+            Source::Live(_) => vec![
+                Span::styled(" pause: ", self.theme.block_border()),
+                Span::styled("P", self.theme.block_label()),
+                Span::styled("  step instruction: ", self.theme.block_border()),
+                Span::styled("Space", self.theme.block_label()),
+                Span::styled("  export image/view: ", self.theme.block_border()),
+                Span::styled("X", self.theme.block_label()),
+                Span::styled("/", self.theme.block_border()),
+                Span::styled("V", self.theme.block_label()),
+                Span::styled("  save state: ", self.theme.block_border()),
+                Span::styled("S", self.theme.block_label()),
+            ],
+            Source::Replay(replay) => vec![
+                Span::styled(
+                    format!(" step {} of {}", replay.step() + 1, replay.trace().steps()),
+                    self.theme.label(),
+                ),
+                Span::styled("  play: ", self.theme.block_border()),
+                Span::styled("P", self.theme.block_label()),
+                Span::styled("  step: ", self.theme.block_border()),
+                Span::styled("Space", self.theme.block_label()),
+                Span::styled("/", self.theme.block_border()),
+                Span::styled("Backspace", self.theme.block_label()),
+                Span::styled("  first/last: ", self.theme.block_border()),
+                Span::styled("Home", self.theme.block_label()),
+                Span::styled("/", self.theme.block_border()),
+                Span::styled("End", self.theme.block_label()),
+                Span::styled("  export image/view: ", self.theme.block_border()),
+                Span::styled("X", self.theme.block_label()),
+                Span::styled("/", self.theme.block_border()),
+                Span::styled("V", self.theme.block_label()),
+            ],
+        };
+        let quit = if self.waiting_for_input() { "Esc" } else { "Q" };
+        spans.extend([
+            Span::styled("  quit: ", self.theme.block_border()),
+            Span::styled(quit, self.theme.block_label()),
+            Span::raw("  "),
+            Span::styled(self.status.as_deref().unwrap_or_default(), self.theme.label()),
+        ]);
+        f.render_widget(Paragraph::new(Spans::from(spans)), area);
     }
 
     fn draw_stdout(&self, f: &mut Frame<'_, CrosstermBackend<io::Stdout>>, area: Rect) {
         let block = Block::default()
-            .title(Span::styled("Stdout", *STYLE_BLOCK_LABEL))
+            .title(Span::styled("Stdout", self.theme.block_label()))
             .borders(Borders::all())
             .border_type(BorderType::Rounded)
-            .border_style(*STYLE_BLOCK_BORDER);
+            .border_style(self.theme.block_border());
         let block_area = block.inner(area).inner(&Margin {
             vertical: 0,
             horizontal: 1,
@@ -413,36 +674,226 @@ impl Ui {
         f.render_widget(block, area);
 
         let par =
-            Paragraph::new(String::from_utf8_lossy(&self.machine.stdout)).wrap(Wrap { trim: true });
+            Paragraph::new(String::from_utf8_lossy(self.machine().output_buffer())).wrap(Wrap { trim: true });
         f.render_widget(par, block_area);
     }
 
+    fn draw_text_display(
+        &self,
+        f: &mut Frame<'_, CrosstermBackend<io::Stdout>>,
+        area: Rect,
+        display: &TextDisplay,
+    ) {
+        let block = Block::default()
+            .title(Span::styled("Display", self.theme.block_label()))
+            .borders(Borders::all())
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.block_border());
+        let display_area = block.inner(area);
+        f.render_widget(block, area);
+
+        f.render_widget(
+            TextDisplayView::new(display.cells()).style(self.theme.label()),
+            display_area,
+        );
+    }
+
+    fn draw_framebuffer(
+        &self,
+        f: &mut Frame<'_, CrosstermBackend<io::Stdout>>,
+        area: Rect,
+        framebuffer: &Framebuffer,
+    ) {
+        let block = Block::default()
+            .title(Span::styled("Framebuffer", self.theme.block_label()))
+            .borders(Borders::all())
+            .border_type(BorderType::Rounded)
+            .border_style(self.theme.block_border());
+        let framebuffer_area = block.inner(area);
+        f.render_widget(block, area);
+
+        f.render_widget(
+            TextDisplayView::new(framebuffer.cells())
+                .style(self.theme.label())
+                .cursor(framebuffer.cursor()),
+            framebuffer_area,
+        );
+    }
+
+    /// Whether the live machine is waiting for the user to type input.
+    fn waiting_for_input(&self) -> bool {
+        match &self.source {
+            Source::Live(machine) => machine.state() == MachineState::WaitingForInput,
+            Source::Replay(_) => false,
+        }
+    }
+
     fn input(&mut self, event: event::KeyEvent) -> anyhow::Result<()> {
+        // While the program waits for input, typed characters are its input instead of commands.
+        if let Source::Live(machine) = &mut self.source
+            && machine.state() == MachineState::WaitingForInput
+        {
+            match event.code {
+                KeyCode::Char(c) => machine.push_input(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                KeyCode::Enter => machine.push_input(b"\n"),
+                KeyCode::Esc => self.quit_sender.send(None)?,
+                _ => {}
+            }
+            return Ok(());
+        }
         match event.code {
             KeyCode::Char('q') => {
                 self.quit_sender.send(None)?;
             }
-            KeyCode::Char(' ') => match self.state {
-                UiState::Paused => {
-                    self.machine.run_cycle();
+            KeyCode::Char(' ') | KeyCode::Right => match (&mut self.source, self.state) {
+                (Source::Live(machine), UiState::Paused) => {
+                    if let Some(step) = machine.run_cycle() {
+                        self.status = Some(format!(
+                            "Stepped {}",
+                            describe_instruction(step.pc_before, step.instruction)
+                        ));
+                    }
+                }
+                (Source::Replay(replay), UiState::Paused) => {
+                    replay.forward();
                 }
                 _ => {}
             },
-            KeyCode::Char('p') => {
-                if self.machine.state() == MachineState::Running {
-                    self.state = match self.state {
-                        UiState::Paused => UiState::Running,
-                        UiState::Running => UiState::Paused,
-                    }
+            KeyCode::Backspace | KeyCode::Left => {
+                if let (Source::Replay(replay), UiState::Paused) = (&mut self.source, self.state) {
+                    replay.back();
+                }
+            }
+            KeyCode::Home => {
+                if let Source::Replay(replay) = &mut self.source {
+                    replay.seek(0);
+                }
+            }
+            KeyCode::End => {
+                if let Source::Replay(replay) = &mut self.source {
+                    replay.seek(usize::MAX);
+                }
+            }
+            KeyCode::Char('x') => {
+                let range = self.export.image.clone().unwrap_or(0..0x10000);
+                self.export_listing(range);
+            }
+            KeyCode::Char('v') => {
+                let (start, end) = self.visible_memory.get();
+                self.export_listing(start..end);
+            }
+            KeyCode::Char('s') => {
+                // A replayed machine can't be continued from.
+                if let Source::Live(_) = self.source {
+                    self.save_state();
+                }
+            }
+            KeyCode::Char('p') if self.machine().state() == MachineState::Running => {
+                self.state = match self.state {
+                    UiState::Paused => UiState::Running,
+                    UiState::Running => UiState::Paused,
                 }
             }
             _ => {}
         }
         Ok(())
     }
+
+    /// Write a listing of the memory in `range` to the export file, reporting the outcome in the
+    /// status message.
+    fn export_listing(&mut self, range: Range<usize>) {
+        let origin = range.start as Address;
+        let entries: Vec<Address> = [self.export.entry, self.machine().pc().value()]
+            .into_iter()
+            .filter(|address| range.contains(&(*address as usize)))
+            .collect();
+        let listing = Listing::new(
+            &self.machine().memory().as_raw()[range.clone()],
+            origin,
+            &entries,
+            &self.export.symbols,
+        );
+
+        let path = &self.export.path;
+        let result = fs::File::create(path).and_then(|file| {
+            let mut writer = io::BufWriter::new(file);
+            listing.write(&mut writer, &self.export.columns)?;
+            writer.flush()
+        });
+        self.status = Some(match result {
+            Ok(()) => format!(
+                "Wrote 0x{:04X}-0x{:04X} to '{}'",
+                range.start,
+                range.end.saturating_sub(1),
+                path.display()
+            ),
+            Err(err) => format!("Couldn't write '{}': {}", path.display(), err),
+        });
+    }
+
+    /// Write a save state to the save file, reporting the outcome in the status message.
+    fn save_state(&mut self) {
+        let path = &self.save.path;
+        let result = fs::File::create(path).and_then(|file| {
+            let mut writer = io::BufWriter::new(file);
+            self.machine().save_state(&mut writer, &self.save.info)?;
+            writer.flush()
+        });
+        self.status = Some(match result {
+            Ok(()) => format!("Saved state to '{}'", path.display()),
+            Err(err) => format!("Couldn't write '{}': {}", path.display(), err),
+        });
+    }
+}
+
+/// An instruction and its address, e.g. `0x0100: HLT`.
+fn describe_instruction(pc: Data16, instruction: Option<Instruction>) -> String {
+    match instruction {
+        Some(instruction) => format!("0x{:04X}: {}", pc.value(), instruction),
+        None => format!("0x{:04X}: invalid instruction", pc.value()),
+    }
+}
+
+/// Run the terminal UI until the user quits or the machine halts. The memory in `regions`, e.g.
+/// the loaded program, is drawn in its own color.
+pub fn start(
+    machine: Machine,
+    theme: Theme,
+    regions: Vec<Range<usize>>,
+    export: ListingExport,
+    save: StateSave,
+    speed: Speed,
+) -> anyhow::Result<()> {
+    run(Source::Live(machine), theme, regions, export, save, speed)
+}
+
+/// Show the steps of `replay` in the terminal UI until the user quits. Stepping moves through the
+/// recorded states instead of executing instructions, and the memory panel is left empty if the
+/// trace doesn't record memory.
+pub fn replay(
+    replay: Replay,
+    theme: Theme,
+    regions: Vec<Range<usize>>,
+    export: ListingExport,
+) -> anyhow::Result<()> {
+    run(
+        Source::Replay(replay),
+        theme,
+        regions,
+        export,
+        StateSave::default(),
+        Speed::Batch(1),
+    )
 }
 
-pub fn start(machine: Machine) -> anyhow::Result<()> {
+fn run(
+    source: Source,
+    theme: Theme,
+    regions: Vec<Range<usize>>,
+    export: ListingExport,
+    save: StateSave,
+    speed: Speed,
+) -> anyhow::Result<()> {
     let backend = CrosstermBackend::new(io::stdout());
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
@@ -451,7 +902,16 @@ pub fn start(machine: Machine) -> anyhow::Result<()> {
 
     let (input_sender, input_receiver) = mpsc::channel::<KeyEvent>();
     let (quit_sender, quit_receiver) = mpsc::channel::<Option<String>>();
-    let mut ui = Ui::new(machine, input_receiver, quit_sender.clone());
+    let mut ui = Ui::new(
+        source,
+        input_receiver,
+        quit_sender.clone(),
+        theme,
+        regions,
+        export,
+        save,
+    );
+    ui.speed = speed;
 
     std::thread::spawn(move || -> Result<(), anyhow::Error> {
         let mut last_draw_time = Instant::now();
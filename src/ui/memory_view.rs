@@ -1,4 +1,7 @@
+use std::ops::Range;
+
 use tui::{
+    layout::Rect,
     style::Style,
     text::{Span, Spans},
     widgets::{Paragraph, Widget},
@@ -10,8 +13,12 @@ pub struct MemoryView<'a> {
     memory: &'a [u8],
     shown_address: u16,
     highlighted_address: Option<u16>,
+    regions: &'a [Range<usize>],
+    stack: Option<Range<usize>>,
     address_style: Style,
     data_style: Style,
+    region_style: Style,
+    stack_style: Style,
     highlighted_style: Style,
     label_style: Style,
 }
@@ -22,8 +29,12 @@ impl<'a> MemoryView<'a> {
             memory,
             shown_address: 0,
             highlighted_address: None,
+            regions: &[],
+            stack: None,
             address_style: Style::default(),
             data_style: Style::default(),
+            region_style: Style::default(),
+            stack_style: Style::default(),
             highlighted_style: Style::default(),
             label_style: Style::default(),
         }
@@ -39,6 +50,18 @@ impl<'a> MemoryView<'a> {
         self
     }
 
+    /// Memory drawn with the region style instead of the data style.
+    pub fn regions(mut self, regions: &'a [Range<usize>]) -> Self {
+        self.regions = regions;
+        self
+    }
+
+    /// Memory drawn with the stack style, taking precedence over the regions.
+    pub fn stack(mut self, stack: Option<Range<usize>>) -> Self {
+        self.stack = stack;
+        self
+    }
+
     pub fn address_style(mut self, style: Style) -> Self {
         self.address_style = style;
         self
@@ -49,6 +72,16 @@ impl<'a> MemoryView<'a> {
         self
     }
 
+    pub fn region_style(mut self, style: Style) -> Self {
+        self.region_style = style;
+        self
+    }
+
+    pub fn stack_style(mut self, style: Style) -> Self {
+        self.stack_style = style;
+        self
+    }
+
     pub fn highlighted_style(mut self, style: Style) -> Self {
         self.highlighted_style = style;
         self
@@ -58,10 +91,9 @@ impl<'a> MemoryView<'a> {
         self.label_style = style;
         self
     }
-}
 
-impl<'a> Widget for MemoryView<'a> {
-    fn render(self, mut area: tui::layout::Rect, buf: &mut tui::buffer::Buffer) {
+    /// Number of bytes per row and number of rows when rendered to `area`.
+    fn grid(area: Rect) -> (u16, u16) {
         // Available length of characters to draw bytes to.
         let memory_area_width = area.width - 8;
 
@@ -82,6 +114,23 @@ impl<'a> Widget for MemoryView<'a> {
         static MAX_ROW_BYTES: u16 = 16;
         let row_byte_count = row_byte_count.min(MAX_ROW_BYTES);
 
+        // The first line is the header.
+        (row_byte_count, area.height - 1)
+    }
+
+    /// Range of addresses shown when rendered to `area`.
+    pub fn visible_range(&self, area: Rect) -> Range<usize> {
+        let (row_byte_count, rows) = Self::grid(area);
+        let showable_span_len = rows * row_byte_count;
+        let start = self.shown_address.saturating_sub(showable_span_len / 2) as usize;
+        start..(start + showable_span_len as usize).min(0x10000)
+    }
+}
+
+impl<'a> Widget for MemoryView<'a> {
+    fn render(self, mut area: Rect, buf: &mut tui::buffer::Buffer) {
+        let (row_byte_count, _) = Self::grid(area);
+
         // Draw first line
         Paragraph::new(Spans::from(
             [Span::styled("Offset", self.label_style), Span::raw("  ")]
@@ -129,6 +178,18 @@ impl<'a> Widget for MemoryView<'a> {
                             let offset = offset + byte_index;
                             let style = if Some(offset) == self.highlighted_address {
                                 self.highlighted_style
+                            } else if self
+                                .stack
+                                .as_ref()
+                                .is_some_and(|stack| stack.contains(&(offset as usize)))
+                            {
+                                self.stack_style
+                            } else if self
+                                .regions
+                                .iter()
+                                .any(|region| region.contains(&(offset as usize)))
+                            {
+                                self.region_style
                             } else {
                                 self.data_style
                             };
@@ -0,0 +1,121 @@
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style},
+    widgets::Widget,
+};
+
+/// The cells of a character screen such as a [`TextDisplay`](crate::devices::TextDisplay) or a
+/// [`Framebuffer`](crate::devices::Framebuffer), cut off at the right and bottom if the area is
+/// smaller than the screen. Bytes outside printable ASCII are drawn as spaces.
+pub struct TextDisplayView<'a> {
+    rows: Vec<&'a [u8]>,
+    style: Style,
+    cursor: Option<(usize, usize)>,
+}
+
+impl<'a> TextDisplayView<'a> {
+    pub fn new<const COLUMNS: usize, const ROWS: usize>(cells: &'a [[u8; COLUMNS]; ROWS]) -> Self {
+        Self {
+            rows: cells.iter().map(|row| &row[..]).collect(),
+            style: Style::default(),
+            cursor: None,
+        }
+    }
+
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Highlight the cell at this row and column.
+    pub fn cursor(mut self, cursor: (usize, usize)) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+}
+
+impl<'a> Widget for TextDisplayView<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for (y, row) in self.rows.iter().take(area.height as usize).enumerate() {
+            let line: String = row
+                .iter()
+                .take(area.width as usize)
+                .map(|&byte| match byte {
+                    0x20..=0x7E => byte as char,
+                    _ => ' ',
+                })
+                .collect();
+            buf.set_string(area.x, area.y + y as u16, line, self.style);
+        }
+        if let Some((row, column)) = self.cursor
+            && row < area.height as usize
+            && column < area.width as usize
+        {
+            let cell = buf.get_mut(area.x + column as u16, area.y + row as u16);
+            cell.set_style(self.style.add_modifier(Modifier::REVERSED));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::{TEXT_COLUMNS, TEXT_ROWS, TextDisplay};
+
+    fn render(cells: &[[u8; TEXT_COLUMNS]; TEXT_ROWS], area: Rect) -> Buffer {
+        let mut buf = Buffer::empty(area);
+        TextDisplayView::new(cells).render(area, &mut buf);
+        buf
+    }
+
+    fn line(buf: &Buffer, y: u16) -> String {
+        let area = buf.area();
+        (area.x..area.right())
+            .map(|x| buf.get(x, y).symbol.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn renders_the_grid() {
+        let mut cells = *TextDisplay::default().cells();
+        cells[1][2..7].copy_from_slice(b"hello");
+        cells[2][0] = 0x07;
+
+        let buf = render(
+            &cells,
+            Rect::new(0, 0, TEXT_COLUMNS as u16, TEXT_ROWS as u16),
+        );
+        assert_eq!(line(&buf, 0), " ".repeat(TEXT_COLUMNS));
+        assert_eq!(line(&buf, 1).trim_end(), "  hello");
+        assert_eq!(line(&buf, 2), " ".repeat(TEXT_COLUMNS));
+    }
+
+    #[test]
+    fn cut_off_in_a_small_area() {
+        let mut cells = *TextDisplay::default().cells();
+        cells[0].fill(b'x');
+        cells[TEXT_ROWS - 1].fill(b'y');
+
+        let buf = render(&cells, Rect::new(3, 2, 10, 4));
+        assert_eq!(line(&buf, 2), "xxxxxxxxxx");
+        assert_eq!(line(&buf, 3), "          ");
+        assert!((2..6).all(|y| !line(&buf, y).contains('y')));
+    }
+
+    #[test]
+    fn highlights_the_cursor() {
+        let cells = [[b'a'; 4]; 3];
+        let area = Rect::new(0, 0, 4, 3);
+        let mut buf = Buffer::empty(area);
+        TextDisplayView::new(&cells)
+            .cursor((1, 2))
+            .render(area, &mut buf);
+
+        assert_eq!(line(&buf, 1), "aaaa");
+        let reversed = |x, y| buf.get(x, y).modifier.contains(Modifier::REVERSED);
+        assert!(reversed(2, 1));
+        assert_eq!((0..4).filter(|&x| reversed(x, 1)).count(), 1);
+        assert!(!reversed(2, 0));
+    }
+}
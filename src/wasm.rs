@@ -0,0 +1,196 @@
+//! Browser bindings, enabled with the `wasm` feature.
+//!
+//! The exported machine never touches the host's stdin or stdout: input is queued with
+//! [`WasmMachine::push_input`] and output is collected with [`WasmMachine::drain_output`].
+
+use js_sys::{Object, Reflect};
+use serde_json::{Map, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    instruction::RegisterPair,
+    machine::{ConditionRegister, Machine, MachineBuilder, MachineState, json::REGISTERS},
+};
+
+const FLAGS: [(&str, ConditionRegister); 5] = [
+    ("sign", ConditionRegister::Sign),
+    ("zero", ConditionRegister::Zero),
+    ("auxiliaryCarry", ConditionRegister::AuxiliaryCarry),
+    ("parity", ConditionRegister::Parity),
+    ("carry", ConditionRegister::Carry),
+];
+
+/// An emulated Intel 8080 for use from JavaScript.
+#[wasm_bindgen]
+pub struct WasmMachine {
+    machine: Machine,
+}
+
+impl Default for WasmMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmMachine {
+    /// Create a machine with empty memory.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            machine: Machine::new(),
+        }
+    }
+
+    /// Replace the machine with one running `bytes` loaded at `origin`.
+    #[wasm_bindgen(js_name = loadProgram)]
+    pub fn load_program(&mut self, bytes: &[u8], origin: u16) -> Result<(), JsError> {
+        self.machine = MachineBuilder::new().program(bytes, origin).build()?;
+        Ok(())
+    }
+
+    /// Replace the machine with one running `bytes` loaded at address 0, like a ROM.
+    pub fn load(&mut self, bytes: &[u8]) -> Result<(), JsError> {
+        self.load_program(bytes, 0x0000)
+    }
+
+    /// Replace the machine with one running the assembled `source`.
+    #[wasm_bindgen(js_name = loadAssembly)]
+    pub fn load_assembly(&mut self, source: &str) -> Result<(), JsError> {
+        self.machine = MachineBuilder::new().assembly(source.as_bytes()).build()?;
+        Ok(())
+    }
+
+    /// Execute a single instruction. Returns whether the machine is still running.
+    pub fn step(&mut self) -> bool {
+        self.machine.step();
+        self.running()
+    }
+
+    /// Execute up to `count` instructions, stopping early if the machine halts. Returns the
+    /// number of executed instructions.
+    pub fn run(&mut self, count: u32) -> u32 {
+        self.machine.steps().take(count as usize).count() as u32
+    }
+
+    pub fn running(&self) -> bool {
+        self.machine.state() == MachineState::Running
+    }
+
+    /// Why the machine halted, or `undefined` while it's running.
+    #[wasm_bindgen(js_name = haltReason)]
+    pub fn halt_reason(&self) -> Option<String> {
+        match self.machine.state() {
+            MachineState::Running | MachineState::WaitingForInput => None,
+            MachineState::Halted(reason) => Some(reason.to_string()),
+        }
+    }
+
+    /// Registers and flags as an object like
+    /// `{ a, b, c, d, e, h, l, sp, pc, flags: { sign, zero, auxiliaryCarry, parity, carry } }`.
+    pub fn registers(&self) -> Result<JsValue, JsValue> {
+        let registers = Object::new();
+        for (name, register) in REGISTERS {
+            let value = self.machine.register_8(register);
+            Reflect::set(&registers, &name.into(), &value.into())?;
+        }
+        let sp = self.machine.register_16(RegisterPair::Sp).value();
+        Reflect::set(&registers, &"sp".into(), &sp.into())?;
+        Reflect::set(&registers, &"pc".into(), &self.machine.pc().value().into())?;
+
+        let flags = Object::new();
+        for (name, condition) in FLAGS {
+            let value = self.machine.conditions().get(condition);
+            Reflect::set(&flags, &name.into(), &value.into())?;
+        }
+        Reflect::set(&registers, &"flags".into(), &flags)?;
+
+        Ok(registers.into())
+    }
+
+    /// The object returned by [`WasmMachine::registers`] as a JSON string, for callers that pass
+    /// the state on, e.g. to a worker, instead of reading it.
+    #[wasm_bindgen(js_name = registersJson)]
+    pub fn registers_json(&self) -> String {
+        let flags: Map<String, Value> = FLAGS
+            .iter()
+            .map(|(name, condition)| {
+                (
+                    name.to_string(),
+                    self.machine.conditions().get(*condition).into(),
+                )
+            })
+            .collect();
+        let mut registers: Map<String, Value> = REGISTERS
+            .iter()
+            .map(|(name, register)| (name.to_string(), self.machine.register_8(*register).into()))
+            .collect();
+        registers.insert(
+            "sp".into(),
+            self.machine.register_16(RegisterPair::Sp).value().into(),
+        );
+        registers.insert("pc".into(), self.machine.pc().value().into());
+        registers.insert("flags".into(), flags.into());
+        Value::from(registers).to_string()
+    }
+
+    /// Copy `length` bytes of memory starting at `start`. The range is cut off at the end of the
+    /// address space.
+    #[wasm_bindgen(js_name = readMemory)]
+    pub fn read_memory(&self, start: u16, length: usize) -> Vec<u8> {
+        let memory = &self.machine.memory().as_raw()[start as usize..];
+        memory[..length.min(memory.len())].to_vec()
+    }
+
+    /// Queue bytes to be read by `IN 0`. Reading from an empty queue halts the machine.
+    #[wasm_bindgen(js_name = pushInput)]
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.machine.push_input(bytes);
+    }
+
+    /// Take all output written by the program since the last call.
+    #[wasm_bindgen(js_name = drainOutput)]
+    pub fn drain_output(&mut self) -> Vec<u8> {
+        self.machine.take_output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_json() {
+        let mut machine = WasmMachine::new();
+        // LXI SP, 1000H; MVI A, 0FFH; INR A; HLT
+        machine
+            .load(&[0x31, 0x00, 0x10, 0x3E, 0xFF, 0x3C, 0x76])
+            .unwrap();
+        assert_eq!(machine.run(10), 4);
+
+        let json: Value = serde_json::from_str(&machine.registers_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "h": 0, "l": 0,
+                "sp": 0x1000,
+                "pc": 6,
+                "flags": {
+                    "sign": false,
+                    "zero": true,
+                    "auxiliaryCarry": true,
+                    "parity": true,
+                    "carry": false,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn registers_json_is_compact() {
+        let machine = WasmMachine::new();
+        let json = machine.registers_json();
+        assert!(json.starts_with("{\"a\":0,"));
+        assert!(!json.contains(char::is_whitespace));
+    }
+}
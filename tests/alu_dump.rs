@@ -0,0 +1,50 @@
+//! Compares ALU truth tables with the reference slices in `tests/data/alu`.
+//!
+//! Every `name.csv` holds rows of the table of the instruction `name` in the format of
+//! `leben alu-dump`, after comment lines starting with `#`. The rows are worked examples from
+//! Intel's manuals, named in the comments, and must match the generated table exactly.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use rsoderh_jonsh_leben_emulator::machine::{AluOperation, alu_table, write_alu_csv};
+
+fn check(operation: AluOperation) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data/alu")
+        .join(format!("{}.csv", operation.name().to_lowercase()));
+    let reference = fs::read_to_string(&path).unwrap();
+
+    let mut generated = String::new();
+    write_alu_csv(&alu_table(operation), &mut generated).unwrap();
+    let mut lines = generated.lines();
+    let header = lines.next().unwrap();
+    // Rows by their inputs, the first four columns.
+    let rows: HashMap<&str, &str> = lines.map(|line| (&line[..10], line)).collect();
+
+    let mut reference = reference.lines().filter(|line| !line.starts_with('#'));
+    assert_eq!(reference.next(), Some(header));
+    let mismatches: Vec<String> = reference
+        .filter_map(|expected| match rows.get(&expected[..10]) {
+            Some(&actual) if actual == expected => None,
+            Some(actual) => Some(format!("expected {}, got {}", expected, actual)),
+            None => Some(format!("expected {}, got no row", expected)),
+        })
+        .collect();
+    assert!(
+        mismatches.is_empty(),
+        "{} rows of {} differ:\n{}",
+        mismatches.len(),
+        operation,
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn add() {
+    check(AluOperation::Add);
+}
+
+#[test]
+fn daa() {
+    check(AluOperation::Daa);
+}
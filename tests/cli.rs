@@ -0,0 +1,604 @@
+#![cfg(feature = "cli")]
+
+use std::{
+    fs,
+    io::{self, Cursor, Read, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use rsoderh_jonsh_leben_emulator::{
+    cli::{self, Exit, HostIo},
+    trace::RecordedTrace,
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("leben-cli-{}-{}", std::process::id(), name))
+}
+
+/// Output of a run that the test can still read after handing it to [`cli::dispatch_with`].
+#[derive(Clone, Default)]
+struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+impl SharedOutput {
+    fn bytes(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Host I/O of a pipe rather than a terminal.
+fn piped(input: impl Read + Send + 'static, output: &SharedOutput) -> HostIo {
+    HostIo {
+        input: Box::new(input),
+        output: Box::new(output.clone()),
+        terminal: false,
+    }
+}
+
+#[test]
+fn missing_file() {
+    let path = temp_path("does-not-exist.bin");
+    let exit = cli::dispatch(["leben", "run", "--headless", path.to_str().unwrap()]);
+    assert_eq!(exit, Exit::Error);
+}
+
+#[test]
+fn bad_origin() {
+    let exit = cli::dispatch(["leben", "run", "--headless", "--origin", "0xZZ", "prog.bin"]);
+    assert_eq!(exit, Exit::Usage);
+
+    let exit = cli::dispatch(["leben", "run", "--headless", "--origin", "0x10000", "prog.bin"]);
+    assert_eq!(exit, Exit::Usage);
+}
+
+#[test]
+fn binary_too_large() {
+    let path = temp_path("too-large.bin");
+    fs::write(&path, [0x00; 0x11]).unwrap();
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--origin",
+        "0xFFF0",
+        path.to_str().unwrap(),
+    ]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(exit, Exit::Error);
+}
+
+#[test]
+fn unknown_extension() {
+    let path = temp_path("program.xyz");
+    fs::write(&path, [0x76]).unwrap();
+    let exit = cli::dispatch(["leben", "run", "--headless", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(exit, Exit::Usage);
+}
+
+#[test]
+fn headless_run() {
+    let program = temp_path("hello.bin");
+    let output = temp_path("hello.out");
+    fs::write(
+        &program,
+        [
+            0x3E, b'H', // MVI A, 'H'
+            0xD3, 0x00, // OUT 0
+            0x3E, b'i', // MVI A, 'i'
+            0xD3, 0x00, // OUT 0
+            0x76, // HLT
+        ],
+    )
+    .unwrap();
+
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--origin",
+        "0x100",
+        "--output-file",
+        output.to_str().unwrap(),
+        program.to_str().unwrap(),
+    ]);
+    let written = fs::read(&output).unwrap();
+    fs::remove_file(&program).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(written, b"Hi");
+}
+
+#[test]
+fn headless_run_intel_hex() {
+    let program = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/hello.hex");
+    let output = temp_path("hello-hex.out");
+
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--output-file",
+        output.to_str().unwrap(),
+        program,
+    ]);
+    let written = fs::read(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(written, b"Hi");
+
+    let program = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/bad_checksum.hex");
+    let exit = cli::dispatch(["leben", "run", "--headless", program]);
+    assert_eq!(exit, Exit::Error);
+}
+
+#[test]
+fn headless_run_cpm() {
+    let program = temp_path("hello.com");
+    let output = temp_path("hello-com.out");
+    fs::write(
+        &program,
+        [
+            0x0E, 0x02, // MVI C, 2
+            0x1E, b'!', // MVI E, '!'
+            0xCD, 0x05, 0x00, // CALL 0005H
+            0xC9, // RET
+        ],
+    )
+    .unwrap();
+
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--cpm",
+        "--output-file",
+        output.to_str().unwrap(),
+        program.to_str().unwrap(),
+    ]);
+    let written = fs::read(&output).unwrap();
+    fs::remove_file(&program).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(written, b"!");
+}
+
+#[test]
+fn headless_run_budget_exhausted() {
+    let program = temp_path("loop.bin");
+    let output = temp_path("loop.out");
+    // JMP 0000H
+    fs::write(&program, [0xC3, 0x00, 0x00]).unwrap();
+
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--max-instructions",
+        "1000",
+        "--output-file",
+        output.to_str().unwrap(),
+        program.to_str().unwrap(),
+    ]);
+    fs::remove_file(&program).unwrap();
+    let _ = fs::remove_file(&output);
+
+    assert_eq!(exit, Exit::Fault);
+}
+
+#[test]
+fn headless_run_trace_file() {
+    let program = temp_path("trace.bin");
+    let output = temp_path("trace.out");
+    let trace = temp_path("trace.txt");
+    fs::write(
+        &program,
+        [
+            0x3E, 0x2A, // MVI A, 2AH
+            0x76, // HLT
+        ],
+    )
+    .unwrap();
+
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--output-file",
+        output.to_str().unwrap(),
+        "--trace-file",
+        trace.to_str().unwrap(),
+        program.to_str().unwrap(),
+    ]);
+    let written = fs::read_to_string(&trace).unwrap();
+    fs::remove_file(&program).unwrap();
+    fs::remove_file(&output).unwrap();
+    fs::remove_file(&trace).unwrap();
+
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(
+        written,
+        "PC=0000 AF=00 02 BC=0000 DE=0000 HL=0000 SP=0000  MVI A,2AH\n\
+         PC=0002 AF=2A 02 BC=0000 DE=0000 HL=0000 SP=0000  HLT\n"
+    );
+}
+
+#[test]
+fn headless_run_json_trace() {
+    let program = temp_path("json-trace.bin");
+    let output = temp_path("json-trace.out");
+    let trace = temp_path("json-trace.jsonl");
+    fs::write(
+        &program,
+        [
+            0x3E, 0x2A, // MVI A, 2AH
+            0x32, 0x00, 0x20, // STA 2000H
+            0xD3, 0x00, // OUT 0
+            0x76, // HLT
+        ],
+    )
+    .unwrap();
+
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--output-file",
+        output.to_str().unwrap(),
+        "--trace-file",
+        trace.to_str().unwrap(),
+        "--trace-format",
+        "json",
+        program.to_str().unwrap(),
+    ]);
+    let written = fs::read(&trace).unwrap();
+    fs::remove_file(&program).unwrap();
+    fs::remove_file(&output).unwrap();
+    fs::remove_file(&trace).unwrap();
+
+    assert_eq!(exit, Exit::Success);
+    let recorded = RecordedTrace::read(&written[..]).unwrap();
+    assert_eq!(recorded.steps(), 4);
+    assert_eq!(recorded.record(1).unwrap().writes, Some(vec![(0x2000, 0x2A)]));
+    assert_eq!(recorded.record(2).unwrap().output, b"*");
+}
+
+#[test]
+fn replay_invalid_trace() {
+    let trace = temp_path("invalid.jsonl");
+    fs::write(&trace, "{\"pc\": \"0000\"}\n").unwrap();
+
+    let exit = cli::dispatch(["leben", "replay", trace.to_str().unwrap()]);
+    fs::remove_file(&trace).unwrap();
+
+    assert_eq!(exit, Exit::Error);
+}
+
+#[test]
+fn headless_run_coverage_report() {
+    let program = temp_path("coverage.bin");
+    let report = temp_path("coverage.txt");
+    fs::write(
+        &program,
+        [
+            0x3E, 0x2A, // MVI A, 2AH
+            0x76, // HLT
+        ],
+    )
+    .unwrap();
+
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--coverage-report",
+        report.to_str().unwrap(),
+        program.to_str().unwrap(),
+    ]);
+    let written = fs::read_to_string(&report).unwrap();
+    fs::remove_file(&program).unwrap();
+    fs::remove_file(&report).unwrap();
+
+    assert_eq!(exit, Exit::Success);
+    assert!(written.starts_with(
+        "OP  INSTRUCTION  EXECUTED  ADDRESSES\n\
+         3E  MVI A,d8            1  0000\n\
+         76  HLT                 1  0002\n\
+         \n\
+         2 of 244 documented opcodes executed, missing:\n\
+         00 NOP\n"
+    ));
+}
+
+#[test]
+fn test_directory() {
+    let dir = temp_path("suite");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("pass.asm"),
+        "        MVI A, 41H\n        OUT 0\n        HLT\n        END\n",
+    )
+    .unwrap();
+    fs::write(dir.join("pass.expected"), "A").unwrap();
+    fs::write(dir.join("spin.asm"), "LOOP:   JMP LOOP\n        END\n").unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let exit = cli::dispatch(["leben", "test", dir_arg, "--max-instructions", "100"]);
+    assert_eq!(exit, Exit::Fault);
+
+    fs::remove_file(dir.join("spin.asm")).unwrap();
+    let exit = cli::dispatch(["leben", "test", dir_arg, "--jobs", "1"]);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(exit, Exit::Success);
+}
+
+#[test]
+fn save_and_resume() {
+    let program = temp_path("countdown.bin");
+    let other = temp_path("other.bin");
+    let save = temp_path("countdown.sav");
+    let full = temp_path("countdown-full.out");
+    let resumed = temp_path("countdown-resumed.out");
+    fs::write(
+        &program,
+        [
+            0x06, 0x0A, // MVI B, 10
+            0x78, // MOV A, B
+            0xC6, 0x40, // ADI 40H
+            0xD3, 0x00, // OUT 0
+            0x05, // DCR B
+            0xC2, 0x02, 0x00, // JNZ 0002H
+            0x76, // HLT
+        ],
+    )
+    .unwrap();
+    fs::write(&other, [0x76]).unwrap();
+    let run = |args: &[&str]| {
+        cli::dispatch(
+            ["leben", "run", "--headless"]
+                .iter()
+                .chain(args)
+                .copied()
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    let exit = run(&[
+        "--output-file",
+        full.to_str().unwrap(),
+        program.to_str().unwrap(),
+    ]);
+    assert_eq!(exit, Exit::Success);
+
+    // Stop halfway through the loop, then continue in a separate run.
+    let exit = run(&[
+        "--max-instructions",
+        "23",
+        "--save-state",
+        save.to_str().unwrap(),
+        "--output-file",
+        resumed.to_str().unwrap(),
+        program.to_str().unwrap(),
+    ]);
+    assert_eq!(exit, Exit::Fault);
+    let exit = run(&[
+        "--resume",
+        save.to_str().unwrap(),
+        "--output-file",
+        resumed.to_str().unwrap(),
+        program.to_str().unwrap(),
+    ]);
+    assert_eq!(exit, Exit::Success);
+    let expected = fs::read(&full).unwrap();
+    assert_eq!(expected, b"JIHGFEDCBA");
+    assert_eq!(fs::read(&resumed).unwrap(), expected);
+
+    // A different program only warns.
+    let exit = run(&[
+        "--resume",
+        save.to_str().unwrap(),
+        "--output-file",
+        resumed.to_str().unwrap(),
+        other.to_str().unwrap(),
+    ]);
+    assert_eq!(exit, Exit::Success);
+
+    let exit = run(&["--resume", save.to_str().unwrap(), "--origin", "0x100"]);
+    assert_eq!(exit, Exit::Usage);
+
+    for path in [program, other, save, full, resumed] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn alu_dump() {
+    let output = temp_path("daa.csv");
+    let exit = cli::dispatch(["leben", "alu-dump", "daa", "-o", output.to_str().unwrap()]);
+    let table = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert_eq!(exit, Exit::Success);
+    assert!(table.starts_with("a,b,cy,ac,result,flags\n00,00,0,0,"));
+    assert_eq!(table.lines().count(), 1 + 256 * 4);
+
+    let exit = cli::dispatch(["leben", "alu-dump", "adi"]);
+    assert_eq!(exit, Exit::Usage);
+}
+
+#[test]
+fn load_segments() {
+    let code = temp_path("segments.asm");
+    let data = temp_path("segments.bin");
+    let output = temp_path("segments.out");
+    fs::write(
+        &code,
+        "        LDA 2000H\n        OUT 0\n        LDA 2001H\n        OUT 0\n        HLT\n        END\n",
+    )
+    .unwrap();
+    fs::write(&data, b"ok").unwrap();
+    let data_at = |origin: &str| format!("{}@{}", data.to_str().unwrap(), origin);
+
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--output-file",
+        output.to_str().unwrap(),
+        code.to_str().unwrap(),
+        "--load",
+        &data_at("0x2000"),
+    ]);
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(fs::read(&output).unwrap(), b"ok");
+
+    // The data overlaps the code.
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        code.to_str().unwrap(),
+        "--load",
+        &data_at("0x0004"),
+    ]);
+    assert_eq!(exit, Exit::Error);
+
+    // Overlapping files can be allowed, the later one wins.
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--allow-overlap",
+        "--output-file",
+        output.to_str().unwrap(),
+        code.to_str().unwrap(),
+        "--load",
+        &data_at("0x2000"),
+        "--load",
+        &data_at("0x2001"),
+    ]);
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(fs::read(&output).unwrap(), b"oo");
+
+    // Without a program, execution starts at the first segment unless told otherwise.
+    let exit = cli::dispatch([
+        "leben",
+        "run",
+        "--headless",
+        "--max-instructions",
+        "10",
+        "--load",
+        &data_at("0x2000"),
+        "--load",
+        code.to_str().unwrap(),
+        "--entry",
+        "0",
+        "--output-file",
+        output.to_str().unwrap(),
+    ]);
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(fs::read(&output).unwrap(), b"ok");
+
+    for path in [code, data, output] {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn piped_echo_runs_headless() {
+    let program = temp_path("cat.bin");
+    fs::write(
+        &program,
+        [
+            0xDB, 0x00, // IN 0
+            0xD3, 0x00, // OUT 0
+            0xC3, 0x00, 0x00, // JMP 0000H
+        ],
+    )
+    .unwrap();
+
+    // Without --headless, the UI is skipped because the output isn't a terminal. The program halts
+    // when the input ends.
+    let output = SharedOutput::default();
+    let exit = cli::dispatch_with(
+        ["leben", "run", program.to_str().unwrap()],
+        piped(Cursor::new(b"copy me\n".to_vec()), &output),
+    );
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(output.bytes(), b"copy me\n");
+
+    let exit = cli::dispatch_with(
+        [
+            "leben",
+            "run",
+            "--headless",
+            "--ui",
+            program.to_str().unwrap(),
+        ],
+        piped(io::empty(), &output),
+    );
+    fs::remove_file(&program).unwrap();
+    assert_eq!(exit, Exit::Usage);
+}
+
+/// Input that checks the output written so far before handing out each byte.
+struct Prompted {
+    output: SharedOutput,
+    expected_prompts: Vec<&'static [u8]>,
+    answers: Vec<u8>,
+}
+
+impl Read for Prompted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.answers.is_empty() {
+            return Ok(0);
+        }
+        assert_eq!(self.output.bytes(), self.expected_prompts.remove(0));
+        buf[0] = self.answers.remove(0);
+        Ok(1)
+    }
+}
+
+#[test]
+fn piped_output_is_written_before_input_is_read() {
+    let program = temp_path("prompt.bin");
+    fs::write(
+        &program,
+        [
+            0x3E, b'?', // MVI A, '?'
+            0xD3, 0x00, // OUT 0
+            0xDB, 0x00, // IN 0
+            0xD3, 0x00, // OUT 0
+            0xC3, 0x00, 0x00, // JMP 0000H
+        ],
+    )
+    .unwrap();
+
+    let output = SharedOutput::default();
+    let input = Prompted {
+        output: output.clone(),
+        expected_prompts: vec![b"?", b"?a?"],
+        answers: b"ab".to_vec(),
+    };
+    let exit = cli::dispatch_with(
+        ["leben", "run", program.to_str().unwrap()],
+        piped(input, &output),
+    );
+    fs::remove_file(&program).unwrap();
+
+    assert_eq!(exit, Exit::Success);
+    assert_eq!(output.bytes(), b"?a?b?");
+}
@@ -0,0 +1,38 @@
+//! Runs CP/M programs through the BDOS shim of `Machine::enable_cpm_shim`, the way the 8080
+//! diagnostics expect to be run.
+
+use rsoderh_jonsh_leben_emulator::machine::{HaltReason, MachineBuilder, MachineState, RunOutcome};
+
+/// Prints a greeting with function 9, a newline with function 2, and exits through the warm boot.
+const HELLO: &str = "        ORG 100H
+        LXI SP, 0F000H
+        LXI D, MSG
+        MVI C, 9
+        CALL 5
+        MVI E, 0AH
+        MVI C, 2
+        CALL 5
+        JMP 0
+MSG:    DB 'Hello, CP/M!$'
+        END
+";
+
+#[test]
+fn hello() {
+    let mut machine = MachineBuilder::new()
+        .assembly(HELLO.as_bytes())
+        .build()
+        .unwrap();
+    machine.enable_cpm_shim();
+
+    assert_eq!(
+        machine.run_until_halt(1000),
+        (RunOutcome::Halted(HaltReason::HaltInstruction), 11)
+    );
+    assert_eq!(machine.output_buffer(), b"Hello, CP/M!\n");
+    assert_eq!(machine.pc().value(), 0x0000);
+    assert_eq!(
+        machine.state(),
+        MachineState::Halted(HaltReason::HaltInstruction)
+    );
+}
@@ -0,0 +1,34 @@
+;
+; Write HELLO to the top left of the framebuffer at 0F000H, and WORLD
+; below it, starting at column 10
+;
+
+        ORG 0
+
+        LXI SP, 0E000H
+
+        LXI H, HELLO
+        LXI D, 0F000H
+        CALL PRINT
+
+        LXI H, WORLD
+        LXI D, 0F02AH    ; Row 1, column 10
+        CALL PRINT
+
+        HLT
+
+; Copy the zero-terminated string at HL to DE
+PRINT:  MOV A, M
+        ORA A
+        RZ
+        STAX D
+        INX H
+        INX D
+        JMP PRINT
+
+HELLO:  DB 'HELLO'
+        DB 0
+WORLD:  DB 'WORLD'
+        DB 0
+
+        END
@@ -0,0 +1,38 @@
+;
+; Fixture for the disassembly listing tests: labels from the assembler are used instead of
+; synthesized ones, strings and tables are listed as data
+;
+
+        ORG 100H
+
+START:  LXI SP, 0F000H
+        LXI H, GREET
+        CALL PRINT
+        LDA COUNT
+        MOV B, A
+LOOP:   MOV A, B
+        ADI 30H
+        OUT 0
+        DCR B
+        JNZ LOOP
+        LHLD TABLE
+        CALL PRINT
+        HLT
+
+; Print the zero-terminated string at HL
+PRINT:  MOV A, M
+        CPI 0
+        RZ
+        OUT 0
+        INX H
+        JMP PRINT
+
+GREET:  DB 'Counting: '
+        DB 0
+COUNT:  DB 3
+TABLE:  DW DONE
+DONE:   DB ' done'
+        DB 0AH
+        DB 0
+BUF:    DS 6
+        END
@@ -0,0 +1,122 @@
+//! Golden-file tests for `leben disasm`. The expected listings are in `tests/data/*.lst`; run with
+//! `LEBEN_BLESS=1` to write the current listings to them instead of comparing.
+#![cfg(feature = "cli")]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use rsoderh_jonsh_leben_emulator::cli::{self, Exit};
+
+const BLESS_VAR: &str = "LEBEN_BLESS";
+
+fn data_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("leben-disasm-{}-{}", std::process::id(), name))
+}
+
+/// Disassemble `program` with the extra `args` and compare the listing to `expected`.
+fn check(program: &Path, args: &[&str], expected: &str) {
+    let output = temp_path(expected);
+    let mut command = vec![
+        "leben",
+        "disasm",
+        program.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+    ];
+    command.extend_from_slice(args);
+    assert_eq!(cli::dispatch(command), Exit::Success);
+    let listing = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+
+    let expected_path = data_path(expected);
+    if env::var_os(BLESS_VAR).is_some_and(|value| value != "0") {
+        fs::write(&expected_path, &listing).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&expected_path).unwrap();
+    assert_eq!(
+        listing,
+        expected,
+        "listing differs from {}",
+        expected_path.display()
+    );
+}
+
+#[test]
+fn assembled_program_uses_its_labels() {
+    check(&data_path("listing.asm"), &[], "listing.lst");
+}
+
+#[test]
+fn binary_with_data_in_the_middle() {
+    let program = temp_path("junk.bin");
+    #[rustfmt::skip]
+    fs::write(
+        &program,
+        [
+            0x31, 0x00, 0xF0, // LXI SP, 0F000H
+            0x21, 0x15, 0x01, // LXI H, 0115H
+            0xCD, 0x0C, 0x01, // CALL 010CH
+            0xC3, 0x1D, 0x01, // JMP 011DH
+            0x7E,             // MOV A, M
+            0xB7,             // ORA A
+            0xC8,             // RZ
+            0xD3, 0x00,       // OUT 0
+            0x23,             // INX H
+            0xC3, 0x0C, 0x01, // JMP 010CH
+            b'J', b'u', b'n', b'k', 0xFF, 0xC3, 0xED, 0x00, // never executed
+            0x3A, 0x19, 0x01, // LDA 0119H
+            0x76,             // HLT
+        ],
+    )
+    .unwrap();
+    check(
+        &program,
+        &["--origin", "0x100", "--format", "bin"],
+        "junk.lst",
+    );
+    fs::remove_file(&program).unwrap();
+}
+
+#[test]
+fn linear_listing_decodes_every_byte() {
+    let program = temp_path("linear.bin");
+    #[rustfmt::skip]
+    fs::write(
+        &program,
+        [
+            0x3E, 0x2A,       // MVI A, 2AH
+            0xD3, 0x00,       // OUT 0
+            0xC3, 0x02, 0x01, // JMP 0102H
+            b'O', b'K', 0x00, // data, decoded as instructions anyway
+            0xCD, 0x00,       // CALL cut off by the end of the file
+        ],
+    )
+    .unwrap();
+    check(
+        &program,
+        &["--origin", "0x100", "--format", "bin", "--linear"],
+        "linear.lst",
+    );
+    fs::remove_file(&program).unwrap();
+}
+
+#[test]
+fn default_output_path() {
+    let program = temp_path("default.bin");
+    fs::write(&program, [0x76]).unwrap();
+    let exit = cli::dispatch(["leben", "disasm", program.to_str().unwrap()]);
+    assert_eq!(exit, Exit::Success);
+    let listing = program.with_extension("lst");
+    assert!(fs::read_to_string(&listing).unwrap().contains("0000  76"));
+    fs::remove_file(&program).unwrap();
+    fs::remove_file(&listing).unwrap();
+}
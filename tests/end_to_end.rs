@@ -0,0 +1,179 @@
+//! End-to-end tests of the programs in `tests/end_to_end`.
+//!
+//! Every program is assembled from source and run headlessly until it halts, then both its output
+//! and the memory it leaves behind are checked. The programs keep their data right after a `JMP`
+//! at address 0, so the tests know where to look without the assembler's labels.
+//!
+//! Programs that still trip over known emulator bugs are ignored with the bug as the reason. Run
+//! them with `cargo test --test end_to_end -- --include-ignored`.
+
+use std::{fs, ops::Range, path::Path};
+
+use rsoderh_jonsh_leben_emulator::machine::{HaltReason, Machine, MachineBuilder, RunOutcome};
+
+const INSTRUCTION_BUDGET: u64 = 1_000_000;
+
+/// Assemble and run `name` until it halts with `HLT`.
+fn run(name: &str) -> Machine {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/end_to_end")
+        .join(name);
+    let source = fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    let mut machine = MachineBuilder::new()
+        .assembly(&source)
+        .build()
+        .unwrap_or_else(|err| panic!("{}: {}", name, err));
+
+    let (outcome, executed) = machine.run_until_halt(INSTRUCTION_BUDGET);
+    assert_eq!(
+        outcome,
+        RunOutcome::Halted(HaltReason::HaltInstruction),
+        "{} stopped after {} instructions",
+        name,
+        executed
+    );
+    machine
+}
+
+fn output(machine: &Machine) -> &str {
+    std::str::from_utf8(machine.output_buffer()).unwrap()
+}
+
+fn memory(machine: &Machine, range: Range<usize>) -> &[u8] {
+    &machine.memory().as_raw()[range]
+}
+
+#[test]
+fn fibonacci() {
+    let machine = run("fibonacci.asm");
+    let numbers: [u16; 15] = [0, 1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 144, 233, 377];
+
+    let expected: String = numbers.iter().map(|n| format!("{}\n", n)).collect();
+    assert_eq!(output(&machine), expected);
+    let words: Vec<u8> = numbers.iter().flat_map(|n| n.to_le_bytes()).collect();
+    assert_eq!(memory(&machine, 0x0003..0x0021), words);
+}
+
+#[test]
+fn bubble_sort() {
+    let machine = run("bubble_sort.asm");
+    let sorted = [0, 1, 7, 7, 19, 42, 64, 128, 200, 255];
+
+    let expected: String = sorted.iter().map(|n| format!("{}\n", n)).collect();
+    assert_eq!(output(&machine), expected);
+    assert_eq!(memory(&machine, 0x0003..0x000D), sorted);
+}
+
+#[test]
+fn reverse() {
+    let machine = run("reverse.asm");
+
+    assert_eq!(output(&machine), "desserts\n");
+    assert_eq!(memory(&machine, 0x0003..0x000C), b"stressed\0");
+    assert_eq!(memory(&machine, 0x000C..0x0015), b"desserts\0");
+}
+
+#[test]
+fn multiply() {
+    let machine = run("multiply.asm");
+
+    assert_eq!(output(&machine), "143\n24600\n65025\n0\n");
+    let mut expected = Vec::new();
+    for (a, b) in [(13u8, 11u8), (200, 123), (255, 255), (0, 77)] {
+        expected.extend([a, b]);
+        expected.extend((a as u16 * b as u16).to_le_bytes());
+    }
+    assert_eq!(memory(&machine, 0x0003..0x0013), expected);
+}
+
+#[test]
+fn nested_calls() {
+    let machine = run("nested_calls.asm");
+
+    assert_eq!(output(&machine), "ABCa");
+    // Three return addresses deep.
+    assert_eq!(memory(&machine, 0x0003..0x0005), 0xEFFAu16.to_le_bytes());
+    assert_eq!(memory(&machine, 0x0005..0x0007), 0xF000u16.to_le_bytes());
+    assert_eq!(machine.pc().value(), 0x0014);
+}
+
+/// `leben diff` on states of the same program saved at different points.
+#[cfg(feature = "cli")]
+#[test]
+fn diff_states() {
+    use rsoderh_jonsh_leben_emulator::cli::{self, Exit, HostIo};
+
+    fn dispatch(args: &[&str]) -> (Exit, String) {
+        let output = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let host = HostIo {
+            input: Box::new(std::io::empty()),
+            output: Box::new(SharedOutput(output.clone())),
+            terminal: false,
+        };
+        let exit = cli::dispatch_with(std::iter::once("leben").chain(args.iter().copied()), host);
+        let output = String::from_utf8(output.lock().unwrap().clone()).unwrap();
+        (exit, output)
+    }
+
+    struct SharedOutput(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let program = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/end_to_end/fibonacci.asm");
+    let program = program.to_str().unwrap();
+    let temp = |name: &str| {
+        std::env::temp_dir()
+            .join(format!("leben-diff-{}-{}", std::process::id(), name))
+            .to_str()
+            .unwrap()
+            .to_owned()
+    };
+    let (halted, stopped) = (temp("halted.json"), temp("stopped.sav"));
+
+    let (exit, _) = dispatch(&[
+        "run",
+        "--headless",
+        program,
+        "--dump-state-on-halt",
+        &halted,
+    ]);
+    assert_eq!(exit, Exit::Success);
+    let (exit, _) = dispatch(&[
+        "run",
+        "--headless",
+        program,
+        "--max-instructions",
+        "10",
+        "--save-state",
+        &stopped,
+    ]);
+    assert_eq!(exit, Exit::Fault);
+
+    assert_eq!(
+        dispatch(&["diff", &halted, &halted]),
+        (Exit::Success, String::from("no differences\n"))
+    );
+    let (exit, output) = dispatch(&["diff", &halted, &stopped]);
+    assert_eq!(exit, Exit::Fault);
+    // After 10 instructions the second number, 1, isn't stored yet.
+    assert!(output.contains("\nPC: 0044 vs 0036\n"), "{}", output);
+    assert!(
+        output.contains("\nmemory 0005-0005: 01 vs 00\n"),
+        "{}",
+        output
+    );
+
+    let (exit, _) = dispatch(&["diff", &halted, &temp("missing.json")]);
+    assert_eq!(exit, Exit::Error);
+
+    fs::remove_file(&halted).unwrap();
+    fs::remove_file(&stopped).unwrap();
+}
@@ -0,0 +1,51 @@
+;
+; Sort TABLE in place with bubble sort, then print it
+;
+
+        ORG 0
+
+        JMP START
+TABLE:  DB 42           ; 0003H
+        DB 7
+        DB 255
+        DB 0
+        DB 128
+        DB 19
+        DB 7
+        DB 200
+        DB 1
+        DB 64
+
+START:  LXI SP, 0F000H
+
+PASS:   MVI C, 0        ; C = 1 if this pass swapped anything
+        LXI H, TABLE
+        MVI B, 9        ; Pairs to compare
+NEXT:   MOV A, M        ; A = first of the pair, D = second
+        INX H
+        MOV D, M
+        CMP D
+        JC NOSWP        ; A < D
+        JZ NOSWP        ; A = D
+        MOV M, A        ; Swap
+        DCX H
+        MOV M, D
+        INX H
+        MVI C, 1
+NOSWP:  DCR B
+        JNZ NEXT
+        MOV A, C
+        ORA A
+        JNZ PASS
+
+        LXI H, TABLE
+        MVI B, 10
+PRINT:  MOV A, M
+        OUT 1
+        MVI A, 0AH
+        OUT 0
+        INX H
+        DCR B
+        JNZ PRINT
+        HLT
+        END
@@ -0,0 +1,34 @@
+;
+; Print the first 15 Fibonacci numbers and store them as words in FIBS.
+; OUT 1 only prints one byte and the last numbers don't fit in one, so they
+; are printed from HL with OUT 2.
+;
+
+        ORG 0
+
+        JMP START
+FIBS:   DS 30           ; 0003H
+
+START:  LXI SP, 0F000H
+        LXI D, FIBS
+        LXI B, 1        ; BC = the number before HL
+        LXI H, 0        ; HL = the next number to print
+        MVI A, 15
+LOOP:   PUSH PSW        ; Numbers left
+        OUT 2
+        MVI A, 0AH
+        OUT 0
+        MOV A, L        ; Store HL at DE
+        STAX D
+        INX D
+        MOV A, H
+        STAX D
+        INX D
+        PUSH H          ; HL, BC = HL + BC, HL
+        DAD B
+        POP B
+        POP PSW
+        DCR A
+        JNZ LOOP
+        HLT
+        END
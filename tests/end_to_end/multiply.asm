@@ -0,0 +1,68 @@
+;
+; Multiply pairs of bytes with shift-and-add, storing and printing the products
+;
+
+        ORG 0
+
+        JMP START
+PAIRS:  DB 13           ; 0003H: multiplicand, multiplier, product
+        DB 11
+        DW 0
+        DB 200          ; 0007H
+        DB 123
+        DW 0
+        DB 255          ; 000BH
+        DB 255
+        DW 0
+        DB 0            ; 000FH
+        DB 77
+        DW 0
+
+START:  LXI SP, 0F000H
+
+        LXI B, PAIRS
+        MVI A, 4
+LOOP:   PUSH PSW        ; Pairs left
+        LDAX B
+        MOV D, A
+        INX B
+        LDAX B
+        MOV E, A
+        INX B
+        PUSH B
+        CALL MUL
+        POP B
+        MOV A, L        ; Store the product after the pair
+        STAX B
+        INX B
+        MOV A, H
+        STAX B
+        INX B
+        OUT 2
+        MVI A, 0AH
+        OUT 0
+        POP PSW
+        DCR A
+        JNZ LOOP
+        HLT
+
+; HL = D * E. Adds D, shifted left once per bit, for every set bit of E.
+MUL:    LXI H, 0
+        MOV C, D        ; BC = D, shifted
+        MVI B, 0
+        MVI D, 8        ; Bits left
+MBIT:   MOV A, E        ; Rotate the next bit of E into the carry
+        RRC
+        MOV E, A
+        JNC MSKIP
+        DAD B
+MSKIP:  MOV A, C        ; BC = BC * 2
+        ADD A
+        MOV C, A
+        MOV A, B
+        ADC A
+        MOV B, A
+        DCR D
+        JNZ MBIT
+        RET
+        END
@@ -0,0 +1,41 @@
+;
+; Call subroutines three levels deep, printing a letter on the way in and out, and store the
+; stack pointer at the deepest level and after returning
+;
+
+        ORG 0
+
+        JMP START
+DEEP:   DW 0            ; 0003H: SP inside THREE
+TOP:    DW 0            ; 0005H: SP after returning
+
+START:  LXI SP, 0F000H
+        CALL ONE
+        LXI H, 0
+        DAD SP
+        SHLD TOP
+        HLT             ; 0014H
+
+ONE:    MVI A, 41H      ; 'A'
+        OUT 0
+        CALL TWO
+        MVI A, 61H      ; 'a'
+        OUT 0
+        RET
+
+TWO:    MVI A, 42H      ; 'B'
+        OUT 0
+        CALL THREE
+        XRA A           ; Return through a taken RZ
+        RZ
+        HLT             ; Not reached
+
+THREE:  MVI A, 43H      ; 'C'
+        OUT 0
+        LXI H, 0
+        DAD SP
+        SHLD DEEP
+        ORI 1           ; RZ is not taken
+        RZ
+        RET
+        END
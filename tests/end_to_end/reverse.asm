@@ -0,0 +1,37 @@
+;
+; Reverse a string by pushing its characters onto the stack and popping them
+; into REV, printing them on the way
+;
+
+        ORG 0
+
+        JMP START
+TEXT:   DB 'stressed'   ; 0003H
+        DB 0
+REV:    DS 9            ; 000CH
+
+START:  LXI SP, 0F000H
+
+        LXI H, TEXT
+        MVI B, 0        ; B = characters on the stack
+PUSHC:  MOV A, M
+        CPI 0
+        JZ POPS
+        PUSH PSW
+        INX H
+        INR B
+        JMP PUSHC
+
+POPS:   LXI H, REV
+POPC:   POP PSW
+        MOV M, A
+        OUT 0
+        INX H
+        DCR B
+        JNZ POPC
+        MVI M, 0
+
+        MVI A, 0AH
+        OUT 0
+        HLT
+        END
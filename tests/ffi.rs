@@ -0,0 +1,165 @@
+//! Exercise the C interface through the C ABI, declaring the functions the way a C host would.
+
+use std::ffi::{CStr, c_char};
+
+// Link the library even though nothing is imported from it on the Rust side.
+use rsoderh_jonsh_leben_emulator as _;
+
+#[repr(C)]
+struct LebenMachine {
+    _private: [u8; 0],
+}
+
+unsafe extern "C" {
+    fn leben_create() -> *mut LebenMachine;
+    fn leben_destroy(machine: *mut LebenMachine);
+    fn leben_load_program(
+        machine: *mut LebenMachine,
+        bytes: *const u8,
+        len: usize,
+        origin: u16,
+    ) -> i32;
+    fn leben_step(machine: *mut LebenMachine) -> i32;
+    fn leben_run(machine: *mut LebenMachine, max_instructions: u64) -> i64;
+    fn leben_state(machine: *const LebenMachine) -> i32;
+    fn leben_get_register(machine: *const LebenMachine, register_id: u32, out: *mut u8) -> i32;
+    fn leben_get_flags(machine: *const LebenMachine, out: *mut u8) -> i32;
+    fn leben_get_pc(machine: *const LebenMachine) -> u16;
+    fn leben_get_sp(machine: *const LebenMachine) -> u16;
+    fn leben_peek(machine: *const LebenMachine, address: u16, buf: *mut u8, len: usize) -> i32;
+    fn leben_poke(machine: *mut LebenMachine, address: u16, buf: *const u8, len: usize) -> i32;
+    fn leben_push_input(machine: *mut LebenMachine, bytes: *const u8, len: usize) -> i32;
+    fn leben_drain_output(machine: *mut LebenMachine, buf: *mut u8, capacity: usize) -> i64;
+    fn leben_last_error_message() -> *const c_char;
+}
+
+const LEBEN_OK: i32 = 0;
+const LEBEN_ERR_NULL: i32 = -1;
+const LEBEN_ERR_INVALID_ARGUMENT: i32 = -2;
+const LEBEN_STATE_RUNNING: i32 = 0;
+const LEBEN_STATE_HALTED: i32 = 1;
+const LEBEN_REGISTER_A: u32 = 0;
+const LEBEN_REGISTER_B: u32 = 1;
+
+fn last_error() -> String {
+    let message = unsafe { leben_last_error_message() };
+    assert!(!message.is_null());
+    unsafe { CStr::from_ptr(message) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+// 0100: IN 0
+// 0102: OUT 0
+// 0104: MVI B, 7
+// 0106: HLT
+const ECHO: [u8; 7] = [0xDB, 0x00, 0xD3, 0x00, 0x06, 0x07, 0x76];
+
+#[test]
+fn load_run_and_inspect() {
+    unsafe {
+        let machine = leben_create();
+        assert!(!machine.is_null());
+
+        assert_eq!(
+            leben_load_program(machine, ECHO.as_ptr(), ECHO.len(), 0x0100),
+            LEBEN_OK
+        );
+        assert_eq!(leben_get_pc(machine), 0x0100);
+        assert_eq!(leben_push_input(machine, b"x".as_ptr(), 1), LEBEN_OK);
+
+        assert_eq!(leben_step(machine), LEBEN_STATE_RUNNING);
+        assert_eq!(leben_run(machine, 100), 3);
+        assert_eq!(leben_state(machine), LEBEN_STATE_HALTED);
+
+        let mut value = 0;
+        assert_eq!(
+            leben_get_register(machine, LEBEN_REGISTER_A, &mut value),
+            LEBEN_OK
+        );
+        assert_eq!(value, b'x');
+        assert_eq!(
+            leben_get_register(machine, LEBEN_REGISTER_B, &mut value),
+            LEBEN_OK
+        );
+        assert_eq!(value, 7);
+        assert_eq!(leben_get_flags(machine, &mut value), LEBEN_OK);
+        assert_eq!(value, 0b0000_0010);
+        assert_eq!(leben_get_pc(machine), 0x0106);
+        assert_eq!(leben_get_sp(machine), 0x0000);
+
+        let mut output = [0; 1];
+        assert_eq!(
+            leben_drain_output(machine, output.as_mut_ptr(), output.len()),
+            1
+        );
+        assert_eq!(&output, b"x");
+        assert_eq!(
+            leben_drain_output(machine, output.as_mut_ptr(), output.len()),
+            0
+        );
+
+        leben_destroy(machine);
+    }
+}
+
+#[test]
+fn peek_and_poke() {
+    unsafe {
+        let machine = leben_create();
+
+        assert_eq!(leben_poke(machine, 0xFFFE, [1, 2].as_ptr(), 2), LEBEN_OK);
+        let mut bytes = [0; 3];
+        assert_eq!(leben_peek(machine, 0xFFFD, bytes.as_mut_ptr(), 3), LEBEN_OK);
+        assert_eq!(bytes, [0, 1, 2]);
+
+        assert_eq!(
+            leben_peek(machine, 0xFFFE, bytes.as_mut_ptr(), 3),
+            LEBEN_ERR_INVALID_ARGUMENT
+        );
+        assert!(last_error().contains("past the end of memory"));
+
+        leben_destroy(machine);
+    }
+}
+
+#[test]
+fn partial_output_drain() {
+    // MVI A, 'a'; OUT 0; OUT 0; OUT 0; HLT
+    let program = [0x3E, b'a', 0xD3, 0x00, 0xD3, 0x00, 0xD3, 0x00, 0x76];
+    unsafe {
+        let machine = leben_create();
+        leben_load_program(machine, program.as_ptr(), program.len(), 0);
+        leben_run(machine, 100);
+
+        let mut output = [0; 2];
+        assert_eq!(leben_drain_output(machine, output.as_mut_ptr(), 2), 2);
+        assert_eq!(leben_drain_output(machine, output.as_mut_ptr(), 2), 1);
+
+        leben_destroy(machine);
+    }
+}
+
+#[test]
+fn errors() {
+    unsafe {
+        assert_eq!(leben_step(std::ptr::null_mut()), LEBEN_ERR_NULL);
+        assert!(last_error().contains("machine"));
+
+        let machine = leben_create();
+        let mut value = 0;
+        assert_eq!(
+            leben_get_register(machine, 42, &mut value),
+            LEBEN_ERR_INVALID_ARGUMENT
+        );
+        assert_eq!(last_error(), "unknown register 42");
+        assert_eq!(
+            leben_get_register(machine, LEBEN_REGISTER_A, std::ptr::null_mut()),
+            LEBEN_ERR_NULL
+        );
+
+        // Destroying null is allowed.
+        leben_destroy(std::ptr::null_mut());
+        leben_destroy(machine);
+    }
+}
@@ -0,0 +1,37 @@
+//! Replays the seed corpora and the regression inputs under `fuzz/` through the fuzz targets'
+//! checks, so inputs that once crashed keep being tested without running the fuzzer. When the
+//! fuzzer finds a crash, fix it and copy the input from `fuzz/artifacts/<target>/` to
+//! `fuzz/regressions/<target>/`.
+
+use std::{fs, path::Path};
+
+use rsoderh_jonsh_leben_emulator::fuzzing;
+
+fn replay(target: &str, check: fn(&[u8])) {
+    let fuzz_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz");
+    let mut replayed = 0;
+    for directory in ["corpus", "regressions"] {
+        let Ok(entries) = fs::read_dir(fuzz_dir.join(directory).join(target)) else {
+            continue;
+        };
+        let mut paths: Vec<_> = entries.map(|entry| entry.unwrap().path()).collect();
+        paths.sort();
+        for path in paths {
+            let input = fs::read(&path).unwrap();
+            println!("replaying {}", path.display());
+            check(&input);
+            replayed += 1;
+        }
+    }
+    assert!(replayed > 0, "no inputs found for {}", target);
+}
+
+#[test]
+fn assemble() {
+    replay("assemble", fuzzing::assemble);
+}
+
+#[test]
+fn decode() {
+    replay("decode", fuzzing::decode);
+}
@@ -0,0 +1,131 @@
+#![cfg(feature = "cli")]
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use rsoderh_jonsh_leben_emulator::{gdb, machine::MachineBuilder};
+
+/// Scripted GDB client speaking just enough RSP for the tests.
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn send(&mut self, data: &str) -> String {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, checksum).unwrap();
+        assert_eq!(self.read_byte(), b'+');
+        self.receive()
+    }
+
+    /// Send a packet that gets no reply.
+    fn send_kill(&mut self) {
+        write!(self.stream, "$k#6b").unwrap();
+        assert_eq!(self.read_byte(), b'+');
+    }
+
+    fn receive(&mut self) -> String {
+        assert_eq!(self.read_byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.read_byte(), self.read_byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(
+            checksum,
+            data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+}
+
+fn start(program: &[u8]) -> (Client, thread::JoinHandle<()>) {
+    let machine = MachineBuilder::new()
+        .program(program, 0x0000)
+        .sp(0x1000)
+        .build()
+        .unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        gdb::serve(machine, &listener).unwrap();
+    });
+    let stream = TcpStream::connect(address).unwrap();
+    (Client { stream }, server)
+}
+
+// 0000: MVI B, 3
+// 0002: DCR B
+// 0003: JNZ 0002H
+// 0006: MVI A, 2AH
+// 0008: HLT
+const COUNTDOWN: [u8; 9] = [0x06, 0x03, 0x05, 0xC2, 0x02, 0x00, 0x3E, 0x2A, 0x76];
+
+#[test]
+fn registers_breakpoint_and_continue() {
+    let (mut client, server) = start(&COUNTDOWN);
+
+    assert!(
+        client
+            .send("qSupported:swbreak+")
+            .contains("qXfer:features:read+")
+    );
+    assert_eq!(client.send("?"), "S05");
+    // a f b c d e h l sp pc
+    assert_eq!(client.send("g"), "000200000000000000100000");
+
+    assert_eq!(client.send("Z0,2,1"), "OK");
+    assert_eq!(client.send("c"), "S05");
+    assert_eq!(client.send("p9"), "0200");
+    assert_eq!(client.send("p2"), "03");
+
+    // The breakpoint inside the loop is hit on every iteration.
+    assert_eq!(client.send("c"), "S05");
+    assert_eq!(client.send("p2"), "02");
+
+    assert_eq!(client.send("z0,2,1"), "OK");
+    assert_eq!(client.send("c"), "W00");
+    assert_eq!(client.send("p0"), "2a");
+    assert_eq!(client.send("p9"), "0800");
+
+    assert_eq!(client.send("D"), "OK");
+    server.join().unwrap();
+}
+
+#[test]
+fn memory_and_step() {
+    let (mut client, server) = start(&COUNTDOWN);
+
+    assert_eq!(client.send("m0,3"), "060305");
+    // Patch the loop counter to 1.
+    assert_eq!(client.send("M1,1:01"), "OK");
+    assert_eq!(client.send("s"), "S05");
+    assert_eq!(client.send("p2"), "01");
+    assert_eq!(client.send("P2=07"), "OK");
+    assert_eq!(client.send("s"), "S05");
+    assert_eq!(client.send("p2"), "06");
+    assert_eq!(client.send("p9"), "0300");
+
+    let target = client.send("qXfer:features:read:target.xml:0,1000");
+    assert!(target.starts_with('l'));
+    assert!(target.contains(r#"<reg name="pc" bitsize="16""#));
+
+    assert_eq!(client.send("qUnknownPacket"), "");
+
+    client.send_kill();
+    server.join().unwrap();
+}
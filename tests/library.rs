@@ -0,0 +1,43 @@
+//! Uses the emulator the way a crate depending on it would, through the public API only.
+
+use rsoderh_jonsh_leben_emulator::{
+    assembler::parse_assembly,
+    coding::{decode, encode_program, reader::Reader},
+    instruction::{Instruction, Register},
+    machine::{HaltReason, MachineBuilder, RunOutcome},
+};
+
+const COUNTDOWN: &[u8] = b"        ORG 0100H
+        MVI B, 3
+LOOP:   MOV A, B
+        ADI '0'
+        OUT 0
+        DCR B
+        JNZ LOOP
+        HLT
+        END
+";
+
+#[test]
+fn assemble_load_and_run() {
+    let (items, origin) = parse_assembly(COUNTDOWN).unwrap();
+    assert_eq!(origin, 0x0100);
+    let mut program = Vec::new();
+    encode_program(&mut program, &items).unwrap();
+
+    let mut stream = Reader::new(&program);
+    assert_eq!(decode(&mut stream), Some(Instruction::Mvi(Register::B, 3)));
+    assert_eq!(
+        decode(&mut stream),
+        Some(Instruction::Mov(Register::A, Register::B))
+    );
+
+    let mut machine = MachineBuilder::new()
+        .program(&program, origin)
+        .build()
+        .unwrap();
+    let (outcome, _) = machine.run_until_halt(1000);
+    assert_eq!(outcome, RunOutcome::Halted(HaltReason::HaltInstruction));
+    assert_eq!(machine.take_output(), b"321");
+    assert_eq!(machine.register_8(Register::B), 0);
+}
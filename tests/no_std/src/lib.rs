@@ -0,0 +1,67 @@
+//! Proves that the machine builds and runs programs with only `core` and `alloc`.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use rsoderh_jonsh_leben_emulator::machine::{Machine, MachineBuilder, MachineState};
+
+/// Run `program` from address 0 until it halts, returning the final state, the number of
+/// decoded instructions and the program output.
+pub fn run(program: &[u8], input: &[u8]) -> (MachineState, usize, Vec<u8>) {
+    let mut machine: Machine = MachineBuilder::new()
+        .program(program, 0x0000)
+        .sp(0x1000)
+        .input(input)
+        .build()
+        .expect("program fits in memory");
+    let decoded = machine
+        .steps()
+        .filter(|step| step.instruction.is_some())
+        .count();
+    (machine.state(), decoded, machine.take_output())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsoderh_jonsh_leben_emulator::machine::HaltReason;
+
+    #[test]
+    fn runs_program() {
+        // MVI A, 'H'; OUT 0; IN 0; OUT 1; HLT
+        let program = [0x3E, 0x48, 0xD3, 0x00, 0xDB, 0x00, 0xD3, 0x01, 0x76];
+        let (state, decoded, output) = run(&program, &[42]);
+        assert_eq!(state, MachineState::Halted(HaltReason::HaltInstruction));
+        assert_eq!(decoded, 5);
+        assert_eq!(output, b"H42");
+    }
+
+    #[test]
+    fn runs_with_devices() {
+        use alloc::boxed::Box;
+        use rsoderh_jonsh_leben_emulator::devices::{Framebuffer, ScriptedInput};
+
+        // IN 0; STA F000H; HLT
+        let program = [0xDB, 0x00, 0x32, 0x00, 0xF0, 0x76];
+        let mut machine = MachineBuilder::new()
+            .program(&program, 0x0000)
+            .build()
+            .unwrap();
+        machine.attach_device(&[0], Box::new(ScriptedInput::new(b"@")));
+        Framebuffer::default().attach(&mut machine);
+        machine.steps().for_each(drop);
+
+        let framebuffer = machine.memory_handler::<Framebuffer>().unwrap();
+        assert_eq!(framebuffer.cells()[0][0], b'@');
+    }
+
+    #[test]
+    fn invalid_instruction_halts() {
+        let (state, decoded, _) = run(&[0x00, 0x08], &[]);
+        assert_eq!(state, MachineState::Halted(HaltReason::InvalidInstruction));
+        assert_eq!(decoded, 1);
+    }
+}
@@ -0,0 +1,27 @@
+//! Proves that the library builds and runs assembly with `std` but without the terminal UI and
+//! the CLI, and so without their dependencies.
+
+use rsoderh_jonsh_leben_emulator::machine::{Machine, MachineBuilder, MachineState};
+
+/// Assemble `source`, run it until it halts and return the final state and the program output.
+pub fn run(source: &str) -> (MachineState, Vec<u8>) {
+    let mut machine: Machine = MachineBuilder::new()
+        .assembly(source.as_bytes())
+        .build()
+        .expect("program assembles");
+    machine.steps().count();
+    (machine.state(), machine.take_output())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsoderh_jonsh_leben_emulator::machine::HaltReason;
+
+    #[test]
+    fn runs_assembly() {
+        let (state, output) = run("MVI A, 48H\nOUT 0\nMVI A, 49H\nOUT 0\nHLT\nEND\n");
+        assert_eq!(state, MachineState::Halted(HaltReason::HaltInstruction));
+        assert_eq!(output, b"HI");
+    }
+}
@@ -0,0 +1,218 @@
+//! Snapshot tests for the programs in `tests/programs`.
+//!
+//! Every `name.asm` is assembled and run with the contents of `name.input`, if it exists, queued
+//! as input. Its output must match `name.expected` byte for byte, and it must stop at a `HLT`. To
+//! add a test, drop the source (and input) into the directory and run the tests with
+//! `LEBEN_BLESS=1`, which writes the current output to the `.expected` files instead of comparing.
+//!
+//! Every program is also run a second time, saved and resumed from the save state halfway through,
+//! and must end in the same state as the uninterrupted run.
+
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+};
+
+use rsoderh_jonsh_leben_emulator::{
+    machine::{HaltReason, Machine, MachineBuilder, MachineState, SaveInfo},
+    testing::diff_machines,
+};
+
+const INSTRUCTION_BUDGET: u64 = 10_000_000;
+const BLESS_VAR: &str = "LEBEN_BLESS";
+
+struct Outcome {
+    state: MachineState,
+    executed: u64,
+    output: Vec<u8>,
+    machine: Machine,
+}
+
+impl Outcome {
+    fn describe(&self) -> String {
+        match self.state {
+            MachineState::Running => {
+                format!("still running after {} instructions", self.executed)
+            }
+            MachineState::WaitingForInput => {
+                format!("waiting for input after {} instructions", self.executed)
+            }
+            MachineState::Halted(reason) => {
+                format!("halted after {} instructions: {}", self.executed, reason)
+            }
+        }
+    }
+}
+
+fn programs_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs")
+}
+
+fn discover(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "asm") {
+            sources.push(path);
+        }
+    }
+    sources.sort();
+    Ok(sources)
+}
+
+/// Read a fixture file, treating a missing file as empty.
+fn read_optional(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn build(source: &[u8], input: &[u8]) -> Result<Machine, String> {
+    MachineBuilder::new()
+        .assembly(source)
+        .input(input)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+fn run(source: &[u8], input: &[u8]) -> Result<Outcome, String> {
+    let mut machine = build(source, input)?;
+    let (_, executed) = machine.run_until_halt(INSTRUCTION_BUDGET);
+    Ok(Outcome {
+        state: machine.state(),
+        executed,
+        output: machine.output_buffer().to_vec(),
+        machine,
+    })
+}
+
+/// Run the program again, saving and resuming it halfway, and compare it with `outcome`.
+fn check_resume(source: &[u8], input: &[u8], outcome: &Outcome) -> Result<(), String> {
+    let halfway = outcome.executed / 2;
+    let mut machine = build(source, input)?;
+    machine.run_until_halt(halfway);
+
+    let mut save = Vec::new();
+    machine
+        .save_state(&mut save, &SaveInfo::default())
+        .map_err(|err| err.to_string())?;
+    let (mut resumed, _) = Machine::load_state(&save[..]).map_err(|err| err.to_string())?;
+    resumed.run_until_halt(INSTRUCTION_BUDGET);
+
+    let state_diff = diff_machines(&outcome.machine, &resumed);
+    if !state_diff.is_empty() {
+        return Err(format!(
+            "resuming after {} instructions changed the final state, uninterrupted vs resumed:\n{}",
+            halfway,
+            state_diff.colored(io::stderr().is_terminal())
+        ));
+    }
+    if resumed.output_buffer() != outcome.output {
+        return Err(format!(
+            "resuming after {} instructions changed the output, - uninterrupted + resumed:\n{}",
+            halfway,
+            diff(&outcome.output, resumed.output_buffer())
+        ));
+    }
+    Ok(())
+}
+
+/// Line by line comparison of two outputs, with non-printable characters escaped.
+fn diff(expected: &[u8], actual: &[u8]) -> String {
+    let expected = String::from_utf8_lossy(expected);
+    let actual = String::from_utf8_lossy(actual);
+    let expected: Vec<&str> = expected.split_inclusive('\n').collect();
+    let actual: Vec<&str> = actual.split_inclusive('\n').collect();
+
+    let mut diff = String::new();
+    for line in 0..expected.len().max(actual.len()) {
+        match (expected.get(line), actual.get(line)) {
+            (Some(expected), Some(actual)) if expected == actual => {
+                writeln!(diff, "  {}", expected.escape_debug()).unwrap();
+            }
+            (expected, actual) => {
+                if let Some(expected) = expected {
+                    writeln!(diff, "- {}", expected.escape_debug()).unwrap();
+                }
+                if let Some(actual) = actual {
+                    writeln!(diff, "+ {}", actual.escape_debug()).unwrap();
+                }
+            }
+        }
+    }
+    diff
+}
+
+/// Run one program, returning a description of what went wrong.
+fn check(source_path: &Path, bless: bool) -> Result<(), String> {
+    let read = |path: &Path| {
+        read_optional(path).map_err(|err| format!("couldn't read {}: {}", path.display(), err))
+    };
+    let source = read(source_path)?.unwrap_or_default();
+    let input = read(&source_path.with_extension("input"))?.unwrap_or_default();
+    let expected_path = source_path.with_extension("expected");
+
+    let outcome = run(&source, &input)?;
+    if outcome.state != MachineState::Halted(HaltReason::HaltInstruction) {
+        return Err(outcome.describe());
+    }
+
+    if bless {
+        return fs::write(&expected_path, &outcome.output)
+            .map_err(|err| format!("couldn't write {}: {}", expected_path.display(), err));
+    }
+
+    let Some(expected) = read(&expected_path)? else {
+        return Err(format!(
+            "{} is missing, run with {}=1 to create it",
+            expected_path.display(),
+            BLESS_VAR
+        ));
+    };
+    if expected != outcome.output {
+        return Err(format!(
+            "output differs ({}), - expected + actual:\n{}",
+            outcome.describe(),
+            diff(&expected, &outcome.output)
+        ));
+    }
+    check_resume(&source, &input, &outcome)
+}
+
+#[test]
+fn programs() {
+    let bless = env::var_os(BLESS_VAR).is_some_and(|value| value != "0");
+    let sources = discover(&programs_dir()).unwrap();
+    assert!(!sources.is_empty(), "no programs found");
+
+    let failures: Vec<String> = sources
+        .iter()
+        .filter_map(|source| {
+            let name = source.file_stem().unwrap().to_string_lossy();
+            check(source, bless)
+                .err()
+                .map(|message| format!("{}: {}", name, message))
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} programs failed:\n\n{}",
+        failures.len(),
+        sources.len(),
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn diff_marks_changed_lines() {
+    assert_eq!(
+        diff(b"1\n2\n3\n", b"1\n4\n3\n\t"),
+        "  1\\n\n- 2\\n\n+ 4\\n\n  3\\n\n+ \\t\n"
+    );
+}
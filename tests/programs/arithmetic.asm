@@ -0,0 +1,43 @@
+;
+; 8-bit and 16-bit additions, with and without carry
+;
+
+        ORG 0100H
+
+        LXI SP, 0F000H
+
+        MVI A, 100
+        MVI B, 55
+        ADD B           ; A = 155
+        OUT 1
+        MVI A, 0AH
+        OUT 0
+
+        MVI A, 200
+        ADI 100         ; A = 44, carry set
+        ACI 0           ; A = 45
+        OUT 1
+        MVI A, 0AH
+        OUT 0
+
+        MVI A, 10
+        SUI 3           ; A = 7
+        OUT 1
+        MVI A, 0AH
+        OUT 0
+
+        LXI H, 1234
+        LXI D, 4321
+        DAD D           ; HL = 5555
+        OUT 2
+        MVI A, 0AH
+        OUT 0
+
+        LXI H, 0FFFFH
+        INX H           ; HL wraps to 0
+        OUT 2
+        MVI A, 0AH
+        OUT 0
+
+        HLT
+        END
@@ -0,0 +1,59 @@
+;
+; Read data defined with DB, DW and DS
+;
+
+        ORG 0
+
+        LXI SP, 0F000H
+
+        LXI H, TABLE    ; Sum the bytes of TABLE
+        MVI B, 5
+        MVI A, 0
+SUM:    ADD M
+        INX H
+        DCR B
+        JNZ SUM
+        OUT 1           ; 150
+        MVI A, 0AH
+        OUT 0
+
+        LHLD WORD
+        OUT 2           ; 4660
+        MVI A, 0AH
+        OUT 0
+
+        LXI H, MSG      ; Copy MSG into BUF, then print BUF
+        LXI D, BUF
+COPY:   MOV A, M
+        STAX D
+        INX H
+        INX D
+        CPI 0
+        JNZ COPY
+
+        LXI H, BUF
+PRINT:  MOV A, M
+        CPI 0
+        JZ DONE
+        OUT 0
+        INX H
+        JMP PRINT
+
+DONE:   LDA SEVEN
+        OUT 1
+        MVI A, 0AH
+        OUT 0
+        HLT
+
+TABLE:  DB 10
+        DB 20
+        DB 30
+        DB 40
+        DB 50
+WORD:   DW 4660
+MSG:    DB 'copied'
+        DB 0AH
+        DB 0
+SEVEN:  DB 7
+BUF:    DS 16
+        END
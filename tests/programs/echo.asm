@@ -0,0 +1,29 @@
+;
+; Echo a line of input in upper case, followed by its length
+;
+
+        ORG 0
+
+        LXI SP, 0F000H
+
+        MVI C, 0
+READ:   IN 0
+        CPI 0AH
+        JZ DONE
+        CPI 61H         ; 'a'
+        JC WRITE
+        CPI 7BH         ; 'z' + 1
+        JNC WRITE
+        SUI 20H
+WRITE:  OUT 0
+        INR C
+        JMP READ
+
+DONE:   MVI A, 0AH
+        OUT 0
+        MOV A, C
+        OUT 1
+        MVI A, 0AH
+        OUT 0
+        HLT
+        END
@@ -0,0 +1,31 @@
+;
+; Count down from 9, then multiply 6 by 7 with a nested loop
+;
+
+        ORG 0
+
+        LXI SP, 0F000H
+
+        MVI B, 9
+COUNT:  MOV A, B
+        ADI 30H         ; '0' + B
+        OUT 0
+        DCR B
+        JNZ COUNT
+        MVI A, 0AH
+        OUT 0
+
+        MVI A, 0
+        MVI C, 6
+OUTER:  MVI D, 7
+INNER:  INR A
+        DCR D
+        JNZ INNER
+        DCR C
+        JNZ OUTER
+        OUT 1           ; 42
+        MVI A, 0AH
+        OUT 0
+
+        HLT
+        END
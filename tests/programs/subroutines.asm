@@ -0,0 +1,43 @@
+;
+; Print strings through a subroutine that preserves the caller's registers
+;
+
+        ORG 0
+
+        LXI SP, 0F000H
+
+        MVI B, 3
+AGAIN:  LXI H, HELLO
+        CALL PRINT
+        DCR B           ; B survives the call
+        JNZ AGAIN
+
+        LXI H, BYE
+        CALL PRINT
+        MOV A, B
+        OUT 1           ; 0
+        MVI A, 0AH
+        OUT 0
+        HLT
+
+; Print the zero-terminated string at HL
+PRINT:  PUSH PSW
+        PUSH B
+        PUSH H
+NEXT:   MOV A, M
+        CPI 0
+        JZ PDONE
+        OUT 0
+        INX H
+        JMP NEXT
+PDONE:  POP H
+        POP B
+        POP PSW
+        RET
+
+HELLO:  DB 'Hello!'
+        DB 0AH
+        DB 0
+BYE:    DB 'B = '
+        DB 0
+        END
@@ -0,0 +1,106 @@
+"""Tests of the Python bindings, run with `pytest` after `maturin develop`."""
+
+import pytest
+
+import leben
+
+HELLO = """        ORG 0
+        LXI H, TEXT
+LOOP:   MOV A, M
+        CPI 0
+        JZ DONE
+        OUT 0
+        INX H
+        JMP LOOP
+DONE:   HLT
+TEXT:   DB 'hi'
+        DB 0
+        END
+"""
+
+
+def test_run_assembly():
+    machine = leben.Machine()
+    machine.load_assembly(HELLO)
+    executed = machine.run(1000)
+    assert 0 < executed < 1000
+    assert not machine.running
+    assert machine.halt_reason == leben.HaltReason.HaltInstruction
+    assert machine.drain_output() == b"hi"
+    assert machine.drain_output() == b""
+
+
+def test_step_and_registers():
+    machine = leben.Machine()
+    machine.load_bytes(bytes([0x3E, 0x12, 0x06, 0x34, 0x76]), origin=0x100)
+    assert machine.register("pc") == 0x100
+    assert machine.step()
+    assert machine.register("A") == 0x12
+    assert machine.step()
+    assert machine.register("bc") == 0x3400
+    assert not machine.step()
+    assert machine.halt_reason == leben.HaltReason.HaltInstruction
+
+    machine.set_register("hl", 0xBEEF)
+    assert machine.register("h") == 0xBE
+    with pytest.raises(ValueError):
+        machine.set_register("a", 0x100)
+    with pytest.raises(ValueError):
+        machine.register("psw")
+    assert set(machine.flags()) == {"sign", "zero", "auxiliary_carry", "parity", "carry"}
+
+
+def test_memory_is_copied():
+    machine = leben.Machine()
+    machine.write_memory(0x2000, b"\x01\x02\x03")
+    snapshot = machine.read_memory(0x2000, 3)
+    assert snapshot == b"\x01\x02\x03"
+
+    machine.write_memory(0x2000, b"\xff")
+    assert snapshot == b"\x01\x02\x03"
+    assert machine.read_memory(0x2000, 3) == b"\xff\x02\x03"
+
+    assert len(machine.read_memory(0xFFFE, 16)) == 2
+    with pytest.raises(ValueError):
+        machine.write_memory(0xFFFF, b"\x00\x00")
+
+
+def test_input_queue():
+    machine = leben.Machine()
+    # IN 0, OUT 0, JMP 0000H: copy input until it runs out.
+    machine.load_bytes(bytes([0xDB, 0x00, 0xD3, 0x00, 0xC3, 0x00, 0x00]))
+    machine.push_input(b"abc")
+    machine.run(1000)
+    assert machine.drain_output() == b"abc"
+    assert machine.halt_reason == leben.HaltReason.HaltInstruction
+
+
+def test_faults():
+    machine = leben.Machine()
+    # LXI SP, 0FFFEH, then POP B past the top of memory.
+    machine.load_bytes(bytes([0x31, 0xFE, 0xFF, 0xC1]))
+    machine.run(10)
+    assert machine.halt_reason == leben.HaltReason.StackUnderflow
+
+
+def test_assemble_and_disassemble():
+    program, origin, symbols = leben.assemble(HELLO)
+    assert isinstance(program, bytes)
+    assert origin == 0
+    assert symbols["TEXT"] == program.index(b"hi")
+
+    lines = leben.disassemble(program, origin)
+    assert lines[0] == (0x0000, program[:3], None, "LXI H,D%04X" % symbols["TEXT"])
+    assert all(isinstance(line[1], bytes) for line in lines)
+
+
+def test_load_errors():
+    machine = leben.Machine()
+    with pytest.raises(leben.AssemblyError):
+        machine.load_assembly("        FOO\n        END\n")
+    with pytest.raises(leben.AssemblyError):
+        leben.assemble("        FOO\n        END\n")
+    assert issubclass(leben.AssemblyError, ValueError)
+    with pytest.raises(ValueError):
+        machine.load_bytes(b"\x00\x00", origin=0xFFFF)
+
@@ -0,0 +1,125 @@
+use rsoderh_jonsh_leben_emulator::{
+    instruction::Instruction,
+    machine::{HaltReason, Machine, MachineBuilder, MachineState, MemoryDump},
+};
+
+// 0100: MVI A, 2AH
+// 0102: STC
+// 0103: LXI H, 1234H
+// 0106: HLT
+// 2000: DB 0FFH
+fn known_machine() -> Machine {
+    let mut machine = MachineBuilder::new()
+        .program(&[0x3E, 0x2A, 0x37, 0x21, 0x34, 0x12, 0x76], 0x0100)
+        .sp(0xFFFE)
+        .build()
+        .unwrap();
+    machine.memory_mut().write_8(0x2000, 0xFF);
+    machine.steps().for_each(drop);
+    machine
+}
+
+fn dump(machine: &Machine, memory: MemoryDump) -> String {
+    let mut json = Vec::new();
+    machine.dump_json(&mut json, memory).unwrap();
+    String::from_utf8(json).unwrap()
+}
+
+#[test]
+fn golden() {
+    let machine = known_machine();
+    assert_eq!(
+        dump(&machine, MemoryDump::NonZero),
+        include_str!("data/state.json")
+    );
+}
+
+#[test]
+fn round_trip() {
+    let machine = known_machine();
+
+    for memory in [MemoryDump::NonZero, MemoryDump::Full] {
+        let json = dump(&machine, memory);
+        let restored = Machine::restore_json(json.as_bytes()).unwrap();
+
+        assert_eq!(
+            restored.state(),
+            MachineState::Halted(HaltReason::HaltInstruction)
+        );
+        assert_eq!(restored.pc(), machine.pc());
+        assert_eq!(restored.get_status_word(), machine.get_status_word());
+        assert_eq!(
+            restored.memory().as_raw()[..],
+            machine.memory().as_raw()[..]
+        );
+        // Dumping the restored machine gives the same JSON again.
+        assert_eq!(dump(&restored, memory), json);
+    }
+}
+
+#[test]
+fn interrupts() {
+    // 0000: EI
+    // 0001: NOP
+    let mut machine = MachineBuilder::new()
+        .program(&[0xFB, 0x00], 0x0000)
+        .build()
+        .unwrap();
+    machine.step();
+    let json = dump(&machine, MemoryDump::NonZero);
+    assert!(json.contains("\"delayed\": true"));
+    let mut restored = Machine::restore_json(json.as_bytes()).unwrap();
+    assert!(!restored.interrupts_enabled());
+    restored.step();
+    assert!(restored.interrupts_enabled());
+
+    assert!(restored.request_interrupt(Instruction::Call(0x1234)));
+    let json = dump(&restored, MemoryDump::NonZero);
+    assert!(json.contains("\"pending\": \"CD3412\""));
+    assert_eq!(
+        dump(
+            &Machine::restore_json(json.as_bytes()).unwrap(),
+            MemoryDump::NonZero
+        ),
+        json
+    );
+
+    // Dumps from before interrupts were recorded restore with them disabled.
+    let start = json.find("\"interrupts\"").unwrap();
+    let end = start + json[start..].find("},").unwrap() + 2;
+    let old = format!("{}{}", &json[..start], &json[end..].trim_start());
+    assert!(
+        !Machine::restore_json(old.as_bytes())
+            .unwrap()
+            .interrupts_enabled()
+    );
+}
+
+#[test]
+fn full_dump_restores_like_non_zero_dump() {
+    let machine = known_machine();
+    let full = Machine::restore_json(dump(&machine, MemoryDump::Full).as_bytes()).unwrap();
+    assert_eq!(
+        dump(&full, MemoryDump::NonZero),
+        dump(&machine, MemoryDump::NonZero)
+    );
+}
+
+#[test]
+fn invalid_input() {
+    assert!(Machine::restore_json(&b"not json"[..]).is_err());
+    assert!(Machine::restore_json(&b"{}"[..]).is_err());
+
+    let json = dump(&known_machine(), MemoryDump::NonZero);
+    let misaligned = json.replace("\"0100\":", "\"0101\":");
+    let Err(err) = Machine::restore_json(misaligned.as_bytes()) else {
+        panic!("misaligned chunk was accepted");
+    };
+    assert!(err.to_string().contains("not aligned"));
+
+    let bad_register = json.replace("\"a\": \"2A\"", "\"a\": \"2A2\"");
+    let Err(err) = Machine::restore_json(bad_register.as_bytes()) else {
+        panic!("invalid register was accepted");
+    };
+    assert!(err.to_string().contains("'a'"));
+}
@@ -0,0 +1,40 @@
+//! Smoke test of the browser bindings, run with
+//! `cargo test --target wasm32-unknown-unknown --features wasm`.
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use rsoderh_jonsh_leben_emulator::wasm::WasmMachine;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const ECHO: &str = "        ORG 0100H
+        MVI A, 48H      ; 'H'
+        OUT 0
+        IN 0
+        OUT 0
+        HLT
+        END
+";
+
+#[wasm_bindgen_test]
+fn assemble_and_run() {
+    let mut machine = WasmMachine::new();
+    machine.load_assembly(ECHO).unwrap();
+    machine.push_input(b"i");
+
+    let executed = machine.run(100);
+
+    assert_eq!(executed, 5);
+    assert!(!machine.running());
+    assert_eq!(machine.drain_output(), b"Hi");
+    assert!(machine.drain_output().is_empty());
+    assert_eq!(machine.read_memory(0x0100, 2), vec![0x3E, b'H']);
+}
+
+#[wasm_bindgen_test]
+fn empty_input_halts() {
+    let mut machine = WasmMachine::new();
+    // IN 0
+    machine.load_program(&[0xDB, 0x00], 0x0000).unwrap();
+
+    assert!(!machine.step());
+    assert_eq!(machine.read_memory(0xFFFF, 10).len(), 1);
+}